@@ -0,0 +1,131 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufRead, BufReader},
+};
+
+use crate::{
+    EngineEvent, EventKind, PaymentEngine, UserTransactions,
+    errors::{EngineError, ErrorCode},
+};
+
+/// Which clients may transact. A client is refused when it is blocked, or
+/// when an allowlist is set and the client is not on it.
+#[derive(Debug, Default, Clone)]
+pub struct AccessList {
+    pub blocked: HashSet<u16>,
+    pub allowed: Option<HashSet<u16>>,
+    /// Hold refused transactions for review instead of rejecting them.
+    pub hold: bool,
+}
+
+impl AccessList {
+    pub fn permits(&self, client_id: u16) -> bool {
+        !self.blocked.contains(&client_id)
+            && self
+                .allowed
+                .as_ref()
+                .is_none_or(|allowed| allowed.contains(&client_id))
+    }
+}
+
+/// Reads one client id per line. Blank lines, `#` comments and a `client`
+/// header are skipped.
+pub fn read_client_list(path: &str) -> Result<HashSet<u16>, Box<dyn std::error::Error>> {
+    let mut clients = HashSet::new();
+    for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        let entry = line.split('#').next().unwrap_or("").trim();
+        if entry.is_empty() || entry == "client" {
+            continue;
+        }
+        let client_id = entry
+            .parse()
+            .map_err(|_| format!("line {}: invalid client id '{}'", index + 1, entry))?;
+        clients.insert(client_id);
+    }
+    Ok(clients)
+}
+
+impl PaymentEngine {
+    pub fn set_access_list(&mut self, access: AccessList) {
+        self.access = access;
+    }
+
+    /// Transactions held because their client was refused.
+    pub fn take_held_for_review(&mut self) -> Vec<UserTransactions> {
+        std::mem::take(&mut self.held_for_review)
+            .into_iter()
+            .collect()
+    }
+
+    pub(crate) fn check_access(&self, client_id: u16) -> Result<(), EngineError> {
+        if self.access.permits(client_id) {
+            Ok(())
+        } else {
+            Err(EngineError::new(
+                ErrorCode::ClientBlocked,
+                format!("Client {} is blocked", client_id),
+            ))
+        }
+    }
+
+    /// Holds `action` if its client is refused and the access list asks for
+    /// holding. Returns the action back when it should be processed normally.
+    pub(crate) fn try_hold_blocked(
+        &mut self,
+        action: UserTransactions,
+    ) -> Option<UserTransactions> {
+        if !self.access.hold || self.access.permits(action.client_id) {
+            return Some(action);
+        }
+        self.events.push(EngineEvent {
+            kind: EventKind::HeldForReview,
+            action: action.clone(),
+        });
+        self.held_for_review.push_back(action);
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TxType, tx};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_blocked_clients_are_rejected_or_held() {
+        let mut engine = PaymentEngine::new();
+        engine.set_access_list(AccessList {
+            blocked: HashSet::from([2]),
+            allowed: Some(HashSet::from([1, 2])),
+            hold: false,
+        });
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10)))
+            .unwrap();
+        assert_eq!(
+            engine
+                .process_action(tx(TxType::Deposit, 2, 2).with_amount(dec!(10)))
+                .unwrap_err()
+                .code(),
+            ErrorCode::ClientBlocked
+        );
+        engine
+            .process_action(tx(TxType::Deposit, 3, 3).with_amount(dec!(10)))
+            .unwrap_err();
+
+        engine.set_access_list(AccessList {
+            blocked: HashSet::from([2]),
+            allowed: None,
+            hold: true,
+        });
+        engine
+            .process_action(tx(TxType::Deposit, 2, 4).with_amount(dec!(10)))
+            .unwrap();
+        assert!(!engine.accounts.contains_key(&2));
+        assert_eq!(engine.take_held_for_review().len(), 1);
+        assert_eq!(engine.drain_events()[0].kind, EventKind::HeldForReview);
+    }
+}
@@ -0,0 +1,178 @@
+use std::path::Path;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    PaymentEngine, UserTransactions,
+    errors::{EngineError, ErrorCode, no_account},
+};
+
+/// Attributes an account is opened with, either by an `open_account`
+/// transaction or from an accounts seed file.
+#[derive(Debug, Default, PartialEq, Clone, Deserialize, Serialize)]
+pub struct AccountAttributes {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tier: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// How far below zero withdrawals may take the available balance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credit_limit: Option<Decimal>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SeedRow {
+    client: u16,
+    currency: Option<String>,
+    tier: Option<String>,
+    kind: Option<String>,
+    credit_limit: Option<Decimal>,
+}
+
+impl SeedRow {
+    fn into_seed(self) -> (u16, AccountAttributes) {
+        let attributes = AccountAttributes {
+            currency: self.currency,
+            tier: self.tier,
+            kind: self.kind,
+            credit_limit: self.credit_limit,
+        };
+        (self.client, attributes)
+    }
+}
+
+/// Loads a `client,currency,tier,kind,credit_limit` CSV of accounts to open
+/// before the run. Every column but `client` may be left empty.
+pub fn read_account_seeds(
+    path: &str,
+) -> Result<Vec<(u16, AccountAttributes)>, Box<dyn std::error::Error>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(Path::new(path))?;
+    let mut seeds = Vec::new();
+    for result in rdr.deserialize::<SeedRow>() {
+        seeds.push(result?.into_seed());
+    }
+    Ok(seeds)
+}
+
+impl PaymentEngine {
+    /// In strict mode every transaction for an account that was never opened
+    /// (by `open_account`, a seed file or opening balances) is rejected,
+    /// instead of the first deposit creating the account.
+    pub fn set_require_open_accounts(&mut self, strict: bool) {
+        self.require_open_accounts = strict;
+    }
+
+    /// Opens `client_id` with `attributes`. An account that already exists
+    /// implicitly keeps its balances; one opened explicitly can't be opened
+    /// again.
+    pub fn open_account(
+        &mut self,
+        client_id: u16,
+        attributes: AccountAttributes,
+    ) -> Result<(), EngineError> {
+        if self.attributes.contains_key(&client_id) {
+            return Err(EngineError::new(
+                ErrorCode::AccountAlreadyOpen,
+                format!("Client {} is already open", client_id),
+            ));
+        }
+        self.get_or_create_account(client_id);
+        self.attributes.insert(client_id, attributes);
+        Ok(())
+    }
+
+    /// Closes `client_id` for good: every later transaction for it is
+    /// refused. Its balances stay as they are.
+    pub fn close_account(&mut self, client_id: u16) -> Result<(), EngineError> {
+        if !self.accounts.contains_key(&client_id) {
+            return Err(no_account(client_id));
+        }
+        self.closed_accounts.insert(client_id);
+        Ok(())
+    }
+
+    pub fn is_closed(&self, client_id: u16) -> bool {
+        self.closed_accounts.contains(&client_id)
+    }
+
+    pub(crate) fn process_close_account(
+        &mut self,
+        action: &UserTransactions,
+    ) -> Result<(), EngineError> {
+        self.close_account(action.client_id)
+    }
+
+    pub fn account_attributes(&self, client_id: u16) -> Option<&AccountAttributes> {
+        self.attributes.get(&client_id)
+    }
+
+    pub(crate) fn process_open_account(
+        &mut self,
+        action: &UserTransactions,
+    ) -> Result<(), EngineError> {
+        self.open_account(
+            action.client_id,
+            action.attributes.clone().unwrap_or_default(),
+        )
+    }
+
+    pub(crate) fn credit_limit(&self, client_id: u16) -> Decimal {
+        self.attributes
+            .get(&client_id)
+            .and_then(|a| a.credit_limit)
+            .unwrap_or(Decimal::ZERO)
+    }
+
+    /// Whether a transaction may touch `client_id` under the strict-mode rule.
+    pub(crate) fn is_account_open(&self, client_id: u16) -> bool {
+        !self.require_open_accounts || self.accounts.contains_key(&client_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TxType, tx};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_strict_mode_requires_open_and_honours_credit_limit() {
+        let mut engine = PaymentEngine::new();
+        engine.set_require_open_accounts(true);
+
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10)))
+            .unwrap_err();
+        assert!(engine.accounts.is_empty());
+
+        let mut open = tx(TxType::OpenAccount, 1, 2);
+        open.attributes = Some(AccountAttributes {
+            currency: Some("EUR".to_string()),
+            credit_limit: Some(dec!(50)),
+            ..Default::default()
+        });
+        engine.process_action(open.clone()).unwrap();
+        engine.process_action(open).unwrap_err();
+
+        engine
+            .process_action(tx(TxType::Deposit, 1, 3).with_amount(dec!(10)))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 4).with_amount(dec!(40)))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 5).with_amount(dec!(40)))
+            .unwrap_err();
+
+        assert_eq!(engine.accounts[&1].available, dec!(-30));
+        assert_eq!(
+            engine.account_attributes(1).unwrap().currency.as_deref(),
+            Some("EUR")
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use std::{fmt, path::Path, str::FromStr};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    EngineEvent, EventKind, PaymentEngine, RESERVED_ACCOUNTS, TxType, UserAccount,
+    UserTransactions,
+    errors::{EngineError, ErrorCode, no_account},
+    ids::SyntheticKind,
+    money::{Amount, SignedAmount},
+};
+
+/// Internal account every adjustment is offset against, so the sum of all
+/// balances only changes through real deposits and withdrawals.
+pub const ADJUSTMENTS_ACCOUNT: u16 = u16::MAX;
+
+/// Why a balance was adjusted, e.g. `FEE_REFUND`. Must be a single
+/// non-empty word.
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+pub struct ReasonCode(String);
+
+impl FromStr for ReasonCode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() || s.contains(char::is_whitespace) {
+            return Err(format!("Invalid reason code '{}'", s));
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl fmt::Display for ReasonCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// One applied adjustment. A positive `amount` credits the client.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct Adjustment {
+    pub tx_id: u32,
+    pub client_id: u16,
+    pub amount: Decimal,
+    pub reason: ReasonCode,
+}
+
+/// Adjustment as requested in an adjustments file, before it is applied.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+pub struct AdjustmentRequest {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    #[serde(deserialize_with = "deserialize_amount")]
+    pub amount: SignedAmount,
+    pub reason: String,
+}
+
+fn deserialize_amount<'de, D>(deserializer: D) -> Result<SignedAmount, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = crate::deserialize_decimal(deserializer)?;
+    SignedAmount::new(value).map_err(serde::de::Error::custom)
+}
+
+/// Loads a `client,amount,reason` CSV; negative amounts are debits.
+pub fn read_adjustments(path: &str) -> Result<Vec<AdjustmentRequest>, Box<dyn std::error::Error>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(Path::new(path))?;
+    let mut requests = Vec::new();
+    for result in rdr.deserialize::<AdjustmentRequest>() {
+        requests.push(result?);
+    }
+    Ok(requests)
+}
+
+impl PaymentEngine {
+    /// Adjustments applied so far, in order.
+    pub fn adjustments(&self) -> &im::Vector<Adjustment> {
+        &self.adjustments
+    }
+
+    /// Moves `amount` between `client_id` and [`ADJUSTMENTS_ACCOUNT`]
+    /// outside the deposit/withdrawal rules. Reached through
+    /// [`crate::admin::AdminHandle::adjust`].
+    pub(crate) fn apply_adjustment(
+        &mut self,
+        client_id: u16,
+        amount: SignedAmount,
+        reason: ReasonCode,
+    ) -> Result<Adjustment, EngineError> {
+        let amount = self.config.round(amount.value());
+        if amount.is_zero() {
+            return Err(EngineError::new(
+                ErrorCode::InvalidAmount,
+                "Adjustment amount must not be zero",
+            ));
+        }
+        if RESERVED_ACCOUNTS.contains(&client_id) {
+            return Err(EngineError::new(
+                ErrorCode::ReservedAccount,
+                format!("Internal account {} can't be adjusted directly", client_id),
+            ));
+        }
+        let magnitude =
+            Amount::new(amount.abs()).map_err(|e| EngineError::new(ErrorCode::InvalidAmount, e))?;
+        if !self.accounts.contains_key(&client_id) {
+            return Err(no_account(client_id));
+        }
+        let tx_id = self.synthetic_ids.next(SyntheticKind::Adjustment)?;
+
+        let (client_kind, offset_kind) = if amount > Decimal::ZERO {
+            (EventKind::AdjustmentCredit, EventKind::AdjustmentDebit)
+        } else {
+            (EventKind::AdjustmentDebit, EventKind::AdjustmentCredit)
+        };
+        for (account_id, delta, kind) in [
+            (client_id, amount, client_kind),
+            (ADJUSTMENTS_ACCOUNT, -amount, offset_kind),
+        ] {
+            let account = self
+                .accounts
+                .entry(account_id)
+                .or_insert_with(|| UserAccount::new(account_id));
+            account.available += delta;
+            account.calculate_total();
+
+            let action = UserTransactions {
+                tx_type: TxType::Adjustment,
+                client_id: account_id,
+                tx_id,
+                amount: Some(magnitude),
+                timestamp: self.stream_time,
+                ..Default::default()
+            };
+            self.record_action(action.clone());
+            self.events.push(EngineEvent { kind, action });
+        }
+        self.mark_activity(client_id);
+
+        let adjustment = Adjustment {
+            tx_id,
+            client_id,
+            amount,
+            reason,
+        };
+        self.adjustments.push_back(adjustment.clone());
+        Ok(adjustment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{admin::AdminCapability, amount, signed_amount, tx};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_adjustments_are_offset_and_admin_only() {
+        let mut engine = PaymentEngine::new();
+        engine.client(1).deposit(1, amount(dec!(10.0))).unwrap();
+        let refused = engine.process_action(UserTransactions {
+            tx_type: TxType::Adjustment,
+            client_id: 1,
+            tx_id: 2,
+            amount: Some(Amount::new(dec!(5.0)).unwrap()),
+            ..Default::default()
+        });
+        assert!(refused.is_err());
+
+        engine.set_admin_secret("ops").unwrap();
+        let capability = AdminCapability::grant("ops");
+        let mut admin = engine.admin(&capability).unwrap();
+        let credit = admin
+            .adjust(1, signed_amount(dec!(2.5)), "FEE_REFUND".parse().unwrap())
+            .unwrap();
+        admin
+            .adjust(1, signed_amount(dec!(-1.0)), "MANUAL_FIX".parse().unwrap())
+            .unwrap();
+        assert!(
+            admin
+                .adjust(1, signed_amount(dec!(0)), "NOOP".parse().unwrap())
+                .is_err()
+        );
+        assert!(
+            admin
+                .adjust(9, signed_amount(dec!(1)), "NEW".parse().unwrap())
+                .is_err()
+        );
+        assert!("two words".parse::<ReasonCode>().is_err());
+
+        assert_eq!(credit.tx_id, SyntheticKind::Adjustment.range_start());
+        assert_eq!(engine.accounts[&1].available, dec!(11.5));
+        assert_eq!(engine.accounts[&ADJUSTMENTS_ACCOUNT].available, dec!(-1.5));
+        assert_eq!(engine.adjustments().len(), 2);
+        let kinds: Vec<EventKind> = engine.drain_events().iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                EventKind::AdjustmentCredit,
+                EventKind::AdjustmentDebit,
+                EventKind::AdjustmentDebit,
+                EventKind::AdjustmentCredit,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_adjustments_account_refuses_transactions() {
+        let mut engine = PaymentEngine::new();
+        engine.client(1).deposit(1, amount(dec!(10.0))).unwrap();
+        let refused = [
+            tx(TxType::Deposit, ADJUSTMENTS_ACCOUNT, 2).with_amount(dec!(5.0)),
+            tx(TxType::Withdrawal, ADJUSTMENTS_ACCOUNT, 3).with_amount(dec!(1.0)),
+            tx(TxType::Transfer, 1, 4)
+                .with_amount(dec!(1.0))
+                .to(ADJUSTMENTS_ACCOUNT),
+        ];
+        for action in refused {
+            let error = engine.process_action(action).unwrap_err();
+            assert_eq!(error.code(), ErrorCode::ReservedAccount);
+        }
+        assert!(!engine.accounts.contains_key(&ADJUSTMENTS_ACCOUNT));
+        assert_eq!(engine.accounts[&1].available, dec!(10.0));
+    }
+}
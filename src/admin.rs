@@ -0,0 +1,231 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    PaymentEngine,
+    adjustments::{Adjustment, ReasonCode},
+    errors::{EngineError, ErrorCode, no_account},
+    money::SignedAmount,
+    pipeline::RunSummary,
+    wal::WalEntry,
+};
+
+/// Permission to use [`AdminHandle`]: a secret, checked against the one the
+/// engine was set up with by [`PaymentEngine::set_admin_secret`]. Code
+/// handed the engine but not the secret can't reach the operations that
+/// bypass the normal transaction rules.
+pub struct AdminCapability {
+    digest: [u8; 32],
+}
+
+impl AdminCapability {
+    pub fn grant(secret: &str) -> Self {
+        Self {
+            digest: digest(secret),
+        }
+    }
+}
+
+fn digest(secret: &str) -> [u8; 32] {
+    Sha256::digest(secret.as_bytes()).into()
+}
+
+/// Operations that override the engine's own bookkeeping.
+pub struct AdminHandle<'a> {
+    engine: &'a mut PaymentEngine,
+}
+
+impl PaymentEngine {
+    /// Sets the secret [`Self::admin`] checks. It can only be set once, so
+    /// code handed the engine afterwards can't swap in its own.
+    pub fn set_admin_secret(&mut self, secret: &str) -> Result<(), EngineError> {
+        if self.admin_secret.is_some() {
+            return Err(EngineError::new(
+                ErrorCode::AdminOnly,
+                "The admin secret is already set",
+            ));
+        }
+        self.admin_secret = Some(digest(secret));
+        Ok(())
+    }
+
+    /// Fails unless `capability` carries the engine's admin secret; an
+    /// engine without one refuses every capability.
+    pub fn admin(&mut self, capability: &AdminCapability) -> Result<AdminHandle<'_>, EngineError> {
+        if self.admin_secret != Some(capability.digest) {
+            return Err(EngineError::new(
+                ErrorCode::AdminOnly,
+                "The admin capability doesn't match this engine",
+            ));
+        }
+        Ok(AdminHandle { engine: self })
+    }
+}
+
+/// An [`AdminHandle`] operation as written to the write-ahead log, so a
+/// replay makes the same corrections at the same point.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum AdminOp {
+    Unlock {
+        client: u16,
+    },
+    PurgeBefore {
+        timestamp: u64,
+    },
+    Adjust {
+        client: u16,
+        amount: SignedAmount,
+        reason: ReasonCode,
+    },
+    ForceBalance {
+        client: u16,
+        available: Decimal,
+        held: Decimal,
+    },
+}
+
+impl PaymentEngine {
+    pub(crate) fn apply_admin_op(&mut self, op: AdminOp) -> Result<(), EngineError> {
+        match op {
+            AdminOp::Unlock { client } => self.unlock(client),
+            AdminOp::PurgeBefore { timestamp } => {
+                self.purge_before(timestamp);
+                Ok(())
+            }
+            AdminOp::Adjust {
+                client,
+                amount,
+                reason,
+            } => self.apply_adjustment(client, amount, reason).map(drop),
+            AdminOp::ForceBalance {
+                client,
+                available,
+                held,
+            } => self.force_balance(client, available, held),
+        }
+    }
+
+    fn unlock(&mut self, client_id: u16) -> Result<(), EngineError> {
+        let account = self
+            .accounts
+            .get_mut(&client_id)
+            .ok_or_else(|| no_account(client_id))?;
+        account.locked = false;
+        Ok(())
+    }
+
+    fn force_balance(
+        &mut self,
+        client_id: u16,
+        available: Decimal,
+        held: Decimal,
+    ) -> Result<(), EngineError> {
+        let account = self
+            .accounts
+            .get_mut(&client_id)
+            .ok_or_else(|| no_account(client_id))?;
+        account.available = available;
+        account.held = held;
+        account.calculate_total();
+        Ok(())
+    }
+}
+
+/// Every operation is written to the engine's write-ahead log, if it has
+/// one, once it has applied.
+impl AdminHandle<'_> {
+    /// Lifts the lock a chargeback put on `client_id`.
+    pub fn unlock(&mut self, client_id: u16) -> Result<(), EngineError> {
+        let op = AdminOp::Unlock { client: client_id };
+        self.engine.logged(WalEntry::Admin { admin: op }, |engine| {
+            engine.unlock(client_id)
+        })
+    }
+
+    /// Drops transaction records dated before `timestamp`, except those
+    /// under an open dispute. Returns how many were dropped.
+    pub fn purge_before(&mut self, timestamp: u64) -> Result<usize, EngineError> {
+        let op = AdminOp::PurgeBefore { timestamp };
+        self.engine.logged(WalEntry::Admin { admin: op }, |engine| {
+            Ok(engine.purge_before(timestamp))
+        })
+    }
+
+    /// Credits (positive `amount`) or debits `client_id`, offsetting the
+    /// change against [`crate::adjustments::ADJUSTMENTS_ACCOUNT`].
+    pub fn adjust(
+        &mut self,
+        client_id: u16,
+        amount: SignedAmount,
+        reason: ReasonCode,
+    ) -> Result<Adjustment, EngineError> {
+        let op = AdminOp::Adjust {
+            client: client_id,
+            amount,
+            reason: reason.clone(),
+        };
+        self.engine.logged(WalEntry::Admin { admin: op }, |engine| {
+            engine.apply_adjustment(client_id, amount, reason)
+        })
+    }
+
+    /// Overwrites `client_id`'s balances. Nothing is recorded against any
+    /// transaction, so disputes of earlier transactions still move the old
+    /// amounts.
+    pub fn force_balance(
+        &mut self,
+        client_id: u16,
+        available: Decimal,
+        held: Decimal,
+    ) -> Result<(), EngineError> {
+        let op = AdminOp::ForceBalance {
+            client: client_id,
+            available,
+            held,
+        };
+        self.engine.logged(WalEntry::Admin { admin: op }, |engine| {
+            engine.force_balance(client_id, available, held)
+        })
+    }
+
+    /// [`PaymentEngine::replay_wal`] for a log that also holds admin
+    /// operations, which are applied in their place.
+    pub fn replay_wal(&mut self, path: &str) -> Result<RunSummary, String> {
+        self.engine.replay(path, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_admin_overrides() {
+        let mut engine = PaymentEngine::new();
+        engine.client(1).deposit(1, amount(dec!(10.0))).unwrap();
+        engine.client(1).dispute(1).unwrap();
+        engine.client(1).chargeback(1).unwrap();
+        assert!(engine.accounts()[&1].locked);
+
+        let capability = AdminCapability::grant("s3cret");
+        let refused =
+            |engine: &mut PaymentEngine| engine.admin(&capability).err().map(|e| e.code());
+        assert_eq!(refused(&mut engine), Some(ErrorCode::AdminOnly));
+        engine.set_admin_secret("s3cret").unwrap();
+        assert!(engine.set_admin_secret("mine").is_err());
+        assert!(engine.admin(&AdminCapability::grant("mine")).is_err());
+
+        let mut admin = engine.admin(&capability).unwrap();
+        admin.unlock(1).unwrap();
+        admin.force_balance(1, dec!(3.0), dec!(1.0)).unwrap();
+        assert!(admin.unlock(2).is_err());
+
+        let account = &engine.accounts[&1];
+        assert!(!account.locked);
+        assert_eq!(account.total, dec!(4.0));
+    }
+}
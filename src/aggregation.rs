@@ -0,0 +1,170 @@
+use std::{collections::BTreeMap, io::Write, str::FromStr};
+
+use rust_decimal::Decimal;
+
+use crate::{TxType, UserTransactions, money::Amount};
+
+/// Width of an aggregation window, in seconds of stream time.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct WindowSize(pub u64);
+
+impl Default for WindowSize {
+    fn default() -> Self {
+        Self(24 * 60 * 60)
+    }
+}
+
+impl FromStr for WindowSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hourly" => Ok(Self(60 * 60)),
+            "daily" => Ok(Self(24 * 60 * 60)),
+            secs => match secs.parse() {
+                Ok(0) | Err(_) => Err(format!(
+                    "Unknown window '{}', expected hourly, daily or a number of seconds",
+                    s
+                )),
+                Ok(secs) => Ok(Self(secs)),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct FlowTotals {
+    pub deposits: Decimal,
+    pub withdrawals: Decimal,
+}
+
+impl FlowTotals {
+    pub fn net(&self) -> Decimal {
+        self.deposits - self.withdrawals
+    }
+}
+
+/// Running deposit and withdrawal volume per client and overall, written
+/// as one block of rows each time stream time leaves a window.
+///
+/// Windows only move forward: a record timestamped before the current
+/// window, or not timestamped at all, counts towards the current one.
+pub struct WindowAggregator<W: Write> {
+    size: WindowSize,
+    current: Option<u64>,
+    clients: BTreeMap<u16, FlowTotals>,
+    writer: csv::Writer<W>,
+}
+
+impl<W: Write> WindowAggregator<W> {
+    pub fn new(writer: W, size: WindowSize) -> Result<Self, String> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer
+            .write_record(["window_start", "client", "deposits", "withdrawals", "net"])
+            .map_err(|e| format!("Failed to write header: {}", e))?;
+        Ok(Self {
+            size,
+            current: None,
+            clients: BTreeMap::new(),
+            writer,
+        })
+    }
+
+    /// Counts an applied deposit or withdrawal; other types are ignored.
+    pub fn observe(&mut self, action: &UserTransactions) -> Result<(), String> {
+        if !matches!(action.tx_type, TxType::Deposit | TxType::Withdrawal) {
+            return Ok(());
+        }
+        let amount = action.amount.map_or(Decimal::ZERO, Amount::value);
+
+        if let Some(ts) = action.timestamp {
+            let start = ts - ts % self.size.0;
+            match self.current {
+                Some(current) if start > current => {
+                    self.emit()?;
+                    self.current = Some(start);
+                }
+                None => self.current = Some(start),
+                _ => {}
+            }
+        }
+
+        let totals = self.clients.entry(action.client_id).or_default();
+        match action.tx_type {
+            TxType::Deposit => totals.deposits += amount,
+            _ => totals.withdrawals += amount,
+        }
+        Ok(())
+    }
+
+    /// Writes the last window and flushes.
+    pub fn finish(mut self) -> Result<(), String> {
+        self.emit()?;
+        self.writer
+            .flush()
+            .map_err(|e| format!("Failed to flush writer: {}", e))
+    }
+
+    fn emit(&mut self) -> Result<(), String> {
+        if self.clients.is_empty() {
+            return Ok(());
+        }
+        let start = self.current.unwrap_or(0).to_string();
+        let mut overall = FlowTotals::default();
+        let clients = std::mem::take(&mut self.clients);
+        for (client_id, totals) in &clients {
+            overall.deposits += totals.deposits;
+            overall.withdrawals += totals.withdrawals;
+            self.write_row(&start, &client_id.to_string(), totals)?;
+        }
+        self.write_row(&start, "all", &overall)
+    }
+
+    fn write_row(&mut self, start: &str, client: &str, totals: &FlowTotals) -> Result<(), String> {
+        self.writer
+            .write_record([
+                start,
+                client,
+                &format!("{:.4}", totals.deposits),
+                &format!("{:.4}", totals.withdrawals),
+                &format!("{:.4}", totals.net()),
+            ])
+            .map_err(|e| format!("Failed to write aggregate: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tx;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_emits_one_block_per_window() {
+        let mut buf = Vec::new();
+        let mut aggregator = WindowAggregator::new(&mut buf, "hourly".parse().unwrap()).unwrap();
+        aggregator
+            .observe(&tx(TxType::Deposit, 1, 1).with_amount(dec!(10)).at(100))
+            .unwrap();
+        aggregator
+            .observe(&tx(TxType::Withdrawal, 1, 1).with_amount(dec!(4)).at(200))
+            .unwrap();
+        aggregator
+            .observe(&tx(TxType::Deposit, 2, 1).with_amount(dec!(1.5)).at(300))
+            .unwrap();
+        aggregator
+            .observe(&tx(TxType::Deposit, 1, 1).with_amount(dec!(2)).at(3700))
+            .unwrap();
+        aggregator.finish().unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "window_start,client,deposits,withdrawals,net\n\
+             0,1,10.0000,4.0000,6.0000\n\
+             0,2,1.5000,0.0000,1.5000\n\
+             0,all,11.5000,4.0000,7.5000\n\
+             3600,1,2.0000,0.0000,2.0000\n\
+             3600,all,2.0000,0.0000,2.0000\n"
+        );
+    }
+}
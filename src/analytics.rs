@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{
+    PaymentEngine, TxType, aggregation::WindowSize, disputes::DisputeState, money::Amount,
+};
+
+/// Percentiles reported for the balance distribution.
+const PERCENTILES: [u32; 6] = [10, 25, 50, 75, 90, 99];
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct BalancePercentile {
+    pub percentile: u32,
+    pub total: Decimal,
+}
+
+/// Deposits and chargeback losses of the clients whose first activity fell
+/// in the same window.
+#[derive(Debug, Default, PartialEq, Clone, Serialize)]
+pub struct CohortStats {
+    /// Start of the window, or `None` for clients without timestamps.
+    pub cohort_start: Option<u64>,
+    pub clients: u64,
+    pub deposited: Decimal,
+    /// Deposits lost to chargebacks.
+    pub charged_back: Decimal,
+    /// `charged_back / deposited`, or `None` when nothing was deposited.
+    pub loss_rate: Option<Decimal>,
+}
+
+/// Portfolio-level metrics over the engine's accounts and ledger.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct PortfolioAnalytics {
+    pub clients: u64,
+    /// Nearest-rank percentiles of account totals.
+    pub balance_percentiles: Vec<BalancePercentile>,
+    /// Gini coefficient of account totals, from 0 when every client holds
+    /// the same to 1 when one holds everything. Negative totals count as
+    /// zero. `None` when no client holds anything.
+    pub gini: Option<Decimal>,
+    /// Oldest cohort first, clients without timestamps last.
+    pub cohorts: Vec<CohortStats>,
+}
+
+impl PaymentEngine {
+    /// Computes [`PortfolioAnalytics`], grouping clients into cohorts of
+    /// `cohort_window` by their earliest timestamped record. Cohorts only
+    /// see the transactions retention kept.
+    pub fn analytics(&self, cohort_window: WindowSize) -> PortfolioAnalytics {
+        let mut totals: Vec<Decimal> = self.accounts.values().map(|a| a.total).collect();
+        totals.sort_unstable();
+        PortfolioAnalytics {
+            clients: totals.len() as u64,
+            balance_percentiles: percentiles(&totals),
+            gini: gini(&totals),
+            cohorts: self.cohorts(cohort_window),
+        }
+    }
+
+    fn cohorts(&self, window: WindowSize) -> Vec<CohortStats> {
+        let mut cohorts: BTreeMap<Option<u64>, CohortStats> = BTreeMap::new();
+        for &client_id in self.actions.keys() {
+            let entries = self.transactions(client_id);
+            let first = entries
+                .iter()
+                .flat_map(|entry| entry.records)
+                .filter_map(|record| record.timestamp)
+                .min();
+            let cohort_start = first.map(|ts| ts - ts % window.0);
+            let cohort = cohorts.entry(cohort_start).or_insert_with(|| CohortStats {
+                cohort_start,
+                ..CohortStats::default()
+            });
+            cohort.clients += 1;
+            for entry in &entries {
+                let Some(deposit) = entry
+                    .original()
+                    .filter(|record| record.tx_type == TxType::Deposit)
+                else {
+                    continue;
+                };
+                let amount = deposit.amount.map_or(Decimal::ZERO, Amount::value);
+                cohort.deposited += amount;
+                if entry.dispute_state == DisputeState::ChargedBack {
+                    cohort.charged_back += amount;
+                }
+            }
+        }
+        // `None` sorts first in the map, but untimed clients belong last.
+        let untimed = cohorts.remove(&None);
+        let mut cohorts: Vec<CohortStats> = cohorts.into_values().chain(untimed).collect();
+        for cohort in &mut cohorts {
+            cohort.loss_rate = (cohort.deposited > Decimal::ZERO)
+                .then(|| (cohort.charged_back / cohort.deposited).round_dp(4));
+        }
+        cohorts
+    }
+}
+
+fn percentiles(sorted: &[Decimal]) -> Vec<BalancePercentile> {
+    if sorted.is_empty() {
+        return Vec::new();
+    }
+    let n = sorted.len() as u64;
+    PERCENTILES
+        .iter()
+        .map(|&percentile| {
+            let rank = (u64::from(percentile) * n).div_ceil(100).max(1);
+            BalancePercentile {
+                percentile,
+                total: sorted[rank as usize - 1],
+            }
+        })
+        .collect()
+}
+
+fn gini(sorted: &[Decimal]) -> Option<Decimal> {
+    let values = sorted.iter().map(|total| (*total).max(Decimal::ZERO));
+    let sum: Decimal = values.clone().sum();
+    if sum <= Decimal::ZERO {
+        return None;
+    }
+    let n = Decimal::from(sorted.len() as u64);
+    let weighted: Decimal = values
+        .enumerate()
+        .map(|(i, value)| Decimal::from(i as u64 + 1) * value)
+        .sum();
+    let gini = Decimal::TWO * weighted / (n * sum) - (n + Decimal::ONE) / n;
+    Some(gini.round_dp(4))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UserTransactions;
+    use rust_decimal_macros::dec;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn record(
+        tx_type: TxType,
+        client_id: u16,
+        tx_id: u32,
+        amount: Option<Decimal>,
+        at: u64,
+    ) -> UserTransactions {
+        UserTransactions {
+            tx_type,
+            client_id,
+            tx_id,
+            amount: amount.map(|a| Amount::new(a).unwrap()),
+            timestamp: Some(at),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_portfolio_metrics_and_cohorts() {
+        let mut engine = PaymentEngine::new();
+        let records = [
+            record(TxType::Deposit, 1, 1, Some(dec!(100)), 10),
+            record(TxType::Deposit, 2, 2, Some(dec!(40)), 20),
+            record(TxType::Deposit, 2, 3, Some(dec!(60)), DAY + 5),
+            record(TxType::Deposit, 3, 4, Some(dec!(50)), DAY + 10),
+            record(TxType::Dispute, 2, 2, None, DAY + 20),
+            record(TxType::Chargeback, 2, 2, None, DAY + 30),
+        ];
+        for record in records {
+            engine.process_action(record).unwrap();
+        }
+
+        let analytics = engine.analytics(WindowSize::default());
+        assert_eq!(analytics.clients, 3);
+        // Totals are 50, 60 and 100.
+        let percentiles: Vec<_> = analytics
+            .balance_percentiles
+            .iter()
+            .map(|p| (p.percentile, p.total))
+            .collect();
+        assert_eq!(percentiles[0], (10, dec!(50)));
+        assert_eq!(percentiles[2], (50, dec!(60)));
+        assert_eq!(percentiles[5], (99, dec!(100)));
+        // (2 * (50 + 120 + 300)) / (3 * 210) - 4 / 3
+        assert_eq!(analytics.gini, Some(dec!(0.1587)));
+
+        let [day0, day1] = &analytics.cohorts[..] else {
+            panic!("expected two cohorts");
+        };
+        assert_eq!((day0.cohort_start, day0.clients), (Some(0), 2));
+        assert_eq!(
+            (day0.deposited, day0.charged_back, day0.loss_rate),
+            (dec!(200), dec!(40), Some(dec!(0.2)))
+        );
+        assert_eq!((day1.cohort_start, day1.clients), (Some(DAY), 1));
+        assert_eq!(day1.loss_rate, Some(dec!(0)));
+
+        assert_eq!(
+            PaymentEngine::new().analytics(WindowSize::default()).gini,
+            None
+        );
+    }
+}
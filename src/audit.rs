@@ -0,0 +1,182 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::UserTransactions;
+
+/// `prev_hash` of the first entry in a log.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One line of the audit log. `hash` covers every other field, and
+/// `prev_hash` links to the entry before it, so editing, dropping or
+/// reordering lines breaks the chain.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub event: String,
+    pub action: UserTransactions,
+    pub prev_hash: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub hash: String,
+}
+
+impl AuditEntry {
+    fn compute_hash(&self) -> String {
+        let mut unsigned = self.clone();
+        unsigned.hash.clear();
+        let bytes = serde_json::to_vec(&unsigned).expect("audit entries always serialize");
+        format!("{:x}", Sha256::digest(bytes))
+    }
+}
+
+/// Append-only, hash-chained JSON-lines log of everything the engine applied.
+pub struct AuditLog {
+    file: BufWriter<File>,
+    seq: u64,
+    last_hash: String,
+}
+
+impl AuditLog {
+    /// Opens `path` for appending, continuing the chain of any entries
+    /// already in it.
+    pub fn open(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut seq = 0;
+        let mut last_hash = GENESIS_HASH.to_string();
+        if Path::new(path).exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let entry: AuditEntry = serde_json::from_str(&line)?;
+                seq = entry.seq;
+                last_hash = entry.hash;
+            }
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: BufWriter::new(file),
+            seq,
+            last_hash,
+        })
+    }
+
+    pub fn append(&mut self, event: &str, action: &UserTransactions) -> Result<(), String> {
+        let mut entry = AuditEntry {
+            seq: self.seq + 1,
+            event: event.to_string(),
+            action: action.clone(),
+            prev_hash: self.last_hash.clone(),
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash();
+
+        let mut line =
+            serde_json::to_vec(&entry).map_err(|e| format!("Failed to encode entry: {}", e))?;
+        line.push(b'\n');
+        self.file
+            .write_all(&line)
+            .map_err(|e| format!("Failed to append audit entry: {}", e))?;
+
+        self.seq = entry.seq;
+        self.last_hash = entry.hash;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), io::Error> {
+        self.file.flush()
+    }
+}
+
+/// Walks the whole log and checks every link. Returns the number of entries,
+/// or a description of the first broken one.
+pub fn verify_log(path: &str) -> Result<u64, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open '{}': {}", path, e))?;
+    let mut expected_prev = GENESIS_HASH.to_string();
+    let mut count = 0;
+
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.map_err(|e| format!("line {}: {}", line_no, e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: AuditEntry =
+            serde_json::from_str(&line).map_err(|e| format!("line {}: {}", line_no, e))?;
+
+        if entry.seq != count + 1 {
+            return Err(format!(
+                "line {}: expected seq {}, found {}",
+                line_no,
+                count + 1,
+                entry.seq
+            ));
+        }
+        if entry.prev_hash != expected_prev {
+            return Err(format!(
+                "line {}: chain broken at seq {}",
+                line_no, entry.seq
+            ));
+        }
+        if entry.compute_hash() != entry.hash {
+            return Err(format!(
+                "line {}: entry seq {} was modified",
+                line_no, entry.seq
+            ));
+        }
+
+        expected_prev = entry.hash;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TxType, tx};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_chain_survives_reopen_and_detects_edits() {
+        let path = std::env::temp_dir().join(format!("audit-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let mut log = AuditLog::open(path).unwrap();
+        log.append(
+            "transaction",
+            &tx(TxType::Deposit, 1, 1).with_amount(dec!(10.0)),
+        )
+        .unwrap();
+        log.append(
+            "transaction",
+            &tx(TxType::Deposit, 1, 2).with_amount(dec!(10.0)),
+        )
+        .unwrap();
+        drop(log);
+
+        let mut log = AuditLog::open(path).unwrap();
+        log.append(
+            "transaction",
+            &tx(TxType::Deposit, 1, 3).with_amount(dec!(10.0)),
+        )
+        .unwrap();
+        drop(log);
+        assert_eq!(verify_log(path), Ok(3));
+
+        let tampered = std::fs::read_to_string(path)
+            .unwrap()
+            .replacen("\"10.0\"", "\"99.0\"", 1);
+        std::fs::write(path, tampered).unwrap();
+        assert!(verify_log(path).unwrap_err().contains("seq 1 was modified"));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,295 @@
+use std::fmt;
+
+use crate::{
+    PaymentEngine, TxOutcome, UserTransactions,
+    errors::{EngineError, ErrorCode},
+};
+
+/// Why a batch was rolled back: the transaction that failed and its error.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BatchError {
+    /// Position of the failed transaction in the batch.
+    pub index: usize,
+    pub error: EngineError,
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "batch entry {} failed: {}", self.index + 1, self.error)
+    }
+}
+
+impl std::error::Error for BatchError {}
+
+/// Error for the other transactions of a batch that was rolled back.
+pub fn rolled_back(batch_id: u32) -> EngineError {
+    EngineError::new(
+        ErrorCode::BatchRolledBack,
+        format!("Batch {} was rolled back", batch_id),
+    )
+}
+
+/// The state a batch or transaction can change, as it was before it. The
+/// collections are persistent, so taking one copies nothing up front and
+/// only the entries the batch then touches are ever duplicated.
+pub(crate) struct Savepoint {
+    accounts: im::HashMap<u16, crate::UserAccount>,
+    actions: im::HashMap<u16, im::HashMap<u32, Vec<UserTransactions>>>,
+    tx_recency: crate::retention::TxRecency,
+    stream_time: Option<u64>,
+    dispute_opened_at: im::HashMap<(u16, u32), u64>,
+    synthetic_ids: crate::ids::SyntheticIds,
+    events: usize,
+    stats: im::HashMap<u16, crate::risk::AccountStats>,
+    attributes: im::HashMap<u16, crate::accounts::AccountAttributes>,
+    dispute_holds: im::HashMap<(u16, u32), rust_decimal::Decimal>,
+    dispute_states: im::HashMap<(u16, u32), crate::disputes::DisputeState>,
+    seen_tx_ids: im::HashMap<u32, u16>,
+    debts: im::Vector<crate::debts::Debt>,
+    reserved_funds: im::Vector<crate::funds::ReservedFunds>,
+    closed_accounts: im::HashSet<u16>,
+    last_active_at: im::HashMap<u16, u64>,
+    dormancy_due: Option<u64>,
+    dormant: im::HashSet<u16>,
+    queued_disputes: im::Vector<(UserTransactions, crate::money::Amount)>,
+    last_activity: im::HashMap<u16, u64>,
+    activity_seq: u64,
+    quarantine: Option<crate::quarantine::Quarantine>,
+    held_for_review: im::Vector<UserTransactions>,
+    closed_periods: im::Vector<crate::periods::ClosedPeriod>,
+    adjustments: im::Vector<crate::adjustments::Adjustment>,
+    decisions: Option<usize>,
+}
+
+impl PaymentEngine {
+    /// Applies `actions` all or nothing: if one is rejected, the engine is
+    /// put back exactly as it was before the batch, events included. Hooks
+    /// are only called once the batch is decided, and a rolled-back batch
+    /// only calls the `pre_*` hooks of the rows it tried. With a write-ahead log, the batch is logged in one
+    /// go once all of it has applied; if that write fails, it is rolled
+    /// back too.
+    pub fn process_batch(
+        &mut self,
+        actions: Vec<UserTransactions>,
+    ) -> Result<Vec<TxOutcome>, BatchError> {
+        let entries: Option<Vec<_>> = self.wal.is_some().then(|| {
+            actions
+                .iter()
+                .cloned()
+                .map(crate::wal::WalEntry::Transaction)
+                .collect()
+        });
+        let savepoint = self.savepoint();
+        self.defer_hooks();
+        let mut outcomes = Vec::with_capacity(actions.len());
+        for (index, action) in actions.into_iter().enumerate() {
+            match self.process_unlogged(action) {
+                Ok(outcome) => outcomes.push(outcome),
+                Err(error) => {
+                    self.finish_deferred_hooks(false);
+                    self.roll_back(savepoint);
+                    return Err(BatchError { index, error });
+                }
+            }
+        }
+        if let Some(entries) = entries
+            && let Err(error) = self.log(&entries)
+        {
+            self.finish_deferred_hooks(false);
+            self.roll_back(savepoint);
+            return Err(BatchError { index: 0, error });
+        }
+        self.finish_deferred_hooks(true);
+        Ok(outcomes)
+    }
+
+    pub(crate) fn savepoint(&self) -> Savepoint {
+        // Destructured so that a new field has to be saved, or ruled out,
+        // here.
+        let PaymentEngine {
+            accounts,
+            actions,
+            tx_recency,
+            stream_time,
+            dispute_opened_at,
+            synthetic_ids,
+            events,
+            stats,
+            attributes,
+            dispute_holds,
+            dispute_states,
+            seen_tx_ids,
+            debts,
+            reserved_funds,
+            closed_accounts,
+            last_active_at,
+            dormancy_due,
+            dormant,
+            queued_disputes,
+            last_activity,
+            activity_seq,
+            quarantine,
+            held_for_review,
+            closed_periods,
+            adjustments,
+            decisions,
+            // Policies, hooks and the log, which a transaction doesn't change.
+            retention: _,
+            sweep_rules: _,
+            dispute_timeout_secs: _,
+            freeze_policy: _,
+            withdrawal_policy: _,
+            hooks: _,
+            deferred_hooks: _,
+            rules: _,
+            require_open_accounts: _,
+            dispute_funds_policy: _,
+            duplicate_policy: _,
+            debt_repayment: _,
+            config: _,
+            funds_holds: _,
+            dormancy: _,
+            backfill: _,
+            access: _,
+            late_entry_policy: _,
+            wal: _,
+            admin_secret: _,
+        } = self;
+        Savepoint {
+            accounts: accounts.clone(),
+            actions: actions.clone(),
+            tx_recency: tx_recency.clone(),
+            stream_time: *stream_time,
+            dispute_opened_at: dispute_opened_at.clone(),
+            synthetic_ids: synthetic_ids.clone(),
+            events: events.len(),
+            stats: stats.clone(),
+            attributes: attributes.clone(),
+            dispute_holds: dispute_holds.clone(),
+            dispute_states: dispute_states.clone(),
+            seen_tx_ids: seen_tx_ids.clone(),
+            debts: debts.clone(),
+            reserved_funds: reserved_funds.clone(),
+            closed_accounts: closed_accounts.clone(),
+            last_active_at: last_active_at.clone(),
+            dormancy_due: *dormancy_due,
+            dormant: dormant.clone(),
+            queued_disputes: queued_disputes.clone(),
+            last_activity: last_activity.clone(),
+            activity_seq: *activity_seq,
+            quarantine: quarantine.clone(),
+            held_for_review: held_for_review.clone(),
+            closed_periods: closed_periods.clone(),
+            adjustments: adjustments.clone(),
+            decisions: decisions.as_ref().map(Vec::len),
+        }
+    }
+
+    /// [`Self::savepoint`] for a single transaction. Rolling back to it
+    /// keeps the decisions noted since, so a refusal stays explained.
+    pub(crate) fn row_savepoint(&self) -> Savepoint {
+        Savepoint {
+            decisions: None,
+            ..self.savepoint()
+        }
+    }
+
+    pub(crate) fn roll_back(&mut self, savepoint: Savepoint) {
+        self.accounts = savepoint.accounts;
+        self.actions = savepoint.actions;
+        self.tx_recency = savepoint.tx_recency;
+        self.stream_time = savepoint.stream_time;
+        self.dispute_opened_at = savepoint.dispute_opened_at;
+        self.synthetic_ids = savepoint.synthetic_ids;
+        self.events.truncate(savepoint.events);
+        self.stats = savepoint.stats;
+        self.attributes = savepoint.attributes;
+        self.dispute_holds = savepoint.dispute_holds;
+        self.dispute_states = savepoint.dispute_states;
+        self.seen_tx_ids = savepoint.seen_tx_ids;
+        self.debts = savepoint.debts;
+        self.reserved_funds = savepoint.reserved_funds;
+        self.closed_accounts = savepoint.closed_accounts;
+        self.last_active_at = savepoint.last_active_at;
+        self.dormancy_due = savepoint.dormancy_due;
+        self.dormant = savepoint.dormant;
+        self.queued_disputes = savepoint.queued_disputes;
+        self.last_activity = savepoint.last_activity;
+        self.activity_seq = savepoint.activity_seq;
+        self.quarantine = savepoint.quarantine;
+        self.held_for_review = savepoint.held_for_review;
+        self.closed_periods = savepoint.closed_periods;
+        self.adjustments = savepoint.adjustments;
+        if let (Some(decisions), Some(len)) = (self.decisions.as_mut(), savepoint.decisions) {
+            decisions.truncate(len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TxType, UserAccount, hooks::EngineHooks, tx};
+    use rust_decimal_macros::dec;
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl EngineHooks for Recorder {
+        fn pre_withdrawal(&mut self, action: &UserTransactions, _: Option<&UserAccount>) {
+            self.0.lock().unwrap().push(format!("pre {}", action.tx_id));
+        }
+
+        fn post_withdrawal(&mut self, action: &UserTransactions, account: &UserAccount) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("post {} {}", action.tx_id, account.available));
+        }
+    }
+
+    #[test]
+    fn test_failed_batch_leaves_no_trace() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = PaymentEngine::new();
+        engine.set_hooks(Box::new(Recorder(calls.clone())));
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10)).in_batch(1))
+            .unwrap();
+
+        let error = engine
+            .process_batch(vec![
+                tx(TxType::Withdrawal, 1, 2)
+                    .with_amount(dec!(6))
+                    .in_batch(1),
+                tx(TxType::Deposit, 2, 3).with_amount(dec!(6)).in_batch(1),
+                tx(TxType::Withdrawal, 1, 4)
+                    .with_amount(dec!(6))
+                    .in_batch(1),
+            ])
+            .unwrap_err();
+        assert_eq!(error.index, 2);
+        assert_eq!(error.error.code(), ErrorCode::InsufficientFunds);
+        assert_eq!(engine.accounts[&1].available, dec!(10));
+        assert!(!engine.accounts.contains_key(&2));
+        assert!(engine.transaction(2).is_none());
+        // Only the pre hooks ran, as they do for any rejected transaction.
+        assert_eq!(*calls.lock().unwrap(), vec!["pre 2", "pre 4"]);
+        calls.lock().unwrap().clear();
+
+        let outcomes = engine
+            .process_batch(vec![
+                tx(TxType::Withdrawal, 1, 2)
+                    .with_amount(dec!(6))
+                    .in_batch(1),
+                tx(TxType::Deposit, 2, 3).with_amount(dec!(6)).in_batch(1),
+            ])
+            .unwrap();
+        assert_eq!(outcomes, vec![TxOutcome::Applied, TxOutcome::Applied]);
+        assert_eq!(engine.accounts[&1].available, dec!(4));
+        assert_eq!(engine.accounts[&2].available, dec!(6));
+        // Held back until the batch committed, each with the account as it
+        // was at the time.
+        assert_eq!(*calls.lock().unwrap(), vec!["pre 2", "post 2 4"]);
+    }
+}
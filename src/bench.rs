@@ -0,0 +1,204 @@
+use std::{io::Write, time::Instant};
+
+use rust_decimal::Decimal;
+
+use crate::{
+    PaymentEngine, TxType, UserTransactions, disputes::DisputeFundsPolicy, money::Amount,
+    quarantine::QuarantineConfig,
+};
+
+/// Shape of a generated workload.
+#[derive(Debug, Clone, Copy)]
+pub struct WorkloadConfig {
+    pub records: u32,
+    pub clients: u16,
+    /// Same seed, same workload.
+    pub seed: u64,
+}
+
+impl Default for WorkloadConfig {
+    fn default() -> Self {
+        Self {
+            records: 100_000,
+            clients: 1_000,
+            seed: 1,
+        }
+    }
+}
+
+/// Small deterministic generator; benchmarks need repeatable input, not
+/// good randomness.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        self.0 >> 33
+    }
+}
+
+/// Mostly deposits and withdrawals, with disputes, resolves and chargebacks
+/// against earlier deposits of the same client.
+pub fn generate_workload(config: &WorkloadConfig) -> Vec<UserTransactions> {
+    let mut rng = Lcg(config.seed);
+    let mut deposits: Vec<(u16, u32)> = Vec::new();
+    let mut workload = Vec::with_capacity(config.records as usize);
+
+    for tx_id in 1..=config.records {
+        let roll = rng.next() % 100;
+        let client_id = (rng.next() % u64::from(config.clients.max(1))) as u16 + 1;
+        let cents = Decimal::from(rng.next() % 100_000 + 1) / Decimal::from(100);
+        let amount = Amount::new(cents).ok();
+
+        let (tx_type, client_id, tx_id, amount) = match roll {
+            0..=59 => {
+                deposits.push((client_id, tx_id));
+                (TxType::Deposit, client_id, tx_id, amount)
+            }
+            60..=89 => (TxType::Withdrawal, client_id, tx_id, amount),
+            _ if deposits.is_empty() => continue,
+            _ => {
+                let (client_id, deposit) = deposits[rng.next() as usize % deposits.len()];
+                let tx_type = match roll {
+                    90..=95 => TxType::Dispute,
+                    96..=98 => TxType::Resolve,
+                    _ => TxType::Chargeback,
+                };
+                (tx_type, client_id, deposit, None)
+            }
+        };
+        workload.push(UserTransactions {
+            tx_type,
+            client_id,
+            tx_id,
+            amount,
+            ..Default::default()
+        });
+    }
+    workload
+}
+
+/// How one engine configuration did on a workload.
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub name: String,
+    pub records: usize,
+    pub applied: usize,
+    pub accounts: usize,
+    pub elapsed_secs: f64,
+}
+
+impl BenchResult {
+    pub fn records_per_sec(&self) -> f64 {
+        if self.elapsed_secs > 0.0 {
+            self.records as f64 / self.elapsed_secs
+        } else {
+            0.0
+        }
+    }
+}
+
+/// A named way of building an engine to benchmark.
+pub type Configuration = (&'static str, fn() -> PaymentEngine);
+
+/// The engine setups `bench compare` runs by default.
+pub fn standard_configurations() -> Vec<Configuration> {
+    vec![
+        ("default", PaymentEngine::new),
+        ("cap-disputes", || {
+            let mut engine = PaymentEngine::new();
+            engine.set_dispute_funds_policy(DisputeFundsPolicy::CapAtAvailable);
+            engine
+        }),
+        ("queue-disputes", || {
+            let mut engine = PaymentEngine::new();
+            engine.set_dispute_funds_policy(DisputeFundsPolicy::Queue);
+            engine
+        }),
+        ("quarantine", || {
+            let mut engine = PaymentEngine::new();
+            engine.enable_quarantine(QuarantineConfig::default());
+            engine
+        }),
+    ]
+}
+
+/// Runs `workload` through a fresh engine from each named constructor.
+pub fn compare(
+    workload: &[UserTransactions],
+    configurations: &[Configuration],
+) -> Vec<BenchResult> {
+    configurations
+        .iter()
+        .map(|(name, build)| {
+            let mut engine = build();
+            let start = Instant::now();
+            let applied = workload
+                .iter()
+                .filter(|action| engine.process_action((*action).clone()).is_ok())
+                .count();
+            BenchResult {
+                name: name.to_string(),
+                records: workload.len(),
+                applied,
+                accounts: engine.accounts.len(),
+                elapsed_secs: start.elapsed().as_secs_f64(),
+            }
+        })
+        .collect()
+}
+
+pub fn write_comparison<W: Write>(mut writer: W, results: &[BenchResult]) -> std::io::Result<()> {
+    writeln!(
+        writer,
+        "{:<16} {:>10} {:>10} {:>10} {:>12} {:>14}",
+        "configuration", "records", "applied", "accounts", "secs", "records/sec"
+    )?;
+    for result in results {
+        writeln!(
+            writer,
+            "{:<16} {:>10} {:>10} {:>10} {:>12.3} {:>14.0}",
+            result.name,
+            result.records,
+            result.applied,
+            result.accounts,
+            result.elapsed_secs,
+            result.records_per_sec()
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workload_is_repeatable_and_compared() {
+        let config = WorkloadConfig {
+            records: 500,
+            clients: 10,
+            seed: 7,
+        };
+        let workload = generate_workload(&config);
+        let again = generate_workload(&config);
+        assert!(!workload.is_empty());
+        assert!(
+            workload
+                .iter()
+                .zip(&again)
+                .all(|(a, b)| a.tx_id == b.tx_id && a.amount == b.amount)
+        );
+
+        let results = compare(&workload, &standard_configurations());
+        assert_eq!(results.len(), 4);
+        for result in &results {
+            assert_eq!(result.records, workload.len());
+            assert!(result.applied > 0);
+            assert!(result.accounts <= 10);
+        }
+    }
+}
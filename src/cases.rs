@@ -0,0 +1,243 @@
+use std::io::Write;
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{
+    PaymentEngine, TxType, disputes::DisputeState, money::Amount, serialize_to_four_places,
+};
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaseStatus {
+    /// Funds are held.
+    Open,
+    /// Funds are held, but less than the disputed amount.
+    Shortfall,
+    /// Waiting for the client's available balance to cover it.
+    Queued,
+}
+
+/// One dispute still waiting for a resolve or chargeback.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct DisputeCase {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    #[serde(rename = "tx")]
+    pub tx_id: u32,
+    /// Amount under dispute.
+    #[serde(serialize_with = "serialize_to_four_places")]
+    pub amount: Amount,
+    #[serde(serialize_with = "serialize_to_four_places")]
+    pub held: Decimal,
+    pub opened_at: Option<u64>,
+    /// Stream time since the dispute opened, when both are known.
+    pub age_secs: Option<u64>,
+    pub status: CaseStatus,
+}
+
+impl PaymentEngine {
+    /// Every open dispute, ordered by client and transaction id.
+    pub fn open_disputes(&self) -> Vec<DisputeCase> {
+        let mut cases = Vec::new();
+        for (client_id, txs) in &self.actions {
+            for (tx_id, acts) in txs {
+                if self.dispute_state(*client_id, *tx_id) != DisputeState::Disputed {
+                    continue;
+                }
+                let Some(amount) = acts
+                    .iter()
+                    .find(|a| matches!(a.tx_type, TxType::Deposit | TxType::Withdrawal))
+                    .and_then(|a| a.amount)
+                else {
+                    continue;
+                };
+                let key = (*client_id, *tx_id);
+                let held = self
+                    .dispute_holds
+                    .get(&key)
+                    .copied()
+                    .unwrap_or(amount.value());
+                let opened_at = self.dispute_opened_at.get(&key).copied();
+                cases.push(DisputeCase {
+                    client_id: *client_id,
+                    tx_id: *tx_id,
+                    amount,
+                    held,
+                    opened_at,
+                    age_secs: self.case_age(opened_at),
+                    status: if held < amount.value() {
+                        CaseStatus::Shortfall
+                    } else {
+                        CaseStatus::Open
+                    },
+                });
+            }
+        }
+        for (action, amount) in &self.queued_disputes {
+            cases.push(DisputeCase {
+                client_id: action.client_id,
+                tx_id: action.tx_id,
+                amount: *amount,
+                held: Decimal::ZERO,
+                opened_at: action.timestamp,
+                age_secs: self.case_age(action.timestamp),
+                status: CaseStatus::Queued,
+            });
+        }
+        cases.sort_unstable_by_key(|case| (case.client_id, case.tx_id));
+        cases
+    }
+
+    fn case_age(&self, opened_at: Option<u64>) -> Option<u64> {
+        Some(self.stream_time?.saturating_sub(opened_at?))
+    }
+}
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// Held dispute liabilities of one client, or of every client when
+/// `client_id` is `None`, with the open disputes bucketed by age.
+#[derive(Debug, Default, PartialEq, Clone, Serialize)]
+pub struct Liabilities {
+    #[serde(rename = "client")]
+    pub client_id: Option<u16>,
+    pub open_disputes: u32,
+    #[serde(serialize_with = "serialize_to_four_places")]
+    pub disputed: Decimal,
+    #[serde(serialize_with = "serialize_to_four_places")]
+    pub held: Decimal,
+    pub under_1d: u32,
+    pub from_1d_to_7d: u32,
+    pub from_7d_to_30d: u32,
+    pub over_30d: u32,
+    /// Disputes without a timestamp to age them by.
+    pub age_unknown: u32,
+}
+
+impl Liabilities {
+    fn add(&mut self, case: &DisputeCase) {
+        self.open_disputes += 1;
+        self.disputed += case.amount.value();
+        self.held += case.held;
+        let bucket = match case.age_secs {
+            None => &mut self.age_unknown,
+            Some(age) if age < DAY_SECS => &mut self.under_1d,
+            Some(age) if age < 7 * DAY_SECS => &mut self.from_1d_to_7d,
+            Some(age) if age < 30 * DAY_SECS => &mut self.from_7d_to_30d,
+            Some(_) => &mut self.over_30d,
+        };
+        *bucket += 1;
+    }
+}
+
+/// One [`Liabilities`] row per client with an open dispute, by client id,
+/// followed by the total over all of them.
+pub fn liabilities(cases: &[DisputeCase]) -> Vec<Liabilities> {
+    let mut rows: Vec<Liabilities> = Vec::new();
+    let mut total = Liabilities::default();
+    for case in cases {
+        if rows
+            .last()
+            .is_none_or(|row| row.client_id != Some(case.client_id))
+        {
+            rows.push(Liabilities {
+                client_id: Some(case.client_id),
+                ..Default::default()
+            });
+        }
+        rows.last_mut().expect("pushed above").add(case);
+        total.add(case);
+    }
+    rows.push(total);
+    rows
+}
+
+pub fn write_liabilities<W: Write>(writer: W, rows: &[Liabilities]) -> Result<(), String> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for row in rows {
+        writer
+            .serialize(row)
+            .map_err(|e| format!("Failed to serialize liabilities: {}", e))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to flush writer: {}", e))
+}
+
+pub fn write_cases<W: Write>(writer: W, cases: &[DisputeCase]) -> Result<(), String> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for case in cases {
+        writer
+            .serialize(case)
+            .map_err(|e| format!("Failed to serialize case: {}", e))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to flush writer: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{amount, tx};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_lists_only_open_disputes_with_age() {
+        let mut engine = PaymentEngine::new();
+        for act in [
+            tx(TxType::Deposit, 1, 1).with_amount(dec!(10)).at(100),
+            tx(TxType::Deposit, 1, 2).with_amount(dec!(5)).at(110),
+            tx(TxType::Dispute, 1, 1).at(200),
+            tx(TxType::Dispute, 1, 2).at(210),
+            tx(TxType::Resolve, 1, 2).at(220),
+            tx(TxType::Deposit, 1, 3).with_amount(dec!(1)).at(500),
+        ] {
+            engine.process_action(act).unwrap();
+        }
+
+        let cases = engine.open_disputes();
+        assert_eq!(cases.len(), 1);
+        assert_eq!(cases[0].tx_id, 1);
+        assert_eq!(cases[0].held, dec!(10));
+        assert_eq!(cases[0].age_secs, Some(300));
+        assert_eq!(cases[0].status, CaseStatus::Open);
+
+        let mut buf = Vec::new();
+        write_cases(&mut buf, &cases).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "client,tx,amount,held,opened_at,age_secs,status\n\
+             1,1,10.0000,10.0000,200,300,open\n"
+        );
+    }
+
+    #[test]
+    fn test_liabilities_per_client_and_total() {
+        let case = |client_id, held, age_secs| DisputeCase {
+            client_id,
+            tx_id: 1,
+            amount: amount(dec!(10)),
+            held,
+            opened_at: None,
+            age_secs,
+            status: CaseStatus::Open,
+        };
+        let rows = liabilities(&[
+            case(1, dec!(10), Some(60)),
+            case(1, dec!(4), Some(8 * DAY_SECS)),
+            case(2, dec!(10), None),
+        ]);
+
+        let mut buf = Vec::new();
+        write_liabilities(&mut buf, &rows).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "client,open_disputes,disputed,held,under_1d,from_1d_to_7d,from_7d_to_30d,over_30d,age_unknown\n\
+             1,2,20.0000,14.0000,1,0,1,0,0\n\
+             2,1,10.0000,10.0000,0,0,0,0,1\n\
+             ,3,30.0000,24.0000,1,0,1,0,1\n"
+        );
+    }
+}
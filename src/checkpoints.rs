@@ -0,0 +1,268 @@
+use std::{
+    collections::HashSet,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{PaymentEngine, data_sinks::staged::write_staged, session::hash_file};
+
+const PREFIX: &str = "checkpoint-";
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// Which checkpoints [`CheckpointStore::prune`] keeps. Everything else goes.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CheckpointRetention {
+    /// The newest checkpoints, kept whatever their age.
+    pub keep_last: usize,
+    /// Days back for which the newest checkpoint of each day is kept.
+    pub keep_daily_days: u64,
+}
+
+impl Default for CheckpointRetention {
+    fn default() -> Self {
+        Self {
+            keep_last: 5,
+            keep_daily_days: 30,
+        }
+    }
+}
+
+/// A snapshot in a [`CheckpointStore`], named after when it was taken and
+/// how many records had been read.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Checkpoint {
+    pub path: PathBuf,
+    /// Unix time the checkpoint was taken.
+    pub taken_at: u64,
+    pub records: u64,
+}
+
+impl Checkpoint {
+    fn parse(path: PathBuf) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        let (taken_at, records) = name
+            .strip_prefix(PREFIX)?
+            .strip_suffix(".json")?
+            .split_once('-')?;
+        Some(Self {
+            taken_at: taken_at.parse().ok()?,
+            records: records.parse().ok()?,
+            path,
+        })
+    }
+
+    fn digest_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".sha256");
+        PathBuf::from(path)
+    }
+
+    /// Whether the snapshot still hashes to the digest recorded when it was
+    /// written. A snapshot without a digest never finished saving.
+    pub fn verify(&self) -> bool {
+        let Ok(recorded) = std::fs::read_to_string(self.digest_path()) else {
+            return false;
+        };
+        let path = self.path.to_string_lossy();
+        hash_file(&path).is_ok_and(|actual| actual == recorded.trim())
+    }
+}
+
+/// What [`CheckpointStore::prune`] did.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct PruneReport {
+    pub kept: Vec<Checkpoint>,
+    pub removed: Vec<Checkpoint>,
+    /// Checkpoints that failed verification, which are never kept.
+    pub corrupt: Vec<Checkpoint>,
+}
+
+/// Directory of periodic engine snapshots with a retention policy, so a
+/// long-lived deployment's checkpoints don't grow without bound.
+pub struct CheckpointStore {
+    dir: PathBuf,
+    retention: CheckpointRetention,
+}
+
+impl CheckpointStore {
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, String> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create '{}': {}", dir.display(), e))?;
+        Ok(Self {
+            dir,
+            retention: CheckpointRetention::default(),
+        })
+    }
+
+    pub fn with_retention(mut self, retention: CheckpointRetention) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Snapshots `engine`, then records the snapshot's digest next to it.
+    pub fn save(
+        &self,
+        engine: &PaymentEngine,
+        taken_at: u64,
+        records: u64,
+    ) -> Result<Checkpoint, String> {
+        let path = self
+            .dir
+            .join(format!("{}{}-{}.json", PREFIX, taken_at, records));
+        let checkpoint = Checkpoint {
+            path,
+            taken_at,
+            records,
+        };
+        let path = checkpoint.path.to_string_lossy();
+        write_staged(&path, |file| {
+            let mut writer = BufWriter::new(file);
+            engine.snapshot(&mut writer)?;
+            writer
+                .flush()
+                .map_err(|e| format!("Failed to write '{}': {}", path, e))
+        })?;
+        let digest = hash_file(&path).map_err(|e| format!("Failed to hash '{}': {}", path, e))?;
+        let digest_path = checkpoint.digest_path();
+        write_staged(&digest_path.to_string_lossy(), |file| {
+            writeln!(&file, "{}", digest)
+                .map_err(|e| format!("Failed to write '{}': {}", digest_path.display(), e))
+        })?;
+        Ok(checkpoint)
+    }
+
+    /// Checkpoints in the directory, oldest first.
+    pub fn list(&self) -> Result<Vec<Checkpoint>, String> {
+        let entries = std::fs::read_dir(&self.dir)
+            .map_err(|e| format!("Failed to list '{}': {}", self.dir.display(), e))?;
+        let mut checkpoints: Vec<Checkpoint> = entries
+            .filter_map(|entry| Checkpoint::parse(entry.ok()?.path()))
+            .collect();
+        checkpoints.sort_by_key(|c| (c.taken_at, c.records));
+        Ok(checkpoints)
+    }
+
+    /// Newest verified checkpoint taken at or after unix time `since` with
+    /// exactly `records` records read, which is what an interrupted import
+    /// resumes from.
+    pub fn find(&self, records: u64, since: u64) -> Result<Option<Checkpoint>, String> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .rev()
+            .find(|c| c.records == records && c.taken_at >= since && c.verify()))
+    }
+
+    /// Verifies every checkpoint and removes those the retention policy
+    /// doesn't keep, as of unix time `now`. Only verified checkpoints count
+    /// towards what's kept, so a corrupt one never displaces a good one.
+    /// Leftovers of interrupted saves are removed too.
+    pub fn prune(&self, now: u64) -> Result<PruneReport, String> {
+        let mut report = PruneReport::default();
+        let mut days = HashSet::new();
+        let today = now / DAY_SECS;
+        for (newest, checkpoint) in self.list()?.into_iter().rev().enumerate() {
+            if !checkpoint.verify() {
+                report.corrupt.push(checkpoint);
+                continue;
+            }
+            let day = checkpoint.taken_at / DAY_SECS;
+            let daily =
+                today.saturating_sub(day) < self.retention.keep_daily_days && days.insert(day);
+            let recent = newest - report.corrupt.len() < self.retention.keep_last;
+            if recent || daily {
+                report.kept.push(checkpoint);
+            } else {
+                report.removed.push(checkpoint);
+            }
+        }
+        for checkpoint in report.removed.iter().chain(&report.corrupt) {
+            remove(&checkpoint.path)?;
+            remove(&checkpoint.digest_path())?;
+        }
+        self.remove_leftovers()?;
+        Ok(report)
+    }
+
+    fn remove_leftovers(&self) -> Result<(), String> {
+        let entries = std::fs::read_dir(&self.dir)
+            .map_err(|e| format!("Failed to list '{}': {}", self.dir.display(), e))?;
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with(PREFIX) && name.ends_with(".tmp") {
+                remove(&entry.path())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn remove(path: &Path) -> Result<(), String> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("Failed to remove '{}': {}", path.display(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TxType, UserTransactions, money::Amount};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_prune_keeps_recent_and_daily_checkpoints() {
+        let dir = std::env::temp_dir().join(format!("checkpoints-{}", std::process::id()));
+        let store = CheckpointStore::open(&dir)
+            .unwrap()
+            .with_retention(CheckpointRetention {
+                keep_last: 2,
+                keep_daily_days: 4,
+            });
+        let mut engine = PaymentEngine::new();
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Deposit,
+                client_id: 1,
+                tx_id: 1,
+                amount: Some(Amount::new(dec!(10)).unwrap()),
+                ..Default::default()
+            })
+            .unwrap();
+
+        // Two checkpoints an hour apart late on each of days 5 to 9.
+        let now = 10 * DAY_SECS;
+        for day in (6..=10).rev().map(|d| d * DAY_SECS) {
+            store.save(&engine, day - 3600, day).unwrap();
+            store.save(&engine, day - 7200, day - 1).unwrap();
+        }
+        std::fs::write(dir.join("checkpoint-1-1.json.tmp"), "partial").unwrap();
+        let newest = store.list().unwrap().pop().unwrap();
+        std::fs::write(&newest.path, "{}").unwrap();
+
+        let report = store.prune(now).unwrap();
+        assert_eq!(report.corrupt, vec![newest]);
+        let kept: Vec<u64> = report.kept.iter().map(|c| c.records).collect();
+        // The two newest good ones, and the newest of each of days 7 to 9,
+        // the ones within four days of day 10.
+        assert_eq!(kept, [now - 1, 9 * DAY_SECS, 8 * DAY_SECS]);
+        assert_eq!(report.removed.len(), 6);
+        assert_eq!(store.list().unwrap().len(), 3);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 6);
+
+        let restored =
+            PaymentEngine::restore(std::fs::File::open(&report.kept[0].path).unwrap()).unwrap();
+        assert_eq!(restored.accounts[&1].available, dec!(10));
+
+        let found = |records, since| store.find(records, since).unwrap();
+        assert_eq!(found(9 * DAY_SECS, 0).as_ref(), Some(&report.kept[1]));
+        assert_eq!(found(9 * DAY_SECS, 9 * DAY_SECS), None);
+        // The one taken after `now` records was corrupt and is gone.
+        assert_eq!(found(now, 0), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1386 @@
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+use clap::{Args, Parser, Subcommand};
+use rust_decimal::Decimal;
+
+use crate::{
+    RetentionConfig,
+    aggregation::WindowSize,
+    bench::WorkloadConfig,
+    checkpoints::CheckpointRetention,
+    config::EngineConfig,
+    data_sinks::{
+        DataSink, OutputFormat,
+        csv::OutputStyle,
+        filter::{AccountFilter, parse_client_list},
+        sink_for,
+        staged::{StagedFile, StagedSink},
+    },
+    data_sources::{
+        amount::AmountFormat,
+        csv::STDIN_PATH,
+        multi::{MultiFileDataSource, is_glob},
+    },
+    debts::DebtRepayment,
+    disputes::DisputeFundsPolicy,
+    dormancy::DormancyPolicy,
+    duplicates::DuplicatePolicy,
+    estimate::DEFAULT_SAMPLE_ROWS,
+    extract::ExtractConfig,
+    funds::FundsHold,
+    periods::LateEntryPolicy,
+    pipeline::SkipThresholds,
+    quarantine::QuarantineConfig,
+    report::ReportFormat,
+    risk::{FreezePolicy, WithdrawalPolicy},
+    settlement::{PayoutFormat, SettlementConfig},
+    view::AccountColumns,
+};
+
+/// Options of the default `process` command. Files named here are only
+/// recorded; opening them is left to the caller.
+#[derive(Debug, Default, Clone)]
+pub struct ProcessOptions {
+    /// Transactions file, `-` for stdin, or a glob pattern; see
+    /// [`Self::expand_input_glob`].
+    pub input: String,
+    /// Files read after `input`, as part of the same feed.
+    pub next_inputs: Vec<String>,
+    /// Read `input` and `next_inputs` in file name order.
+    pub sort_inputs: bool,
+    /// More feeds merged with `input` by timestamp.
+    pub merge_inputs: Vec<String>,
+    /// How far behind the merged watermark a transaction may arrive and
+    /// still be applied.
+    pub allowed_lateness_secs: Option<u64>,
+    /// Where transactions that arrived too late are written.
+    pub late_events: Option<String>,
+    pub output: Option<String>,
+    pub style: OutputStyle,
+    pub format: OutputFormat,
+    /// Column order of the accounts output; the style's own when `None`.
+    pub columns: Option<AccountColumns>,
+    pub amount_format: AmountFormat,
+    /// Factor every input amount is multiplied by, e.g. `0.01` for cents.
+    pub amount_scale: Option<Decimal>,
+    /// Precision and rounding of balances and output amounts.
+    pub config: EngineConfig,
+    pub client_map: Option<String>,
+    pub journal: Option<String>,
+    pub opening_balances: Option<String>,
+    pub retention: Option<RetentionConfig>,
+    pub manifest: Option<String>,
+    pub provenance: Option<String>,
+    pub audit_log: Option<String>,
+    /// JSON-lines log of every rule evaluation; see [`crate::decisions`].
+    pub decisions_log: Option<String>,
+    /// CSV of every rejected transaction with its error code.
+    pub rejects: Option<String>,
+    /// Marker listing every output with its hash, written only once all of
+    /// them are in place.
+    pub success_marker: Option<String>,
+    /// Rhai script with validate, fraud and fee rules; see
+    /// `payment_engine::scripting`.
+    pub script: Option<String>,
+    pub watch_output: Option<Duration>,
+    pub payouts: Option<String>,
+    pub payout_format: PayoutFormat,
+    /// `client,name,iban,bic` CSV of merchant bank accounts, for pain.001.
+    pub payout_accounts: Option<String>,
+    /// TOML file describing the account payouts are paid from, for pain.001.
+    pub payout_debtor: Option<String>,
+    pub settlement: SettlementConfig,
+    pub sweep_rules: Option<String>,
+    pub dispute_timeout_secs: Option<u64>,
+    /// Directory periodic engine snapshots are written to and pruned in.
+    pub checkpoint_dir: Option<String>,
+    /// Records between checkpoints.
+    pub checkpoint_every: Option<u64>,
+    pub checkpoint_retention: CheckpointRetention,
+    pub freeze_policy: FreezePolicy,
+    pub withdrawal_policy: WithdrawalPolicy,
+    pub account_seeds: Option<String>,
+    pub require_open_accounts: bool,
+    pub dispute_funds_policy: DisputeFundsPolicy,
+    pub duplicate_policy: DuplicatePolicy,
+    pub debt_repayment: DebtRepayment,
+    pub debts: Option<String>,
+    /// Open-dispute liabilities report; see [`crate::cases::liabilities`].
+    pub liabilities: Option<String>,
+    pub dormancy: Option<DormancyPolicy>,
+    pub funds_holds: Vec<FundsHold>,
+    pub filter: AccountFilter,
+    pub backfill: bool,
+    pub quarantine: Option<QuarantineConfig>,
+    pub orphans: Option<String>,
+    /// `client,amount,reason` corrections applied once the input is done.
+    pub adjustments: Option<String>,
+    pub aggregates: Option<String>,
+    pub aggregate_window: WindowSize,
+    pub blocklist: Option<String>,
+    pub allowlist: Option<String>,
+    pub hold_blocked: bool,
+    /// Import the input even if the journal shows it was already imported.
+    pub force: bool,
+    pub closed_before: Option<u64>,
+    pub late_entries: LateEntryPolicy,
+    /// Skipped-record limits past which the run exits as failed.
+    pub skip_thresholds: SkipThresholds,
+    /// Exit with a distinct code when records were skipped, even within
+    /// the thresholds.
+    pub strict_exit: bool,
+    /// Replace client ids in the output with keyed pseudonyms.
+    pub pseudonymize: bool,
+    /// With `pseudonymize`, round output amounts down to multiples of this.
+    pub amount_bucket: Option<Decimal>,
+    /// Apply the input on this many threads, sharded by client; see
+    /// [`crate::PaymentEngine::process_parallel`].
+    pub threads: Option<usize>,
+}
+
+impl ProcessOptions {
+    /// `<input> [output] [--flag value]... [--require-open-accounts]
+    /// [--only-locked] [--non-zero] [--only-touched] [--backfill] [--hold-blocked] [--force]
+    /// [--strict-exit] [--pseudonymize] [--close-dormant] [--sweep-dormant]
+    /// [--retain-disputable-only]`
+    ///
+    /// The input is read from stdin when it's `-` or left out.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let (input, rest) = match args.split_first() {
+            Some((input, rest)) if !input.starts_with("--") => (input.clone(), rest),
+            _ => (STDIN_PATH.to_string(), args),
+        };
+        let mut options = Self {
+            input,
+            ..Self::default()
+        };
+
+        let (mut close_dormant, mut sweep_dormant) = (false, false);
+        let mut disputable_only = false;
+        let mut rest = rest.iter();
+        while let Some(arg) = rest.next() {
+            if !arg.starts_with("--") {
+                options.output = Some(arg.clone());
+                continue;
+            }
+            let switch = match arg.as_str() {
+                "--require-open-accounts" => Some(&mut options.require_open_accounts),
+                "--only-locked" => Some(&mut options.filter.locked_only),
+                "--non-zero" => Some(&mut options.filter.non_zero_only),
+                "--only-touched" => Some(&mut options.filter.touched_only),
+                "--backfill" => Some(&mut options.backfill),
+                "--hold-blocked" => Some(&mut options.hold_blocked),
+                "--force" => Some(&mut options.force),
+                "--strict-exit" => Some(&mut options.strict_exit),
+                "--pseudonymize" => Some(&mut options.pseudonymize),
+                "--close-dormant" => Some(&mut close_dormant),
+                "--sweep-dormant" => Some(&mut sweep_dormant),
+                "--retain-disputable-only" => Some(&mut disputable_only),
+                "--sort-inputs" => Some(&mut options.sort_inputs),
+                _ => None,
+            };
+            if let Some(switch) = switch {
+                *switch = true;
+                continue;
+            }
+            let value = rest
+                .next()
+                .ok_or_else(|| format!("Missing value for '{}'", arg))?;
+            match arg.as_str() {
+                "--output-style" => options.style = parse_flag(arg, value)?,
+                "--output-format" => options.format = parse_flag(arg, value)?,
+                "--amount-format" => options.amount_format = parse_flag(arg, value)?,
+                "--amount-scale" => options.amount_scale = Some(parse_flag(arg, value)?),
+                "--columns" => options.columns = Some(parse_flag(arg, value)?),
+                "--precision" => {
+                    options.config =
+                        EngineConfig::new(parse_flag(arg, value)?, options.config.rounding)?
+                }
+                "--rounding" => options.config.rounding = parse_flag(arg, value)?,
+                "--client-map" => options.client_map = Some(value.clone()),
+                "--journal" => options.journal = Some(value.clone()),
+                "--opening-balances" => options.opening_balances = Some(value.clone()),
+                "--retention-secs" => {
+                    options
+                        .retention
+                        .get_or_insert_default()
+                        .dispute_window_secs = Some(parse_flag(arg, value)?)
+                }
+                "--checkpoint-dir" => options.checkpoint_dir = Some(value.clone()),
+                "--checkpoint-every" => options.checkpoint_every = Some(parse_flag(arg, value)?),
+                "--keep-checkpoints" => {
+                    options.checkpoint_retention.keep_last = parse_flag(arg, value)?
+                }
+                "--keep-daily-checkpoints" => {
+                    options.checkpoint_retention.keep_daily_days = parse_flag(arg, value)?
+                }
+                "--retention-max-txs" => {
+                    options.retention.get_or_insert_default().max_transactions =
+                        Some(parse_flag(arg, value)?)
+                }
+                "--manifest" => options.manifest = Some(value.clone()),
+                "--success-marker" => options.success_marker = Some(value.clone()),
+                "--adjustments" => options.adjustments = Some(value.clone()),
+                "--provenance" => options.provenance = Some(value.clone()),
+                "--audit-log" => options.audit_log = Some(value.clone()),
+                "--decisions-log" => options.decisions_log = Some(value.clone()),
+                "--rejects" => options.rejects = Some(value.clone()),
+                "--script" => options.script = Some(value.clone()),
+                "--watch-output" => {
+                    options.watch_output = Some(Duration::from_secs(parse_flag(arg, value)?))
+                }
+                "--payouts" => options.payouts = Some(value.clone()),
+                "--payout-format" => options.payout_format = parse_flag(arg, value)?,
+                "--payout-accounts" => options.payout_accounts = Some(value.clone()),
+                "--payout-debtor" => options.payout_debtor = Some(value.clone()),
+                "--sweep-rules" => options.sweep_rules = Some(value.clone()),
+                "--dispute-timeout-days" => {
+                    let days: u64 = parse_flag(arg, value)?;
+                    options.dispute_timeout_secs = Some(days * 24 * 60 * 60);
+                }
+                "--freeze-open-disputes" => {
+                    options.freeze_policy.max_open_disputes = Some(parse_flag(arg, value)?)
+                }
+                "--freeze-held-ratio" => {
+                    options.freeze_policy.max_held_ratio = Some(parse_flag(arg, value)?)
+                }
+                "--withdrawal-policy" => options.withdrawal_policy = parse_flag(arg, value)?,
+                "--dispute-funds-policy" => options.dispute_funds_policy = parse_flag(arg, value)?,
+                "--duplicates" => options.duplicate_policy = parse_flag(arg, value)?,
+                "--debt-repayment" => options.debt_repayment = parse_flag(arg, value)?,
+                "--debts" => options.debts = Some(value.clone()),
+                "--liabilities" => options.liabilities = Some(value.clone()),
+                "--merge-input" => options.merge_inputs.push(value.clone()),
+                "--next-input" => options.next_inputs.push(value.clone()),
+                "--funds-hold" => options.funds_holds.push(parse_flag(arg, value)?),
+                "--allowed-lateness-secs" => {
+                    options.allowed_lateness_secs = Some(parse_flag(arg, value)?)
+                }
+                "--late-events" => options.late_events = Some(value.clone()),
+                "--dormant-after-days" => {
+                    let days: u64 = parse_flag(arg, value)?;
+                    options.dormancy = Some(DormancyPolicy {
+                        after_secs: days * 24 * 60 * 60,
+                        close: false,
+                        sweep: false,
+                    });
+                }
+                "--clients" => options.filter.clients = Some(parse_client_list(value)?),
+                "--quarantine-size" => {
+                    options.quarantine.get_or_insert_default().max_entries = parse_flag(arg, value)?
+                }
+                "--quarantine-secs" => {
+                    options.quarantine.get_or_insert_default().max_age_secs =
+                        Some(parse_flag(arg, value)?)
+                }
+                "--orphans" => {
+                    options.quarantine.get_or_insert_default();
+                    options.orphans = Some(value.clone());
+                }
+                "--aggregates" => options.aggregates = Some(value.clone()),
+                "--aggregate-window" => options.aggregate_window = parse_flag(arg, value)?,
+                "--blocklist" => options.blocklist = Some(value.clone()),
+                "--allowlist" => options.allowlist = Some(value.clone()),
+                "--closed-before" => options.closed_before = Some(parse_flag(arg, value)?),
+                "--late-entries" => options.late_entries = parse_flag(arg, value)?,
+                "--account-seeds" => options.account_seeds = Some(value.clone()),
+                "--max-skipped" => {
+                    options.skip_thresholds.max_skipped = Some(parse_flag(arg, value)?)
+                }
+                "--max-skipped-percent" => {
+                    let percent: Decimal = parse_flag(arg, value)?;
+                    if percent < Decimal::ZERO || percent > Decimal::ONE_HUNDRED {
+                        return Err(format!(
+                            "--max-skipped-percent must be between 0 and 100, got {}",
+                            percent
+                        ));
+                    }
+                    options.skip_thresholds.max_skipped_percent = Some(percent);
+                }
+                "--amount-bucket" => options.amount_bucket = Some(parse_flag(arg, value)?),
+                "--threads" => options.threads = Some(parse_flag(arg, value)?),
+                _ => return Err(format!("Unknown argument '{}'", arg)),
+            }
+        }
+
+        if disputable_only {
+            options.retention.get_or_insert_default().disputable_only = true;
+        }
+        if let Some(dormancy) = options.dormancy.as_mut() {
+            dormancy.close = close_dormant;
+            dormancy.sweep = sweep_dormant;
+        } else if close_dormant || sweep_dormant {
+            return Err(
+                "--close-dormant and --sweep-dormant require --dormant-after-days".to_string(),
+            );
+        }
+        if options.amount_bucket.is_some() && !options.pseudonymize {
+            return Err("--amount-bucket only applies with --pseudonymize".to_string());
+        }
+        if options
+            .amount_bucket
+            .is_some_and(|bucket| bucket <= Decimal::ZERO)
+        {
+            return Err("--amount-bucket must be positive".to_string());
+        }
+        if (options.manifest.is_some() || options.watch_output.is_some())
+            && options.output.is_none()
+        {
+            return Err("--manifest and --watch-output require an output file".to_string());
+        }
+        if options.late_events.is_some() && options.allowed_lateness_secs.is_none() {
+            return Err("--late-events requires --allowed-lateness-secs".to_string());
+        }
+        // The journal only tracks the primary input.
+        if !options.merge_inputs.is_empty() && options.journal.is_some() {
+            return Err("--merge-input can't be combined with --journal".to_string());
+        }
+        if (is_glob(&options.input) || !options.next_inputs.is_empty()) && options.journal.is_some()
+        {
+            return Err("--journal needs a single input file".to_string());
+        }
+        // Both hash the input before it's read, which stdin only allows once.
+        if options.input == STDIN_PATH
+            && (options.journal.is_some() || options.provenance.is_some())
+        {
+            return Err("--journal and --provenance need an input file, not stdin".to_string());
+        }
+        if options.force && options.journal.is_none() {
+            return Err("--force only applies with --journal".to_string());
+        }
+        if cfg!(not(feature = "scripting")) && options.script.is_some() {
+            return Err("--script needs a build with the scripting feature".to_string());
+        }
+        if options.threads == Some(0) {
+            return Err("--threads must be at least 1".to_string());
+        }
+        if options.checkpoint_dir.is_none()
+            && (options.checkpoint_every.is_some()
+                || options.checkpoint_retention != CheckpointRetention::default())
+        {
+            return Err(
+                "--checkpoint-every and checkpoint retention only apply with --checkpoint-dir"
+                    .to_string(),
+            );
+        }
+        if options.checkpoint_every == Some(0) || options.checkpoint_retention.keep_last == 0 {
+            return Err("--checkpoint-every and --keep-checkpoints must be at least 1".to_string());
+        }
+        // A threaded run applies the whole input at once, with nothing to
+        // hook into after each record.
+        if options.threads.is_some()
+            && (options.journal.is_some()
+                || options.audit_log.is_some()
+                || options.decisions_log.is_some()
+                || options.rejects.is_some()
+                || options.aggregates.is_some()
+                || options.watch_output.is_some()
+                || options.checkpoint_dir.is_some()
+                || options.retention.is_some())
+        {
+            return Err(
+                "--threads can't be combined with --journal, --audit-log, --decisions-log, --rejects, --aggregates, --watch-output, --checkpoint-dir or retention"
+                    .to_string(),
+            );
+        }
+        // Paying out historical balances again would move real money.
+        let pain001 = options.payout_format == PayoutFormat::Pain001;
+        let bank_details = options.payout_accounts.is_some() || options.payout_debtor.is_some();
+        if (pain001 || bank_details) && options.payouts.is_none() {
+            return Err(
+                "--payout-format, --payout-accounts and --payout-debtor require --payouts"
+                    .to_string(),
+            );
+        }
+        if pain001 && (options.payout_accounts.is_none() || options.payout_debtor.is_none()) {
+            return Err(
+                "--payout-format pain001 requires --payout-accounts and --payout-debtor"
+                    .to_string(),
+            );
+        }
+        if bank_details && !pain001 {
+            return Err(
+                "--payout-accounts and --payout-debtor only apply to --payout-format pain001"
+                    .to_string(),
+            );
+        }
+        if options.backfill && options.payouts.is_some() {
+            return Err("--payouts can't be combined with --backfill".to_string());
+        }
+        Ok(options)
+    }
+
+    /// Account sink for this run: the output file if one was given, stdout
+    /// otherwise.
+    pub fn open_sink(&self) -> Result<Box<dyn DataSink>, String> {
+        open_sink(
+            self.output.as_deref(),
+            self.format,
+            self.style,
+            self.columns.as_ref(),
+            self.config,
+        )
+    }
+
+    /// Replaces a glob pattern `input` with the files it matches, in path
+    /// order: the first becomes `input` and the rest go before
+    /// `next_inputs`.
+    pub fn expand_input_glob(&mut self) -> Result<(), String> {
+        if !is_glob(&self.input) {
+            return Ok(());
+        }
+        let source = MultiFileDataSource::from_glob(&self.input)?;
+        let mut paths = source.paths().into_iter().map(str::to_string);
+        self.input = paths.next().unwrap_or_default();
+        self.next_inputs.splice(0..0, paths);
+        Ok(())
+    }
+
+    /// Every file the run reads, the transactions inputs first.
+    pub fn input_files(&self) -> Vec<&str> {
+        std::iter::once(self.input.as_str())
+            .chain(self.next_inputs.iter().map(String::as_str))
+            .chain(self.merge_inputs.iter().map(String::as_str))
+            .chain(
+                [
+                    &self.client_map,
+                    &self.opening_balances,
+                    &self.sweep_rules,
+                    &self.payout_accounts,
+                    &self.payout_debtor,
+                    &self.account_seeds,
+                    &self.blocklist,
+                    &self.allowlist,
+                    &self.adjustments,
+                ]
+                .into_iter()
+                .filter_map(|path| path.as_deref()),
+            )
+            .collect()
+    }
+
+    /// Every file the run writes, other than the logs it appends to.
+    pub fn output_files(&self) -> Vec<&str> {
+        [
+            &self.output,
+            &self.rejects,
+            &self.aggregates,
+            &self.payouts,
+            &self.orphans,
+            &self.late_events,
+            &self.debts,
+            &self.liabilities,
+            &self.manifest,
+            &self.provenance,
+        ]
+        .into_iter()
+        .filter_map(|path| path.as_deref())
+        .collect()
+    }
+}
+
+/// The `process`, `validate` and `report` subcommands. The bare
+/// `<input> [output] [--flag value]...` form and the other subcommands
+/// keep their own parsers.
+#[derive(Debug, Parser)]
+#[command(name = "payment_engine")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Applies transactions and writes the resulting accounts.
+    Process(Box<ProcessArgs>),
+    /// Checks the input for schema and value problems without applying it.
+    Validate(ValidateArgs),
+    /// Applies transactions and writes summary statistics of the run.
+    Report(ReportArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ProcessArgs {
+    /// Transactions file, or `-` for stdin.
+    #[arg(long, default_value = STDIN_PATH)]
+    pub input: String,
+    /// Accounts file; stdout if not given.
+    #[arg(long)]
+    pub output: Option<String>,
+    /// csv or arrow.
+    #[arg(long, default_value = "csv")]
+    pub format: OutputFormat,
+    /// Refuse transactions for accounts that weren't opened first.
+    #[arg(long)]
+    pub require_open_accounts: bool,
+    /// Only write locked accounts.
+    #[arg(long)]
+    pub only_locked: bool,
+    /// Only write accounts with a non-zero balance.
+    #[arg(long)]
+    pub non_zero: bool,
+    /// Only write accounts this run changed.
+    #[arg(long)]
+    pub only_touched: bool,
+    /// Rebuild state from historical input, without hooks or payouts.
+    #[arg(long)]
+    pub backfill: bool,
+    /// Hold transactions of blocked clients instead of rejecting them.
+    #[arg(long)]
+    pub hold_blocked: bool,
+    /// Import the input even if the journal shows it was already imported.
+    #[arg(long)]
+    pub force: bool,
+    /// Exit with a distinct code when records were skipped.
+    #[arg(long)]
+    pub strict_exit: bool,
+    /// Replace client ids in the output with keyed pseudonyms.
+    #[arg(long)]
+    pub pseudonymize: bool,
+    /// Close dormant accounts; needs --dormant-after-days.
+    #[arg(long)]
+    pub close_dormant: bool,
+    /// Sweep dormant balances to the dormant account; needs --dormant-after-days.
+    #[arg(long)]
+    pub sweep_dormant: bool,
+    /// Only keep transactions that can still be disputed.
+    #[arg(long)]
+    pub retain_disputable_only: bool,
+    /// Read the inputs in file name order.
+    #[arg(long)]
+    pub sort_inputs: bool,
+    /// spec, legacy or quoted.
+    #[arg(long)]
+    pub output_style: Option<String>,
+    /// strict or tolerant.
+    #[arg(long)]
+    pub amount_format: Option<String>,
+    /// Factor every input amount is multiplied by, e.g. 0.01 for cents.
+    #[arg(long)]
+    pub amount_scale: Option<String>,
+    /// Comma-separated account columns, e.g. client,total,locked.
+    #[arg(long)]
+    pub columns: Option<String>,
+    /// Decimal places of balances and output amounts.
+    #[arg(long)]
+    pub precision: Option<String>,
+    /// bankers or half-up.
+    #[arg(long)]
+    pub rounding: Option<String>,
+    /// CSV mapping partner client ids to engine ones.
+    #[arg(long)]
+    pub client_map: Option<String>,
+    /// Import journal, so the same input isn't applied twice.
+    #[arg(long)]
+    pub journal: Option<String>,
+    /// Accounts CSV to start from.
+    #[arg(long)]
+    pub opening_balances: Option<String>,
+    /// How long transactions stay disputable.
+    #[arg(long)]
+    pub retention_secs: Option<String>,
+    /// Directory periodic engine snapshots are written to.
+    #[arg(long)]
+    pub checkpoint_dir: Option<String>,
+    /// Records between checkpoints.
+    #[arg(long)]
+    pub checkpoint_every: Option<String>,
+    /// Checkpoints kept, newest first.
+    #[arg(long)]
+    pub keep_checkpoints: Option<String>,
+    /// Days for which the last checkpoint is also kept.
+    #[arg(long)]
+    pub keep_daily_checkpoints: Option<String>,
+    /// Most transactions kept for disputes.
+    #[arg(long)]
+    pub retention_max_txs: Option<String>,
+    /// Manifest of the output files; needs --output.
+    #[arg(long)]
+    pub manifest: Option<String>,
+    /// Marker written once every output is in place.
+    #[arg(long)]
+    pub success_marker: Option<String>,
+    /// `client,amount,reason` corrections applied once the input is done.
+    #[arg(long)]
+    pub adjustments: Option<String>,
+    /// Provenance record of the inputs and outputs.
+    #[arg(long)]
+    pub provenance: Option<String>,
+    /// Log every applied transaction to this file.
+    #[arg(long)]
+    pub audit_log: Option<String>,
+    /// JSON-lines log of every rule evaluation.
+    #[arg(long)]
+    pub decisions_log: Option<String>,
+    /// CSV of every rejected transaction with its error code.
+    #[arg(long)]
+    pub rejects: Option<String>,
+    /// Rhai script with validate, fraud and fee rules.
+    #[arg(long)]
+    pub script: Option<String>,
+    /// Rewrite the output every this many seconds; needs --output.
+    #[arg(long)]
+    pub watch_output: Option<String>,
+    /// Pay out balances at the end of the run, writing the payouts here.
+    #[arg(long)]
+    pub payouts: Option<String>,
+    /// csv or pain001.
+    #[arg(long)]
+    pub payout_format: Option<String>,
+    /// `client,name,iban,bic` CSV of merchant bank accounts, for pain001.
+    #[arg(long)]
+    pub payout_accounts: Option<String>,
+    /// TOML file describing the account payouts are paid from, for pain001.
+    #[arg(long)]
+    pub payout_debtor: Option<String>,
+    /// CSV of standing sweep rules.
+    #[arg(long)]
+    pub sweep_rules: Option<String>,
+    /// Resolve disputes left open this many days.
+    #[arg(long)]
+    pub dispute_timeout_days: Option<String>,
+    /// Freeze withdrawals above this many open disputes.
+    #[arg(long)]
+    pub freeze_open_disputes: Option<String>,
+    /// Freeze withdrawals above this share of held funds.
+    #[arg(long)]
+    pub freeze_held_ratio: Option<String>,
+    /// available, block-disputed or reserve:<amount>.
+    #[arg(long)]
+    pub withdrawal_policy: Option<String>,
+    /// allow-negative, cap or queue.
+    #[arg(long)]
+    pub dispute_funds_policy: Option<String>,
+    /// strict or last-write-wins.
+    #[arg(long)]
+    pub duplicates: Option<String>,
+    /// deposits or none.
+    #[arg(long)]
+    pub debt_repayment: Option<String>,
+    /// Write outstanding debts here.
+    #[arg(long)]
+    pub debts: Option<String>,
+    /// Write the open-dispute liabilities report here.
+    #[arg(long)]
+    pub liabilities: Option<String>,
+    /// How far behind the merged watermark a transaction may arrive.
+    #[arg(long)]
+    pub allowed_lateness_secs: Option<String>,
+    /// Write transactions that arrived too late here.
+    #[arg(long)]
+    pub late_events: Option<String>,
+    /// Flag accounts inactive for this many days as dormant.
+    #[arg(long)]
+    pub dormant_after_days: Option<String>,
+    /// Only write these clients, e.g. 1,2,5-9.
+    #[arg(long)]
+    pub clients: Option<String>,
+    /// Transactions held while waiting for the one they reference.
+    #[arg(long)]
+    pub quarantine_size: Option<String>,
+    /// How long a quarantined transaction waits.
+    #[arg(long)]
+    pub quarantine_secs: Option<String>,
+    /// Write the transactions still quarantined at the end here.
+    #[arg(long)]
+    pub orphans: Option<String>,
+    /// Write per-window aggregates here.
+    #[arg(long)]
+    pub aggregates: Option<String>,
+    /// hourly, daily or a number of seconds.
+    #[arg(long)]
+    pub aggregate_window: Option<String>,
+    /// Clients whose transactions are refused.
+    #[arg(long)]
+    pub blocklist: Option<String>,
+    /// The only clients whose transactions are applied.
+    #[arg(long)]
+    pub allowlist: Option<String>,
+    /// Close the period before this unix time with the balances the run starts from.
+    #[arg(long)]
+    pub closed_before: Option<String>,
+    /// reject or adjust entries for closed periods.
+    #[arg(long)]
+    pub late_entries: Option<String>,
+    /// CSV of accounts to open before the input.
+    #[arg(long)]
+    pub account_seeds: Option<String>,
+    /// Fail the run past this many skipped records.
+    #[arg(long)]
+    pub max_skipped: Option<String>,
+    /// Fail the run past this percentage of skipped records.
+    #[arg(long)]
+    pub max_skipped_percent: Option<String>,
+    /// With --pseudonymize, round output amounts down to multiples of this.
+    #[arg(long)]
+    pub amount_bucket: Option<String>,
+    /// Apply the input on this many threads, sharded by client.
+    #[arg(long)]
+    pub threads: Option<String>,
+    /// More feeds merged with the input by timestamp.
+    #[arg(long)]
+    pub merge_input: Vec<String>,
+    /// Files read after the input, as part of the same feed.
+    #[arg(long)]
+    pub next_input: Vec<String>,
+    /// Hold a funds class for some days, as <class>:<days>.
+    #[arg(long)]
+    pub funds_hold: Vec<String>,
+}
+
+impl ProcessArgs {
+    /// Hands the flags to [`ProcessOptions::parse`] in the bare form, so
+    /// both forms share its checks.
+    pub fn into_options(self) -> Result<ProcessOptions, String> {
+        let mut args = vec![self.input];
+        args.extend(self.output);
+        let format = match self.format {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Arrow => "arrow",
+        };
+        args.extend(["--output-format".to_string(), format.to_string()]);
+        let switches = [
+            ("--require-open-accounts", self.require_open_accounts),
+            ("--only-locked", self.only_locked),
+            ("--non-zero", self.non_zero),
+            ("--only-touched", self.only_touched),
+            ("--backfill", self.backfill),
+            ("--hold-blocked", self.hold_blocked),
+            ("--force", self.force),
+            ("--strict-exit", self.strict_exit),
+            ("--pseudonymize", self.pseudonymize),
+            ("--close-dormant", self.close_dormant),
+            ("--sweep-dormant", self.sweep_dormant),
+            ("--retain-disputable-only", self.retain_disputable_only),
+            ("--sort-inputs", self.sort_inputs),
+        ];
+        for (flag, on) in switches {
+            if on {
+                args.push(flag.to_string());
+            }
+        }
+        let values = [
+            ("--output-style", self.output_style),
+            ("--amount-format", self.amount_format),
+            ("--amount-scale", self.amount_scale),
+            ("--columns", self.columns),
+            ("--precision", self.precision),
+            ("--rounding", self.rounding),
+            ("--client-map", self.client_map),
+            ("--journal", self.journal),
+            ("--opening-balances", self.opening_balances),
+            ("--retention-secs", self.retention_secs),
+            ("--checkpoint-dir", self.checkpoint_dir),
+            ("--checkpoint-every", self.checkpoint_every),
+            ("--keep-checkpoints", self.keep_checkpoints),
+            ("--keep-daily-checkpoints", self.keep_daily_checkpoints),
+            ("--retention-max-txs", self.retention_max_txs),
+            ("--manifest", self.manifest),
+            ("--success-marker", self.success_marker),
+            ("--adjustments", self.adjustments),
+            ("--provenance", self.provenance),
+            ("--audit-log", self.audit_log),
+            ("--decisions-log", self.decisions_log),
+            ("--rejects", self.rejects),
+            ("--script", self.script),
+            ("--watch-output", self.watch_output),
+            ("--payouts", self.payouts),
+            ("--payout-format", self.payout_format),
+            ("--payout-accounts", self.payout_accounts),
+            ("--payout-debtor", self.payout_debtor),
+            ("--sweep-rules", self.sweep_rules),
+            ("--dispute-timeout-days", self.dispute_timeout_days),
+            ("--freeze-open-disputes", self.freeze_open_disputes),
+            ("--freeze-held-ratio", self.freeze_held_ratio),
+            ("--withdrawal-policy", self.withdrawal_policy),
+            ("--dispute-funds-policy", self.dispute_funds_policy),
+            ("--duplicates", self.duplicates),
+            ("--debt-repayment", self.debt_repayment),
+            ("--debts", self.debts),
+            ("--liabilities", self.liabilities),
+            ("--allowed-lateness-secs", self.allowed_lateness_secs),
+            ("--late-events", self.late_events),
+            ("--dormant-after-days", self.dormant_after_days),
+            ("--clients", self.clients),
+            ("--quarantine-size", self.quarantine_size),
+            ("--quarantine-secs", self.quarantine_secs),
+            ("--orphans", self.orphans),
+            ("--aggregates", self.aggregates),
+            ("--aggregate-window", self.aggregate_window),
+            ("--blocklist", self.blocklist),
+            ("--allowlist", self.allowlist),
+            ("--closed-before", self.closed_before),
+            ("--late-entries", self.late_entries),
+            ("--account-seeds", self.account_seeds),
+            ("--max-skipped", self.max_skipped),
+            ("--max-skipped-percent", self.max_skipped_percent),
+            ("--amount-bucket", self.amount_bucket),
+            ("--threads", self.threads),
+        ];
+        let lists = [
+            ("--merge-input", self.merge_input),
+            ("--next-input", self.next_input),
+            ("--funds-hold", self.funds_hold),
+        ];
+        let values = values
+            .into_iter()
+            .flat_map(|(flag, value)| value.map(|value| (flag, value)));
+        let lists = lists
+            .into_iter()
+            .flat_map(|(flag, list)| list.into_iter().map(move |value| (flag, value)));
+        for (flag, value) in values.chain(lists) {
+            args.extend([flag.to_string(), value]);
+        }
+        ProcessOptions::parse(&args)
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ValidateArgs {
+    /// Transactions file, or `-` for stdin.
+    #[arg(long, default_value = STDIN_PATH)]
+    pub input: String,
+    /// Report file; stdout if not given.
+    #[arg(long)]
+    pub output: Option<String>,
+    /// json, or csv for one row per anomaly.
+    #[arg(long, default_value = "json")]
+    pub format: ReportFormat,
+    /// Flag amounts above this.
+    #[arg(long)]
+    pub max_amount: Option<Decimal>,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    /// Transactions file, or `-` for stdin.
+    #[arg(long, default_value = STDIN_PATH)]
+    pub input: String,
+    /// Report file; stdout if not given.
+    #[arg(long)]
+    pub output: Option<String>,
+    /// json or csv.
+    #[arg(long, default_value = "json")]
+    pub format: ReportFormat,
+    /// Add balance percentiles, concentration and chargeback losses by
+    /// cohort; json only.
+    #[arg(long)]
+    pub analytics: bool,
+    /// Cohort width: hourly, daily or a number of seconds.
+    #[arg(long, default_value = "daily", requires = "analytics")]
+    pub cohort_window: WindowSize,
+}
+
+/// Options of the `estimate` command.
+#[derive(Debug, Clone)]
+pub struct EstimateOptions {
+    pub input: String,
+    pub sample_rows: u64,
+    pub output: Option<String>,
+}
+
+impl EstimateOptions {
+    /// `<input> [--sample-rows N] [--output estimate.json]`
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Self {
+            input: args
+                .first()
+                .cloned()
+                .ok_or("Input file path required as first argument")?,
+            sample_rows: DEFAULT_SAMPLE_ROWS,
+            output: None,
+        };
+
+        let mut rest = args[1..].iter();
+        while let Some(flag) = rest.next() {
+            let value = rest
+                .next()
+                .ok_or_else(|| format!("Missing value for '{}'", flag))?;
+            match flag.as_str() {
+                "--sample-rows" => options.sample_rows = parse_flag(flag, value)?,
+                "--output" => options.output = Some(value.clone()),
+                _ => return Err(format!("Unknown argument '{}'", flag)),
+            }
+        }
+        if options.sample_rows == 0 {
+            return Err("--sample-rows must be at least 1".to_string());
+        }
+        Ok(options)
+    }
+}
+
+/// Options of the `cases` command.
+#[derive(Debug, Default, Clone)]
+pub struct CasesOptions {
+    pub input: String,
+    pub output: Option<String>,
+}
+
+impl CasesOptions {
+    /// `<input> [--output cases.csv]`
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Self {
+            input: args
+                .first()
+                .cloned()
+                .ok_or("Input file path required as first argument")?,
+            ..Self::default()
+        };
+
+        let mut rest = args[1..].iter();
+        while let Some(flag) = rest.next() {
+            let value = rest
+                .next()
+                .ok_or_else(|| format!("Missing value for '{}'", flag))?;
+            match flag.as_str() {
+                "--output" => options.output = Some(value.clone()),
+                _ => return Err(format!("Unknown argument '{}'", flag)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Options of the `extract` command.
+#[derive(Debug, Default, Clone)]
+pub struct ExtractOptions {
+    pub input: String,
+    pub output: Option<String>,
+    pub config: ExtractConfig,
+}
+
+impl ExtractOptions {
+    /// `<input> [--client ids] [--sample rate] [--with-referenced-txs]
+    /// [-o|--output out.csv]`
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Self {
+            input: args
+                .first()
+                .cloned()
+                .ok_or("Input file path required as first argument")?,
+            ..Self::default()
+        };
+
+        let mut rest = args[1..].iter();
+        while let Some(flag) = rest.next() {
+            if flag == "--with-referenced-txs" {
+                options.config.with_referenced = true;
+                continue;
+            }
+            let value = rest
+                .next()
+                .ok_or_else(|| format!("Missing value for '{}'", flag))?;
+            match flag.as_str() {
+                "--client" => options
+                    .config
+                    .clients
+                    .get_or_insert_default()
+                    .extend(parse_client_list(value)?),
+                "--sample" => {
+                    let rate: Decimal = parse_flag(flag, value)?;
+                    if rate <= Decimal::ZERO || rate > Decimal::ONE {
+                        return Err(format!("--sample must be in (0, 1], got {}", rate));
+                    }
+                    options.config.sample_rate = Some(rate);
+                }
+                "-o" | "--output" => options.output = Some(value.clone()),
+                _ => return Err(format!("Unknown argument '{}'", flag)),
+            }
+        }
+        if options.config.clients.is_none() && options.config.sample_rate.is_none() {
+            return Err("Nothing to extract: give --client or --sample".to_string());
+        }
+        Ok(options)
+    }
+}
+
+/// Options of the `scenario` command.
+#[derive(Debug, Default, Clone)]
+pub struct ScenarioOptions {
+    pub dir: String,
+}
+
+impl ScenarioOptions {
+    /// `run <dir>`
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        match args {
+            [command, dir] if command == "run" => Ok(Self { dir: dir.clone() }),
+            _ => Err("Usage: scenario run <dir>".to_string()),
+        }
+    }
+}
+
+/// Options of the `preview` command.
+#[derive(Debug, Default, Clone)]
+pub struct PreviewOptions {
+    pub input: String,
+    /// Accounts file to start from; empty state when absent.
+    pub state: Option<String>,
+    pub output: Option<String>,
+}
+
+impl PreviewOptions {
+    /// `<pending.csv> [--state accounts.csv] [--output changes.csv]`
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Self {
+            input: args
+                .first()
+                .cloned()
+                .ok_or("Input file path required as first argument")?,
+            ..Self::default()
+        };
+
+        let mut rest = args[1..].iter();
+        while let Some(flag) = rest.next() {
+            let value = rest
+                .next()
+                .ok_or_else(|| format!("Missing value for '{}'", flag))?;
+            match flag.as_str() {
+                "--state" => options.state = Some(value.clone()),
+                "--output" => options.output = Some(value.clone()),
+                _ => return Err(format!("Unknown argument '{}'", flag)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Options of the `reconcile` command.
+#[derive(Debug, Default, Clone)]
+pub struct ReconcileOptions {
+    pub input: String,
+    pub expected: String,
+    /// Largest amount difference that still counts as a match.
+    pub tolerance: Decimal,
+    pub output: Option<String>,
+}
+
+impl ReconcileOptions {
+    /// `--expected balances.csv <input> [--tolerance N] [--output report.csv]`
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut input = None;
+        let mut expected = None;
+
+        let mut rest = args.iter();
+        while let Some(arg) = rest.next() {
+            if !arg.starts_with("--") {
+                if input.replace(arg.clone()).is_some() {
+                    return Err(format!("Unexpected argument '{}'", arg));
+                }
+                continue;
+            }
+            let value = rest
+                .next()
+                .ok_or_else(|| format!("Missing value for '{}'", arg))?;
+            match arg.as_str() {
+                "--expected" => expected = Some(value.clone()),
+                "--tolerance" => options.tolerance = parse_flag(arg, value)?,
+                "--output" => options.output = Some(value.clone()),
+                _ => return Err(format!("Unknown argument '{}'", arg)),
+            }
+        }
+        options.input = input.ok_or("Input file path required")?;
+        options.expected = expected.ok_or("--expected balances file required")?;
+        if options.tolerance < Decimal::ZERO {
+            return Err("--tolerance must not be negative".to_string());
+        }
+        Ok(options)
+    }
+}
+
+/// Options of the `bench compare` command.
+#[derive(Debug, Default, Clone)]
+pub struct BenchOptions {
+    pub workload: WorkloadConfig,
+}
+
+impl BenchOptions {
+    /// `compare [--records N] [--clients N] [--seed N]`
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        match args.first().map(String::as_str) {
+            Some("compare") => {}
+            Some(other) => return Err(format!("Unknown bench command '{}'", other)),
+            None => return Err("Bench command required, e.g. 'bench compare'".to_string()),
+        }
+        let mut options = Self::default();
+
+        let mut rest = args[1..].iter();
+        while let Some(flag) = rest.next() {
+            let value = rest
+                .next()
+                .ok_or_else(|| format!("Missing value for '{}'", flag))?;
+            match flag.as_str() {
+                "--records" => options.workload.records = parse_flag(flag, value)?,
+                "--clients" => options.workload.clients = parse_flag(flag, value)?,
+                "--seed" => options.workload.seed = parse_flag(flag, value)?,
+                _ => return Err(format!("Unknown argument '{}'", flag)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// CSV account sink writing to `path`, or to stdout when there is none.
+pub fn open_sink(
+    path: Option<&str>,
+    format: OutputFormat,
+    style: OutputStyle,
+    columns: Option<&AccountColumns>,
+    config: EngineConfig,
+) -> Result<Box<dyn DataSink>, String> {
+    match path {
+        Some(path) => {
+            let (staged, file) = StagedFile::create(path)?;
+            let sink = sink_for(file, format, style, columns, config);
+            Ok(Box::new(StagedSink::new(sink, staged)))
+        }
+        None => Ok(sink_for(std::io::stdout(), format, style, columns, config)),
+    }
+}
+
+fn parse_flag<T>(flag: &str, value: &str) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|e| format!("Invalid value '{}' for {}: {}", value, flag, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::RoundingMode, funds::FundsClass};
+    use clap::CommandFactory;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_process_options() {
+        let options = ProcessOptions::parse(&args(
+            "in.csv out.csv --output-style legacy --dispute-timeout-days 2 --freeze-open-disputes 3",
+        ))
+        .unwrap();
+        assert_eq!(options.input, "in.csv");
+        assert_eq!(options.output.as_deref(), Some("out.csv"));
+        assert_eq!(options.style, OutputStyle::Legacy);
+        assert_eq!(options.dispute_timeout_secs, Some(2 * 24 * 60 * 60));
+        assert_eq!(options.freeze_policy.max_open_disputes, Some(3));
+
+        let options = ProcessOptions::parse(&args(
+            "in.csv --checkpoint-dir ckpt --checkpoint-every 500 --keep-checkpoints 3",
+        ))
+        .unwrap();
+        assert_eq!(options.checkpoint_dir.as_deref(), Some("ckpt"));
+        assert_eq!(options.checkpoint_every, Some(500));
+        assert_eq!(options.checkpoint_retention.keep_last, 3);
+        assert!(ProcessOptions::parse(&args("in.csv --keep-daily-checkpoints 7")).is_err());
+        assert!(
+            ProcessOptions::parse(&args("in.csv --checkpoint-dir c --keep-checkpoints 0")).is_err()
+        );
+
+        let options = ProcessOptions::parse(&args(
+            "in.csv --payouts p.xml --payout-format pain001 --payout-accounts banks.csv --payout-debtor debtor.toml",
+        ))
+        .unwrap();
+        assert_eq!(options.payout_format, PayoutFormat::Pain001);
+        assert!(options.input_files().contains(&"debtor.toml"));
+        assert!(
+            ProcessOptions::parse(&args("in.csv --payouts p.xml --payout-format pain001")).is_err()
+        );
+        assert!(
+            ProcessOptions::parse(&args("in.csv --payouts p.csv --payout-accounts banks.csv"))
+                .is_err()
+        );
+
+        let options = ProcessOptions::parse(&args(
+            "in.csv --max-skipped-percent 0.1 --strict-exit --duplicates last-write-wins --debt-repayment none --output-format arrow",
+        ))
+        .unwrap();
+        assert_eq!(options.format, OutputFormat::Arrow);
+        assert_eq!(options.duplicate_policy, DuplicatePolicy::LastWriteWins);
+        assert_eq!(options.debt_repayment, DebtRepayment::None);
+        assert_eq!(
+            options.skip_thresholds.max_skipped_percent,
+            Some(Decimal::new(1, 1))
+        );
+        assert!(options.strict_exit);
+        assert!(ProcessOptions::parse(&args("in.csv --max-skipped-percent 150")).is_err());
+        assert!(ProcessOptions::parse(&args("in.csv --sweep-dormant")).is_err());
+        let options =
+            ProcessOptions::parse(&args("in.csv --rounding half-up --precision 2")).unwrap();
+        assert_eq!(
+            options.config,
+            EngineConfig::new(2, RoundingMode::HalfUp).unwrap()
+        );
+        assert!(ProcessOptions::parse(&args("in.csv --precision 29")).is_err());
+        let options = ProcessOptions::parse(&args(
+            "eu.csv --merge-input us.csv --allowed-lateness-secs 300 --late-events late.csv",
+        ))
+        .unwrap();
+        assert_eq!(options.input_files(), vec!["eu.csv", "us.csv"]);
+        assert_eq!(options.output_files(), vec!["late.csv"]);
+        assert_eq!(options.allowed_lateness_secs, Some(300));
+        let options =
+            ProcessOptions::parse(&args("d1.csv --next-input d2.csv --sort-inputs")).unwrap();
+        assert_eq!(options.input_files(), vec!["d1.csv", "d2.csv"]);
+        assert!(options.sort_inputs);
+        assert!(ProcessOptions::parse(&args("d*.csv --journal j.json")).is_err());
+        let mut options =
+            ProcessOptions::parse(&args("test_*.csv --next-input extra.csv")).unwrap();
+        options.expand_input_glob().unwrap();
+        assert!(options.input.starts_with("test_"));
+        assert_eq!(
+            options.next_inputs.last().map(String::as_str),
+            Some("extra.csv")
+        );
+        assert!(options.next_inputs.len() > 2);
+        let options = ProcessOptions::parse(&args("in.csv --funds-hold card:3")).unwrap();
+        assert_eq!(
+            options.funds_holds,
+            vec![FundsHold {
+                class: FundsClass::Card,
+                secs: 3 * 24 * 60 * 60,
+            }]
+        );
+        assert!(ProcessOptions::parse(&args("in.csv --late-events late.csv")).is_err());
+        let options =
+            ProcessOptions::parse(&args("in.csv --dormant-after-days 30 --sweep-dormant")).unwrap();
+        assert_eq!(
+            options.dormancy,
+            Some(DormancyPolicy {
+                after_secs: 30 * 24 * 60 * 60,
+                close: false,
+                sweep: true,
+            })
+        );
+        assert_eq!(
+            ProcessOptions::parse(&args("in.csv --amount-bucket 10")).unwrap_err(),
+            "--amount-bucket only applies with --pseudonymize"
+        );
+
+        assert_eq!(
+            ProcessOptions::parse(&args("in.csv --manifest m.json")).unwrap_err(),
+            "--manifest and --watch-output require an output file"
+        );
+        assert_eq!(
+            ProcessOptions::parse(&args("in.csv --force")).unwrap_err(),
+            "--force only applies with --journal"
+        );
+
+        // A threaded run has no per-record hook to feed these from.
+        for flag in [
+            "--journal j.json",
+            "--audit-log a.log",
+            "--decisions-log d.log",
+            "--rejects r.csv",
+            "--aggregates agg.csv",
+            "--watch-output 5",
+            "--checkpoint-dir ckpt",
+            "--retention-max-txs 10",
+        ] {
+            let line = format!("in.csv out.csv --threads 2 {flag}");
+            assert!(
+                ProcessOptions::parse(&args(&line))
+                    .unwrap_err()
+                    .starts_with("--threads can't be combined"),
+                "{flag}"
+            );
+        }
+
+        assert_eq!(ProcessOptions::parse(&[]).unwrap().input, STDIN_PATH);
+        let options = ProcessOptions::parse(&args("--threads 2")).unwrap();
+        assert_eq!((options.input.as_str(), options.threads), ("-", Some(2)));
+        let options = ProcessOptions::parse(&args("- out.csv")).unwrap();
+        assert_eq!(options.output.as_deref(), Some("out.csv"));
+        assert!(ProcessOptions::parse(&args("- --provenance p.json")).is_err());
+        assert!(
+            ProcessOptions::parse(&args("in.csv --bogus 1"))
+                .unwrap_err()
+                .contains("Unknown argument")
+        );
+    }
+
+    #[test]
+    fn test_parse_subcommands() {
+        let parse = |line: &str| Cli::try_parse_from(args(&format!("payment_engine {line}")));
+        let Command::Process(process) = parse(
+            "process --input in.csv --format arrow --output out.arrow --threads 4 --non-zero",
+        )
+        .unwrap()
+        .command
+        else {
+            panic!("expected process");
+        };
+        let options = process.into_options().unwrap();
+        assert_eq!(options.input, "in.csv");
+        assert_eq!(options.output.as_deref(), Some("out.arrow"));
+        assert_eq!(options.format, OutputFormat::Arrow);
+        assert_eq!(options.threads, Some(4));
+        assert!(options.filter.non_zero_only);
+        let Command::Process(process) =
+            parse("process --input in.csv --merge-input a.csv --merge-input b.csv --clients 1,2")
+                .unwrap()
+                .command
+        else {
+            panic!("expected process");
+        };
+        let options = process.into_options().unwrap();
+        assert_eq!(options.merge_inputs, vec!["a.csv", "b.csv"]);
+        assert!(options.filter.clients.is_some());
+        // Every flag is clap's own, so strays are refused and the flags
+        // show up in `--help`.
+        assert!(parse("process --input in.csv extra.csv").is_err());
+        assert!(parse("process --input in.csv --bogus 1").is_err());
+        let command = Cli::command();
+        let process = command.find_subcommand("process").unwrap();
+        assert!(
+            process
+                .get_arguments()
+                .any(|arg| arg.get_long() == Some("dormant-after-days"))
+        );
+        let Command::Process(process) =
+            parse("process --input in.csv --threads 0").unwrap().command
+        else {
+            panic!("expected process");
+        };
+        assert!(process.into_options().is_err());
+
+        let Command::Validate(validate) =
+            parse("validate --input in.csv --format csv --max-amount 10")
+                .unwrap()
+                .command
+        else {
+            panic!("expected validate");
+        };
+        assert_eq!(validate.format, ReportFormat::Csv);
+        assert_eq!(validate.max_amount, Some(Decimal::TEN));
+        assert!(matches!(
+            parse("report --input in.csv").unwrap().command,
+            Command::Report(ReportArgs {
+                format: ReportFormat::Json,
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("report").unwrap().command,
+            Command::Report(ReportArgs { input, .. }) if input == STDIN_PATH
+        ));
+        assert!(parse("report --input in.csv --format xml").is_err());
+        assert!(matches!(
+            parse("report --input in.csv --analytics --cohort-window hourly")
+                .unwrap()
+                .command,
+            Command::Report(ReportArgs {
+                analytics: true,
+                cohort_window: WindowSize(3600),
+                ..
+            })
+        ));
+        assert!(parse("report --input in.csv --cohort-window hourly").is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::{
+    PaymentEngine, TxOutcome, TxType, UserAccount, UserTransactions,
+    errors::{EngineError, ErrorCode},
+    money::Amount,
+    parallel::ParallelRun,
+    pipeline::RunSummary,
+    risk::Decision,
+};
+
+/// Operations scoped to a single client's account, either on an engine
+/// borrowed outright or on a [`SharedEngine`], where each operation only
+/// locks the shard that owns the client.
+pub struct ClientHandle<'a> {
+    target: Target<'a>,
+    client_id: u16,
+}
+
+enum Target<'a> {
+    Engine(&'a mut PaymentEngine),
+    Shared(&'a SharedEngine),
+}
+
+impl PaymentEngine {
+    pub fn client(&mut self, client_id: u16) -> ClientHandle<'_> {
+        ClientHandle {
+            target: Target::Engine(self),
+            client_id,
+        }
+    }
+
+    /// Splits the engine into `shards` independently locked shards for
+    /// handlers on several threads; see [`SharedEngine`].
+    pub fn into_shared(self, shards: usize) -> Result<SharedEngine, String> {
+        self.check_shardable()?;
+        let shards = shards.max(1);
+        let mut tx_owners = vec![HashMap::new(); shards];
+        for (&tx_id, &client_id) in &self.seen_tx_ids {
+            tx_owners[tx_id as usize % shards].insert(tx_id, client_id);
+        }
+        Ok(SharedEngine {
+            shards: (0..shards).map(|_| Mutex::new(self.fork())).collect(),
+            tx_owners: tx_owners.into_iter().map(Mutex::new).collect(),
+            base: Mutex::new(self),
+        })
+    }
+}
+
+/// An engine split by client into shards that are locked one at a time, so
+/// handles for clients of different shards run concurrently. Shard `i`
+/// owns the clients whose `client_id % shards == i`, as in
+/// [`PaymentEngine::process_parallel`], and the same features are refused.
+/// Tx ids are checked for reuse across shards through their own, separately
+/// locked, registry. Hooks aren't called for its operations.
+pub struct SharedEngine {
+    /// The engine the shards were split from, with its hooks. Nothing locks
+    /// it; the mutex only lets the hooks, which needn't be `Sync`, sit in a
+    /// type shared between threads.
+    base: Mutex<PaymentEngine>,
+    shards: Vec<Mutex<PaymentEngine>>,
+    /// Client of every deposit and withdrawal, sharded by tx id.
+    tx_owners: Vec<Mutex<HashMap<u32, u16>>>,
+}
+
+impl SharedEngine {
+    pub fn client(&self, client_id: u16) -> ClientHandle<'_> {
+        ClientHandle {
+            target: Target::Shared(self),
+            client_id,
+        }
+    }
+
+    /// Merges the shards back into the engine they were split from.
+    pub fn into_engine(self) -> PaymentEngine {
+        let mut engine = self.base.into_inner().expect("engine poisoned");
+        ParallelRun {
+            shards: self
+                .shards
+                .into_iter()
+                .map(|shard| shard.into_inner().expect("engine shard poisoned"))
+                .collect(),
+            summary: RunSummary::default(),
+        }
+        .merge_into(&mut engine);
+        engine
+    }
+
+    fn shard(&self, client_id: u16) -> MutexGuard<'_, PaymentEngine> {
+        self.shards[usize::from(client_id) % self.shards.len()]
+            .lock()
+            .expect("engine shard poisoned")
+    }
+
+    /// Applies `action` under its client's shard lock, and for deposits and
+    /// withdrawals its tx id's registry lock, always taken first.
+    fn process(&self, action: UserTransactions) -> Result<TxOutcome, EngineError> {
+        let claims = matches!(action.tx_type, TxType::Deposit | TxType::Withdrawal);
+        let mut owners = claims.then(|| {
+            self.tx_owners[action.tx_id as usize % self.tx_owners.len()]
+                .lock()
+                .expect("tx id registry poisoned")
+        });
+        if let Some(&client_id) = owners.as_ref().and_then(|o| o.get(&action.tx_id))
+            && client_id != action.client_id
+        {
+            return Err(EngineError::new(
+                ErrorCode::DuplicateTransaction,
+                format!(
+                    "Transaction {} was already applied for client {}",
+                    action.tx_id, client_id
+                ),
+            ));
+        }
+        let (tx_id, client_id) = (action.tx_id, action.client_id);
+        let outcome = self.shard(client_id).process_action(action);
+        if outcome.is_ok()
+            && let Some(owners) = owners.as_mut()
+        {
+            owners.insert(tx_id, client_id);
+        }
+        outcome
+    }
+}
+
+impl ClientHandle<'_> {
+    pub fn client_id(&self) -> u16 {
+        self.client_id
+    }
+
+    pub fn account(&self) -> Option<UserAccount> {
+        match &self.target {
+            Target::Engine(engine) => engine.accounts.get(&self.client_id).cloned(),
+            Target::Shared(shared) => shared
+                .shard(self.client_id)
+                .accounts
+                .get(&self.client_id)
+                .cloned(),
+        }
+    }
+
+    pub fn deposit(&mut self, tx_id: u32, amount: Amount) -> Result<TxOutcome, EngineError> {
+        self.apply(TxType::Deposit, tx_id, Some(amount))
+    }
+
+    pub fn withdraw(&mut self, tx_id: u32, amount: Amount) -> Result<TxOutcome, EngineError> {
+        self.apply(TxType::Withdrawal, tx_id, Some(amount))
+    }
+
+    /// See [`PaymentEngine::can_withdraw`].
+    pub fn can_withdraw(&self, amount: Amount) -> Decision {
+        match &self.target {
+            Target::Engine(engine) => engine.can_withdraw(self.client_id, amount),
+            Target::Shared(shared) => shared
+                .shard(self.client_id)
+                .can_withdraw(self.client_id, amount),
+        }
+    }
+
+    pub fn dispute(&mut self, tx_id: u32) -> Result<TxOutcome, EngineError> {
+        self.apply(TxType::Dispute, tx_id, None)
+    }
+
+    pub fn resolve(&mut self, tx_id: u32) -> Result<TxOutcome, EngineError> {
+        self.apply(TxType::Resolve, tx_id, None)
+    }
+
+    pub fn chargeback(&mut self, tx_id: u32) -> Result<TxOutcome, EngineError> {
+        self.apply(TxType::Chargeback, tx_id, None)
+    }
+
+    fn apply(
+        &mut self,
+        tx_type: TxType,
+        tx_id: u32,
+        amount: Option<Amount>,
+    ) -> Result<TxOutcome, EngineError> {
+        let action = UserTransactions {
+            tx_type,
+            client_id: self.client_id,
+            tx_id,
+            amount,
+            ..Default::default()
+        };
+        match &mut self.target {
+            Target::Engine(engine) => engine.process_action(action),
+            Target::Shared(shared) => shared.process(action),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_handle_checks_balances() {
+        let mut engine = PaymentEngine::new();
+        let mut client = engine.client(1);
+
+        assert!(client.withdraw(1, amount(dec!(5.0))).is_err());
+        client.deposit(2, amount(dec!(10.0))).unwrap();
+        assert!(client.withdraw(3, amount(dec!(15.0))).is_err());
+        client.withdraw(4, amount(dec!(4.0))).unwrap();
+        assert!(client.dispute(99).is_err());
+        client.dispute(2).unwrap();
+
+        // The disputed deposit was partly withdrawn already. The default
+        // dispute funds policy still holds all of it, which takes available
+        // below zero.
+        let account = client.account().unwrap();
+        assert_eq!(account.available, dec!(-4.0));
+        assert_eq!(account.held, dec!(10.0));
+        assert!(!engine.accounts.contains_key(&2));
+    }
+
+    #[test]
+    fn test_shared_handles_run_clients_concurrently() {
+        let mut engine = PaymentEngine::new();
+        engine.client(1).deposit(1, amount(dec!(100))).unwrap();
+        let shared = engine.into_shared(2).unwrap();
+
+        // Clients 1 and 2 live in different shards, so neither thread waits
+        // on the other's account.
+        std::thread::scope(|scope| {
+            for (client_id, first_tx) in [(1, 1_000), (2, 2_000)] {
+                let shared = &shared;
+                scope.spawn(move || {
+                    let mut client = shared.client(client_id);
+                    for tx_id in first_tx..first_tx + 100 {
+                        client.deposit(tx_id, amount(dec!(2))).unwrap();
+                        client.withdraw(tx_id + 500, amount(dec!(1))).unwrap();
+                    }
+                });
+            }
+        });
+        // Tx ids stay unique across shards.
+        let error = shared.client(2).deposit(1, amount(dec!(1))).unwrap_err();
+        assert_eq!(error.code(), ErrorCode::DuplicateTransaction);
+        assert_eq!(shared.client(1).account().unwrap().available, dec!(200));
+
+        let engine = shared.into_engine();
+        assert_eq!(engine.accounts[&1].available, dec!(200));
+        assert_eq!(engine.accounts[&2].available, dec!(100));
+        assert!(engine.transaction(2_050).is_some());
+    }
+}
@@ -0,0 +1,146 @@
+use rust_decimal::Decimal;
+
+use crate::{
+    PaymentEngine, TxType, UserTransactions,
+    errors::{EngineError, ErrorCode},
+    money::Amount,
+    pipeline::RunSummary,
+};
+
+/// A batch of transactions laid out column by column, e.g. borrowed from
+/// the buffers of an Arrow record batch. Every column must have the same
+/// length.
+#[derive(Debug, Clone, Copy)]
+pub struct TransactionColumns<'a> {
+    pub types: &'a [TxType],
+    pub clients: &'a [u16],
+    pub txs: &'a [u32],
+    pub amounts: &'a [Option<Decimal>],
+    /// Optional; rows are untimestamped without it.
+    pub timestamps: Option<&'a [Option<u64>]>,
+}
+
+impl TransactionColumns<'_> {
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    fn check_lengths(&self) -> Result<(), String> {
+        let len = self.len();
+        let lengths = [
+            ("clients", self.clients.len()),
+            ("txs", self.txs.len()),
+            ("amounts", self.amounts.len()),
+            ("timestamps", self.timestamps.map_or(len, <[_]>::len)),
+        ];
+        match lengths.iter().find(|(_, l)| *l != len) {
+            Some((name, l)) => Err(format!(
+                "Column '{}' has {} rows, expected {}",
+                name, l, len
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Result of [`PaymentEngine::apply_columns`].
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct BatchReport {
+    pub summary: RunSummary,
+    /// Row index and reason for every row that wasn't applied.
+    pub failures: Vec<(usize, EngineError)>,
+}
+
+impl PaymentEngine {
+    /// Applies every row of `columns` in order. Only mismatched column
+    /// lengths fail the whole batch; bad or rejected rows are reported in
+    /// the returned [`BatchReport`].
+    pub fn apply_columns(&mut self, columns: &TransactionColumns) -> Result<BatchReport, String> {
+        columns.check_lengths()?;
+        let mut report = BatchReport::default();
+
+        for row in 0..columns.len() {
+            let amount = match columns.amounts[row].map(Amount::new).transpose() {
+                Ok(amount) => amount,
+                Err(e) => {
+                    report.summary.record_source_error();
+                    report
+                        .failures
+                        .push((row, EngineError::new(ErrorCode::InvalidAmount, e)));
+                    continue;
+                }
+            };
+            let outcome = self.process_action(UserTransactions {
+                tx_type: columns.types[row],
+                client_id: columns.clients[row],
+                tx_id: columns.txs[row],
+                amount,
+                timestamp: columns.timestamps.and_then(|ts| ts[row]),
+                ..Default::default()
+            });
+            report.summary.record_outcome(&outcome);
+            if let Err(e) = outcome {
+                report.failures.push((row, e));
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_applies_columns_and_reports_bad_rows() {
+        let mut engine = PaymentEngine::new();
+        let report = engine
+            .apply_columns(&TransactionColumns {
+                types: &[TxType::Deposit, TxType::Deposit, TxType::Withdrawal],
+                clients: &[1, 1, 1],
+                txs: &[1, 2, 3],
+                amounts: &[Some(dec!(10)), Some(dec!(-1)), Some(dec!(50))],
+                timestamps: None,
+            })
+            .unwrap();
+
+        assert_eq!(engine.accounts[&1].available, dec!(10));
+        assert_eq!(
+            report.summary,
+            RunSummary {
+                records_read: 3,
+                source_errors: 1,
+                applied: 1,
+                rejected: 1,
+            }
+        );
+        let rows: Vec<(usize, ErrorCode)> = report
+            .failures
+            .iter()
+            .map(|(row, e)| (*row, e.code()))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (1, ErrorCode::InvalidAmount),
+                (2, ErrorCode::InsufficientFunds)
+            ]
+        );
+
+        let err = engine
+            .apply_columns(&TransactionColumns {
+                types: &[TxType::Deposit],
+                clients: &[1, 2],
+                txs: &[4],
+                amounts: &[None],
+                timestamps: None,
+            })
+            .unwrap_err();
+        assert_eq!(err, "Column 'clients' has 2 rows, expected 1");
+    }
+}
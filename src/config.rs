@@ -0,0 +1,140 @@
+use std::str::FromStr;
+
+use rust_decimal::{Decimal, RoundingStrategy};
+
+use crate::{PaymentEngine, UserTransactions, money::Amount};
+
+/// Largest precision a [`Decimal`] can hold.
+pub const MAX_PRECISION: u32 = 28;
+
+/// How amounts are brought to the configured precision.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum RoundingMode {
+    /// Halves go to the even neighbour: `0.125` becomes `0.12`.
+    #[default]
+    Bankers,
+    /// Halves go away from zero: `0.125` becomes `0.13`.
+    HalfUp,
+}
+
+impl FromStr for RoundingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bankers" => Ok(Self::Bankers),
+            "half-up" => Ok(Self::HalfUp),
+            other => Err(format!(
+                "Unknown rounding mode '{}', expected bankers or half-up",
+                other
+            )),
+        }
+    }
+}
+
+/// Decimal places the engine keeps and writes. Incoming amounts are rounded
+/// to `precision` before they touch a balance, and sinks write every amount
+/// with exactly `precision` places.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct EngineConfig {
+    pub precision: u32,
+    pub rounding: RoundingMode,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            precision: 4,
+            rounding: RoundingMode::default(),
+        }
+    }
+}
+
+impl EngineConfig {
+    pub fn new(precision: u32, rounding: RoundingMode) -> Result<Self, String> {
+        if precision > MAX_PRECISION {
+            return Err(format!(
+                "Precision {} is above the maximum of {}",
+                precision, MAX_PRECISION
+            ));
+        }
+        Ok(Self {
+            precision,
+            rounding,
+        })
+    }
+
+    pub fn round(&self, value: Decimal) -> Decimal {
+        let strategy = match self.rounding {
+            RoundingMode::Bankers => RoundingStrategy::MidpointNearestEven,
+            RoundingMode::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+        };
+        value.round_dp_with_strategy(self.precision, strategy)
+    }
+
+    /// `value` rounded and written with exactly `precision` places.
+    pub fn format(&self, value: Decimal) -> String {
+        format!("{:.*}", self.precision as usize, self.round(value))
+    }
+
+    fn round_amount(&self, amount: Amount) -> Amount {
+        // Rounding a valid amount keeps it non-negative and can only drop
+        // decimal places.
+        Amount::new(self.round(amount.value())).unwrap_or(amount)
+    }
+}
+
+impl PaymentEngine {
+    pub fn set_config(&mut self, config: EngineConfig) {
+        self.config = config;
+    }
+
+    pub fn config(&self) -> EngineConfig {
+        self.config
+    }
+
+    pub(crate) fn round_action(&self, mut action: UserTransactions) -> UserTransactions {
+        action.amount = action.amount.map(|amount| self.config.round_amount(amount));
+        action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TxType;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_rounding_modes_apply_to_balances_and_formatting() {
+        let bankers = EngineConfig::new(2, RoundingMode::Bankers).unwrap();
+        let half_up = EngineConfig::new(2, RoundingMode::HalfUp).unwrap();
+        assert_eq!(bankers.round(dec!(0.125)), dec!(0.12));
+        assert_eq!(half_up.round(dec!(0.125)), dec!(0.13));
+        assert_eq!(bankers.format(dec!(1)), "1.00");
+        assert_eq!(
+            EngineConfig::new(6, RoundingMode::Bankers)
+                .unwrap()
+                .format(dec!(1.5)),
+            "1.500000"
+        );
+        assert!(EngineConfig::new(29, RoundingMode::Bankers).is_err());
+
+        for (config, expected) in [(bankers, dec!(0.24)), (half_up, dec!(0.26))] {
+            let mut engine = PaymentEngine::new();
+            engine.set_config(config);
+            for tx_id in 1..=2 {
+                engine
+                    .process_action(UserTransactions {
+                        tx_type: TxType::Deposit,
+                        client_id: 1,
+                        tx_id,
+                        amount: Amount::new(dec!(0.125)).ok(),
+                        ..Default::default()
+                    })
+                    .unwrap();
+            }
+            assert_eq!(engine.accounts[&1].available, expected);
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use std::{io::Write, sync::Arc};
+
+use arrow_array::{
+    ArrayRef, BooleanArray, Decimal128Array, RecordBatch, StringArray, UInt16Array, UInt32Array,
+    UInt64Array,
+};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType, Field, Schema};
+use rust_decimal::Decimal;
+
+use crate::{
+    config::EngineConfig,
+    data_sinks::DataSink,
+    view::{AccountColumns, AccountField, ClientAccountView},
+};
+
+/// Amounts are written as `Decimal128(AMOUNT_PRECISION, precision)`, with
+/// the precision of the sink's [`EngineConfig`].
+const AMOUNT_PRECISION: u8 = 38;
+
+enum State<W: Write> {
+    Pending(W),
+    Started(StreamWriter<W>),
+    Finished,
+}
+
+/// Writes accounts as an Arrow IPC stream, one record batch per
+/// [`DataSink::write_accounts`] call, so Arrow readers (pyarrow, Spark) get
+/// typed columns without parsing CSV. The stream is closed on flush.
+pub struct ArrowIpcSink<W: Write> {
+    state: State<W>,
+    columns: AccountColumns,
+    config: EngineConfig,
+    schema: Arc<Schema>,
+}
+
+impl<W: Write> ArrowIpcSink<W> {
+    pub fn new(writer: W, columns: AccountColumns) -> Self {
+        let config = EngineConfig::default();
+        Self {
+            state: State::Pending(writer),
+            schema: schema(&columns, config),
+            columns,
+            config,
+        }
+    }
+
+    /// Rounds amounts with `config` and writes them at its precision.
+    pub fn with_config(mut self, config: EngineConfig) -> Self {
+        self.schema = schema(&self.columns, config);
+        self.config = config;
+        self
+    }
+}
+
+fn schema(columns: &AccountColumns, config: EngineConfig) -> Arc<Schema> {
+    let fields: Vec<Field> = columns
+        .0
+        .iter()
+        .map(|field| {
+            Field::new(
+                field.as_str(),
+                data_type(*field, config.precision),
+                nullable(*field),
+            )
+        })
+        .collect();
+    Arc::new(Schema::new(fields))
+}
+
+fn data_type(field: AccountField, scale: u32) -> DataType {
+    match field {
+        AccountField::Client => DataType::UInt16,
+        AccountField::Available
+        | AccountField::Held
+        | AccountField::Total
+        | AccountField::Debt
+        | AccountField::Reserved => DataType::Decimal128(AMOUNT_PRECISION, scale as i8),
+        AccountField::Locked
+        | AccountField::Dormant
+        | AccountField::Closed
+        | AccountField::ChangedThisRun => DataType::Boolean,
+        AccountField::Currency | AccountField::Tier => DataType::Utf8,
+        AccountField::OpenDisputes | AccountField::LifetimeChargebacks => DataType::UInt32,
+        AccountField::LastActivitySeq => DataType::UInt64,
+    }
+}
+
+fn nullable(field: AccountField) -> bool {
+    matches!(
+        field,
+        AccountField::Currency | AccountField::Tier | AccountField::LastActivitySeq
+    )
+}
+
+fn scaled(amount: Decimal, config: EngineConfig) -> i128 {
+    let mut amount = config.round(amount);
+    amount.rescale(config.precision);
+    amount.mantissa()
+}
+
+fn column(
+    accounts: &[ClientAccountView],
+    field: AccountField,
+    config: EngineConfig,
+) -> Result<ArrayRef, String> {
+    let amounts = |amount: fn(&ClientAccountView) -> Decimal| -> Result<ArrayRef, String> {
+        let array =
+            Decimal128Array::from_iter_values(accounts.iter().map(|a| scaled(amount(a), config)))
+                .with_precision_and_scale(AMOUNT_PRECISION, config.precision as i8)
+                .map_err(|e| format!("Failed to build {} column: {}", field.as_str(), e))?;
+        Ok(Arc::new(array))
+    };
+    Ok(match field {
+        AccountField::Client => Arc::new(UInt16Array::from_iter_values(
+            accounts.iter().map(|a| a.client_id),
+        )),
+        AccountField::Available => amounts(|a| a.available)?,
+        AccountField::Held => amounts(|a| a.held)?,
+        AccountField::Total => amounts(|a| a.total)?,
+        AccountField::Debt => amounts(|a| a.debt)?,
+        AccountField::Reserved => amounts(|a| a.reserved)?,
+        AccountField::Locked => Arc::new(BooleanArray::from_iter(
+            accounts.iter().map(|a| Some(a.locked)),
+        )),
+        AccountField::Dormant => Arc::new(BooleanArray::from_iter(
+            accounts.iter().map(|a| Some(a.dormant)),
+        )),
+        AccountField::Closed => Arc::new(BooleanArray::from_iter(
+            accounts.iter().map(|a| Some(a.closed)),
+        )),
+        AccountField::ChangedThisRun => Arc::new(BooleanArray::from_iter(
+            accounts.iter().map(|a| Some(a.changed_this_run)),
+        )),
+        AccountField::Currency => Arc::new(StringArray::from_iter(
+            accounts.iter().map(|a| a.currency.as_deref()),
+        )),
+        AccountField::Tier => Arc::new(StringArray::from_iter(
+            accounts.iter().map(|a| a.tier.as_deref()),
+        )),
+        AccountField::OpenDisputes => Arc::new(UInt32Array::from_iter_values(
+            accounts.iter().map(|a| a.open_disputes),
+        )),
+        AccountField::LifetimeChargebacks => Arc::new(UInt32Array::from_iter_values(
+            accounts.iter().map(|a| a.lifetime_chargebacks),
+        )),
+        AccountField::LastActivitySeq => Arc::new(UInt64Array::from_iter(
+            accounts.iter().map(|a| a.last_activity_seq),
+        )),
+    })
+}
+
+impl<W: Write> DataSink for ArrowIpcSink<W> {
+    fn write_accounts(&mut self, accounts: &[ClientAccountView]) -> Result<(), String> {
+        let columns = self
+            .columns
+            .0
+            .iter()
+            .map(|field| column(accounts, *field, self.config))
+            .collect::<Result<Vec<_>, _>>()?;
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)
+            .map_err(|e| format!("Failed to build record batch: {}", e))?;
+
+        if let State::Pending(_) = self.state {
+            let State::Pending(writer) = std::mem::replace(&mut self.state, State::Finished) else {
+                unreachable!()
+            };
+            let writer = StreamWriter::try_new(writer, &self.schema)
+                .map_err(|e| format!("Failed to start Arrow stream: {}", e))?;
+            self.state = State::Started(writer);
+        }
+        match &mut self.state {
+            State::Started(writer) => writer
+                .write(&batch)
+                .map_err(|e| format!("Failed to write record batch: {}", e)),
+            _ => Err("Arrow stream is already closed".to_string()),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        match &mut self.state {
+            State::Started(writer) => writer
+                .finish()
+                .map_err(|e| format!("Failed to finish Arrow stream: {}", e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::Array;
+    use arrow_ipc::reader::StreamReader;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_writes_typed_arrow_stream() {
+        let accounts = [ClientAccountView {
+            client_id: 7,
+            available: dec!(1.5),
+            held: dec!(0.25),
+            total: dec!(1.75),
+            currency: Some("EUR".to_string()),
+            ..Default::default()
+        }];
+        let columns: AccountColumns = "client,available,total,locked,currency,last_activity_seq"
+            .parse()
+            .unwrap();
+        let mut out = Vec::new();
+        let mut sink = ArrowIpcSink::new(&mut out, columns);
+        sink.write_accounts(&accounts).unwrap();
+        sink.flush().unwrap();
+        drop(sink);
+
+        let batches: Vec<RecordBatch> = StreamReader::try_new(out.as_slice(), None)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 1);
+        assert_eq!(batch.schema().field(1).name(), "available");
+        let available = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Decimal128Array>()
+            .unwrap();
+        assert_eq!(available.value_as_string(0), "1.5000");
+        assert!(batch.column(5).is_null(0));
+
+        let mut out = Vec::new();
+        let config = EngineConfig::new(1, crate::config::RoundingMode::HalfUp).unwrap();
+        let mut sink = ArrowIpcSink::new(&mut out, "held".parse().unwrap()).with_config(config);
+        sink.write_accounts(&accounts).unwrap();
+        sink.flush().unwrap();
+        drop(sink);
+        let batch = StreamReader::try_new(out.as_slice(), None)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        let held = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Decimal128Array>()
+            .unwrap();
+        assert_eq!(held.value_as_string(0), "0.3");
+    }
+}
@@ -0,0 +1,77 @@
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    config::EngineConfig,
+    data_sinks::{
+        AsyncDataSink, BoxFuture, DataSink,
+        csv::{CsvDataSink, OutputStyle},
+    },
+    view::{AccountColumns, ClientAccountView},
+};
+
+/// Writes the same CSV as [`CsvDataSink`] to an async writer. Each call
+/// renders its rows in memory and then writes them out in one go.
+pub struct AsyncCsvSink<W> {
+    writer: W,
+    style: OutputStyle,
+    columns: Option<AccountColumns>,
+    config: EngineConfig,
+}
+
+impl<W: AsyncWrite + Unpin + Send> AsyncCsvSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            style: OutputStyle::default(),
+            columns: None,
+            config: EngineConfig::default(),
+        }
+    }
+
+    pub fn with_style(mut self, style: OutputStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn with_columns(mut self, columns: AccountColumns) -> Self {
+        self.columns = Some(columns);
+        self
+    }
+
+    pub fn with_config(mut self, config: EngineConfig) -> Self {
+        self.config = config;
+        self
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send> AsyncDataSink for AsyncCsvSink<W> {
+    fn write_accounts<'a>(
+        &'a mut self,
+        accounts: &'a [ClientAccountView],
+    ) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let mut rendered = Vec::new();
+            let mut sink =
+                CsvDataSink::with_style(&mut rendered, self.style).with_config(self.config);
+            if let Some(columns) = &self.columns {
+                sink = sink.with_columns(columns.clone());
+            }
+            sink.write_accounts(accounts)?;
+            sink.flush()?;
+            drop(sink);
+            self.writer
+                .write_all(&rendered)
+                .await
+                .map_err(|e| format!("Failed to write accounts: {}", e))
+        })
+    }
+
+    fn flush(&mut self) -> BoxFuture<'_, Result<(), String>> {
+        Box::pin(async move {
+            self.writer
+                .flush()
+                .await
+                .map_err(|e| format!("Failed to flush writer: {}", e))
+        })
+    }
+}
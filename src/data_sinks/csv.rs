@@ -1,26 +1,174 @@
-use std::io::Write;
+use std::{io::Write, str::FromStr};
 
-use crate::{UserAccount, data_sinks::DataSink};
+use crate::{
+    config::EngineConfig,
+    data_sinks::DataSink,
+    view::{AccountColumns, AccountField, ClientAccountView},
+};
+
+/// Controls how account rows are laid out, for downstreams that disagree
+/// about the shape of the output: header names, default column order and
+/// amount formatting. No style writes amounts in scientific notation, and
+/// every one reads back with [`crate::data_sources::csv::read_accounts`].
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum OutputStyle {
+    /// `client,available,held,total,locked` with fixed decimal places, four
+    /// unless configured.
+    #[default]
+    Spec,
+    /// The pre-spec layout, `client_id,total,held,available,locked`, with
+    /// amounts dropping trailing zeros (`1.5`, `0`).
+    Legacy,
+    /// Spreadsheet-friendly `"Client","Locked","Available","Held","Total"`:
+    /// quoted title-case headers and amounts written as quoted strings
+    /// (`"1.5000"`).
+    Quoted,
+}
+
+impl OutputStyle {
+    /// Column order used when no `--columns` are given.
+    pub fn columns(self) -> AccountColumns {
+        use AccountField::*;
+        match self {
+            Self::Spec => AccountColumns::default(),
+            Self::Legacy => AccountColumns(vec![Client, Total, Held, Available, Locked]),
+            Self::Quoted => AccountColumns(vec![Client, Locked, Available, Held, Total]),
+        }
+    }
+
+    /// Name this style gives `field` in the header row.
+    pub fn header(self, field: AccountField) -> String {
+        match (self, field) {
+            (Self::Spec, field) => field.as_str().to_string(),
+            (Self::Legacy, AccountField::Client) => "client_id".to_string(),
+            (Self::Legacy, field) => field.as_str().to_string(),
+            (Self::Quoted, field) => {
+                let words: Vec<String> = field
+                    .as_str()
+                    .split('_')
+                    .map(|word| {
+                        let mut chars = word.chars();
+                        chars
+                            .next()
+                            .map(|first| first.to_uppercase().chain(chars).collect())
+                            .unwrap_or_default()
+                    })
+                    .collect();
+                format!("\"{}\"", words.join(" "))
+            }
+        }
+    }
+}
+
+impl FromStr for OutputStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "spec" => Ok(Self::Spec),
+            "legacy" => Ok(Self::Legacy),
+            "quoted" => Ok(Self::Quoted),
+            other => Err(format!(
+                "Unknown output style '{}', expected spec, legacy or quoted",
+                other
+            )),
+        }
+    }
+}
 
 pub struct CsvDataSink<W: Write> {
     writer: csv::Writer<W>,
+    style: OutputStyle,
+    columns: Option<AccountColumns>,
+    config: EngineConfig,
 }
 
 impl<W: Write> CsvDataSink<W> {
     pub fn new(writer: W) -> Self {
+        Self::with_style(writer, OutputStyle::default())
+    }
+
+    pub fn with_style(writer: W, style: OutputStyle) -> Self {
         Self {
-            writer: csv::Writer::from_writer(writer),
+            writer: csv::WriterBuilder::new()
+                .quote_style(csv::QuoteStyle::Never)
+                .from_writer(writer),
+            style,
+            columns: None,
+            config: EngineConfig::default(),
+        }
+    }
+
+    /// Writes `columns` in the given order instead of the style's own,
+    /// still under the style's header names.
+    pub fn with_columns(mut self, columns: AccountColumns) -> Self {
+        self.columns = Some(columns);
+        self
+    }
+
+    /// Rounds amounts with `config` and writes them at its precision.
+    pub fn with_config(mut self, config: EngineConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    fn format_amount(&self, amount: &rust_decimal::Decimal) -> String {
+        match self.style {
+            OutputStyle::Spec => self.config.format(*amount),
+            OutputStyle::Legacy => self.config.round(*amount).normalize().to_string(),
+            OutputStyle::Quoted => format!("\"{}\"", self.config.format(*amount)),
+        }
+    }
+
+    fn format_field(&self, account: &ClientAccountView, field: AccountField) -> String {
+        match field {
+            AccountField::Client => account.client_id.to_string(),
+            AccountField::Available => self.format_amount(&account.available),
+            AccountField::Held => self.format_amount(&account.held),
+            AccountField::Total => self.format_amount(&account.total),
+            AccountField::Locked => account.locked.to_string(),
+            AccountField::Currency => account.currency.clone().unwrap_or_default(),
+            AccountField::Tier => account.tier.clone().unwrap_or_default(),
+            AccountField::OpenDisputes => account.open_disputes.to_string(),
+            AccountField::LifetimeChargebacks => account.lifetime_chargebacks.to_string(),
+            AccountField::Debt => self.format_amount(&account.debt),
+            AccountField::Reserved => self.format_amount(&account.reserved),
+            AccountField::Dormant => account.dormant.to_string(),
+            AccountField::Closed => account.closed.to_string(),
+            AccountField::ChangedThisRun => account.changed_this_run.to_string(),
+            AccountField::LastActivitySeq => account
+                .last_activity_seq
+                .map(|seq| seq.to_string())
+                .unwrap_or_default(),
         }
     }
 }
 
 impl<W: Write> DataSink for CsvDataSink<W> {
-    fn write_accounts(&mut self, accounts: Vec<&UserAccount>) -> Result<(), String> {
+    fn write_accounts(&mut self, accounts: &[ClientAccountView]) -> Result<(), String> {
+        let columns = self.columns.clone().unwrap_or_else(|| self.style.columns());
+        let header: Vec<String> = columns
+            .0
+            .iter()
+            .map(|field| self.style.header(*field))
+            .collect();
+        self.writer
+            .write_record(&header)
+            .map_err(|e| format!("Failed to write header: {}", e))?;
         for account in accounts {
+            let row: Vec<String> = columns
+                .0
+                .iter()
+                .map(|field| self.format_field(account, *field))
+                .collect();
             self.writer
-                .serialize(account)
+                .write_record(&row)
                 .map_err(|e| format!("Failed to serialize account: {}", e))?;
         }
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
         self.writer
             .flush()
             .map_err(|e| format!("Failed to flush writer: {}", e))
@@ -0,0 +1,123 @@
+use std::collections::HashSet;
+
+use rust_decimal::Decimal;
+
+use crate::{PaymentEngine, UserAccount};
+
+/// Narrows which accounts are written out. Every condition that is set must
+/// hold; the default filter keeps everything.
+#[derive(Debug, Default, Clone)]
+pub struct AccountFilter {
+    pub locked_only: bool,
+    /// Drop accounts whose available, held and total are all zero.
+    pub non_zero_only: bool,
+    pub clients: Option<HashSet<u16>>,
+    /// Keep only accounts a transaction was applied to during this run.
+    pub touched_only: bool,
+}
+
+impl AccountFilter {
+    pub fn matches(&self, engine: &PaymentEngine, account: &UserAccount) -> bool {
+        (!self.locked_only || account.locked)
+            && (!self.non_zero_only
+                || [account.available, account.held, account.total]
+                    .iter()
+                    .any(|amount| *amount != Decimal::ZERO))
+            && self
+                .clients
+                .as_ref()
+                .is_none_or(|clients| clients.contains(&account.client_id))
+            && (!self.touched_only || engine.was_touched(account.client_id))
+    }
+
+    /// Accounts of `engine` that pass the filter, ordered by client id so
+    /// the same state always produces the same output.
+    pub fn apply<'a>(&self, engine: &'a PaymentEngine) -> Vec<&'a UserAccount> {
+        let mut accounts: Vec<_> = engine
+            .accounts
+            .values()
+            .filter(|account| self.matches(engine, account))
+            .collect();
+        accounts.sort_unstable_by_key(|account| account.client_id);
+        accounts
+    }
+}
+
+/// Parses a comma-separated client list such as `1,2,7`.
+pub fn parse_client_list(raw: &str) -> Result<HashSet<u16>, String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            id.parse()
+                .map_err(|_| format!("Invalid client id '{}'", id))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Amount;
+    use crate::{TxType, UserTransactions};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_filters_combine() {
+        let mut engine = PaymentEngine::new();
+        let mut carried = UserAccount::new(1);
+        carried.available = dec!(3);
+        carried.locked = true;
+        engine
+            .load_opening_balances([carried, UserAccount::new(2)])
+            .unwrap();
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Deposit,
+                client_id: 3,
+                tx_id: 1,
+                amount: Some(Amount::new(dec!(5)).unwrap()),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let ids = |filter: AccountFilter| {
+            filter
+                .apply(&engine)
+                .iter()
+                .map(|a| a.client_id)
+                .collect::<Vec<u16>>()
+        };
+
+        assert_eq!(ids(AccountFilter::default()), vec![1, 2, 3]);
+        assert_eq!(
+            ids(AccountFilter {
+                non_zero_only: true,
+                ..Default::default()
+            }),
+            vec![1, 3]
+        );
+        assert_eq!(
+            ids(AccountFilter {
+                locked_only: true,
+                ..Default::default()
+            }),
+            vec![1]
+        );
+        assert_eq!(
+            ids(AccountFilter {
+                touched_only: true,
+                ..Default::default()
+            }),
+            vec![3]
+        );
+        assert_eq!(
+            ids(AccountFilter {
+                clients: Some(parse_client_list("2, 3").unwrap()),
+                non_zero_only: true,
+                ..Default::default()
+            }),
+            vec![3]
+        );
+    }
+}
@@ -0,0 +1,35 @@
+use crate::{EngineEvent, PaymentEngine, data_sinks::DataSink, view::ClientAccountView};
+
+/// Sink that keeps everything written to it, so callers can inspect the
+/// results without going through a file.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryDataSink {
+    /// Every account written, across all writes, in order.
+    pub accounts: Vec<ClientAccountView>,
+    pub events: Vec<EngineEvent>,
+}
+
+impl MemoryDataSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves the engine's pending events into [`Self::events`].
+    pub fn collect_events(&mut self, engine: &mut PaymentEngine) {
+        self.events.extend(engine.drain_events());
+    }
+
+    pub fn account(&self, client_id: u16) -> Option<&ClientAccountView> {
+        self.accounts
+            .iter()
+            .rev()
+            .find(|a| a.client_id == client_id)
+    }
+}
+
+impl DataSink for MemoryDataSink {
+    fn write_accounts(&mut self, accounts: &[ClientAccountView]) -> Result<(), String> {
+        self.accounts.extend_from_slice(accounts);
+        Ok(())
+    }
+}
@@ -1,7 +1,134 @@
+pub mod arrow;
+#[cfg(feature = "tokio")]
+pub mod async_csv;
 pub mod csv;
+pub mod filter;
+pub mod memory;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod pseudonymize;
+pub mod rejects;
+pub mod staged;
 
-use crate::UserAccount;
+use std::{io::Write, str::FromStr};
+
+use crate::{
+    UserTransactions,
+    config::EngineConfig,
+    data_sinks::{
+        arrow::ArrowIpcSink,
+        csv::{CsvDataSink, OutputStyle},
+        staged::StagedFile,
+    },
+    data_sources::SourceLocation,
+    errors::EngineError,
+    view::{AccountColumns, ClientAccountView},
+};
 
 pub trait DataSink {
-    fn write_accounts(&mut self, accounts: Vec<&UserAccount>) -> Result<(), String>;
+    fn write_accounts(&mut self, accounts: &[ClientAccountView]) -> Result<(), String>;
+
+    /// Pushes anything still buffered to its destination. Called once the
+    /// run's output is complete.
+    fn flush(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Secondary output receiving every transaction the engine refused, so
+/// operators can reconcile what was dropped.
+pub trait RejectSink {
+    fn write_rejection(
+        &mut self,
+        action: &UserTransactions,
+        error: &EngineError,
+        location: Option<&SourceLocation>,
+    ) -> Result<(), String>;
+
+    fn flush(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Future returned by [`AsyncDataSink`] methods.
+#[cfg(feature = "tokio")]
+pub type BoxFuture<'a, T> = std::pin::Pin<Box<dyn std::future::Future<Output = T> + Send + 'a>>;
+
+/// Async counterpart of [`DataSink`].
+#[cfg(feature = "tokio")]
+pub trait AsyncDataSink {
+    fn write_accounts<'a>(
+        &'a mut self,
+        accounts: &'a [ClientAccountView],
+    ) -> BoxFuture<'a, Result<(), String>>;
+
+    fn flush(&mut self) -> BoxFuture<'_, Result<(), String>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// File format of the accounts output.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    /// Arrow IPC stream; see [`ArrowIpcSink`].
+    Arrow,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "arrow" => Ok(Self::Arrow),
+            other => Err(format!(
+                "Unknown output format '{}', expected csv or arrow",
+                other
+            )),
+        }
+    }
+}
+
+/// Sink writing `format` to `writer`, with amounts at the precision of
+/// `config`. `style` only applies to CSV. Without
+/// `columns` CSV uses the style's own order and Arrow the spec's.
+pub fn sink_for<W: Write + 'static>(
+    writer: W,
+    format: OutputFormat,
+    style: OutputStyle,
+    columns: Option<&AccountColumns>,
+    config: EngineConfig,
+) -> Box<dyn DataSink> {
+    match format {
+        OutputFormat::Csv => {
+            let mut sink = CsvDataSink::with_style(writer, style).with_config(config);
+            if let Some(columns) = columns {
+                sink = sink.with_columns(columns.clone());
+            }
+            Box::new(sink)
+        }
+        OutputFormat::Arrow => Box::new(
+            ArrowIpcSink::new(writer, columns.cloned().unwrap_or_default()).with_config(config),
+        ),
+    }
+}
+
+/// Writes `accounts` to a temporary file next to `path` and renames it into
+/// place, so readers polling `path` never see a half-written file.
+pub fn write_accounts_atomic(
+    path: &str,
+    accounts: &[ClientAccountView],
+    format: OutputFormat,
+    style: OutputStyle,
+    columns: Option<&AccountColumns>,
+    config: EngineConfig,
+) -> Result<(), String> {
+    let (staged, file) = StagedFile::create(path)?;
+    let mut sink = sink_for(file, format, style, columns, config);
+    sink.write_accounts(accounts)?;
+    sink.flush()?;
+    drop(sink);
+    staged.commit()
 }
@@ -0,0 +1,184 @@
+use postgres::{Client, NoTls, types::ToSql};
+use rust_decimal::Decimal;
+
+use crate::{config::EngineConfig, data_sinks::DataSink, view::ClientAccountView};
+
+const DEFAULT_BATCH_SIZE: usize = 500;
+
+/// Upserts accounts into a PostgreSQL table keyed by client, so a run's
+/// results land straight in a reporting database. Rows are buffered until
+/// [`DataSink::flush`], which writes them in one transaction, `batch_size`
+/// rows per statement; a failed flush leaves the table as it was.
+///
+/// The table needs at least these columns:
+///
+/// ```sql
+/// client    integer PRIMARY KEY,
+/// available numeric NOT NULL,
+/// held      numeric NOT NULL,
+/// total     numeric NOT NULL,
+/// locked    boolean NOT NULL
+/// ```
+pub struct PostgresDataSink {
+    client: Client,
+    table: String,
+    batch_size: usize,
+    config: EngineConfig,
+    pending: Vec<ClientAccountView>,
+}
+
+impl PostgresDataSink {
+    /// Connects with a libpq-style connection string, without TLS.
+    pub fn connect(url: &str, table: &str) -> Result<Self, String> {
+        let client = Client::connect(url, NoTls)
+            .map_err(|e| format!("Failed to connect to PostgreSQL: {}", e))?;
+        Self::new(client, table)
+    }
+
+    /// `table` may be schema-qualified, as in `reporting.accounts`.
+    pub fn new(client: Client, table: &str) -> Result<Self, String> {
+        Ok(Self {
+            client,
+            table: quote_table(table)?,
+            batch_size: DEFAULT_BATCH_SIZE,
+            config: EngineConfig::default(),
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Rounds amounts with `config` before they're stored.
+    pub fn with_config(mut self, config: EngineConfig) -> Self {
+        self.config = config;
+        self
+    }
+}
+
+impl DataSink for PostgresDataSink {
+    fn write_accounts(&mut self, accounts: &[ClientAccountView]) -> Result<(), String> {
+        self.pending.extend_from_slice(accounts);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let error = |e: postgres::Error| format!("Failed to write accounts to PostgreSQL: {}", e);
+        let mut transaction = self.client.transaction().map_err(error)?;
+        for batch in self.pending.chunks(self.batch_size) {
+            let rows: Vec<Row> = batch
+                .iter()
+                .map(|account| Row::new(account, self.config))
+                .collect();
+            let params: Vec<&(dyn ToSql + Sync)> = rows.iter().flat_map(Row::params).collect();
+            transaction
+                .execute(&upsert_statement(&self.table, rows.len()), &params)
+                .map_err(error)?;
+        }
+        transaction.commit().map_err(error)?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+struct Row {
+    client: i32,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: bool,
+}
+
+impl Row {
+    fn new(account: &ClientAccountView, config: EngineConfig) -> Self {
+        Self {
+            client: i32::from(account.client_id),
+            available: config.round(account.available),
+            held: config.round(account.held),
+            total: config.round(account.total),
+            locked: account.locked,
+        }
+    }
+
+    fn params(&self) -> [&(dyn ToSql + Sync); 5] {
+        [
+            &self.client,
+            &self.available,
+            &self.held,
+            &self.total,
+            &self.locked,
+        ]
+    }
+}
+
+/// Quotes each part of a possibly schema-qualified table name. Names are
+/// limited to letters, digits and underscores, so none can break out of the
+/// quotes.
+fn quote_table(table: &str) -> Result<String, String> {
+    let parts: Vec<&str> = table.split('.').collect();
+    let valid = |part: &&str| {
+        part.chars().next().is_some_and(|c| !c.is_ascii_digit())
+            && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    if parts.len() > 2 || !parts.iter().all(valid) {
+        return Err(format!("Invalid PostgreSQL table name '{}'", table));
+    }
+    Ok(parts
+        .iter()
+        .map(|part| format!("\"{}\"", part))
+        .collect::<Vec<_>>()
+        .join("."))
+}
+
+fn upsert_statement(table: &str, rows: usize) -> String {
+    let values: Vec<String> = (0..rows)
+        .map(|row| {
+            let first = row * 5 + 1;
+            format!(
+                "(${}, ${}, ${}, ${}, ${})",
+                first,
+                first + 1,
+                first + 2,
+                first + 3,
+                first + 4
+            )
+        })
+        .collect();
+    format!(
+        "INSERT INTO {} (client, available, held, total, locked) VALUES {} \
+         ON CONFLICT (client) DO UPDATE SET available = EXCLUDED.available, \
+         held = EXCLUDED.held, total = EXCLUDED.total, locked = EXCLUDED.locked",
+        table,
+        values.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_names_and_upsert_statement() {
+        assert_eq!(quote_table("accounts").unwrap(), "\"accounts\"");
+        assert_eq!(
+            quote_table("reporting.accounts").unwrap(),
+            "\"reporting\".\"accounts\""
+        );
+        for invalid in ["", "a.b.c", "1accounts", "accounts; drop table x", "a\"b"] {
+            assert!(quote_table(invalid).is_err(), "{invalid}");
+        }
+
+        assert_eq!(
+            upsert_statement("\"accounts\"", 2),
+            "INSERT INTO \"accounts\" (client, available, held, total, locked) \
+             VALUES ($1, $2, $3, $4, $5), ($6, $7, $8, $9, $10) \
+             ON CONFLICT (client) DO UPDATE SET available = EXCLUDED.available, \
+             held = EXCLUDED.held, total = EXCLUDED.total, locked = EXCLUDED.locked"
+        );
+    }
+}
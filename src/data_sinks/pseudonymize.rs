@@ -0,0 +1,111 @@
+use hmac::{Hmac, Mac};
+use rust_decimal::Decimal;
+use sha2::Sha256;
+
+use crate::view::ClientAccountView;
+
+/// Environment variable holding the key client ids are pseudonymized with.
+pub const PSEUDONYM_KEY_ENV: &str = "PAYMENT_ENGINE_PSEUDONYM_KEY";
+
+const FEISTEL_ROUNDS: u8 = 4;
+
+/// Rewrites accounts so an output can be shared without real client ids.
+/// Ids go through a keyed permutation of the `u16` space, so every client
+/// keeps a distinct, stable pseudonym for a given key and the output keeps
+/// its schema. Without the key the mapping can't be reversed or rebuilt.
+#[derive(Clone)]
+pub struct Pseudonymizer {
+    mac: Hmac<Sha256>,
+    amount_bucket: Option<Decimal>,
+}
+
+impl Pseudonymizer {
+    pub fn new(key: &[u8]) -> Self {
+        Self {
+            mac: Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length"),
+            amount_bucket: None,
+        }
+    }
+
+    /// Also rounds every amount down to a multiple of `bucket`.
+    pub fn with_amount_bucket(mut self, bucket: Decimal) -> Self {
+        self.amount_bucket = Some(bucket);
+        self
+    }
+
+    /// Four-round Feistel network over the two bytes of `client_id`, with
+    /// HMAC-SHA256 as the round function.
+    pub fn client_id(&self, client_id: u16) -> u16 {
+        let [mut left, mut right] = client_id.to_be_bytes();
+        for round in 0..FEISTEL_ROUNDS {
+            let mut mac = self.mac.clone();
+            mac.update(&[round, right]);
+            let f = mac.finalize().into_bytes()[0];
+            (left, right) = (right, left ^ f);
+        }
+        u16::from_be_bytes([left, right])
+    }
+
+    pub fn amount(&self, amount: Decimal) -> Decimal {
+        match self.amount_bucket {
+            Some(bucket) if bucket > Decimal::ZERO => (amount / bucket).floor() * bucket,
+            _ => amount,
+        }
+    }
+
+    /// Pseudonymized copies of `accounts`, ordered by pseudonym so the row
+    /// order doesn't give the real ids away.
+    pub fn apply(&self, accounts: &[ClientAccountView]) -> Vec<ClientAccountView> {
+        let mut rewritten: Vec<ClientAccountView> = accounts
+            .iter()
+            .map(|account| ClientAccountView {
+                client_id: self.client_id(account.client_id),
+                available: self.amount(account.available),
+                held: self.amount(account.held),
+                total: self.amount(account.total),
+                debt: self.amount(account.debt),
+                reserved: self.amount(account.reserved),
+                ..account.clone()
+            })
+            .collect();
+        rewritten.sort_unstable_by_key(|account| account.client_id);
+        rewritten
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_pseudonyms_are_keyed_and_distinct() {
+        let pseudonymizer = Pseudonymizer::new(b"secret");
+        let ids: HashSet<u16> = (0..=u16::MAX)
+            .map(|id| pseudonymizer.client_id(id))
+            .collect();
+        assert_eq!(ids.len(), 1 << 16);
+        assert_eq!(
+            pseudonymizer.client_id(7),
+            Pseudonymizer::new(b"secret").client_id(7)
+        );
+        assert_ne!(
+            pseudonymizer.client_id(7),
+            Pseudonymizer::new(b"other").client_id(7)
+        );
+
+        let bucketed = pseudonymizer.with_amount_bucket(dec!(10));
+        let accounts = bucketed.apply(&[ClientAccountView {
+            client_id: 7,
+            available: dec!(123.45),
+            held: dec!(-3),
+            total: dec!(120.45),
+            ..Default::default()
+        }]);
+        assert_eq!(accounts[0].client_id, bucketed.client_id(7));
+        assert_eq!(accounts[0].available, dec!(120));
+        assert_eq!(accounts[0].held, dec!(-10));
+        assert_eq!(accounts[0].total, dec!(120));
+    }
+}
@@ -0,0 +1,113 @@
+use std::io::Write;
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{
+    TxType, UserTransactions, data_sinks::RejectSink, data_sources::SourceLocation,
+    errors::EngineError, money::Amount,
+};
+
+#[derive(Serialize)]
+struct RejectRow<'a> {
+    #[serde(rename = "type")]
+    tx_type: TxType,
+    client: u16,
+    tx: u32,
+    amount: Option<Decimal>,
+    code: &'static str,
+    name: &'static str,
+    reason: &'a str,
+    location: Option<String>,
+}
+
+/// Writes each rejection as a CSV row: the transaction, the error code and
+/// its name, the message, and where the record was in the input.
+pub struct CsvRejectSink<W: Write> {
+    writer: csv::Writer<W>,
+}
+
+impl<W: Write> CsvRejectSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: csv::Writer::from_writer(writer),
+        }
+    }
+
+    pub fn into_inner(self) -> Result<W, String> {
+        self.writer
+            .into_inner()
+            .map_err(|e| format!("Failed to flush rejections: {}", e.error()))
+    }
+}
+
+impl<W: Write> RejectSink for CsvRejectSink<W> {
+    fn write_rejection(
+        &mut self,
+        action: &UserTransactions,
+        error: &EngineError,
+        location: Option<&SourceLocation>,
+    ) -> Result<(), String> {
+        self.writer
+            .serialize(RejectRow {
+                tx_type: action.tx_type,
+                client: action.client_id,
+                tx: action.tx_id,
+                amount: action.amount.map(Amount::value),
+                code: error.code().code(),
+                name: error.code().name(),
+                reason: error.message(),
+                location: location.map(ToString::to_string),
+            })
+            .map_err(|e| format!("Failed to write rejection: {}", e))
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        self.writer
+            .flush()
+            .map_err(|e| format!("Failed to flush rejections: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PaymentEngine, errors::ErrorCode};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_rejections_are_written_with_codes() {
+        let mut engine = PaymentEngine::new();
+        let mut sink = CsvRejectSink::new(Vec::new());
+        let withdrawal = UserTransactions {
+            tx_type: TxType::Withdrawal,
+            client_id: 2,
+            tx_id: 7,
+            amount: Some(Amount::new(dec!(1.5)).unwrap()),
+            ..Default::default()
+        };
+        let error = engine.process_action(withdrawal.clone()).unwrap_err();
+        assert_eq!(error.code(), ErrorCode::NoAccount);
+        let location = SourceLocation {
+            file: Some("tx.csv".into()),
+            line: 3,
+            byte: 40,
+        };
+        sink.write_rejection(&withdrawal, &error, Some(&location))
+            .unwrap();
+        let dispute = UserTransactions {
+            tx_type: TxType::Dispute,
+            amount: None,
+            ..withdrawal
+        };
+        let error = engine.process_action(dispute.clone()).unwrap_err();
+        sink.write_rejection(&dispute, &error, None).unwrap();
+
+        let text = String::from_utf8(sink.into_inner().unwrap()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "type,client,tx,amount,code,name,reason,location");
+        assert!(lines[1].starts_with("withdrawal,2,7,1.5,PE1004,NoAccount,"));
+        assert!(lines[1].ends_with(",tx.csv:3 (byte 40)"));
+        assert!(lines[2].starts_with("dispute,2,7,,PE2001,TransactionNotFound,"));
+    }
+}
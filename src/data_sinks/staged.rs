@@ -0,0 +1,137 @@
+use std::{fs::File, path::Path};
+
+use crate::{data_sinks::DataSink, view::ClientAccountView};
+
+/// Output written under a temporary name next to its destination and
+/// renamed into place by [`Self::commit`], so a run that dies halfway leaves
+/// a stray `.tmp` file rather than a truncated output downstream jobs would
+/// read as complete.
+pub struct StagedFile {
+    path: String,
+    tmp: String,
+    file: File,
+    committed: bool,
+}
+
+impl StagedFile {
+    /// Creates the temporary file and returns it with a handle to write the
+    /// output through.
+    pub fn create(path: &str) -> Result<(Self, File), String> {
+        let tmp = format!("{}.tmp", path);
+        let file = File::create(&tmp).map_err(|e| format!("Failed to create '{}': {}", tmp, e))?;
+        let handle = file
+            .try_clone()
+            .map_err(|e| format!("Failed to create '{}': {}", tmp, e))?;
+        let staged = Self {
+            path: path.to_string(),
+            tmp,
+            file,
+            committed: false,
+        };
+        Ok((staged, handle))
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Syncs the file to disk and renames it to its destination. Anything
+    /// buffered in front of the handle must be flushed first.
+    pub fn commit(mut self) -> Result<(), String> {
+        self.file
+            .sync_all()
+            .map_err(|e| format!("Failed to sync '{}': {}", self.tmp, e))?;
+        std::fs::rename(&self.tmp, &self.path)
+            .map_err(|e| format!("Failed to rename '{}': {}", self.tmp, e))?;
+        self.committed = true;
+        // Makes the rename itself durable; not every platform can open a
+        // directory for this, so it's best effort.
+        let dir = Path::new(&self.path)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for StagedFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+/// Runs `write` on a staged file for `path` and commits it if `write`
+/// succeeds.
+pub fn write_staged<T, E: From<String>>(
+    path: &str,
+    write: impl FnOnce(File) -> Result<T, E>,
+) -> Result<T, E> {
+    let (staged, file) = StagedFile::create(path)?;
+    let written = write(file)?;
+    staged.commit()?;
+    Ok(written)
+}
+
+/// [`DataSink`] whose output only appears at its path once it's flushed.
+pub struct StagedSink {
+    inner: Box<dyn DataSink>,
+    staged: Option<StagedFile>,
+}
+
+impl StagedSink {
+    pub fn new(inner: Box<dyn DataSink>, staged: StagedFile) -> Self {
+        Self {
+            inner,
+            staged: Some(staged),
+        }
+    }
+}
+
+impl DataSink for StagedSink {
+    fn write_accounts(&mut self, accounts: &[ClientAccountView]) -> Result<(), String> {
+        self.inner.write_accounts(accounts)
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        self.inner.flush()?;
+        match self.staged.take() {
+            Some(staged) => staged.commit(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_output_appears_only_on_commit() {
+        let dir = std::env::temp_dir().join(format!("staged-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("accounts.csv").to_string_lossy().into_owned();
+        std::fs::write(&path, "previous run\n").unwrap();
+
+        let (staged, mut file) = StagedFile::create(&path).unwrap();
+        file.write_all(b"client\n1\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "previous run\n");
+        staged.commit().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "client\n1\n");
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
+
+        let failed: Result<(), String> = write_staged(&path, |mut file| {
+            file.write_all(b"client\n").unwrap();
+            Err("source failed".to_string())
+        });
+        assert!(failed.is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "client\n1\n");
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
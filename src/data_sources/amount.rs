@@ -0,0 +1,98 @@
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+
+/// How the `amount` column is interpreted by the sources.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum AmountFormat {
+    /// Only plain decimals like `1234.56` are accepted.
+    #[default]
+    Strict,
+    /// Also accepts thousands separators (`1,234.56`, `1.234,56`) and
+    /// scientific notation (`1e3`).
+    Tolerant,
+}
+
+impl FromStr for AmountFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(Self::Strict),
+            "tolerant" => Ok(Self::Tolerant),
+            other => Err(format!(
+                "Unknown amount format '{}', expected strict or tolerant",
+                other
+            )),
+        }
+    }
+}
+
+pub fn parse_amount(raw: &str, format: AmountFormat) -> Result<Decimal, String> {
+    let raw = raw.trim();
+    if let Ok(amount) = Decimal::from_str(raw) {
+        return Ok(amount);
+    }
+    if format == AmountFormat::Strict {
+        return Err(format!("Invalid amount '{}'", raw));
+    }
+
+    if raw.contains(['e', 'E']) {
+        return Decimal::from_scientific(raw)
+            .map_err(|e| format!("Invalid amount '{}': {}", raw, e));
+    }
+
+    let normalized = match (raw.rfind(','), raw.rfind('.')) {
+        // Whichever separator comes last is the decimal mark.
+        (Some(comma), Some(dot)) if comma > dot => raw.replace('.', "").replace(',', "."),
+        (Some(_), Some(_)) => raw.replace(',', ""),
+        (Some(comma), None) => {
+            let single = raw.matches(',').count() == 1;
+            if single && raw.len() - comma - 1 != 3 {
+                raw.replace(',', ".")
+            } else {
+                raw.replace(',', "")
+            }
+        }
+        (None, Some(_)) => raw.replace('.', ""),
+        (None, None) => raw.to_string(),
+    };
+
+    Decimal::from_str(&normalized).map_err(|e| format!("Invalid amount '{}': {}", raw, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_strict_accepts_plain_decimals_only() {
+        assert_eq!(
+            parse_amount("1234.56", AmountFormat::Strict),
+            Ok(dec!(1234.56))
+        );
+        assert!(parse_amount("1,234.56", AmountFormat::Strict).is_err());
+        assert!(parse_amount("1e3", AmountFormat::Strict).is_err());
+    }
+
+    #[test]
+    fn test_tolerant_normalizes_separators() {
+        let tolerant = AmountFormat::Tolerant;
+        assert_eq!(parse_amount("1,234.56", tolerant), Ok(dec!(1234.56)));
+        assert_eq!(parse_amount("1.234,56", tolerant), Ok(dec!(1234.56)));
+        assert_eq!(parse_amount("1.234.567", tolerant), Ok(dec!(1234567)));
+        assert_eq!(parse_amount("1,234", tolerant), Ok(dec!(1234)));
+        assert_eq!(parse_amount("12,5", tolerant), Ok(dec!(12.5)));
+    }
+
+    #[test]
+    fn test_tolerant_parses_scientific_notation() {
+        assert_eq!(parse_amount("1e3", AmountFormat::Tolerant), Ok(dec!(1000)));
+        assert_eq!(
+            parse_amount("2.5E-2", AmountFormat::Tolerant),
+            Ok(dec!(0.025))
+        );
+        assert!(parse_amount("abc", AmountFormat::Tolerant).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use futures_core::Stream;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, Lines};
+
+use crate::data_sources::{
+    AsyncDataSource, SourceRecord, amount::AmountFormat, client_map::ClientIdMap, csv::parse_row,
+};
+
+/// Reads transactions CSV from an async reader, such as a socket. Every
+/// record must fit on one line; quoted fields can't span lines.
+pub struct AsyncCsvSource<R> {
+    lines: Option<Lines<R>>,
+    amount_format: AmountFormat,
+    client_map: Option<ClientIdMap>,
+}
+
+impl<R: AsyncBufRead + Unpin + Send> AsyncCsvSource<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: Some(reader.lines()),
+            amount_format: AmountFormat::default(),
+            client_map: None,
+        }
+    }
+
+    pub fn with_amount_format(mut self, format: AmountFormat) -> Self {
+        self.amount_format = format;
+        self
+    }
+
+    pub fn with_client_map(mut self, client_map: ClientIdMap) -> Self {
+        self.client_map = Some(client_map);
+        self
+    }
+}
+
+struct RecordStream<'a, R> {
+    lines: &'a mut Option<Lines<R>>,
+    headers: Option<csv::StringRecord>,
+    amount_format: AmountFormat,
+    client_map: Option<&'a ClientIdMap>,
+}
+
+fn split_line(line: &str) -> Result<csv::StringRecord, String> {
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .from_reader(line.as_bytes())
+        .records()
+        .next()
+        .unwrap_or_else(|| Ok(csv::StringRecord::new()))
+        .map_err(|e| e.to_string())
+}
+
+impl<R: AsyncBufRead + Unpin> Stream for RecordStream<'_, R> {
+    type Item = SourceRecord;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<SourceRecord>> {
+        let this = &mut *self;
+        loop {
+            let Some(lines) = this.lines.as_mut() else {
+                return Poll::Ready(None);
+            };
+            let line = match ready!(Pin::new(lines).poll_next_line(cx)) {
+                Ok(Some(line)) => line,
+                Ok(None) => {
+                    *this.lines = None;
+                    return Poll::Ready(None);
+                }
+                // The reader can't be trusted after an I/O error.
+                Err(e) => {
+                    *this.lines = None;
+                    return Poll::Ready(Some(Err(format!("Failed to read input: {}", e).into())));
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let row = match split_line(&line) {
+                Ok(row) => row,
+                Err(e) => return Poll::Ready(Some(Err(e.into()))),
+            };
+            match &this.headers {
+                None => this.headers = Some(row),
+                Some(headers) => {
+                    return Poll::Ready(Some(parse_row(
+                        &row,
+                        headers,
+                        this.amount_format,
+                        this.client_map,
+                    )));
+                }
+            }
+        }
+    }
+}
+
+impl<R: AsyncBufRead + Unpin + Send> AsyncDataSource for AsyncCsvSource<R> {
+    fn read_transactions(&mut self) -> Pin<Box<dyn Stream<Item = SourceRecord> + Send + '_>> {
+        Box::pin(RecordStream {
+            lines: &mut self.lines,
+            headers: None,
+            amount_format: self.amount_format,
+            client_map: self.client_map.as_ref(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PaymentEngine, data_sinks::async_csv::AsyncCsvSink};
+
+    #[tokio::test]
+    async fn test_run_async_reads_and_writes_csv() {
+        let input: &[u8] = b"type, client, tx, amount\n\
+            deposit, 1, 1, 2.0\n\
+            \n\
+            withdrawal, 1, 2, 5.0\n\
+            deposit, x, 3, 1.0\n\
+            deposit, 2, 4, 1.5\n";
+        let mut source = AsyncCsvSource::new(input);
+        let mut out = Vec::new();
+        let mut sink = AsyncCsvSink::new(&mut out);
+
+        let summary = PaymentEngine::new()
+            .run_async(&mut source, &mut sink)
+            .await
+            .unwrap();
+        assert_eq!(summary.records_read, 4);
+        assert_eq!(summary.applied, 2);
+        assert_eq!(summary.rejected, 1);
+        assert_eq!(summary.source_errors, 1);
+        drop(sink);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,available,held,total,locked\n\
+             1,2.0000,0.0000,2.0000,false\n\
+             2,1.5000,0.0000,1.5000,false\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_async_applies_batches_all_or_nothing() {
+        let input = std::fs::read("test_batches.csv").unwrap();
+        let mut source = AsyncCsvSource::new(input.as_slice());
+        let mut out = Vec::new();
+        let mut sink = AsyncCsvSink::new(&mut out);
+
+        let summary = PaymentEngine::new()
+            .run_async(&mut source, &mut sink)
+            .await
+            .unwrap();
+        assert_eq!(summary.applied, 4);
+        assert_eq!(summary.rejected, 3);
+        drop(sink);
+        // Batch 8 overdraws client 1, so its deposit to client 2 is undone.
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,available,held,total,locked\n\
+             1,2.0000,0.0000,2.0000,false\n\
+             2,8.0000,0.0000,8.0000,false\n\
+             3,1.0000,0.0000,1.0000,false\n"
+        );
+    }
+}
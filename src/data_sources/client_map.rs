@@ -0,0 +1,44 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+struct ClientMapRecord {
+    external_id: String,
+    client: u16,
+}
+
+/// Maps partner-supplied client references to internal numeric client ids.
+#[derive(Debug, Default, Clone)]
+pub struct ClientIdMap {
+    ids: HashMap<String, u16>,
+}
+
+impl ClientIdMap {
+    /// Loads an `external_id,client` CSV.
+    pub fn from_path(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path(Path::new(path))?;
+        let mut map = Self::default();
+        for result in rdr.deserialize::<ClientMapRecord>() {
+            let record = result?;
+            map.insert(record.external_id, record.client);
+        }
+        Ok(map)
+    }
+
+    pub fn insert(&mut self, external_id: String, client_id: u16) {
+        self.ids.insert(external_id, client_id);
+    }
+
+    /// Resolves a raw `client` value. Mapped ids win; anything else must
+    /// already be a numeric client id.
+    pub fn resolve(&self, raw: &str) -> Result<u16, String> {
+        if let Some(client_id) = self.ids.get(raw) {
+            return Ok(*client_id);
+        }
+        raw.parse()
+            .map_err(|_| format!("Unknown client reference '{}'", raw))
+    }
+}
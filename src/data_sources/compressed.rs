@@ -0,0 +1,71 @@
+use std::io::{self, BufRead, BufReader, Read};
+
+use flate2::read::MultiGzDecoder;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Decodes `reader` as gzip or zstd when it starts with that format's magic
+/// number, and passes it through otherwise. Sniffing the content rather
+/// than the file name also covers compressed data piped through stdin.
+pub fn decompress<R: Read + 'static>(reader: R) -> io::Result<Box<dyn Read>> {
+    let mut reader = BufReader::new(reader);
+    let head = reader.fill_buf()?;
+    if head.starts_with(&GZIP_MAGIC) {
+        // Concatenated gzip members, as `cat a.gz b.gz` produces, are one
+        // stream.
+        Ok(Box::new(MultiGzDecoder::new(reader)))
+    } else if head.starts_with(&ZSTD_MAGIC) {
+        Ok(Box::new(zstd::Decoder::with_buffer(reader)?))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_sources::{DataSource, csv::CsvDataSource};
+    use std::io::Write;
+
+    fn read_all(path: &str) -> Vec<String> {
+        CsvDataSource::new(path.to_string())
+            .read_transactions()
+            .unwrap()
+            .map(|record| format!("{:?}", record.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_reads_gzip_and_zstd_like_plain_csv() {
+        let plain = std::fs::read("test_comprehensive.csv").unwrap();
+        let expected = read_all("test_comprehensive.csv");
+        let dir = std::env::temp_dir();
+
+        let gz = dir.join(format!("compressed-{}.csv.gz", std::process::id()));
+        // Two members, each holding half the file.
+        let mut encoded = Vec::new();
+        for half in [&plain[..40], &plain[40..]] {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(half).unwrap();
+            encoded.extend(encoder.finish().unwrap());
+        }
+        std::fs::write(&gz, encoded).unwrap();
+        assert_eq!(read_all(gz.to_str().unwrap()), expected);
+
+        // No extension: the format is told from the content.
+        let zst = dir.join(format!("compressed-{}", std::process::id()));
+        std::fs::write(&zst, zstd::encode_all(&plain[..], 3).unwrap()).unwrap();
+        assert_eq!(read_all(zst.to_str().unwrap()), expected);
+
+        let mut empty = String::new();
+        decompress(io::empty())
+            .unwrap()
+            .read_to_string(&mut empty)
+            .unwrap();
+        assert!(empty.is_empty());
+        std::fs::remove_file(gz).unwrap();
+        std::fs::remove_file(zst).unwrap();
+    }
+}
@@ -1,36 +1,207 @@
-use std::path::Path;
+use std::{fs::File, io::Read, path::Path, sync::Arc};
 
-use crate::{UserTransactions, data_sources::DataSource};
+use serde::Deserialize;
+
+use crate::{
+    TxType, UserAccount, UserTransactions,
+    accounts::AccountAttributes,
+    data_sources::{
+        DataSource, LocatedRecord, SourceError, SourceLocation, SourceRecord,
+        amount::{AmountFormat, parse_amount},
+        client_map::ClientIdMap,
+        compressed::decompress,
+        validate::check_amount,
+    },
+    funds::FundsClass,
+    money::Amount,
+};
+
+/// Row as it appears in the file, before the amount is normalized.
+#[derive(Debug, Deserialize)]
+struct CsvRecord {
+    #[serde(rename = "type")]
+    tx_type: TxType,
+    client: String,
+    tx: u32,
+    amount: Option<String>,
+    #[serde(default)]
+    timestamp: Option<u64>,
+    // Only read for `deposit` rows.
+    #[serde(default)]
+    funds_class: Option<FundsClass>,
+    #[serde(default)]
+    batch_id: Option<u32>,
+    // Only read for `transfer` rows.
+    #[serde(default)]
+    to_client: Option<String>,
+    // Only read for `open_account` rows.
+    #[serde(default)]
+    currency: Option<String>,
+    #[serde(default)]
+    tier: Option<String>,
+    #[serde(default)]
+    kind: Option<String>,
+    #[serde(default)]
+    credit_limit: Option<String>,
+}
+
+impl CsvRecord {
+    fn into_transaction(
+        self,
+        format: AmountFormat,
+        client_map: Option<&ClientIdMap>,
+    ) -> SourceRecord {
+        let resolve = |client: &str| match client_map {
+            Some(map) => map.resolve(client),
+            None => client
+                .parse()
+                .map_err(|_| format!("Invalid client id '{}'", client)),
+        };
+        let client_id = resolve(&self.client)?;
+        let to_client_id = match (self.tx_type, self.to_client.as_deref().map(str::trim)) {
+            (TxType::Transfer, None | Some("")) => {
+                return Err(format!("Transfer {} has no destination client", self.tx).into());
+            }
+            (TxType::Transfer, Some(to)) => Some(resolve(to)?),
+            _ => None,
+        };
+        let amount = match self.amount.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(raw) => Some(parse_amount(raw, format)?),
+        };
+        check_amount(self.tx_type, self.tx, amount)?;
+        let amount = amount.map(Amount::new).transpose()?;
+        let attributes = match self.tx_type {
+            TxType::OpenAccount => Some(AccountAttributes {
+                currency: self.currency,
+                tier: self.tier,
+                kind: self.kind,
+                credit_limit: match self.credit_limit.as_deref().map(str::trim) {
+                    None | Some("") => None,
+                    Some(raw) => Some(parse_amount(raw, format)?),
+                },
+            }),
+            _ => None,
+        };
+        Ok(UserTransactions {
+            tx_type: self.tx_type,
+            client_id,
+            tx_id: self.tx,
+            amount,
+            timestamp: self.timestamp,
+            attributes,
+            funds_class: match self.tx_type {
+                TxType::Deposit => self.funds_class,
+                _ => None,
+            },
+            batch_id: self.batch_id,
+            to_client_id,
+        })
+    }
+}
+
+/// Turns one row of a transactions file into a transaction.
+pub(crate) fn parse_row(
+    row: &csv::StringRecord,
+    headers: &csv::StringRecord,
+    format: AmountFormat,
+    client_map: Option<&ClientIdMap>,
+) -> SourceRecord {
+    row.deserialize::<CsvRecord>(Some(headers))
+        .map_err(|e| SourceError::Parse(e.to_string()))
+        .and_then(|record| record.into_transaction(format, client_map))
+}
+
+/// Input path naming stdin rather than a file, for use in shell pipelines.
+pub const STDIN_PATH: &str = "-";
+
+/// Opens `path` for reading, or stdin if it's [`STDIN_PATH`], decoding
+/// gzip and zstd on the way.
+pub fn open_input(path: &str) -> std::io::Result<Box<dyn Read>> {
+    if path == STDIN_PATH {
+        decompress(std::io::stdin().lock())
+    } else {
+        decompress(File::open(path)?)
+    }
+}
 
 pub struct CsvDataSource {
     path: String,
+    amount_format: AmountFormat,
+    client_map: Option<ClientIdMap>,
 }
 
 impl CsvDataSource {
     pub fn new(path: String) -> Self {
-        Self { path }
+        Self {
+            path,
+            amount_format: AmountFormat::default(),
+            client_map: None,
+        }
+    }
+
+    pub fn with_amount_format(mut self, format: AmountFormat) -> Self {
+        self.amount_format = format;
+        self
+    }
+
+    pub fn with_client_map(mut self, client_map: ClientIdMap) -> Self {
+        self.client_map = Some(client_map);
+        self
     }
 }
 
 impl DataSource for CsvDataSource {
     fn read_transactions<'a>(
         &'a mut self,
-    ) -> Result<Box<dyn Iterator<Item = UserTransactions> + 'a>, Box<dyn std::error::Error>> {
-        let path = Path::new(&self.path);
-        let rdr = csv::ReaderBuilder::new()
+    ) -> Result<Box<dyn Iterator<Item = SourceRecord> + 'a>, Box<dyn std::error::Error>> {
+        Ok(Box::new(
+            self.read_located_transactions()?.map(|(_, record)| record),
+        ))
+    }
+
+    fn read_located_transactions<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn Iterator<Item = LocatedRecord> + 'a>, Box<dyn std::error::Error>> {
+        let mut rdr = csv::ReaderBuilder::new()
             .trim(csv::Trim::All)
-            .from_path(path)?;
-
-        let iter = rdr
-            .into_deserialize::<UserTransactions>()
-            .filter_map(|result| match result {
-                Ok(action) => Some(action),
-                Err(e) => {
-                    eprintln!("Error reading record: {}", e);
-                    None
-                }
-            });
+            .from_reader(open_input(&self.path)?);
+        let headers = rdr.headers()?.clone();
+        let file: Arc<str> = match self.path.as_str() {
+            STDIN_PATH => Arc::from("stdin"),
+            path => Arc::from(path),
+        };
+        let format = self.amount_format;
+        let client_map = self.client_map.as_ref();
+
+        let iter = rdr.into_records().map(move |result| {
+            let location = |pos: &csv::Position| SourceLocation {
+                file: Some(Arc::clone(&file)),
+                line: pos.line(),
+                byte: pos.byte(),
+            };
+            match result {
+                Ok(row) => (
+                    row.position().map(location),
+                    parse_row(&row, &headers, format, client_map),
+                ),
+                Err(e) => (e.position().map(location), Err(e.to_string().into())),
+            }
+        });
 
         Ok(Box::new(iter))
     }
 }
+
+/// Reads an accounts file in the engine's own output format, e.g. to use a
+/// previous run's output as opening balances.
+pub fn read_accounts(path: &str) -> Result<Vec<UserAccount>, Box<dyn std::error::Error>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(Path::new(path))?;
+    let mut accounts = Vec::new();
+    for result in rdr.deserialize::<UserAccount>() {
+        accounts.push(result?);
+    }
+    Ok(accounts)
+}
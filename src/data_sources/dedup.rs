@@ -0,0 +1,152 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    TxType, UserTransactions,
+    data_sources::{DataSource, LocatedRecord, SourceRecord},
+};
+
+/// Remembers which `(tx_id, type)` pairs a stream delivered in the last
+/// `ttl_secs` of stream time, so redeliveries from an at-least-once feed
+/// can be dropped before they reach the engine.
+///
+/// Stream time is the latest record timestamp seen; records without one
+/// are stamped with it. An entry is evicted once stream time passes its
+/// first delivery by more than `ttl_secs`, so a redelivery arriving later
+/// than that gets through.
+#[derive(Debug, Clone)]
+pub struct DedupWindow {
+    ttl_secs: u64,
+    now: u64,
+    seen: HashMap<(u32, TxType), u64>,
+    /// Keys in delivery order, for eviction.
+    order: VecDeque<(u64, (u32, TxType))>,
+    dropped: u64,
+}
+
+impl DedupWindow {
+    pub fn new(ttl_secs: u64) -> Self {
+        Self {
+            ttl_secs,
+            now: 0,
+            seen: HashMap::new(),
+            order: VecDeque::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Whether `action` is the first delivery of its key within the window.
+    pub fn admit(&mut self, action: &UserTransactions) -> bool {
+        if let Some(timestamp) = action.timestamp {
+            self.now = self.now.max(timestamp);
+        }
+        self.evict();
+        let key = (action.tx_id, action.tx_type);
+        if self.seen.contains_key(&key) {
+            self.dropped += 1;
+            return false;
+        }
+        self.seen.insert(key, self.now);
+        self.order.push_back((self.now, key));
+        true
+    }
+
+    fn evict(&mut self) {
+        while let Some(&(seen_at, key)) = self.order.front()
+            && self.now - seen_at > self.ttl_secs
+        {
+            self.order.pop_front();
+            self.seen.remove(&key);
+        }
+    }
+
+    /// Redeliveries dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Keys currently remembered.
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+}
+
+/// Wraps a source so records [`DedupWindow`] has already seen are skipped.
+/// Records the inner source couldn't parse are passed along untouched.
+pub struct DedupSource<S> {
+    inner: S,
+    window: DedupWindow,
+}
+
+impl<S: DataSource> DedupSource<S> {
+    pub fn new(inner: S, ttl_secs: u64) -> Self {
+        Self {
+            inner,
+            window: DedupWindow::new(ttl_secs),
+        }
+    }
+
+    pub fn window(&self) -> &DedupWindow {
+        &self.window
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S: DataSource> DataSource for DedupSource<S> {
+    fn read_transactions<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn Iterator<Item = SourceRecord> + 'a>, Box<dyn std::error::Error>> {
+        Ok(Box::new(
+            self.read_located_transactions()?.map(|(_, record)| record),
+        ))
+    }
+
+    fn read_located_transactions<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn Iterator<Item = LocatedRecord> + 'a>, Box<dyn std::error::Error>> {
+        let window = &mut self.window;
+        let records = self.inner.read_located_transactions()?;
+        Ok(Box::new(records.filter(move |(_, record)| {
+            record.as_ref().map_or(true, |action| window.admit(action))
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data_sources::memory::MemoryDataSource, tx};
+
+    #[test]
+    fn test_redeliveries_within_ttl_are_dropped() {
+        let mut source = DedupSource::new(
+            MemoryDataSource::new(vec![
+                tx(TxType::Deposit, 1, 1).at(100),
+                tx(TxType::Dispute, 1, 1).at(105),
+                tx(TxType::Deposit, 1, 1).at(110),
+                tx(TxType::Deposit, 1, 2).at(120),
+                // Evicts tx 1, first seen at 100.
+                tx(TxType::Deposit, 1, 3).at(161),
+                tx(TxType::Deposit, 1, 1).at(162),
+                tx(TxType::Deposit, 1, 2).at(162),
+            ]),
+            60,
+        );
+        let kept: Vec<(u32, u64)> = source
+            .read_transactions()
+            .unwrap()
+            .map(|record| {
+                let action = record.unwrap();
+                (action.tx_id, action.timestamp.unwrap())
+            })
+            .collect();
+        assert_eq!(kept, [(1, 100), (1, 105), (2, 120), (3, 161), (1, 162)]);
+        assert_eq!(source.window().dropped(), 2);
+    }
+}
@@ -0,0 +1,30 @@
+use crate::{
+    UserTransactions,
+    data_sources::{DataSource, SourceRecord},
+};
+
+/// Source over transactions already in memory, for embedding and tests.
+/// Each read drains the records, so a second run sees an empty source.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryDataSource {
+    records: Vec<SourceRecord>,
+}
+
+impl MemoryDataSource {
+    pub fn new(transactions: Vec<UserTransactions>) -> Self {
+        Self::from_records(transactions.into_iter().map(Ok).collect())
+    }
+
+    /// Like [`Self::new`], but records can also be read failures.
+    pub fn from_records(records: Vec<SourceRecord>) -> Self {
+        Self { records }
+    }
+}
+
+impl DataSource for MemoryDataSource {
+    fn read_transactions<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn Iterator<Item = SourceRecord> + 'a>, Box<dyn std::error::Error>> {
+        Ok(Box::new(self.records.drain(..)))
+    }
+}
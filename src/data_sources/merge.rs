@@ -0,0 +1,257 @@
+use std::{io::Write, iter::Peekable};
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{
+    TxType, UserTransactions,
+    data_sources::{DataSource, LocatedRecord, SourceLocation, SourceRecord},
+    money::Amount,
+};
+
+/// A transaction that arrived after the merged watermark had passed it, so
+/// it was left out of the stream.
+#[derive(Debug, Clone)]
+pub struct LateEvent {
+    pub source: String,
+    pub location: Option<SourceLocation>,
+    pub action: UserTransactions,
+    /// Merged watermark at the time the transaction arrived.
+    pub watermark: u64,
+}
+
+struct MergeInput {
+    name: String,
+    source: Box<dyn DataSource>,
+    /// Latest timestamp read from this source.
+    max_seen: Option<u64>,
+}
+
+/// Merges several timestamped feeds into one stream, e.g. regional feeds
+/// whose clocks skew by a few minutes.
+///
+/// The next record is always the earliest head among the feeds, so skew
+/// between feeds doesn't reorder the stream. Each feed has a watermark, the
+/// latest timestamp it sent minus the allowed lateness, and the merged
+/// watermark is the lowest of them over the feeds that aren't exhausted. A
+/// transaction stamped before the merged watermark is too late: it is kept
+/// out of the stream and collected in [`Self::late_events`]. Without an
+/// allowed lateness every record goes through.
+pub struct MergedSource {
+    inputs: Vec<MergeInput>,
+    allowed_lateness: Option<u64>,
+    late: Vec<LateEvent>,
+}
+
+impl MergedSource {
+    pub fn new() -> Self {
+        Self {
+            inputs: Vec::new(),
+            allowed_lateness: None,
+            late: Vec::new(),
+        }
+    }
+
+    pub fn with_source(
+        mut self,
+        name: impl Into<String>,
+        source: impl DataSource + 'static,
+    ) -> Self {
+        self.inputs.push(MergeInput {
+            name: name.into(),
+            source: Box::new(source),
+            max_seen: None,
+        });
+        self
+    }
+
+    pub fn with_allowed_lateness(mut self, secs: u64) -> Self {
+        self.allowed_lateness = Some(secs);
+        self
+    }
+
+    /// Transactions left out of the stream so far, in arrival order.
+    pub fn late_events(&self) -> &[LateEvent] {
+        &self.late
+    }
+
+    /// Latest timestamp read from each source, by name.
+    pub fn watermarks(&self) -> Vec<(&str, Option<u64>)> {
+        self.inputs
+            .iter()
+            .map(|input| (input.name.as_str(), input.max_seen))
+            .collect()
+    }
+}
+
+impl Default for MergedSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+type Head<'a> = Peekable<Box<dyn Iterator<Item = LocatedRecord> + 'a>>;
+
+/// Records without a timestamp, and records that couldn't be read, go out
+/// as soon as they are at the head of their feed.
+fn head_time(head: &mut Head) -> Option<u64> {
+    match head.peek()? {
+        (_, Ok(action)) => Some(action.timestamp.unwrap_or(0)),
+        (_, Err(_)) => Some(0),
+    }
+}
+
+impl DataSource for MergedSource {
+    fn read_transactions<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn Iterator<Item = SourceRecord> + 'a>, Box<dyn std::error::Error>> {
+        Ok(Box::new(
+            self.read_located_transactions()?.map(|(_, record)| record),
+        ))
+    }
+
+    fn read_located_transactions<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn Iterator<Item = LocatedRecord> + 'a>, Box<dyn std::error::Error>> {
+        let mut names = Vec::with_capacity(self.inputs.len());
+        let mut max_seen = Vec::with_capacity(self.inputs.len());
+        let mut heads: Vec<Head<'a>> = Vec::with_capacity(self.inputs.len());
+        for input in self.inputs.iter_mut() {
+            names.push(input.name.as_str());
+            max_seen.push(&mut input.max_seen);
+            heads.push(input.source.read_located_transactions()?.peekable());
+        }
+        let lateness = self.allowed_lateness;
+        let late = &mut self.late;
+
+        Ok(Box::new(std::iter::from_fn(move || {
+            loop {
+                let next = (0..heads.len())
+                    .filter_map(|i| head_time(&mut heads[i]).map(|time| (time, i)))
+                    .min()?;
+                let index = next.1;
+                // Exhausted feeds no longer hold the watermark back.
+                let watermark = lateness.and_then(|lateness| {
+                    (0..heads.len())
+                        .filter(|&i| heads[i].peek().is_some())
+                        .filter_map(|i| *max_seen[i])
+                        .min()
+                        .map(|seen| seen.saturating_sub(lateness))
+                });
+                let (location, record) = heads[index].next()?;
+                let Ok(action) = &record else {
+                    return Some((location, record));
+                };
+                let Some(ts) = action.timestamp else {
+                    return Some((location, record));
+                };
+                if let Some(watermark) = watermark
+                    && ts < watermark
+                {
+                    late.push(LateEvent {
+                        source: names[index].to_string(),
+                        location,
+                        action: action.clone(),
+                        watermark,
+                    });
+                    continue;
+                }
+                let seen = &mut *max_seen[index];
+                *seen = Some(seen.map_or(ts, |seen| seen.max(ts)));
+                return Some((location, record));
+            }
+        })))
+    }
+}
+
+#[derive(Serialize)]
+struct LateRow<'a> {
+    source: &'a str,
+    #[serde(rename = "type")]
+    tx_type: TxType,
+    client: u16,
+    tx: u32,
+    amount: Option<Decimal>,
+    timestamp: Option<u64>,
+    watermark: u64,
+}
+
+pub fn write_late_events<W: Write>(writer: W, events: &[LateEvent]) -> Result<(), String> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for event in events {
+        writer
+            .serialize(LateRow {
+                source: &event.source,
+                tx_type: event.action.tx_type,
+                client: event.action.client_id,
+                tx: event.action.tx_id,
+                amount: event.action.amount.map(Amount::value),
+                timestamp: event.action.timestamp,
+                watermark: event.watermark,
+            })
+            .map_err(|e| format!("Failed to serialize late event: {}", e))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to flush writer: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_sources::memory::MemoryDataSource;
+
+    fn feed(events: &[(u32, u64)]) -> MemoryDataSource {
+        MemoryDataSource::new(
+            events
+                .iter()
+                .map(|&(tx_id, ts)| UserTransactions {
+                    tx_type: TxType::Deposit,
+                    client_id: 1,
+                    tx_id,
+                    amount: Amount::new(Decimal::ONE).ok(),
+                    timestamp: Some(ts),
+                    ..Default::default()
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_merges_by_time_and_sets_aside_late_events() {
+        let mut source = MergedSource::new()
+            .with_source("eu", feed(&[(1, 100), (2, 400), (3, 900), (4, 500)]))
+            .with_source("us", feed(&[(10, 50), (11, 300), (12, 850), (13, 1000)]))
+            .with_allowed_lateness(300);
+        let order: Vec<u32> = source
+            .read_transactions()
+            .unwrap()
+            .map(|record| record.unwrap().tx_id)
+            .collect();
+        // Tx 4 at 500 arrives once both feeds are past 850.
+        assert_eq!(order, vec![10, 1, 11, 2, 12, 3, 13]);
+        let late: Vec<(&str, u32, u64)> = source
+            .late_events()
+            .iter()
+            .map(|e| (e.source.as_str(), e.action.tx_id, e.watermark))
+            .collect();
+        assert_eq!(late, vec![("eu", 4, 550)]);
+        assert_eq!(
+            source.watermarks(),
+            vec![("eu", Some(900)), ("us", Some(1000))]
+        );
+
+        let mut out = Vec::new();
+        write_late_events(&mut out, source.late_events()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "source,type,client,tx,amount,timestamp,watermark\neu,deposit,1,4,1,500,550\n"
+        );
+
+        let mut unbounded = MergedSource::new()
+            .with_source("eu", feed(&[(1, 900), (2, 100)]))
+            .with_source("us", feed(&[(3, 50)]));
+        assert_eq!(unbounded.read_transactions().unwrap().count(), 3);
+        assert!(unbounded.late_events().is_empty());
+    }
+}
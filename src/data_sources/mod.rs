@@ -1,9 +1,99 @@
+pub mod amount;
+#[cfg(feature = "tokio")]
+pub mod async_csv;
+pub mod client_map;
+pub mod compressed;
 pub mod csv;
+pub mod dedup;
+pub mod memory;
+pub mod merge;
+pub mod multi;
+pub mod transform;
+pub mod validate;
 
-use crate::UserTransactions;
+use std::{fmt, sync::Arc};
+
+use crate::{UserTransactions, data_sources::validate::ValidationError};
+
+/// Per-record outcome of reading a source: either a transaction or why the
+/// record couldn't become one.
+pub type SourceRecord = Result<UserTransactions, SourceError>;
+
+/// Why a record couldn't become a transaction.
+#[derive(Debug, PartialEq, Clone)]
+pub enum SourceError {
+    /// The record couldn't be read or parsed.
+    Parse(String),
+    /// The record parsed, but its values were refused.
+    Invalid(ValidationError),
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceError::Parse(message) => write!(f, "{}", message),
+            SourceError::Invalid(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for SourceError {}
+
+impl From<String> for SourceError {
+    fn from(message: String) -> Self {
+        SourceError::Parse(message)
+    }
+}
+
+impl From<ValidationError> for SourceError {
+    fn from(error: ValidationError) -> Self {
+        SourceError::Invalid(error)
+    }
+}
+
+/// Where a record starts in its input.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SourceLocation {
+    pub file: Option<Arc<str>>,
+    /// 1-based line number.
+    pub line: u64,
+    /// Byte offset from the start of the input.
+    pub byte: u64,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+        }
+        write!(f, "{} (byte {})", self.line, self.byte)
+    }
+}
+
+/// A record paired with its location, for sources that track one.
+pub type LocatedRecord = (Option<SourceLocation>, SourceRecord);
 
 pub trait DataSource {
     fn read_transactions<'a>(
         &'a mut self,
-    ) -> Result<Box<dyn Iterator<Item = UserTransactions> + 'a>, Box<dyn std::error::Error>>;
+    ) -> Result<Box<dyn Iterator<Item = SourceRecord> + 'a>, Box<dyn std::error::Error>>;
+
+    /// Same records as [`Self::read_transactions`], each with its location.
+    /// Sources that can't tell where a record came from yield `None`.
+    fn read_located_transactions<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn Iterator<Item = LocatedRecord> + 'a>, Box<dyn std::error::Error>> {
+        Ok(Box::new(
+            self.read_transactions()?.map(|record| (None, record)),
+        ))
+    }
+}
+
+/// Async counterpart of [`DataSource`], for sources that read from the
+/// network or other non-blocking I/O.
+#[cfg(feature = "tokio")]
+pub trait AsyncDataSource {
+    fn read_transactions(
+        &mut self,
+    ) -> std::pin::Pin<Box<dyn futures_core::Stream<Item = SourceRecord> + Send + '_>>;
 }
@@ -0,0 +1,154 @@
+use std::path::Path;
+
+use crate::data_sources::{
+    DataSource, LocatedRecord, SourceRecord, amount::AmountFormat, client_map::ClientIdMap,
+    csv::CsvDataSource,
+};
+
+/// Whether `input` is a glob pattern rather than a plain path.
+pub fn is_glob(input: &str) -> bool {
+    input.contains(['*', '?', '['])
+}
+
+/// Reads several transactions files one after the other as one stream, e.g.
+/// a month of daily dumps. Every file is opened, and its header checked,
+/// before the first record is read.
+pub struct MultiFileDataSource {
+    sources: Vec<(String, CsvDataSource)>,
+}
+
+impl MultiFileDataSource {
+    /// Reads `paths` in the order given.
+    pub fn new(paths: Vec<String>) -> Self {
+        Self {
+            sources: paths
+                .into_iter()
+                .map(|path| (path.clone(), CsvDataSource::new(path)))
+                .collect(),
+        }
+    }
+
+    /// Reads the files matching `pattern`, in path order.
+    pub fn from_glob(pattern: &str) -> Result<Self, String> {
+        let paths = glob::glob(pattern)
+            .map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))?
+            .map(|entry| {
+                entry
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .map_err(|e| format!("Failed to list '{}': {}", pattern, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if paths.is_empty() {
+            return Err(format!("No files match '{}'", pattern));
+        }
+        Ok(Self::new(paths))
+    }
+
+    /// Orders the files by file name, ignoring their directories, so dumps
+    /// named by date read oldest first wherever they are kept.
+    pub fn sorted_by_name(mut self) -> Self {
+        self.sources.sort_by(|(a, _), (b, _)| {
+            let name = |path: &str| Path::new(path).file_name().map(|n| n.to_os_string());
+            name(a).cmp(&name(b)).then_with(|| a.cmp(b))
+        });
+        self
+    }
+
+    pub fn with_amount_format(mut self, format: AmountFormat) -> Self {
+        self.sources = self
+            .sources
+            .into_iter()
+            .map(|(path, source)| (path, source.with_amount_format(format)))
+            .collect();
+        self
+    }
+
+    pub fn with_client_map(mut self, client_map: ClientIdMap) -> Self {
+        self.sources = self
+            .sources
+            .into_iter()
+            .map(|(path, source)| (path, source.with_client_map(client_map.clone())))
+            .collect();
+        self
+    }
+
+    pub fn paths(&self) -> Vec<&str> {
+        self.sources.iter().map(|(path, _)| path.as_str()).collect()
+    }
+}
+
+impl DataSource for MultiFileDataSource {
+    fn read_transactions<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn Iterator<Item = SourceRecord> + 'a>, Box<dyn std::error::Error>> {
+        Ok(Box::new(
+            self.read_located_transactions()?.map(|(_, record)| record),
+        ))
+    }
+
+    fn read_located_transactions<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn Iterator<Item = LocatedRecord> + 'a>, Box<dyn std::error::Error>> {
+        let files = self
+            .sources
+            .iter_mut()
+            .map(|(path, source)| {
+                source
+                    .read_located_transactions()
+                    .map_err(|e| format!("Failed to read '{}': {}", path, e).into())
+            })
+            .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+        Ok(Box::new(files.into_iter().flatten()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_files_in_sequence() {
+        let dir = std::env::temp_dir().join(format!("multi-{}", std::process::id()));
+        let day = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        std::fs::create_dir_all(dir.join("later")).unwrap();
+        std::fs::write(
+            day("2024-01-02.csv"),
+            "type,client,tx,amount\ndeposit,1,2,5\n",
+        )
+        .unwrap();
+        std::fs::write(
+            day("later/2024-01-01.csv"),
+            "type,client,tx,amount\ndeposit,1,1,10\nwithdrawal,1,3,1\n",
+        )
+        .unwrap();
+
+        let pattern = format!("{}/**/*.csv", dir.display());
+        assert!(is_glob(&pattern) && !is_glob("in.csv"));
+        let mut source = MultiFileDataSource::from_glob(&pattern)
+            .unwrap()
+            .sorted_by_name();
+        assert_eq!(
+            source.paths(),
+            vec![day("later/2024-01-01.csv"), day("2024-01-02.csv")]
+        );
+        let records: Vec<LocatedRecord> = source.read_located_transactions().unwrap().collect();
+        let tx_ids: Vec<u32> = records
+            .iter()
+            .map(|(_, r)| r.as_ref().unwrap().tx_id)
+            .collect();
+        assert_eq!(tx_ids, [1, 3, 2]);
+        let (location, _) = &records[2];
+        let location = location.as_ref().unwrap();
+        assert_eq!(
+            location.file.as_deref(),
+            Some(day("2024-01-02.csv").as_str())
+        );
+        assert_eq!(location.line, 2);
+
+        let missing = format!("{}/*.json", dir.display());
+        assert!(MultiFileDataSource::from_glob(&missing).is_err());
+        let mut broken = MultiFileDataSource::new(vec![day("2024-01-02.csv"), day("gone.csv")]);
+        assert!(broken.read_transactions().is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
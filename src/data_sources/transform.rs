@@ -0,0 +1,138 @@
+use rust_decimal::Decimal;
+
+use crate::{
+    UserTransactions,
+    data_sources::{DataSource, LocatedRecord, SourceError, SourceRecord},
+    money::Amount,
+};
+
+/// A rewrite applied to every transaction a source parses, before it reaches
+/// the engine. Returning an error turns the record into a source error.
+pub trait Transform {
+    fn apply(&mut self, action: UserTransactions) -> Result<UserTransactions, SourceError>;
+}
+
+impl<F, E> Transform for F
+where
+    F: FnMut(UserTransactions) -> Result<UserTransactions, E>,
+    E: Into<SourceError>,
+{
+    fn apply(&mut self, action: UserTransactions) -> Result<UserTransactions, SourceError> {
+        self(action).map_err(Into::into)
+    }
+}
+
+/// Multiplies every amount by a fixed factor, e.g. `0.01` for a feed that
+/// sends cents.
+#[derive(Debug, Clone, Copy)]
+pub struct ScaleAmounts(pub Decimal);
+
+impl Transform for ScaleAmounts {
+    fn apply(&mut self, mut action: UserTransactions) -> Result<UserTransactions, SourceError> {
+        if let Some(amount) = action.amount {
+            let scaled = amount
+                .value()
+                .checked_mul(self.0)
+                .ok_or_else(|| format!("Amount {} overflows when scaled", amount.value()))?;
+            action.amount = Some(Amount::new(scaled)?);
+        }
+        Ok(action)
+    }
+}
+
+/// Wraps a source so its transactions pass through `transforms` in order.
+/// Records the inner source couldn't parse are passed along untouched.
+pub struct TransformedSource<S> {
+    inner: S,
+    transforms: Vec<Box<dyn Transform>>,
+}
+
+impl<S: DataSource> TransformedSource<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            transforms: Vec::new(),
+        }
+    }
+
+    pub fn with_transform(mut self, transform: impl Transform + 'static) -> Self {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+fn apply_all(transforms: &mut [Box<dyn Transform>], record: SourceRecord) -> SourceRecord {
+    transforms
+        .iter_mut()
+        .try_fold(record?, |action, transform| transform.apply(action))
+}
+
+impl<S: DataSource> DataSource for TransformedSource<S> {
+    fn read_transactions<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn Iterator<Item = SourceRecord> + 'a>, Box<dyn std::error::Error>> {
+        Ok(Box::new(
+            self.read_located_transactions()?.map(|(_, record)| record),
+        ))
+    }
+
+    fn read_located_transactions<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn Iterator<Item = LocatedRecord> + 'a>, Box<dyn std::error::Error>> {
+        let transforms = &mut self.transforms;
+        let records = self.inner.read_located_transactions()?;
+        Ok(Box::new(records.map(move |(location, record)| {
+            (location, apply_all(transforms, record))
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TxType, data_sources::memory::MemoryDataSource};
+    use rust_decimal_macros::dec;
+
+    fn deposit(tx_id: u32, amount: Decimal) -> SourceRecord {
+        Ok(UserTransactions {
+            tx_type: TxType::Deposit,
+            client_id: 1,
+            tx_id,
+            amount: Some(Amount::new(amount).unwrap()),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_transforms_run_in_order() {
+        let source = MemoryDataSource::from_records(vec![
+            deposit(1, dec!(150)),
+            Err(SourceError::Parse("bad row".to_string())),
+            deposit(2, dec!(5)),
+        ]);
+        let mut source = TransformedSource::new(source)
+            .with_transform(ScaleAmounts(dec!(0.01)))
+            .with_transform(|action: UserTransactions| {
+                if action.tx_id == 2 {
+                    Err("tx 2 is excluded".to_string())
+                } else {
+                    Ok(action)
+                }
+            });
+
+        let records: Vec<SourceRecord> = source.read_transactions().unwrap().collect();
+        assert_eq!(
+            records[0].as_ref().unwrap().amount.unwrap().value(),
+            dec!(1.50)
+        );
+        assert_eq!(records[1].as_ref().unwrap_err().to_string(), "bad row");
+        assert_eq!(
+            records[2].as_ref().unwrap_err().to_string(),
+            "tx 2 is excluded"
+        );
+    }
+}
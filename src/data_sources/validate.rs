@@ -0,0 +1,142 @@
+use std::fmt;
+
+use rust_decimal::Decimal;
+
+use crate::{
+    TxType, UserTransactions,
+    data_sources::{SourceError, transform::Transform},
+    money::{Amount, MAX_SCALE},
+};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ValidationErrorKind {
+    NegativeAmount,
+    /// A deposit, withdrawal or transfer without an amount.
+    MissingAmount,
+    TooManyDecimals,
+    /// A dispute, resolve or chargeback that carries an amount; those take
+    /// the amount of the transaction they refer to.
+    UnexpectedAmount,
+}
+
+impl ValidationErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ValidationErrorKind::NegativeAmount => "negative_amount",
+            ValidationErrorKind::MissingAmount => "missing_amount",
+            ValidationErrorKind::TooManyDecimals => "too_many_decimals",
+            ValidationErrorKind::UnexpectedAmount => "unexpected_amount",
+        }
+    }
+}
+
+/// Why a record was refused before reaching the engine.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ValidationError {
+    pub kind: ValidationErrorKind,
+    pub tx_id: u32,
+    pub amount: Option<Decimal>,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: tx {}", self.kind.as_str(), self.tx_id)?;
+        match self.kind {
+            ValidationErrorKind::NegativeAmount => write!(f, " has a negative amount")?,
+            ValidationErrorKind::MissingAmount => write!(f, " has no amount")?,
+            ValidationErrorKind::TooManyDecimals => {
+                write!(f, " has more than {} decimal places", MAX_SCALE)?
+            }
+            ValidationErrorKind::UnexpectedAmount => write!(f, " can't carry an amount")?,
+        }
+        if let Some(amount) = self.amount {
+            write!(f, " ({})", amount)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Checks the amount of a `tx_type` row as read from the input, before it
+/// becomes an [`Amount`].
+pub fn check_amount(
+    tx_type: TxType,
+    tx_id: u32,
+    amount: Option<Decimal>,
+) -> Result<(), ValidationError> {
+    let error = |kind| ValidationError {
+        kind,
+        tx_id,
+        amount,
+    };
+    match (tx_type, amount) {
+        (TxType::Deposit | TxType::Withdrawal | TxType::Transfer, None) => {
+            Err(error(ValidationErrorKind::MissingAmount))
+        }
+        (TxType::Dispute | TxType::Resolve | TxType::Chargeback, Some(_)) => {
+            Err(error(ValidationErrorKind::UnexpectedAmount))
+        }
+        (_, Some(value)) if value.is_sign_negative() && !value.is_zero() => {
+            Err(error(ValidationErrorKind::NegativeAmount))
+        }
+        (_, Some(value)) if value.normalize().scale() > MAX_SCALE => {
+            Err(error(ValidationErrorKind::TooManyDecimals))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// [`check_amount`] as a [`Transform`], for sources that build their
+/// transactions without going through it. Only the missing and unexpected
+/// amount checks can fail here: an [`Amount`] is never negative and never
+/// carries more than [`MAX_SCALE`] decimal places.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Validator;
+
+impl Transform for Validator {
+    fn apply(&mut self, action: UserTransactions) -> Result<UserTransactions, SourceError> {
+        check_amount(
+            action.tx_type,
+            action.tx_id,
+            action.amount.map(Amount::value),
+        )?;
+        Ok(action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_check_amount() {
+        let kind = |tx_type, amount| check_amount(tx_type, 1, amount).map_err(|e| e.kind);
+        assert_eq!(kind(TxType::Deposit, Some(dec!(1.5))), Ok(()));
+        assert_eq!(kind(TxType::Dispute, None), Ok(()));
+        assert_eq!(
+            kind(TxType::Deposit, Some(dec!(-1))),
+            Err(ValidationErrorKind::NegativeAmount)
+        );
+        assert_eq!(
+            kind(TxType::Withdrawal, None),
+            Err(ValidationErrorKind::MissingAmount)
+        );
+        assert_eq!(
+            kind(TxType::Deposit, Some(dec!(1.23456))),
+            Err(ValidationErrorKind::TooManyDecimals)
+        );
+        assert_eq!(kind(TxType::Deposit, Some(dec!(1.23450))), Ok(()));
+        assert_eq!(
+            kind(TxType::Chargeback, Some(dec!(1))),
+            Err(ValidationErrorKind::UnexpectedAmount)
+        );
+        assert_eq!(
+            check_amount(TxType::Deposit, 7, Some(dec!(-2)))
+                .unwrap_err()
+                .to_string(),
+            "negative_amount: tx 7 has a negative amount (-2)"
+        );
+    }
+}
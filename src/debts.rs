@@ -0,0 +1,160 @@
+use std::{io::Write, str::FromStr};
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{PaymentEngine, serialize_to_four_places};
+
+/// What a chargeback left a client owing: the part of the charged-back
+/// amount their available balance couldn't cover.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Debt {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    /// The charged-back transaction.
+    #[serde(rename = "tx")]
+    pub tx_id: u32,
+    #[serde(serialize_with = "serialize_to_four_places")]
+    pub amount: Decimal,
+    #[serde(serialize_with = "serialize_to_four_places")]
+    pub outstanding: Decimal,
+}
+
+/// Whether deposits pay debts down.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum DebtRepayment {
+    /// Each deposit repays the client's debts first, oldest first.
+    #[default]
+    DepositsFirst,
+    /// Debts are only recorded, e.g. for collection outside the engine.
+    None,
+}
+
+impl FromStr for DebtRepayment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deposits" => Ok(Self::DepositsFirst),
+            "none" => Ok(Self::None),
+            other => Err(format!(
+                "Unknown debt repayment '{}', expected deposits or none",
+                other
+            )),
+        }
+    }
+}
+
+impl PaymentEngine {
+    pub fn set_debt_repayment(&mut self, repayment: DebtRepayment) {
+        self.debt_repayment = repayment;
+    }
+
+    /// Debts not yet repaid, oldest first.
+    pub fn debts(&self) -> &im::Vector<Debt> {
+        &self.debts
+    }
+
+    pub fn outstanding_debt(&self, client_id: u16) -> Decimal {
+        self.debts
+            .iter()
+            .filter(|debt| debt.client_id == client_id)
+            .map(|debt| debt.outstanding)
+            .sum()
+    }
+
+    /// Records the part of a chargeback of `amount` that left the available
+    /// balance at `available` below zero.
+    pub(crate) fn record_debt(
+        &mut self,
+        client_id: u16,
+        tx_id: u32,
+        amount: Decimal,
+        available: Decimal,
+    ) {
+        let deficit = amount.min(-available);
+        if deficit > Decimal::ZERO {
+            self.debts.push_back(Debt {
+                client_id,
+                tx_id,
+                amount: deficit,
+                outstanding: deficit,
+            });
+        }
+    }
+
+    pub(crate) fn repay_debts(&mut self, client_id: u16, mut amount: Decimal) {
+        if self.debt_repayment == DebtRepayment::None {
+            return;
+        }
+        for debt in self
+            .debts
+            .iter_mut()
+            .filter(|debt| debt.client_id == client_id)
+        {
+            let repaid = amount.min(debt.outstanding);
+            debt.outstanding -= repaid;
+            amount -= repaid;
+            if amount.is_zero() {
+                break;
+            }
+        }
+        self.debts.retain(|debt| !debt.outstanding.is_zero());
+    }
+}
+
+pub fn write_debts<'a, W: Write>(
+    writer: W,
+    debts: impl IntoIterator<Item = &'a Debt>,
+) -> Result<(), String> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for debt in debts {
+        writer
+            .serialize(debt)
+            .map_err(|e| format!("Failed to serialize debt: {}", e))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to flush writer: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount;
+    use rust_decimal_macros::dec;
+
+    fn charged_back(repayment: DebtRepayment) -> PaymentEngine {
+        let mut engine = PaymentEngine::new();
+        engine.set_debt_repayment(repayment);
+        let mut client = engine.client(1);
+        client.deposit(1, amount(dec!(10))).unwrap();
+        client.withdraw(2, amount(dec!(4))).unwrap();
+        client.dispute(1).unwrap();
+        client.chargeback(1).unwrap();
+        engine
+    }
+
+    #[test]
+    fn test_deposits_repay_chargeback_debt() {
+        let mut engine = charged_back(DebtRepayment::DepositsFirst);
+        assert!(engine.accounts[&1].available < Decimal::ZERO);
+        assert_eq!(engine.outstanding_debt(1), dec!(4));
+        assert_eq!(engine.debts()[0].tx_id, 1);
+
+        engine.client(1).deposit(3, amount(dec!(3))).unwrap();
+        assert_eq!(engine.outstanding_debt(1), dec!(1));
+
+        let mut out = Vec::new();
+        write_debts(&mut out, engine.debts()).unwrap();
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .starts_with("client,tx,amount,outstanding\n1,1,")
+        );
+
+        let mut engine = charged_back(DebtRepayment::None);
+        engine.client(1).deposit(3, amount(dec!(3))).unwrap();
+        assert_eq!(engine.outstanding_debt(1), dec!(4));
+    }
+}
@@ -0,0 +1,199 @@
+use std::io::Write;
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{PaymentEngine, TxType, UserTransactions, errors::EngineError};
+
+/// What the client rules were judged on: the access list and whether the
+/// account is closed or not yet open.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct ClientInputs {
+    /// Whether the access list lets the client through.
+    pub permitted: bool,
+    pub closed: bool,
+    /// Whether the client has an open account, or doesn't need one.
+    pub open: bool,
+}
+
+/// What a withdrawal was judged on: the freeze policy, the withdrawal
+/// policy and funds holds, the credit limit and the balance.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct WithdrawalInputs {
+    pub amount: Decimal,
+    /// `None` when the client has no account.
+    pub available: Option<Decimal>,
+    pub held: Option<Decimal>,
+    pub open_disputes: u32,
+    pub frozen: bool,
+    /// What the withdrawal must leave in place, or `None` when the
+    /// withdrawal policy refused outright.
+    pub reserve: Option<Decimal>,
+    pub credit_limit: Decimal,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(tag = "check", content = "inputs", rename_all = "snake_case")]
+pub enum DecisionInputs {
+    Client(ClientInputs),
+    Withdrawal(WithdrawalInputs),
+}
+
+/// One evaluation of a rule set against a transaction, with everything it
+/// was judged on and the verdict, so it can be explained later.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct PolicyDecision {
+    /// Stream time when the rules were evaluated.
+    pub at: Option<u64>,
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    #[serde(rename = "tx")]
+    pub tx_id: u32,
+    #[serde(rename = "type")]
+    pub tx_type: TxType,
+    #[serde(flatten)]
+    pub inputs: DecisionInputs,
+    pub allowed: bool,
+    /// Error code of a refusal.
+    pub code: Option<&'static str>,
+    pub reason: Option<String>,
+}
+
+/// Appends `decisions` to `writer` as JSON lines, leaving flushing to the
+/// caller.
+pub fn write_decisions<W: Write>(
+    mut writer: W,
+    decisions: &[PolicyDecision],
+) -> Result<(), String> {
+    for decision in decisions {
+        serde_json::to_writer(&mut writer, decision)
+            .map_err(|e| format!("Failed to write decisions: {}", e))?;
+        writer
+            .write_all(b"\n")
+            .map_err(|e| format!("Failed to write decisions: {}", e))?;
+    }
+    Ok(())
+}
+
+impl PaymentEngine {
+    /// Records every rule evaluation from now on, for [`Self::drain_decisions`].
+    /// Off by default, as it costs an entry per transaction.
+    pub fn set_decision_audit(&mut self, enabled: bool) {
+        self.decisions = enabled.then(Vec::new);
+    }
+
+    pub fn drain_decisions(&mut self) -> Vec<PolicyDecision> {
+        self.decisions
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn note_client_decision(
+        &mut self,
+        action: &UserTransactions,
+        verdict: &Result<(), EngineError>,
+    ) {
+        if self.decisions.is_none() {
+            return;
+        }
+        let inputs = DecisionInputs::Client(ClientInputs {
+            permitted: self.access.permits(action.client_id),
+            closed: self.closed_accounts.contains(&action.client_id),
+            open: self.is_account_open(action.client_id),
+        });
+        self.note_decision(action, inputs, verdict);
+    }
+
+    pub(crate) fn note_withdrawal_decision(
+        &mut self,
+        action: &UserTransactions,
+        amount: Decimal,
+        verdict: &Result<(), EngineError>,
+    ) {
+        if self.decisions.is_none() {
+            return;
+        }
+        let account = self.accounts.get(&action.client_id);
+        let inputs = DecisionInputs::Withdrawal(WithdrawalInputs {
+            amount,
+            available: account.map(|a| a.available),
+            held: account.map(|a| a.held),
+            open_disputes: self.account_stats(action.client_id).open_disputes,
+            frozen: self.is_withdrawal_frozen(action.client_id),
+            reserve: self.withdrawal_reserve(action.client_id).ok(),
+            credit_limit: self.credit_limit(action.client_id),
+        });
+        self.note_decision(action, inputs, verdict);
+    }
+
+    fn note_decision(
+        &mut self,
+        action: &UserTransactions,
+        inputs: DecisionInputs,
+        verdict: &Result<(), EngineError>,
+    ) {
+        let decision = PolicyDecision {
+            at: self.stream_time,
+            client_id: action.client_id,
+            tx_id: action.tx_id,
+            tx_type: action.tx_type,
+            inputs,
+            allowed: verdict.is_ok(),
+            code: verdict.as_ref().err().map(|e| e.code().code()),
+            reason: verdict.as_ref().err().map(ToString::to_string),
+        };
+        if let Some(decisions) = self.decisions.as_mut() {
+            decisions.push(decision);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{risk::WithdrawalPolicy, tx};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_withdrawal_decisions_record_inputs_and_verdict() {
+        let mut engine = PaymentEngine::new();
+        engine.set_withdrawal_policy(WithdrawalPolicy::Reserve(dec!(5)));
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(20)).at(101))
+            .unwrap();
+        assert!(engine.drain_decisions().is_empty(), "off by default");
+
+        engine.set_decision_audit(true);
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 2).with_amount(dec!(16)).at(102))
+            .unwrap_err();
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 3).with_amount(dec!(15)).at(103))
+            .unwrap();
+        let decisions = engine.drain_decisions();
+        assert_eq!(decisions.len(), 4, "client and withdrawal rules each");
+        assert!(decisions[0].allowed);
+        let refused = &decisions[1];
+        assert_eq!(
+            (refused.tx_id, refused.allowed, refused.code, refused.at),
+            (2, false, Some("PE1001"), Some(102))
+        );
+        let DecisionInputs::Withdrawal(inputs) = refused.inputs else {
+            panic!("expected withdrawal inputs");
+        };
+        assert_eq!(
+            (inputs.amount, inputs.available, inputs.reserve),
+            (dec!(16), Some(dec!(20)), Some(dec!(5)))
+        );
+        assert!(decisions[3].allowed);
+
+        let mut out = Vec::new();
+        write_decisions(&mut out, &decisions[1..2]).unwrap();
+        let line: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(line["check"], "withdrawal");
+        assert_eq!(line["inputs"]["reserve"], "5");
+        assert_eq!(line["code"], "PE1001");
+        assert!(engine.drain_decisions().is_empty());
+    }
+}
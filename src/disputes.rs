@@ -0,0 +1,255 @@
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    EngineEvent, EventKind, PaymentEngine, TxType, UserTransactions,
+    errors::{EngineError, ErrorCode},
+    money::Amount,
+};
+
+/// What to do with a dispute whose amount exceeds the client's available
+/// balance, typically because the disputed deposit was already spent.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum DisputeFundsPolicy {
+    /// Hold the full amount and let available go negative.
+    #[default]
+    AllowNegative,
+    /// Hold only what is available and report the rest as a shortfall.
+    CapAtAvailable,
+    /// Leave the dispute pending until the client's available balance
+    /// covers it. A pending dispute can't be resolved or charged back yet.
+    Queue,
+}
+
+impl FromStr for DisputeFundsPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow-negative" => Ok(Self::AllowNegative),
+            "cap" => Ok(Self::CapAtAvailable),
+            "queue" => Ok(Self::Queue),
+            other => Err(format!(
+                "Unknown dispute funds policy '{}', expected allow-negative, cap or queue",
+                other
+            )),
+        }
+    }
+}
+
+/// Where a deposit or withdrawal is in its dispute lifecycle. A transaction
+/// can be disputed once; resolving or charging it back closes it for good.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Deserialize, Serialize)]
+pub enum DisputeState {
+    #[default]
+    Undisputed,
+    Disputed,
+    Resolved,
+    ChargedBack,
+}
+
+impl DisputeState {
+    /// State after applying `step`, or `None` if `step` isn't allowed from
+    /// this state or isn't a dispute step at all.
+    pub fn next(self, step: TxType) -> Option<DisputeState> {
+        match (self, step) {
+            (DisputeState::Undisputed, TxType::Dispute) => Some(DisputeState::Disputed),
+            (DisputeState::Disputed, TxType::Resolve) => Some(DisputeState::Resolved),
+            (DisputeState::Disputed, TxType::Chargeback) => Some(DisputeState::ChargedBack),
+            _ => None,
+        }
+    }
+}
+
+impl PaymentEngine {
+    pub fn dispute_state(&self, client_id: u16, tx_id: u32) -> DisputeState {
+        self.dispute_states
+            .get(&(client_id, tx_id))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Refuses a dispute, resolve or chargeback the transaction's current
+    /// dispute state doesn't allow. A queued dispute counts as disputed.
+    pub(crate) fn check_dispute_step(&self, action: &UserTransactions) -> Result<(), EngineError> {
+        let state = self.dispute_state(action.client_id, action.tx_id);
+        let queued = action.tx_type == TxType::Dispute
+            && self.queued_disputes.iter().any(|(queued, _)| {
+                queued.client_id == action.client_id && queued.tx_id == action.tx_id
+            });
+        if state.next(action.tx_type).is_some() && !queued {
+            return Ok(());
+        }
+        let (code, message) = match state {
+            DisputeState::Undisputed if queued => (
+                ErrorCode::AlreadyDisputed,
+                "already has a dispute waiting in the queue",
+            ),
+            DisputeState::Undisputed => (ErrorCode::NotUnderDispute, "is not under dispute"),
+            DisputeState::Disputed => (ErrorCode::AlreadyDisputed, "is already under dispute"),
+            DisputeState::Resolved => (ErrorCode::DisputeClosed, "had its dispute resolved"),
+            DisputeState::ChargedBack => (ErrorCode::DisputeClosed, "was charged back"),
+        };
+        Err(EngineError::new(
+            code,
+            format!("Transaction {} {}", action.tx_id, message),
+        ))
+    }
+
+    pub(crate) fn advance_dispute_state(&mut self, action: &UserTransactions) {
+        let key = (action.client_id, action.tx_id);
+        if let Some(next) = self.dispute_state(key.0, key.1).next(action.tx_type) {
+            self.dispute_states.insert(key, next);
+        }
+    }
+
+    pub fn set_dispute_funds_policy(&mut self, policy: DisputeFundsPolicy) {
+        self.dispute_funds_policy = policy;
+    }
+
+    fn available(&self, client_id: u16) -> Decimal {
+        self.accounts
+            .get(&client_id)
+            .map_or(Decimal::ZERO, |a| a.available)
+    }
+
+    /// Amount to hold for a new dispute of `amount`, or `None` if the
+    /// dispute has to wait in the queue. Reports any shortfall as an event.
+    pub(crate) fn dispute_hold(
+        &mut self,
+        action: &UserTransactions,
+        amount: Decimal,
+    ) -> Option<Decimal> {
+        let available = self.available(action.client_id).max(Decimal::ZERO);
+        if amount <= available {
+            return Some(amount);
+        }
+        match self.dispute_funds_policy {
+            DisputeFundsPolicy::AllowNegative => Some(amount),
+            DisputeFundsPolicy::CapAtAvailable => {
+                self.events.push(EngineEvent {
+                    kind: EventKind::DisputeShortfall,
+                    action: UserTransactions {
+                        amount: Amount::new(amount - available).ok(),
+                        ..action.clone()
+                    },
+                });
+                Some(available)
+            }
+            DisputeFundsPolicy::Queue => None,
+        }
+    }
+
+    pub(crate) fn queue_dispute(&mut self, action: UserTransactions, amount: Amount) {
+        self.events.push(EngineEvent {
+            kind: EventKind::DisputeQueued,
+            action: action.clone(),
+        });
+        self.queued_disputes.push_back((action, amount));
+    }
+
+    /// Applies, in arrival order, every queued dispute the client can now cover.
+    pub(crate) fn release_queued_disputes(&mut self) {
+        let mut index = 0;
+        while index < self.queued_disputes.len() {
+            let (action, amount) = &self.queued_disputes[index];
+            if self.available(action.client_id) < amount.value() {
+                index += 1;
+                continue;
+            }
+            let (action, _) = self.queued_disputes.remove(index);
+            if self.apply_action(action.clone()).is_ok() {
+                self.events.push(EngineEvent {
+                    kind: EventKind::QueuedDisputeApplied,
+                    action,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tx;
+    use rust_decimal_macros::dec;
+
+    fn spent_deposit(policy: DisputeFundsPolicy) -> PaymentEngine {
+        let mut engine = PaymentEngine::new();
+        engine.set_dispute_funds_policy(policy);
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10)))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 2).with_amount(dec!(6)))
+            .unwrap();
+        engine.process_action(tx(TxType::Dispute, 1, 1)).unwrap();
+        engine
+    }
+
+    #[test]
+    fn test_cap_holds_available_and_reports_shortfall() {
+        let mut engine = spent_deposit(DisputeFundsPolicy::CapAtAvailable);
+        assert_eq!(engine.accounts[&1].available, dec!(0));
+        assert_eq!(engine.accounts[&1].held, dec!(4));
+
+        let events = engine.drain_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, EventKind::DisputeShortfall);
+        assert_eq!(events[0].action.amount.map(Amount::value), Some(dec!(6)));
+
+        engine.process_action(tx(TxType::Resolve, 1, 1)).unwrap();
+        assert_eq!(engine.accounts[&1].available, dec!(4));
+        assert_eq!(engine.accounts[&1].held, dec!(0));
+    }
+
+    #[test]
+    fn test_queued_dispute_applies_once_funds_return() {
+        let mut engine = spent_deposit(DisputeFundsPolicy::Queue);
+        assert_eq!(engine.accounts[&1].held, dec!(0));
+        engine
+            .process_action(tx(TxType::Resolve, 1, 1))
+            .unwrap_err();
+
+        engine
+            .process_action(tx(TxType::Deposit, 1, 3).with_amount(dec!(7)))
+            .unwrap();
+        assert_eq!(engine.accounts[&1].available, dec!(1));
+        assert_eq!(engine.accounts[&1].held, dec!(10));
+
+        let kinds: Vec<_> = engine.drain_events().iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![EventKind::DisputeQueued, EventKind::QueuedDisputeApplied]
+        );
+    }
+
+    #[test]
+    fn test_dispute_state_transitions() {
+        let mut engine = spent_deposit(DisputeFundsPolicy::Queue);
+        let error = engine
+            .process_action(tx(TxType::Dispute, 1, 1))
+            .unwrap_err();
+        assert_eq!(error.code(), ErrorCode::AlreadyDisputed);
+        assert_eq!(engine.dispute_state(1, 1), DisputeState::Undisputed);
+
+        engine
+            .process_action(tx(TxType::Deposit, 1, 3).with_amount(dec!(7)))
+            .unwrap();
+        assert_eq!(engine.dispute_state(1, 1), DisputeState::Disputed);
+        engine.process_action(tx(TxType::Chargeback, 1, 1)).unwrap();
+        assert_eq!(engine.dispute_state(1, 1), DisputeState::ChargedBack);
+        assert_eq!(engine.accounts[&1].held, dec!(0));
+
+        for step in [TxType::Dispute, TxType::Resolve, TxType::Chargeback] {
+            let error = engine.process_action(tx(step, 1, 1)).unwrap_err();
+            assert_eq!(error.code(), ErrorCode::DisputeClosed);
+        }
+        let error = engine
+            .process_action(tx(TxType::Resolve, 1, 3))
+            .unwrap_err();
+        assert_eq!(error.code(), ErrorCode::NotUnderDispute);
+    }
+}
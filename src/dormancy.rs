@@ -0,0 +1,225 @@
+use rust_decimal::Decimal;
+
+use crate::{
+    EngineEvent, EventKind, PaymentEngine, RESERVED_ACCOUNTS, TxType, UserAccount,
+    UserTransactions, errors::EngineError, ids::SyntheticKind, money::Amount,
+};
+
+/// Internal account that collects the balances swept out of dormant
+/// accounts.
+pub const DORMANT_ACCOUNT: u16 = u16::MAX - 1;
+
+/// When an account counts as dormant and what happens to it then.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DormancyPolicy {
+    /// Stream time without a transaction after which an account is dormant.
+    /// Only accounts that had a timestamped transaction are tracked.
+    pub after_secs: u64,
+    /// Close the account, so later transactions are refused. Otherwise it is
+    /// only flagged, and its next transaction clears the flag.
+    pub close: bool,
+    /// Move the available balance to [`DORMANT_ACCOUNT`].
+    pub sweep: bool,
+}
+
+impl PaymentEngine {
+    pub fn set_dormancy_policy(&mut self, policy: DormancyPolicy) {
+        self.dormancy = Some(policy);
+    }
+
+    pub fn is_dormant(&self, client_id: u16) -> bool {
+        self.dormant.contains(&client_id)
+    }
+
+    pub(crate) fn note_activity_time(&mut self, client_id: u16, timestamp: Option<u64>) {
+        let (Some(policy), Some(now)) = (self.dormancy, timestamp.or(self.stream_time)) else {
+            return;
+        };
+        self.last_active_at.insert(client_id, now);
+        self.dormant.remove(&client_id);
+        let due = now.saturating_add(policy.after_secs);
+        self.dormancy_due = Some(self.dormancy_due.map_or(due, |d| d.min(due)));
+    }
+
+    /// Acts on every account that has gone dormant by `now`. Accounts with
+    /// an open dispute wait until it is settled.
+    pub(crate) fn check_dormancy(&mut self, now: u64) {
+        let Some(policy) = self.dormancy else {
+            return;
+        };
+        if self.dormancy_due.is_none_or(|due| due > now) {
+            return;
+        }
+        let mut dormant: Vec<u16> = self
+            .last_active_at
+            .iter()
+            .filter(|(client_id, last)| {
+                last.saturating_add(policy.after_secs) <= now
+                    && !self.dormant.contains(client_id)
+                    && !RESERVED_ACCOUNTS.contains(client_id)
+                    && self.account_stats(**client_id).open_disputes == 0
+            })
+            .map(|(client_id, _)| *client_id)
+            .collect();
+        dormant.sort_unstable();
+        for client_id in dormant {
+            // Out of synthetic ids: leave the account as it is.
+            let _ = self.mark_dormant(client_id, policy);
+        }
+
+        self.dormancy_due = self
+            .last_active_at
+            .iter()
+            .filter(|(client_id, _)| !self.dormant.contains(client_id))
+            .map(|(_, last)| last.saturating_add(policy.after_secs))
+            .filter(|due| *due > now)
+            .min();
+    }
+
+    fn mark_dormant(&mut self, client_id: u16, policy: DormancyPolicy) -> Result<(), EngineError> {
+        let available = self
+            .accounts
+            .get(&client_id)
+            .map_or(Decimal::ZERO, |account| account.available);
+        let sweep = policy.sweep && available > Decimal::ZERO;
+        if sweep || policy.close {
+            let tx_id = self.synthetic_ids.next(SyntheticKind::Sweep)?;
+            if sweep {
+                self.sweep_dormant(client_id, tx_id, available);
+            }
+            if policy.close {
+                self.closed_accounts.insert(client_id);
+                self.record_synthetic(
+                    EventKind::DormantClosed,
+                    TxType::CloseAccount,
+                    client_id,
+                    tx_id,
+                    None,
+                );
+            }
+        }
+        self.dormant.insert(client_id);
+        Ok(())
+    }
+
+    fn sweep_dormant(&mut self, client_id: u16, tx_id: u32, available: Decimal) {
+        let Ok(amount) = Amount::new(available) else {
+            return;
+        };
+        for (account_id, delta, tx_type) in [
+            (client_id, -available, TxType::Withdrawal),
+            (DORMANT_ACCOUNT, available, TxType::Deposit),
+        ] {
+            let account = self
+                .accounts
+                .entry(account_id)
+                .or_insert_with(|| UserAccount::new(account_id));
+            account.available += delta;
+            account.calculate_total();
+            self.record_synthetic(
+                EventKind::DormancySweep,
+                tx_type,
+                account_id,
+                tx_id,
+                Some(amount),
+            );
+        }
+    }
+
+    fn record_synthetic(
+        &mut self,
+        kind: EventKind,
+        tx_type: TxType,
+        client_id: u16,
+        tx_id: u32,
+        amount: Option<Amount>,
+    ) {
+        let action = UserTransactions {
+            tx_type,
+            client_id,
+            tx_id,
+            amount,
+            timestamp: self.stream_time,
+            ..Default::default()
+        };
+        self.record_action(action.clone());
+        self.events.push(EngineEvent { kind, action });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{errors::ErrorCode, signed_amount, tx};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_dormant_accounts_are_flagged_or_closed_and_swept() {
+        let mut engine = PaymentEngine::new();
+        engine.set_dormancy_policy(DormancyPolicy {
+            after_secs: 100,
+            close: false,
+            sweep: false,
+        });
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10)).at(0))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Deposit, 2, 2).with_amount(dec!(10)).at(50))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Deposit, 2, 3).with_amount(dec!(10)).at(120))
+            .unwrap();
+        assert!(engine.is_dormant(1));
+        assert!(!engine.is_dormant(2));
+        engine
+            .process_action(tx(TxType::Deposit, 1, 4).with_amount(dec!(10)).at(130))
+            .unwrap();
+        assert!(!engine.is_dormant(1));
+        assert!(engine.drain_events().is_empty());
+
+        let mut engine = PaymentEngine::new();
+        engine.set_dormancy_policy(DormancyPolicy {
+            after_secs: 100,
+            close: true,
+            sweep: true,
+        });
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10)).at(0))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Deposit, 2, 2).with_amount(dec!(10)).at(100))
+            .unwrap();
+        assert!(engine.is_closed(1));
+        assert_eq!(engine.accounts[&1].available, dec!(0));
+        assert_eq!(engine.accounts[&DORMANT_ACCOUNT].available, dec!(10));
+        let kinds: Vec<EventKind> = engine.drain_events().iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                EventKind::DormancySweep,
+                EventKind::DormancySweep,
+                EventKind::DormantClosed
+            ]
+        );
+        let error = engine
+            .process_action(tx(TxType::Deposit, 1, 3).with_amount(dec!(10)).at(110))
+            .unwrap_err();
+        assert_eq!(error.code(), ErrorCode::AccountClosed);
+    }
+
+    #[test]
+    fn test_dormant_account_refuses_transactions_and_adjustments() {
+        let mut engine = PaymentEngine::new();
+        let error = engine
+            .process_action(tx(TxType::Deposit, DORMANT_ACCOUNT, 1).with_amount(dec!(5)))
+            .unwrap_err();
+        assert_eq!(error.code(), ErrorCode::ReservedAccount);
+        let reason = "FEE_REFUND".parse().unwrap();
+        let error = engine
+            .apply_adjustment(DORMANT_ACCOUNT, signed_amount(dec!(5)), reason)
+            .unwrap_err();
+        assert_eq!(error.code(), ErrorCode::ReservedAccount);
+        assert!(!engine.accounts.contains_key(&DORMANT_ACCOUNT));
+    }
+}
@@ -0,0 +1,197 @@
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+
+use crate::{
+    EngineEvent, EventKind, PaymentEngine, TxType, UserTransactions,
+    disputes::DisputeState,
+    errors::{EngineError, ErrorCode},
+    money::Amount,
+};
+
+/// What to do with a deposit or withdrawal whose tx id was already applied,
+/// for any client.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum DuplicatePolicy {
+    /// Reject the repeat; the first transaction stands.
+    #[default]
+    Strict,
+    /// Undo the earlier transaction and apply the repeat in its place. A
+    /// transaction with a dispute can't be replaced.
+    LastWriteWins,
+}
+
+impl FromStr for DuplicatePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(Self::Strict),
+            "last-write-wins" => Ok(Self::LastWriteWins),
+            other => Err(format!(
+                "Unknown duplicate policy '{}', expected strict or last-write-wins",
+                other
+            )),
+        }
+    }
+}
+
+impl PaymentEngine {
+    pub fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicate_policy = policy;
+    }
+
+    /// Client whose transaction `action` replaces, if any.
+    fn check_duplicate(&self, action: &UserTransactions) -> Result<Option<u16>, EngineError> {
+        let Some(&client_id) = self.seen_tx_ids.get(&action.tx_id) else {
+            return Ok(None);
+        };
+        let duplicate = |reason: String| {
+            Err(EngineError::new(
+                ErrorCode::DuplicateTransaction,
+                format!("Transaction {} {}", action.tx_id, reason),
+            ))
+        };
+        if self.duplicate_policy == DuplicatePolicy::Strict {
+            return duplicate(format!("was already applied for client {}", client_id));
+        }
+        let queued = self
+            .queued_disputes
+            .iter()
+            .any(|(queued, _)| queued.client_id == client_id && queued.tx_id == action.tx_id);
+        if queued || self.dispute_state(client_id, action.tx_id) != DisputeState::Undisputed {
+            return duplicate("has a dispute and can't be replaced".to_string());
+        }
+        let transfer = self
+            .actions
+            .get(&client_id)
+            .and_then(|txs| txs.get(&action.tx_id))
+            .and_then(|acts| acts.first())
+            .is_some_and(|original| original.tx_type == TxType::Transfer);
+        if transfer {
+            return duplicate("is a transfer and can't be replaced".to_string());
+        }
+        Ok(Some(client_id))
+    }
+
+    /// Takes the balance effect of `client_id`'s transaction `tx_id` back
+    /// out, or with `redo` puts it back in.
+    fn reverse_transfer(&mut self, client_id: u16, tx_id: u32, redo: bool) {
+        let Some(original) = self
+            .actions
+            .get(&client_id)
+            .and_then(|txs| txs.get(&tx_id))
+            .and_then(|acts| acts.first())
+        else {
+            return;
+        };
+        let amount = original.amount.map_or(Decimal::ZERO, Amount::value);
+        let mut change = match original.tx_type {
+            TxType::Withdrawal => amount,
+            _ => -amount,
+        };
+        if redo {
+            change = -change;
+        }
+        if let Some(account) = self.accounts.get_mut(&client_id) {
+            account.available += change;
+            account.calculate_total();
+        }
+    }
+
+    /// Applies a deposit or withdrawal, enforcing the duplicate policy.
+    pub(crate) fn process_transfer(
+        &mut self,
+        action: &UserTransactions,
+    ) -> Result<(), EngineError> {
+        let replaced = self.check_duplicate(action)?;
+        if let Some(client_id) = replaced {
+            self.reverse_transfer(client_id, action.tx_id, false);
+        }
+        let result = match action.tx_type {
+            TxType::Withdrawal => self.process_withdrawal(action),
+            _ => self.process_deposit(action),
+        };
+        if let Some(client_id) = replaced {
+            if result.is_err() {
+                self.reverse_transfer(client_id, action.tx_id, true);
+            } else if let Some(original) = self
+                .actions
+                .get_mut(&client_id)
+                .and_then(|txs| txs.remove(&action.tx_id))
+                .and_then(|acts| acts.into_iter().next())
+            {
+                self.events.push(EngineEvent {
+                    kind: EventKind::DuplicateReplaced,
+                    action: original,
+                });
+            }
+        }
+        result?;
+        self.seen_tx_ids.insert(action.tx_id, action.client_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tx;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_strict_rejects_repeated_tx_id() {
+        let mut engine = PaymentEngine::new();
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10)))
+            .unwrap();
+        for repeat in [
+            tx(TxType::Deposit, 1, 1).with_amount(dec!(10)),
+            tx(TxType::Deposit, 2, 1).with_amount(dec!(5)),
+            tx(TxType::Withdrawal, 1, 1).with_amount(dec!(1)),
+        ] {
+            let error = engine.process_action(repeat).unwrap_err();
+            assert_eq!(error.code(), ErrorCode::DuplicateTransaction);
+        }
+        assert_eq!(engine.accounts[&1].available, dec!(10));
+        assert!(!engine.accounts.contains_key(&2));
+    }
+
+    #[test]
+    fn test_last_write_wins_replaces_earlier_transfer() {
+        let mut engine = PaymentEngine::new();
+        engine.set_duplicate_policy(DuplicatePolicy::LastWriteWins);
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10)))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Deposit, 2, 2).with_amount(dec!(3)))
+            .unwrap();
+
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(4)))
+            .unwrap();
+        assert_eq!(engine.accounts[&1].available, dec!(4));
+
+        // A replacement the new client can't afford leaves the original.
+        let error = engine
+            .process_action(tx(TxType::Withdrawal, 2, 1).with_amount(dec!(5)))
+            .unwrap_err();
+        assert_eq!(error.code(), ErrorCode::InsufficientFunds);
+        assert_eq!(engine.accounts[&1].available, dec!(4));
+
+        engine
+            .process_action(tx(TxType::Withdrawal, 2, 1).with_amount(dec!(2)))
+            .unwrap();
+        assert_eq!(engine.accounts[&1].available, dec!(0));
+        assert_eq!(engine.accounts[&2].available, dec!(1));
+        let kinds: Vec<_> = engine.drain_events().iter().map(|e| e.kind).collect();
+        assert_eq!(kinds, vec![EventKind::DuplicateReplaced; 2]);
+
+        engine.process_action(tx(TxType::Dispute, 2, 1)).unwrap();
+        let error = engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(1)))
+            .unwrap_err();
+        assert_eq!(error.code(), ErrorCode::DuplicateTransaction);
+    }
+}
@@ -0,0 +1,195 @@
+use std::fmt;
+
+/// Why the engine refused a transaction. Each code is stable across
+/// versions: new reasons get new codes, and existing ones are never
+/// renumbered or reused, so automation can branch on them.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum ErrorCode {
+    InsufficientFunds,
+    WithdrawalsFrozen,
+    WithdrawalsBlockedByDispute,
+    NoAccount,
+    AccountNotOpen,
+    AccountAlreadyOpen,
+    AccountClosed,
+    /// A locked account can neither send nor receive a transfer.
+    AccountLocked,
+    TransactionNotFound,
+    NotUnderDispute,
+    AlreadyDisputed,
+    DisputeClosed,
+    DuplicateTransaction,
+    ClientBlocked,
+    PeriodClosed,
+    AdminOnly,
+    ReservedAccount,
+    /// Refused by a custom rule; see [`crate::rules::TransactionRule`].
+    RuleRejected,
+    InvalidAmount,
+    BatchRolledBack,
+    /// A transfer without an amount or a destination, to its own client, or
+    /// named by a dispute.
+    InvalidTransfer,
+    IdsExhausted,
+    /// The write-ahead log couldn't be written, so nothing was applied.
+    LogWriteFailed,
+}
+
+impl ErrorCode {
+    /// Stable identifier, e.g. `PE1001`. The first digit groups the codes:
+    /// 1 balances and accounts, 2 transaction references, 3 policy, 4 input,
+    /// 5 engine limits and failures.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorCode::InsufficientFunds => "PE1001",
+            ErrorCode::WithdrawalsFrozen => "PE1002",
+            ErrorCode::WithdrawalsBlockedByDispute => "PE1003",
+            ErrorCode::NoAccount => "PE1004",
+            ErrorCode::AccountNotOpen => "PE1005",
+            ErrorCode::AccountAlreadyOpen => "PE1006",
+            ErrorCode::AccountClosed => "PE1007",
+            ErrorCode::AccountLocked => "PE1008",
+            ErrorCode::TransactionNotFound => "PE2001",
+            ErrorCode::NotUnderDispute => "PE2002",
+            ErrorCode::AlreadyDisputed => "PE2003",
+            ErrorCode::DisputeClosed => "PE2004",
+            ErrorCode::DuplicateTransaction => "PE2005",
+            ErrorCode::ClientBlocked => "PE3001",
+            ErrorCode::PeriodClosed => "PE3002",
+            ErrorCode::AdminOnly => "PE3003",
+            ErrorCode::ReservedAccount => "PE3004",
+            ErrorCode::RuleRejected => "PE3005",
+            ErrorCode::InvalidAmount => "PE4001",
+            ErrorCode::BatchRolledBack => "PE4002",
+            ErrorCode::InvalidTransfer => "PE4003",
+            ErrorCode::IdsExhausted => "PE5001",
+            ErrorCode::LogWriteFailed => "PE5002",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorCode::InsufficientFunds => "InsufficientFunds",
+            ErrorCode::WithdrawalsFrozen => "WithdrawalsFrozen",
+            ErrorCode::WithdrawalsBlockedByDispute => "WithdrawalsBlockedByDispute",
+            ErrorCode::NoAccount => "NoAccount",
+            ErrorCode::AccountNotOpen => "AccountNotOpen",
+            ErrorCode::AccountAlreadyOpen => "AccountAlreadyOpen",
+            ErrorCode::AccountClosed => "AccountClosed",
+            ErrorCode::AccountLocked => "AccountLocked",
+            ErrorCode::TransactionNotFound => "TransactionNotFound",
+            ErrorCode::NotUnderDispute => "NotUnderDispute",
+            ErrorCode::AlreadyDisputed => "AlreadyDisputed",
+            ErrorCode::DisputeClosed => "DisputeClosed",
+            ErrorCode::DuplicateTransaction => "DuplicateTransaction",
+            ErrorCode::ClientBlocked => "ClientBlocked",
+            ErrorCode::PeriodClosed => "PeriodClosed",
+            ErrorCode::AdminOnly => "AdminOnly",
+            ErrorCode::ReservedAccount => "ReservedAccount",
+            ErrorCode::RuleRejected => "RuleRejected",
+            ErrorCode::InvalidAmount => "InvalidAmount",
+            ErrorCode::BatchRolledBack => "BatchRolledBack",
+            ErrorCode::InvalidTransfer => "InvalidTransfer",
+            ErrorCode::IdsExhausted => "IdsExhausted",
+            ErrorCode::LogWriteFailed => "LogWriteFailed",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.code(), self.name())
+    }
+}
+
+/// A refused transaction: a stable [`ErrorCode`] plus a human-readable
+/// message. Displays as the message alone.
+#[derive(Debug, PartialEq, Clone)]
+pub struct EngineError {
+    code: ErrorCode,
+    message: String,
+}
+
+impl EngineError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+impl From<EngineError> for String {
+    fn from(error: EngineError) -> Self {
+        error.message
+    }
+}
+
+pub(crate) fn no_account(client_id: u16) -> EngineError {
+    EngineError::new(
+        ErrorCode::NoAccount,
+        format!("Client {} has no account", client_id),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_codes_are_unique_and_pinned() {
+        let all = [
+            ErrorCode::InsufficientFunds,
+            ErrorCode::WithdrawalsFrozen,
+            ErrorCode::WithdrawalsBlockedByDispute,
+            ErrorCode::NoAccount,
+            ErrorCode::AccountNotOpen,
+            ErrorCode::AccountAlreadyOpen,
+            ErrorCode::AccountClosed,
+            ErrorCode::AccountLocked,
+            ErrorCode::TransactionNotFound,
+            ErrorCode::NotUnderDispute,
+            ErrorCode::AlreadyDisputed,
+            ErrorCode::DisputeClosed,
+            ErrorCode::DuplicateTransaction,
+            ErrorCode::ClientBlocked,
+            ErrorCode::PeriodClosed,
+            ErrorCode::AdminOnly,
+            ErrorCode::ReservedAccount,
+            ErrorCode::RuleRejected,
+            ErrorCode::InvalidAmount,
+            ErrorCode::BatchRolledBack,
+            ErrorCode::InvalidTransfer,
+            ErrorCode::IdsExhausted,
+            ErrorCode::LogWriteFailed,
+        ];
+        let codes: HashSet<&str> = all.iter().map(ErrorCode::code).collect();
+        assert_eq!(codes.len(), all.len());
+        assert_eq!(
+            ErrorCode::InsufficientFunds.to_string(),
+            "PE1001 InsufficientFunds"
+        );
+        assert_eq!(ErrorCode::TransactionNotFound.code(), "PE2001");
+
+        let error = no_account(7);
+        assert_eq!(error.code(), ErrorCode::NoAccount);
+        assert_eq!(String::from(error), "Client 7 has no account");
+    }
+}
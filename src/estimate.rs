@@ -0,0 +1,184 @@
+use std::{collections::HashSet, io::Write, mem::size_of, path::Path};
+
+use serde::Serialize;
+
+use crate::{UserAccount, UserTransactions, risk::AccountStats};
+
+/// How the input reaches the engine.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EngineMode {
+    /// Rows are read one at a time from the file, as a normal run does.
+    Streaming,
+    /// Every row is loaded before processing, as with a memory source or a
+    /// parallel run.
+    InMemory,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct ModeEstimate {
+    pub mode: EngineMode,
+    pub peak_bytes: u64,
+}
+
+/// What [`estimate`] projects for a whole input from a sample of it.
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryEstimate {
+    pub input: String,
+    pub file_bytes: u64,
+    pub sampled_rows: u64,
+    /// Whether the sample covered the whole file, making the projections
+    /// exact counts.
+    pub complete: bool,
+    pub projected_rows: u64,
+    pub projected_clients: u64,
+    /// Distinct transaction ids, each of which keeps a ledger entry.
+    pub projected_transactions: u64,
+    pub modes: Vec<ModeEstimate>,
+}
+
+impl MemoryEstimate {
+    pub fn write_json<W: Write>(&self, writer: W) -> Result<(), String> {
+        serde_json::to_writer_pretty(writer, self)
+            .map_err(|e| format!("Failed to write estimate: {}", e))
+    }
+}
+
+/// Rows read from the start of the input when no sample size is given.
+pub const DEFAULT_SAMPLE_ROWS: u64 = 100_000;
+
+/// Hash maps keep at most 7 of every 8 slots full, plus a control byte per
+/// slot, and double when they grow.
+fn map_entry_bytes(entry: usize) -> u64 {
+    ((entry + 1) * 8 / 7 * 2) as u64
+}
+
+/// Engine memory per client: its account and the per-client maps.
+fn client_bytes() -> u64 {
+    map_entry_bytes(size_of::<u16>() + size_of::<UserAccount>())
+        + map_entry_bytes(size_of::<u16>() + size_of::<AccountStats>())
+        + map_entry_bytes(size_of::<u16>() + size_of::<u64>())
+        + map_entry_bytes(size_of::<u16>() + size_of::<std::collections::HashMap<u32, ()>>())
+}
+
+/// Engine memory per transaction id: its ledger entry, whose record vector
+/// starts with room for four records, and its duplicate-check entry.
+fn transaction_bytes() -> u64 {
+    map_entry_bytes(size_of::<u32>() + size_of::<Vec<UserTransactions>>())
+        + (4 * size_of::<UserTransactions>()) as u64
+        + map_entry_bytes(size_of::<u32>() + size_of::<u16>())
+}
+
+/// Reads up to `sample_rows` rows from the start of `input` and projects
+/// its size and the engine's peak memory in each [`EngineMode`]. The
+/// projection assumes the rest of the file looks like its start, and
+/// counts structures only, not allocator overhead.
+pub fn estimate(
+    input: &str,
+    sample_rows: u64,
+) -> Result<MemoryEstimate, Box<dyn std::error::Error>> {
+    let file_bytes = std::fs::metadata(input)?.len();
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_path(Path::new(input))?;
+    let headers = rdr.headers()?.clone();
+    let header_bytes = rdr.position().byte();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header == name)
+            .ok_or_else(|| format!("Input has no '{}' column", name))
+    };
+    let (client_column, tx_column) = (column("client")?, column("tx")?);
+
+    let mut clients = HashSet::new();
+    let mut transactions = HashSet::new();
+    // Clients first seen in the second half of the sample, to tell how fast
+    // new ones still turn up.
+    let mut late_clients = 0u64;
+    let mut sampled_rows = 0u64;
+    let mut sampled_bytes = header_bytes;
+    let mut record = csv::StringRecord::new();
+    while sampled_rows < sample_rows && rdr.read_record(&mut record)? {
+        sampled_rows += 1;
+        sampled_bytes = rdr.position().byte();
+        let client = record.get(client_column).unwrap_or_default().to_string();
+        if clients.insert(client) && sampled_rows > sample_rows / 2 {
+            late_clients += 1;
+        }
+        transactions.insert(record.get(tx_column).unwrap_or_default().to_string());
+    }
+    let complete = sampled_rows < sample_rows || !rdr.read_record(&mut record)?;
+
+    let (projected_rows, projected_clients, projected_transactions) = if complete {
+        (
+            sampled_rows,
+            clients.len() as u64,
+            transactions.len() as u64,
+        )
+    } else {
+        let rows = (u128::from(sampled_rows) * u128::from(file_bytes - header_bytes)
+            / u128::from((sampled_bytes - header_bytes).max(1))) as u64;
+        let remaining = rows.saturating_sub(sampled_rows);
+        let half = (sampled_rows - sampled_rows / 2).max(1);
+        let clients = clients.len() as u64 + late_clients * remaining / half;
+        let transactions = transactions.len() as u64 * rows / sampled_rows.max(1);
+        (rows, clients.min(1 << 16), transactions)
+    };
+
+    let state = projected_clients * client_bytes() + projected_transactions * transaction_bytes();
+    let rows = projected_rows * size_of::<UserTransactions>() as u64;
+    Ok(MemoryEstimate {
+        input: input.to_string(),
+        file_bytes,
+        sampled_rows,
+        complete,
+        projected_rows,
+        projected_clients,
+        projected_transactions,
+        modes: vec![
+            ModeEstimate {
+                mode: EngineMode::Streaming,
+                peak_bytes: state,
+            },
+            ModeEstimate {
+                mode: EngineMode::InMemory,
+                peak_bytes: state + rows,
+            },
+        ],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_projects_from_a_sample() {
+        let exact = estimate("test_comprehensive.csv", DEFAULT_SAMPLE_ROWS).unwrap();
+        assert!(exact.complete);
+        assert_eq!(exact.sampled_rows, 11);
+        assert_eq!(
+            (
+                exact.projected_rows,
+                exact.projected_clients,
+                exact.projected_transactions
+            ),
+            (11, 3, 6)
+        );
+        let [streaming, in_memory] = exact.modes[..] else {
+            panic!("expected two modes");
+        };
+        assert_eq!(
+            in_memory.peak_bytes - streaming.peak_bytes,
+            11 * size_of::<UserTransactions>() as u64
+        );
+
+        let sampled = estimate("test_comprehensive.csv", 4).unwrap();
+        assert!(!sampled.complete);
+        assert_eq!(sampled.sampled_rows, 4);
+        assert!((9..=13).contains(&sampled.projected_rows));
+        assert!(sampled.projected_clients >= 2);
+    }
+}
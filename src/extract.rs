@@ -0,0 +1,206 @@
+use std::{
+    collections::HashSet,
+    io::{Read, Write},
+};
+
+use rust_decimal::Decimal;
+
+use crate::data_sources::csv::open_input;
+
+/// Which rows of a transactions file [`extract`] keeps.
+#[derive(Debug, Default, Clone)]
+pub struct ExtractConfig {
+    /// Keep only these clients' rows.
+    pub clients: Option<HashSet<u16>>,
+    /// Keep roughly this share of rows, between 0 and 1. The choice depends
+    /// only on each row's type, client and tx, so reruns keep the same rows.
+    pub sample_rate: Option<Decimal>,
+    /// Also keep the deposits and withdrawals that kept disputes, resolves
+    /// and chargebacks refer to, so the slice replays like the original.
+    pub with_referenced: bool,
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct ExtractReport {
+    pub rows_read: u64,
+    pub rows_written: u64,
+}
+
+/// Copies the rows of `input` selected by `config` to `writer`, in their
+/// original order and with the original columns. Reads `input` twice when
+/// `with_referenced` is set.
+pub fn extract<W: Write>(
+    input: &str,
+    config: &ExtractConfig,
+    writer: W,
+) -> Result<ExtractReport, Box<dyn std::error::Error>> {
+    let referenced = if config.with_referenced {
+        let mut referenced = HashSet::new();
+        for_each_row(input, |row| {
+            if is_dispute_step(row.tx_type) && config.selects(row) {
+                referenced.insert((row.client.to_string(), row.tx.to_string()));
+            }
+        })?;
+        referenced
+    } else {
+        HashSet::new()
+    };
+
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(writer);
+    let mut rdr = reader(input)?;
+    writer.write_record(rdr.headers()?)?;
+    let columns = Columns::find(rdr.headers()?)?;
+
+    let mut report = ExtractReport::default();
+    for record in rdr.records() {
+        let record = record?;
+        report.rows_read += 1;
+        let row = columns.row(&record);
+        let keep = config.selects(&row)
+            || (matches!(row.tx_type, "deposit" | "withdrawal")
+                && referenced.contains(&(row.client.to_string(), row.tx.to_string())));
+        if keep {
+            writer.write_record(&record)?;
+            report.rows_written += 1;
+        }
+    }
+    writer.flush()?;
+    Ok(report)
+}
+
+struct Row<'a> {
+    tx_type: &'a str,
+    client: &'a str,
+    tx: &'a str,
+}
+
+struct Columns {
+    tx_type: usize,
+    client: usize,
+    tx: usize,
+}
+
+impl Columns {
+    fn find(headers: &csv::StringRecord) -> Result<Self, String> {
+        let position = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header == name)
+                .ok_or_else(|| format!("Input has no '{}' column", name))
+        };
+        Ok(Self {
+            tx_type: position("type")?,
+            client: position("client")?,
+            tx: position("tx")?,
+        })
+    }
+
+    fn row<'a>(&self, record: &'a csv::StringRecord) -> Row<'a> {
+        Row {
+            tx_type: record.get(self.tx_type).unwrap_or_default(),
+            client: record.get(self.client).unwrap_or_default(),
+            tx: record.get(self.tx).unwrap_or_default(),
+        }
+    }
+}
+
+impl ExtractConfig {
+    fn selects(&self, row: &Row) -> bool {
+        let client_matches = self.clients.as_ref().is_none_or(|clients| {
+            row.client
+                .parse()
+                .is_ok_and(|client: u16| clients.contains(&client))
+        });
+        let sampled = self.sample_rate.is_none_or(|rate| {
+            let bucket = Decimal::from(row_hash(row) % 1_000_000) / Decimal::from(1_000_000);
+            bucket < rate
+        });
+        client_matches && sampled
+    }
+}
+
+fn is_dispute_step(tx_type: &str) -> bool {
+    matches!(tx_type, "dispute" | "resolve" | "chargeback")
+}
+
+/// FNV-1a over the type, client and tx fields: stable across runs and
+/// platforms.
+fn row_hash(row: &Row) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let fields = [row.tx_type, row.client, row.tx].join(",");
+    for byte in fields.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+fn reader(input: &str) -> Result<csv::Reader<Box<dyn Read>>, csv::Error> {
+    Ok(csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(open_input(input)?))
+}
+
+fn for_each_row(
+    input: &str,
+    mut on_row: impl FnMut(&Row),
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut rdr = reader(input)?;
+    let columns = Columns::find(rdr.headers()?)?;
+    for record in rdr.records() {
+        on_row(&columns.row(&record?));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn run(config: &ExtractConfig) -> (ExtractReport, Vec<Vec<String>>) {
+        let mut out = Vec::new();
+        let report = extract("test_comprehensive.csv", config, &mut out).unwrap();
+        let rows = csv::Reader::from_reader(out.as_slice())
+            .records()
+            .map(|r| r.unwrap().iter().map(String::from).collect())
+            .collect();
+        (report, rows)
+    }
+
+    #[test]
+    fn test_extracts_clients_and_referenced_samples() {
+        let (report, rows) = run(&ExtractConfig {
+            clients: Some(HashSet::from([1])),
+            ..Default::default()
+        });
+        assert_eq!(report.rows_read, 11);
+        assert_eq!(report.rows_written, 5);
+        assert!(rows.iter().all(|row| row[1] == "1"));
+
+        let (_, all) = run(&ExtractConfig {
+            sample_rate: Some(dec!(1)),
+            ..Default::default()
+        });
+        assert_eq!(all.len(), 11);
+
+        let sample = ExtractConfig {
+            sample_rate: Some(dec!(0.3)),
+            ..Default::default()
+        };
+        let (_, plain) = run(&sample);
+        let (_, sampled) = run(&ExtractConfig {
+            with_referenced: true,
+            ..sample
+        });
+        assert!(sampled.len() > plain.len());
+        for row in sampled.iter().filter(|row| is_dispute_step(&row[0])) {
+            assert!(sampled.iter().any(|original| {
+                matches!(original[0].as_str(), "deposit" | "withdrawal")
+                    && original[1] == row[1]
+                    && original[2] == row[2]
+            }));
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use crate::PaymentEngine;
+
+impl PaymentEngine {
+    /// Independent copy of the engine's state, e.g. to try a scenario and
+    /// throw it away. Hooks and the write-ahead log aren't carried over and
+    /// pending events stay with the original; everything else, including
+    /// policies, is copied. The client state lives in persistent
+    /// collections, so a fork shares it with the original and only copies
+    /// what either side later changes.
+    pub fn fork(&self) -> PaymentEngine {
+        // Destructured so that a new field has to be given a fork behaviour
+        // here.
+        let PaymentEngine {
+            accounts,
+            actions,
+            retention,
+            tx_recency,
+            stream_time,
+            sweep_rules,
+            dispute_timeout_secs,
+            dispute_opened_at,
+            synthetic_ids,
+            events: _,
+            stats,
+            freeze_policy,
+            withdrawal_policy,
+            hooks: _,
+            deferred_hooks: _,
+            rules,
+            attributes,
+            require_open_accounts,
+            dispute_funds_policy,
+            dispute_holds,
+            dispute_states,
+            seen_tx_ids,
+            duplicate_policy,
+            debts,
+            debt_repayment,
+            config,
+            funds_holds,
+            reserved_funds,
+            closed_accounts,
+            dormancy,
+            last_active_at,
+            dormancy_due,
+            dormant,
+            queued_disputes,
+            last_activity,
+            activity_seq,
+            backfill,
+            quarantine,
+            access,
+            held_for_review,
+            closed_periods,
+            late_entry_policy,
+            adjustments,
+            wal: _,
+            admin_secret,
+            decisions,
+        } = self;
+        PaymentEngine {
+            accounts: accounts.clone(),
+            actions: actions.clone(),
+            retention: *retention,
+            tx_recency: tx_recency.clone(),
+            stream_time: *stream_time,
+            sweep_rules: sweep_rules.clone(),
+            dispute_timeout_secs: *dispute_timeout_secs,
+            dispute_opened_at: dispute_opened_at.clone(),
+            synthetic_ids: synthetic_ids.clone(),
+            events: Vec::new(),
+            stats: stats.clone(),
+            freeze_policy: *freeze_policy,
+            withdrawal_policy: *withdrawal_policy,
+            hooks: None,
+            deferred_hooks: None,
+            rules: rules.clone(),
+            attributes: attributes.clone(),
+            require_open_accounts: *require_open_accounts,
+            dispute_funds_policy: *dispute_funds_policy,
+            dispute_holds: dispute_holds.clone(),
+            dispute_states: dispute_states.clone(),
+            seen_tx_ids: seen_tx_ids.clone(),
+            duplicate_policy: *duplicate_policy,
+            debts: debts.clone(),
+            debt_repayment: *debt_repayment,
+            config: *config,
+            funds_holds: funds_holds.clone(),
+            reserved_funds: reserved_funds.clone(),
+            closed_accounts: closed_accounts.clone(),
+            dormancy: *dormancy,
+            last_active_at: last_active_at.clone(),
+            dormancy_due: *dormancy_due,
+            dormant: dormant.clone(),
+            queued_disputes: queued_disputes.clone(),
+            last_activity: last_activity.clone(),
+            activity_seq: *activity_seq,
+            backfill: *backfill,
+            quarantine: quarantine.clone(),
+            access: access.clone(),
+            held_for_review: held_for_review.clone(),
+            closed_periods: closed_periods.clone(),
+            late_entry_policy: *late_entry_policy,
+            adjustments: adjustments.clone(),
+            wal: None,
+            admin_secret: *admin_secret,
+            decisions: decisions.as_ref().map(|_| Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_fork_is_independent() {
+        let mut engine = PaymentEngine::new();
+        engine.client(1).deposit(1, amount(dec!(10.0))).unwrap();
+
+        let mut fork = engine.fork();
+        assert!(fork.accounts.ptr_eq(&engine.accounts));
+        fork.client(1).withdraw(2, amount(dec!(4.0))).unwrap();
+        fork.client(1).dispute(1).unwrap();
+        assert_eq!(fork.accounts[&1].available, dec!(-4.0));
+        assert_eq!(fork.accounts[&1].held, dec!(10.0));
+
+        assert_eq!(engine.accounts[&1].available, dec!(10.0));
+        assert_eq!(engine.accounts[&1].held, dec!(0.0));
+        // Tx 2 only exists in the fork.
+        engine.client(1).withdraw(2, amount(dec!(1.0))).unwrap();
+        assert_eq!(engine.accounts[&1].available, dec!(9.0));
+    }
+}
@@ -0,0 +1,169 @@
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{PaymentEngine, UserTransactions, money::Amount};
+
+/// Where a deposit's money came from.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FundsClass {
+    Card,
+    BankTransfer,
+    Internal,
+}
+
+impl FromStr for FundsClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "card" => Ok(Self::Card),
+            "bank_transfer" => Ok(Self::BankTransfer),
+            "internal" => Ok(Self::Internal),
+            other => Err(format!(
+                "Unknown funds class '{}', expected card, bank_transfer or internal",
+                other
+            )),
+        }
+    }
+}
+
+/// Keeps deposits of `class` out of reach of withdrawals for `secs` of
+/// stream time.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FundsHold {
+    pub class: FundsClass,
+    pub secs: u64,
+}
+
+impl FromStr for FundsHold {
+    type Err = String;
+
+    /// Parses `<class>:<days>`, e.g. `card:3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (class, days) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid funds hold '{}', expected <class>:<days>", s))?;
+        let days: u64 = days
+            .parse()
+            .map_err(|_| format!("Invalid number of days '{}'", days))?;
+        Ok(Self {
+            class: class.parse()?,
+            secs: days * 24 * 60 * 60,
+        })
+    }
+}
+
+/// Part of a deposit still under its class's hold.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct ReservedFunds {
+    pub client_id: u16,
+    pub tx_id: u32,
+    pub class: FundsClass,
+    pub amount: Amount,
+    /// Stream time at which withdrawals may spend it.
+    pub release_at: u64,
+}
+
+impl PaymentEngine {
+    /// Holds deposits of `hold.class` for `hold.secs`, replacing any earlier
+    /// hold for that class. Only timestamped streams release held funds, so
+    /// deposits that arrive before the stream has a time aren't held.
+    pub fn set_funds_hold(&mut self, hold: FundsHold) {
+        self.funds_holds.insert(hold.class, hold.secs);
+    }
+
+    /// Deposits still under a hold, oldest first.
+    pub fn reserved_funds(&self) -> &im::Vector<ReservedFunds> {
+        &self.reserved_funds
+    }
+
+    /// How much of `client_id`'s available balance is still held back.
+    pub fn reserved_balance(&self, client_id: u16) -> Decimal {
+        self.reserved_funds
+            .iter()
+            .filter(|reserved| reserved.client_id == client_id)
+            .map(|reserved| reserved.amount.value())
+            .sum()
+    }
+
+    pub(crate) fn reserve_deposit(&mut self, action: &UserTransactions) {
+        let (Some(class), Some(now)) = (action.funds_class, self.stream_time) else {
+            return;
+        };
+        let Some(secs) = self.funds_holds.get(&class) else {
+            return;
+        };
+        if let Some(amount) = action.amount.filter(|a| a.value() > Decimal::ZERO) {
+            self.reserved_funds.push_back(ReservedFunds {
+                client_id: action.client_id,
+                tx_id: action.tx_id,
+                class,
+                amount,
+                release_at: action.timestamp.unwrap_or(now).saturating_add(*secs),
+            });
+        }
+    }
+
+    pub(crate) fn release_reserved_funds(&mut self, now: u64) {
+        self.reserved_funds
+            .retain(|reserved| reserved.release_at > now);
+    }
+
+    /// Drops the hold on a deposit whose money has left the account.
+    pub(crate) fn drop_reservation(&mut self, client_id: u16, tx_id: u32) {
+        self.reserved_funds
+            .retain(|reserved| (reserved.client_id, reserved.tx_id) != (client_id, tx_id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TxType, errors::ErrorCode, tx};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_card_deposits_are_held_until_released() {
+        let mut engine = PaymentEngine::new();
+        engine.set_funds_hold("card:3".parse().unwrap());
+        let day = 24 * 60 * 60;
+        engine
+            .process_action(
+                tx(TxType::Deposit, 1, 1)
+                    .with_amount(dec!(10))
+                    .with_funds_class(FundsClass::BankTransfer)
+                    .at(0),
+            )
+            .unwrap();
+        engine
+            .process_action(
+                tx(TxType::Deposit, 1, 2)
+                    .with_amount(dec!(50))
+                    .with_funds_class(FundsClass::Card)
+                    .at(0),
+            )
+            .unwrap();
+        assert_eq!(engine.reserved_balance(1), dec!(50));
+
+        let error = engine
+            .process_action(tx(TxType::Withdrawal, 1, 3).with_amount(dec!(20)).at(day))
+            .unwrap_err();
+        assert_eq!(error.code(), ErrorCode::InsufficientFunds);
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 4).with_amount(dec!(10)).at(day))
+            .unwrap();
+
+        engine
+            .process_action(
+                tx(TxType::Withdrawal, 1, 5)
+                    .with_amount(dec!(20))
+                    .at(3 * day),
+            )
+            .unwrap();
+        assert!(engine.reserved_funds().is_empty());
+        assert_eq!(engine.accounts[&1].available, dec!(30));
+    }
+}
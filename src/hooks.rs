@@ -0,0 +1,252 @@
+use crate::{PaymentEngine, TxType, UserAccount, UserTransactions};
+
+/// Side effects to run around each transaction type, e.g. posting to an
+/// external ledger. Every method defaults to a no-op, so implementors only
+/// override what they need.
+///
+/// `pre_*` sees the account as it is before the transaction (`None` if it
+/// doesn't exist yet) and runs even if the engine then rejects it. `post_*`
+/// runs only once the transaction has been applied. Transfer hooks also
+/// get the destination account.
+///
+/// Hooks must be `Send` so an engine can be moved to a worker thread.
+pub trait EngineHooks: Send {
+    fn pre_deposit(&mut self, _action: &UserTransactions, _account: Option<&UserAccount>) {}
+    fn post_deposit(&mut self, _action: &UserTransactions, _account: &UserAccount) {}
+    fn pre_withdrawal(&mut self, _action: &UserTransactions, _account: Option<&UserAccount>) {}
+    fn post_withdrawal(&mut self, _action: &UserTransactions, _account: &UserAccount) {}
+    fn pre_dispute(&mut self, _action: &UserTransactions, _account: Option<&UserAccount>) {}
+    fn post_dispute(&mut self, _action: &UserTransactions, _account: &UserAccount) {}
+    fn pre_resolve(&mut self, _action: &UserTransactions, _account: Option<&UserAccount>) {}
+    fn post_resolve(&mut self, _action: &UserTransactions, _account: &UserAccount) {}
+    fn pre_chargeback(&mut self, _action: &UserTransactions, _account: Option<&UserAccount>) {}
+    fn post_chargeback(&mut self, _action: &UserTransactions, _account: &UserAccount) {}
+    fn pre_open_account(&mut self, _action: &UserTransactions, _account: Option<&UserAccount>) {}
+    fn post_open_account(&mut self, _action: &UserTransactions, _account: &UserAccount) {}
+    fn pre_close_account(&mut self, _action: &UserTransactions, _account: Option<&UserAccount>) {}
+    fn post_close_account(&mut self, _action: &UserTransactions, _account: &UserAccount) {}
+    fn pre_transfer(
+        &mut self,
+        _action: &UserTransactions,
+        _from: Option<&UserAccount>,
+        _to: Option<&UserAccount>,
+    ) {
+    }
+    fn post_transfer(
+        &mut self,
+        _action: &UserTransactions,
+        _from: &UserAccount,
+        _to: &UserAccount,
+    ) {
+    }
+}
+
+/// Which side of a transaction a hook runs on.
+#[derive(Debug, Clone, Copy)]
+enum Stage {
+    Pre,
+    Post,
+}
+
+/// A hook call held back until the batch it belongs to commits, with the
+/// account as the hook would have seen it.
+pub(crate) struct DeferredHook {
+    stage: Stage,
+    action: UserTransactions,
+    account: Option<UserAccount>,
+    destination: Option<UserAccount>,
+}
+
+fn call(
+    hooks: &mut dyn EngineHooks,
+    stage: Stage,
+    action: &UserTransactions,
+    account: Option<&UserAccount>,
+    destination: Option<&UserAccount>,
+) {
+    match (stage, account) {
+        (Stage::Pre, account) => match action.tx_type {
+            TxType::Deposit => hooks.pre_deposit(action, account),
+            TxType::Withdrawal => hooks.pre_withdrawal(action, account),
+            TxType::Dispute => hooks.pre_dispute(action, account),
+            TxType::Resolve => hooks.pre_resolve(action, account),
+            TxType::Chargeback => hooks.pre_chargeback(action, account),
+            TxType::OpenAccount => hooks.pre_open_account(action, account),
+            TxType::CloseAccount => hooks.pre_close_account(action, account),
+            TxType::Transfer => hooks.pre_transfer(action, account, destination),
+            TxType::Adjustment => {}
+        },
+        (Stage::Post, Some(account)) => match action.tx_type {
+            TxType::Deposit => hooks.post_deposit(action, account),
+            TxType::Withdrawal => hooks.post_withdrawal(action, account),
+            TxType::Dispute => hooks.post_dispute(action, account),
+            TxType::Resolve => hooks.post_resolve(action, account),
+            TxType::Chargeback => hooks.post_chargeback(action, account),
+            TxType::OpenAccount => hooks.post_open_account(action, account),
+            TxType::CloseAccount => hooks.post_close_account(action, account),
+            TxType::Transfer => {
+                if let Some(destination) = destination {
+                    hooks.post_transfer(action, account, destination);
+                }
+            }
+            TxType::Adjustment => {}
+        },
+        (Stage::Post, None) => {}
+    }
+}
+
+impl PaymentEngine {
+    /// Replaces any hooks set before.
+    pub fn set_hooks(&mut self, hooks: Box<dyn EngineHooks>) {
+        self.hooks = Some(hooks);
+    }
+
+    /// While backfilling, state is rebuilt from historical input and hooks
+    /// are not called. Turn it off again before live traffic.
+    pub fn set_backfill_mode(&mut self, backfill: bool) {
+        self.backfill = backfill;
+    }
+
+    pub fn is_backfill(&self) -> bool {
+        self.backfill
+    }
+
+    pub(crate) fn run_pre_hooks(&mut self, action: &UserTransactions) {
+        self.run_hooks(Stage::Pre, action);
+    }
+
+    pub(crate) fn run_post_hooks(&mut self, action: &UserTransactions) {
+        self.run_hooks(Stage::Post, action);
+    }
+
+    fn run_hooks(&mut self, stage: Stage, action: &UserTransactions) {
+        let Some(hooks) = self.hooks.as_mut().filter(|_| !self.backfill) else {
+            return;
+        };
+        let account = self.accounts.get(&action.client_id);
+        let destination = action
+            .to_client_id
+            .filter(|_| action.tx_type == TxType::Transfer)
+            .and_then(|to| self.accounts.get(&to));
+        match self.deferred_hooks.as_mut() {
+            Some(deferred) => deferred.push(DeferredHook {
+                stage,
+                action: action.clone(),
+                account: account.cloned(),
+                destination: destination.cloned(),
+            }),
+            None => call(hooks.as_mut(), stage, action, account, destination),
+        }
+    }
+
+    /// Holds hook calls back until [`Self::finish_deferred_hooks`].
+    pub(crate) fn defer_hooks(&mut self) {
+        self.deferred_hooks = Some(Vec::new());
+    }
+
+    /// Makes the calls held back since [`Self::defer_hooks`]. Without
+    /// `commit` only the `pre_*` calls are made, as for any rejected
+    /// transaction.
+    pub(crate) fn finish_deferred_hooks(&mut self, commit: bool) {
+        let deferred = self.deferred_hooks.take().unwrap_or_default();
+        if let Some(hooks) = self.hooks.as_mut() {
+            for hook in deferred {
+                if !commit && matches!(hook.stage, Stage::Post) {
+                    continue;
+                }
+                call(
+                    hooks.as_mut(),
+                    hook.stage,
+                    &hook.action,
+                    hook.account.as_ref(),
+                    hook.destination.as_ref(),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tx;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder {
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl EngineHooks for Recorder {
+        fn pre_withdrawal(&mut self, action: &UserTransactions, account: Option<&UserAccount>) {
+            let available = account.map_or(Decimal::ZERO, |a| a.available);
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("pre_withdrawal {} {}", action.tx_id, available));
+        }
+
+        fn post_withdrawal(&mut self, action: &UserTransactions, account: &UserAccount) {
+            self.calls.lock().unwrap().push(format!(
+                "post_withdrawal {} {}",
+                action.tx_id, account.available
+            ));
+        }
+    }
+
+    #[test]
+    fn test_hooks_see_account_state_and_skip_post_on_rejection() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = PaymentEngine::new();
+        engine.set_hooks(Box::new(Recorder {
+            calls: calls.clone(),
+        }));
+
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10)))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 2).with_amount(dec!(4)))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 3).with_amount(dec!(50)))
+            .unwrap_err();
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "pre_withdrawal 2 10",
+                "post_withdrawal 2 6",
+                "pre_withdrawal 3 6",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_backfill_suppresses_hooks() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = PaymentEngine::new();
+        engine.set_hooks(Box::new(Recorder {
+            calls: calls.clone(),
+        }));
+
+        engine.set_backfill_mode(true);
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10)))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 2).with_amount(dec!(4)))
+            .unwrap();
+        assert!(calls.lock().unwrap().is_empty());
+
+        engine.set_backfill_mode(false);
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 3).with_amount(dec!(1)))
+            .unwrap();
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec!["pre_withdrawal 3 6", "post_withdrawal 3 5"]
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{EngineError, ErrorCode};
+
+/// Transactions the engine generates itself. Each kind owns a fixed block
+/// of ids above the ones partners send us, so an entry's id depends only on
+/// how many entries of its kind came before it. Replaying the same input
+/// therefore reproduces the same ids.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize, Serialize)]
+pub enum SyntheticKind {
+    Payout,
+    Sweep,
+    Adjustment,
+}
+
+/// Number of ids in each kind's block.
+pub const SYNTHETIC_RANGE_LEN: u32 = 100_000_000;
+
+impl SyntheticKind {
+    pub const fn range_start(self) -> u32 {
+        let block = match self {
+            SyntheticKind::Payout => 0,
+            SyntheticKind::Sweep => 1,
+            SyntheticKind::Adjustment => 2,
+        };
+        4_000_000_000 + block * SYNTHETIC_RANGE_LEN
+    }
+}
+
+/// Hands out synthetic ids in order within each kind's block.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct SyntheticIds {
+    issued: HashMap<SyntheticKind, u32>,
+}
+
+impl SyntheticIds {
+    pub fn next(&mut self, kind: SyntheticKind) -> Result<u32, EngineError> {
+        let issued = self.issued.entry(kind).or_default();
+        if *issued >= SYNTHETIC_RANGE_LEN {
+            return Err(EngineError::new(
+                ErrorCode::IdsExhausted,
+                format!("Synthetic id range for {:?} is exhausted", kind),
+            ));
+        }
+        *issued += 1;
+        Ok(kind.range_start() + *issued - 1)
+    }
+
+    /// Ids still left in `kind`'s block.
+    pub fn remaining(&self, kind: SyntheticKind) -> u32 {
+        SYNTHETIC_RANGE_LEN - self.issued.get(&kind).copied().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kinds_allocate_independently() {
+        let mut ids = SyntheticIds::default();
+        assert_eq!(ids.next(SyntheticKind::Sweep), Ok(4_100_000_000));
+        assert_eq!(ids.next(SyntheticKind::Payout), Ok(4_000_000_000));
+        assert_eq!(ids.next(SyntheticKind::Sweep), Ok(4_100_000_001));
+    }
+}
@@ -0,0 +1,97 @@
+use rust_decimal::Decimal;
+
+use crate::{PaymentEngine, TxType, UserTransactions, disputes::DisputeState, money::Amount};
+
+/// One recorded transaction and every step applied to it since, read
+/// straight from the engine's ledger.
+#[derive(Debug, Clone, Copy)]
+pub struct LedgerEntry<'a> {
+    pub client_id: u16,
+    pub tx_id: u32,
+    /// Applied records in arrival order, e.g. a deposit then its dispute
+    /// and resolve.
+    pub records: &'a [UserTransactions],
+    pub dispute_state: DisputeState,
+}
+
+impl<'a> LedgerEntry<'a> {
+    /// The deposit or withdrawal the later records refer to.
+    pub fn original(&self) -> Option<&'a UserTransactions> {
+        self.records
+            .iter()
+            .find(|record| matches!(record.tx_type, TxType::Deposit | TxType::Withdrawal))
+    }
+
+    pub fn amount(&self) -> Option<Decimal> {
+        self.original()?.amount.map(Amount::value)
+    }
+}
+
+impl PaymentEngine {
+    /// Every transaction recorded for `client_id`, by tx id. Rejected
+    /// transactions are never recorded, and retention drops old ones; see
+    /// [`crate::RetentionConfig`].
+    pub fn transactions(&self, client_id: u16) -> Vec<LedgerEntry<'_>> {
+        let mut entries: Vec<LedgerEntry<'_>> = self
+            .actions
+            .get(&client_id)
+            .into_iter()
+            .flatten()
+            .map(|(&tx_id, records)| self.ledger_entry(client_id, tx_id, records))
+            .collect();
+        entries.sort_unstable_by_key(|entry| entry.tx_id);
+        entries
+    }
+
+    /// The deposit or withdrawal with id `tx_id`, whichever client it
+    /// belongs to.
+    pub fn transaction(&self, tx_id: u32) -> Option<LedgerEntry<'_>> {
+        let client_id = *self.seen_tx_ids.get(&tx_id)?;
+        let records = self.actions.get(&client_id)?.get(&tx_id)?;
+        Some(self.ledger_entry(client_id, tx_id, records))
+    }
+
+    fn ledger_entry<'a>(
+        &self,
+        client_id: u16,
+        tx_id: u32,
+        records: &'a [UserTransactions],
+    ) -> LedgerEntry<'a> {
+        LedgerEntry {
+            client_id,
+            tx_id,
+            records,
+            dispute_state: self.dispute_state(client_id, tx_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_ledger_lists_client_history() {
+        let mut engine = PaymentEngine::new();
+        engine.client(1).deposit(2, amount(dec!(10))).unwrap();
+        engine.client(1).deposit(1, amount(dec!(5))).unwrap();
+        engine.client(1).dispute(2).unwrap();
+        engine.client(2).deposit(3, amount(dec!(7))).unwrap();
+        engine.client(1).withdraw(4, amount(dec!(50))).unwrap_err();
+
+        let history = engine.transactions(1);
+        let ids: Vec<u32> = history.iter().map(|entry| entry.tx_id).collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(history[1].records.len(), 2);
+        assert_eq!(history[1].dispute_state, DisputeState::Disputed);
+        assert_eq!(history[1].amount(), Some(dec!(10)));
+
+        let entry = engine.transaction(3).unwrap();
+        assert_eq!(entry.client_id, 2);
+        assert_eq!(entry.original().unwrap().tx_type, TxType::Deposit);
+        assert!(engine.transaction(4).is_none());
+        assert!(engine.transactions(9).is_empty());
+    }
+}
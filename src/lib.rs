@@ -2,20 +2,92 @@ use rust_decimal::{Decimal, prelude::Zero};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::errors::{EngineError, ErrorCode, no_account};
+
+pub mod access;
+pub mod accounts;
+pub mod adjustments;
+pub mod admin;
+pub mod aggregation;
+pub mod analytics;
+pub mod audit;
+pub mod batches;
+pub mod bench;
+pub mod cases;
+pub mod checkpoints;
+pub mod cli;
+pub mod client;
+pub mod columnar;
+pub mod config;
 pub mod data_sinks;
 pub mod data_sources;
+pub mod debts;
+pub mod decisions;
+pub mod disputes;
+pub mod dormancy;
+pub mod duplicates;
+pub mod errors;
+pub mod estimate;
+pub mod extract;
+pub mod fork;
+pub mod funds;
+pub mod hooks;
+pub mod ids;
+pub mod ledger;
+pub mod manifest;
+pub mod money;
+pub mod parallel;
+pub mod periods;
+pub mod pipeline;
+pub mod preview;
+pub mod provenance;
+pub mod quarantine;
+pub mod reconcile;
+pub mod report;
+pub mod retention;
+pub mod risk;
+pub mod rules;
+pub mod scenario;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod session;
+pub mod settlement;
+pub mod snapshot;
+pub mod sweeps;
+pub mod transfers;
+pub mod validation;
+pub mod view;
+pub mod wal;
+
+/// Internal accounts the engine books against. Transactions can't name
+/// them, so partner traffic never mixes into the engine's own ledgers.
+pub const RESERVED_ACCOUNTS: &[u16] =
+    &[adjustments::ADJUSTMENTS_ACCOUNT, dormancy::DORMANT_ACCOUNT];
 
-#[derive(Debug, PartialEq, Clone, Copy, Deserialize, Serialize)]
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum TxType {
+    #[default]
     Deposit,
     Withdrawal,
     Dispute,
     Resolve,
     Chargeback,
+    OpenAccount,
+    /// Closes the account; later transactions for it are refused. The
+    /// engine also writes these when it closes a dormant account.
+    CloseAccount,
+    /// Admin correction, offset against the internal adjustments account.
+    /// Only the admin API creates these; input rows of this type are refused.
+    Adjustment,
+    /// Moves `amount` from the client's available balance to another
+    /// client's; see [`PaymentEngine::process_client_transfer`].
+    Transfer,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+/// One input row. Only the type, client, tx id and amount are needed for
+/// most rows, so literals can fill in the rest with `..Default::default()`.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct UserTransactions {
     #[serde(rename = "type")]
     pub tx_type: TxType,
@@ -23,11 +95,97 @@ pub struct UserTransactions {
     pub client_id: u16,
     #[serde(rename = "tx")]
     pub tx_id: u32,
-    pub amount: Option<Decimal>,
+    pub amount: Option<money::Amount>,
+    /// Unix seconds. Optional in the input; records without one never age out.
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    /// Only set on `open_account` transactions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attributes: Option<accounts::AccountAttributes>,
+    /// Only read on deposits; see [`funds::FundsHold`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub funds_class: Option<funds::FundsClass>,
+    /// Consecutive rows with the same batch id apply all or nothing; see
+    /// [`PaymentEngine::process_batch`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch_id: Option<u32>,
+    /// Client a `transfer` credits; `client_id` is the one it debits.
+    #[serde(rename = "to_client", default, skip_serializing_if = "Option::is_none")]
+    pub to_client_id: Option<u16>,
 }
 
-fn serialize_to_four_places<S>(t: &Decimal, serializer: S) -> Result<S::Ok, S::Error>
+/// Starts a transaction for tests, e.g.
+/// `tx(TxType::Deposit, 1, 1).with_amount(dec!(10)).at(100)`.
+#[cfg(test)]
+pub(crate) fn tx(tx_type: TxType, client_id: u16, tx_id: u32) -> UserTransactions {
+    UserTransactions {
+        tx_type,
+        client_id,
+        tx_id,
+        ..Default::default()
+    }
+}
+
+/// [`money::Amount`] for tests; panics on a value it refuses.
+#[cfg(test)]
+pub(crate) fn amount(value: Decimal) -> money::Amount {
+    money::Amount::new(value).unwrap()
+}
+
+/// [`money::SignedAmount`] for tests; panics on a value it refuses.
+#[cfg(test)]
+pub(crate) fn signed_amount(value: Decimal) -> money::SignedAmount {
+    money::SignedAmount::new(value).unwrap()
+}
+
+#[cfg(test)]
+impl UserTransactions {
+    /// Panics on an amount [`money::Amount`] refuses; `None` leaves it unset.
+    pub(crate) fn with_amount(mut self, amount: impl Into<Option<Decimal>>) -> Self {
+        self.amount = amount
+            .into()
+            .map(|value| money::Amount::new(value).unwrap());
+        self
+    }
+
+    pub(crate) fn at(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub(crate) fn to(mut self, client_id: u16) -> Self {
+        self.to_client_id = Some(client_id);
+        self
+    }
+
+    pub(crate) fn in_batch(mut self, batch_id: u32) -> Self {
+        self.batch_id = Some(batch_id);
+        self
+    }
+
+    pub(crate) fn with_funds_class(mut self, class: funds::FundsClass) -> Self {
+        self.funds_class = Some(class);
+        self
+    }
+}
+
+/// Reads a decimal from its text. Left to itself, a CSV reader hands
+/// `rust_decimal` an `f64` for anything that looks like a float, which loses
+/// digits on large or precise amounts.
+pub(crate) fn deserialize_decimal<'de, D>(deserializer: D) -> Result<Decimal, D::Error>
 where
+    D: serde::Deserializer<'de>,
+{
+    let text = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+    let text = text.trim();
+    text.parse()
+        .or_else(|_| Decimal::from_scientific(text))
+        .map_err(serde::de::Error::custom)
+}
+
+pub(crate) fn serialize_to_four_places<T, S>(t: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: std::fmt::Display,
     S: serde::Serializer,
 {
     let formatted = format!("{:.4}", t);
@@ -36,14 +194,28 @@ where
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct UserAccount {
-    #[serde(rename = "client")]
+    // The aliases accept the headers of every `OutputStyle`.
+    #[serde(rename = "client", alias = "client_id", alias = "Client")]
     pub client_id: u16,
-    #[serde(serialize_with = "serialize_to_four_places")]
+    #[serde(
+        alias = "Available",
+        serialize_with = "serialize_to_four_places",
+        deserialize_with = "deserialize_decimal"
+    )]
     pub available: Decimal,
-    #[serde(serialize_with = "serialize_to_four_places")]
+    #[serde(
+        alias = "Held",
+        serialize_with = "serialize_to_four_places",
+        deserialize_with = "deserialize_decimal"
+    )]
     pub held: Decimal,
-    #[serde(serialize_with = "serialize_to_four_places")]
+    #[serde(
+        alias = "Total",
+        serialize_with = "serialize_to_four_places",
+        deserialize_with = "deserialize_decimal"
+    )]
     pub total: Decimal,
+    #[serde(alias = "Locked")]
     pub locked: bool,
 }
 
@@ -63,16 +235,280 @@ impl UserAccount {
     }
 }
 
+/// Entries the engine generates on its own, as opposed to those read from
+/// the input. Callers drain them to journal what the engine did.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum EventKind {
+    Sweep,
+    /// A dispute released because it stayed open past the dispute timeout.
+    AutoResolve,
+    /// Part of a dispute that couldn't be held; `amount` is the shortfall.
+    DisputeShortfall,
+    /// A dispute waiting for the client's available balance to cover it.
+    DisputeQueued,
+    /// A queued dispute that has now been applied.
+    QueuedDisputeApplied,
+    /// A reference to a transaction not seen yet, parked until it arrives.
+    Quarantined,
+    /// A parked reference applied once its transaction arrived.
+    QuarantineReleased,
+    /// A transaction for a refused client, held instead of applied.
+    HeldForReview,
+    /// A transaction dated in a closed period, applied re-dated to its end.
+    PeriodAdjustment,
+    /// One side of a balance adjustment that added to the account.
+    AdjustmentCredit,
+    /// One side of a balance adjustment that took from the account.
+    AdjustmentDebit,
+    /// One side of moving a dormant account's balance to the dormant
+    /// accounts pool.
+    DormancySweep,
+    /// A dormant account closed by the engine.
+    DormantClosed,
+    /// A deposit or withdrawal undone because a later one reused its tx id.
+    DuplicateReplaced,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Sweep => "sweep",
+            EventKind::AutoResolve => "auto_resolve",
+            EventKind::DisputeShortfall => "dispute_shortfall",
+            EventKind::DisputeQueued => "dispute_queued",
+            EventKind::QueuedDisputeApplied => "dispute_applied",
+            EventKind::Quarantined => "quarantined",
+            EventKind::QuarantineReleased => "quarantine_released",
+            EventKind::HeldForReview => "held_for_review",
+            EventKind::PeriodAdjustment => "period_adjustment",
+            EventKind::AdjustmentCredit => "adjustment_credit",
+            EventKind::AdjustmentDebit => "adjustment_debit",
+            EventKind::DormancySweep => "dormancy_sweep",
+            EventKind::DormantClosed => "dormant_closed",
+            EventKind::DuplicateReplaced => "duplicate_replaced",
+        }
+    }
+}
+
+/// What [`PaymentEngine::process_action`] did with a transaction it accepted.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TxOutcome {
+    /// Balances were updated.
+    Applied,
+    /// A dispute the client can't cover yet, waiting in the dispute queue.
+    DisputeQueued,
+    /// A reference to a transaction not seen yet, parked in quarantine.
+    Quarantined,
+    /// A transaction for a refused client, held for review.
+    HeldForReview,
+}
+
+#[derive(Debug, Clone)]
+pub struct EngineEvent {
+    pub kind: EventKind,
+    pub action: UserTransactions,
+}
+
+/// Bounds what the engine keeps for dispute lookups. Transactions dropped
+/// by any of these can no longer be disputed.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RetentionConfig {
+    /// How long after a transaction it may still be disputed.
+    pub dispute_window_secs: Option<u64>,
+    /// Most transactions kept; past it the least recently used one is
+    /// dropped. Open disputes are always kept.
+    pub max_transactions: Option<usize>,
+    /// Keep only deposit and withdrawal records, which is all a dispute
+    /// looks up, and not the dispute steps and other records after them.
+    pub disputable_only: bool,
+}
+
 pub struct PaymentEngine {
-    pub accounts: HashMap<u16, UserAccount>,
-    actions: HashMap<u16, HashMap<u32, Vec<UserTransactions>>>,
+    pub(crate) accounts: im::HashMap<u16, UserAccount>,
+    actions: im::HashMap<u16, im::HashMap<u32, Vec<UserTransactions>>>,
+    retention: Option<RetentionConfig>,
+    /// Use order of kept transactions, tracked with a transaction limit.
+    tx_recency: retention::TxRecency,
+    /// Latest timestamp seen in the input.
+    stream_time: Option<u64>,
+    sweep_rules: Vec<sweeps::SweepRule>,
+    dispute_timeout_secs: Option<u64>,
+    /// Stream time at which each timestamped dispute was opened.
+    dispute_opened_at: im::HashMap<(u16, u32), u64>,
+    synthetic_ids: ids::SyntheticIds,
+    events: Vec<EngineEvent>,
+    stats: im::HashMap<u16, risk::AccountStats>,
+    freeze_policy: risk::FreezePolicy,
+    withdrawal_policy: risk::WithdrawalPolicy,
+    hooks: Option<Box<dyn hooks::EngineHooks>>,
+    /// Hook calls held back until the open batch commits.
+    deferred_hooks: Option<Vec<hooks::DeferredHook>>,
+    rules: Vec<std::sync::Arc<dyn rules::TransactionRule>>,
+    attributes: im::HashMap<u16, accounts::AccountAttributes>,
+    require_open_accounts: bool,
+    dispute_funds_policy: disputes::DisputeFundsPolicy,
+    /// Amount actually held per dispute, where it differs from the disputed amount.
+    dispute_holds: im::HashMap<(u16, u32), Decimal>,
+    /// Dispute state per transaction; transactions not listed are undisputed.
+    dispute_states: im::HashMap<(u16, u32), disputes::DisputeState>,
+    /// Client of every deposit and withdrawal applied so far, by tx id.
+    seen_tx_ids: im::HashMap<u32, u16>,
+    duplicate_policy: duplicates::DuplicatePolicy,
+    /// Unpaid chargeback debts, oldest first.
+    debts: im::Vector<debts::Debt>,
+    debt_repayment: debts::DebtRepayment,
+    config: config::EngineConfig,
+    funds_holds: HashMap<funds::FundsClass, u64>,
+    reserved_funds: im::Vector<funds::ReservedFunds>,
+    closed_accounts: im::HashSet<u16>,
+    dormancy: Option<dormancy::DormancyPolicy>,
+    /// Stream time of each client's last timestamped transaction.
+    last_active_at: im::HashMap<u16, u64>,
+    /// Earliest stream time at which a tracked account can turn dormant.
+    dormancy_due: Option<u64>,
+    dormant: im::HashSet<u16>,
+    queued_disputes: im::Vector<(UserTransactions, money::Amount)>,
+    /// Sequence number of the last transaction applied to each client since
+    /// the engine was created.
+    last_activity: im::HashMap<u16, u64>,
+    activity_seq: u64,
+    backfill: bool,
+    quarantine: Option<quarantine::Quarantine>,
+    access: access::AccessList,
+    held_for_review: im::Vector<UserTransactions>,
+    closed_periods: im::Vector<periods::ClosedPeriod>,
+    late_entry_policy: periods::LateEntryPolicy,
+    adjustments: im::Vector<adjustments::Adjustment>,
+    wal: Option<wal::WriteAheadLog>,
+    /// Digest of the secret an [`admin::AdminCapability`] must carry.
+    admin_secret: Option<[u8; 32]>,
+    /// Rule evaluations not yet drained, while decision auditing is on.
+    decisions: Option<Vec<decisions::PolicyDecision>>,
+}
+
+impl Default for PaymentEngine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PaymentEngine {
     pub fn new() -> Self {
         Self {
-            accounts: HashMap::new(),
-            actions: HashMap::new(),
+            accounts: im::HashMap::new(),
+            actions: im::HashMap::new(),
+            retention: None,
+            tx_recency: retention::TxRecency::default(),
+            stream_time: None,
+            sweep_rules: Vec::new(),
+            dispute_timeout_secs: None,
+            dispute_opened_at: im::HashMap::new(),
+            synthetic_ids: ids::SyntheticIds::default(),
+            events: Vec::new(),
+            stats: im::HashMap::new(),
+            freeze_policy: risk::FreezePolicy::default(),
+            withdrawal_policy: risk::WithdrawalPolicy::default(),
+            hooks: None,
+            deferred_hooks: None,
+            rules: Vec::new(),
+            attributes: im::HashMap::new(),
+            require_open_accounts: false,
+            dispute_funds_policy: disputes::DisputeFundsPolicy::default(),
+            dispute_holds: im::HashMap::new(),
+            dispute_states: im::HashMap::new(),
+            seen_tx_ids: im::HashMap::new(),
+            duplicate_policy: duplicates::DuplicatePolicy::default(),
+            debts: im::Vector::new(),
+            debt_repayment: debts::DebtRepayment::default(),
+            config: config::EngineConfig::default(),
+            funds_holds: HashMap::new(),
+            reserved_funds: im::Vector::new(),
+            closed_accounts: im::HashSet::new(),
+            dormancy: None,
+            last_active_at: im::HashMap::new(),
+            dormancy_due: None,
+            dormant: im::HashSet::new(),
+            queued_disputes: im::Vector::new(),
+            last_activity: im::HashMap::new(),
+            activity_seq: 0,
+            backfill: false,
+            quarantine: None,
+            access: access::AccessList::default(),
+            held_for_review: im::Vector::new(),
+            closed_periods: im::Vector::new(),
+            late_entry_policy: periods::LateEntryPolicy::default(),
+            adjustments: im::Vector::new(),
+            wal: None,
+            admin_secret: None,
+            decisions: None,
+        }
+    }
+
+    /// Seeds the engine with balances and locked flags carried over from a
+    /// previous run. Totals are recomputed from available.
+    ///
+    /// An accounts file doesn't say which disputes held funds belong to, so
+    /// nothing could ever release them. Accounts with held funds are refused,
+    /// and none are loaded; carry open disputes over with a snapshot
+    /// instead (see [`Self::restore`]).
+    pub fn load_opening_balances<I>(&mut self, accounts: I) -> Result<(), String>
+    where
+        I: IntoIterator<Item = UserAccount>,
+    {
+        let accounts: Vec<UserAccount> = accounts.into_iter().collect();
+        if let Some(account) = accounts.iter().find(|a| !a.held.is_zero()) {
+            return Err(format!(
+                "Client {} has {} held; opening balances can't carry open disputes",
+                account.client_id, account.held
+            ));
+        }
+        for mut account in accounts {
+            account.calculate_total();
+            self.accounts.insert(account.client_id, account);
+        }
+        Ok(())
+    }
+
+    /// Drops transaction records dated before `timestamp`, except those
+    /// under an open dispute. Returns how many transactions were dropped.
+    pub(crate) fn purge_before(&mut self, timestamp: u64) -> usize {
+        let mut purged = 0;
+        for (client_id, txs) in self.actions.iter_mut() {
+            txs.retain(|tx_id, acts| {
+                let dated_before = acts
+                    .first()
+                    .and_then(|a| a.timestamp)
+                    .is_some_and(|ts| ts < timestamp);
+                let key = (*client_id, *tx_id);
+                let open_dispute =
+                    self.dispute_states.get(&key) == Some(&disputes::DisputeState::Disputed);
+                let keep = !dated_before || open_dispute;
+                if !keep {
+                    purged += 1;
+                    self.dispute_states.remove(&key);
+                    self.tx_recency.forget(&key);
+                }
+                keep
+            });
+        }
+        self.actions.retain(|_, txs| !txs.is_empty());
+        purged
+    }
+
+    /// Sets how long transactions stay disputable; see [`Self::enforce_retention`].
+    /// A transaction limit applies at once to the transactions already kept.
+    pub fn set_retention(&mut self, retention: RetentionConfig) {
+        self.retention = Some(retention);
+        self.track_kept_transactions();
+    }
+
+    /// Purges everything older than the retention window, measured back from
+    /// the latest timestamp seen in the stream.
+    pub fn enforce_retention(&mut self) -> usize {
+        let window = self.retention.and_then(|r| r.dispute_window_secs);
+        match (window, self.stream_time) {
+            (Some(window), Some(now)) => self.purge_before(now.saturating_sub(window)),
+            _ => 0,
         }
     }
 
@@ -82,110 +518,392 @@ impl PaymentEngine {
             .or_insert(UserAccount::new(client_id))
     }
 
-    fn process_deposit(&mut self, action: &UserTransactions) {
+    fn process_deposit(&mut self, action: &UserTransactions) -> Result<(), EngineError> {
+        let amount = action.amount.map_or(Decimal::ZERO, money::Amount::value);
         let account = self.get_or_create_account(action.client_id);
-        account.available += action.amount.unwrap_or(Decimal::zero());
+        account.available += amount;
         account.calculate_total();
+        if !self.debts.is_empty() {
+            self.repay_debts(action.client_id, amount);
+        }
+        if !self.funds_holds.is_empty() {
+            self.reserve_deposit(action);
+        }
+        Ok(())
     }
 
-    fn process_withdrawal(&mut self, action: &UserTransactions) {
-        if let Some(account) = self.accounts.get_mut(&action.client_id) {
-            let amount = action.amount.unwrap_or(Decimal::zero());
-            if account.available >= amount {
-                account.available -= amount;
-                account.calculate_total();
-            }
+    fn process_withdrawal(&mut self, action: &UserTransactions) -> Result<(), EngineError> {
+        let amount = action.amount.map_or(Decimal::ZERO, money::Amount::value);
+        let checked = self.check_withdrawal(action.client_id, amount);
+        self.note_withdrawal_decision(action, amount, &checked);
+        checked?;
+        let account = self
+            .accounts
+            .get_mut(&action.client_id)
+            .ok_or_else(|| no_account(action.client_id))?;
+        account.available -= amount;
+        account.calculate_total();
+        Ok(())
+    }
+
+    /// Every withdrawal rule, without touching the account.
+    pub(crate) fn check_withdrawal(
+        &self,
+        client_id: u16,
+        amount: Decimal,
+    ) -> Result<(), EngineError> {
+        if self.is_withdrawal_frozen(client_id) {
+            return Err(EngineError::new(
+                ErrorCode::WithdrawalsFrozen,
+                format!("Withdrawals are frozen for client {}", client_id),
+            ));
         }
+        let reserve = self.withdrawal_reserve(client_id)?;
+        let credit_limit = self.credit_limit(client_id);
+        let account = self
+            .accounts
+            .get(&client_id)
+            .ok_or_else(|| no_account(client_id))?;
+        if account.available + credit_limit - reserve < amount {
+            return Err(EngineError::new(
+                ErrorCode::InsufficientFunds,
+                format!(
+                    "Insufficient funds: available {}, requested {}",
+                    account.available, amount
+                ),
+            ));
+        }
+        Ok(())
     }
 
-    fn process_dispute(&mut self, action: &UserTransactions) {
-        let amount = match self
+    /// Type and amount of the deposit or withdrawal `action` refers to, once
+    /// `action` is a valid next step for that transaction's dispute.
+    fn referenced_transaction(
+        &self,
+        action: &UserTransactions,
+    ) -> Result<(TxType, money::Amount), EngineError> {
+        let acts = self
             .actions
             .get(&action.client_id)
             .and_then(|acts| acts.get(&action.tx_id))
-        {
-            Some(acts) => acts
-                .iter()
-                .find(|a| a.tx_type == TxType::Deposit || a.tx_type == TxType::Withdrawal)
+            .ok_or_else(|| {
+                EngineError::new(
+                    ErrorCode::TransactionNotFound,
+                    format!(
+                        "Transaction {} not found for client {}",
+                        action.tx_id, action.client_id
+                    ),
+                )
+            })?;
+        if acts.first().is_some_and(|a| a.tx_type == TxType::Transfer) {
+            return Err(EngineError::new(
+                ErrorCode::InvalidTransfer,
+                format!("Transfer {} can't be disputed", action.tx_id),
+            ));
+        }
+        self.check_dispute_step(action)?;
+        let original = acts
+            .iter()
+            .find(|a| a.tx_type == TxType::Deposit || a.tx_type == TxType::Withdrawal);
+        Ok((
+            original.map_or(TxType::Deposit, |a| a.tx_type),
+            original
                 .and_then(|a| a.amount)
-                .unwrap_or(Decimal::zero()),
-            None => return,
-        };
+                .unwrap_or(money::Amount::ZERO),
+        ))
+    }
+
+    /// Type of the disputed transaction and what a resolve or chargeback of
+    /// `action`'s dispute releases.
+    fn held_amount(&mut self, action: &UserTransactions) -> Result<(TxType, Decimal), EngineError> {
+        let (disputed, amount) = self.referenced_transaction(action)?;
+        let hold = self
+            .dispute_holds
+            .remove(&(action.client_id, action.tx_id))
+            .unwrap_or(amount.value());
+        Ok((disputed, hold))
+    }
+
+    /// Holds `hold` of the disputed `amount`; see [`Self::dispute_hold`].
+    /// A disputed deposit moves the hold out of available. A disputed
+    /// withdrawal holds the potential refund on top of the balance, since
+    /// that money already left the account.
+    fn process_dispute(
+        &mut self,
+        action: &UserTransactions,
+        disputed: TxType,
+        amount: Decimal,
+        hold: Decimal,
+    ) -> Result<(), EngineError> {
+        if hold != amount {
+            self.dispute_holds
+                .insert((action.client_id, action.tx_id), hold);
+        }
 
         let account = self.get_or_create_account(action.client_id);
-        account.available -= amount;
-        account.held += amount;
+        if disputed != TxType::Withdrawal {
+            account.available -= hold;
+        }
+        account.held += hold;
         account.calculate_total();
+
+        if let Some(ts) = action.timestamp {
+            self.dispute_opened_at
+                .insert((action.client_id, action.tx_id), ts);
+        }
+        self.stats
+            .entry(action.client_id)
+            .or_default()
+            .open_disputes += 1;
+        Ok(())
     }
 
-    fn process_resolve(&mut self, action: &UserTransactions) {
-        let amount = match self
-            .actions
-            .get(&action.client_id)
-            .and_then(|acts| acts.get(&action.tx_id))
-        {
-            Some(acts) => {
-                let has_dispute = acts.iter().any(|a| a.tx_type == TxType::Dispute);
-                if !has_dispute {
-                    return;
-                }
+    /// Releases the hold to available: a deposit's funds are restored, a
+    /// withdrawal's refund is credited.
+    fn process_resolve(&mut self, action: &UserTransactions) -> Result<(), EngineError> {
+        let (_, amount) = self.held_amount(action)?;
 
-                acts.iter()
-                    .find(|a| a.tx_type == TxType::Deposit || a.tx_type == TxType::Withdrawal)
-                    .and_then(|a| a.amount)
-                    .unwrap_or(Decimal::zero())
+        let account = self
+            .accounts
+            .get_mut(&action.client_id)
+            .ok_or_else(|| no_account(action.client_id))?;
+        account.held -= amount;
+        account.available += amount;
+        account.calculate_total();
+
+        self.dispute_opened_at
+            .remove(&(action.client_id, action.tx_id));
+        let stats = self.stats.entry(action.client_id).or_default();
+        stats.open_disputes = stats.open_disputes.saturating_sub(1);
+        Ok(())
+    }
+
+    /// Drops the hold and locks the account. The dispute already took a
+    /// deposit's funds out of available, so they leave the account exactly
+    /// once. For a withdrawal this cancels the refund, so the withdrawal
+    /// stands.
+    fn process_chargeback(&mut self, action: &UserTransactions) -> Result<(), EngineError> {
+        let (disputed, amount) = self.held_amount(action)?;
+
+        let account = self
+            .accounts
+            .get_mut(&action.client_id)
+            .ok_or_else(|| no_account(action.client_id))?;
+        account.held -= amount;
+        account.locked = true;
+        account.calculate_total();
+        let available = account.available;
+        self.drop_reservation(action.client_id, action.tx_id);
+        if disputed != TxType::Withdrawal && available < Decimal::ZERO {
+            self.record_debt(action.client_id, action.tx_id, amount, available);
+        }
+
+        self.dispute_opened_at
+            .remove(&(action.client_id, action.tx_id));
+        let stats = self.stats.entry(action.client_id).or_default();
+        stats.open_disputes = stats.open_disputes.saturating_sub(1);
+        stats.lifetime_chargebacks += 1;
+        Ok(())
+    }
+
+    /// Auto-resolves disputes that stay open longer than `secs` of stream
+    /// time. Only disputes carrying a timestamp can expire.
+    pub fn set_dispute_timeout(&mut self, secs: u64) {
+        self.dispute_timeout_secs = Some(secs);
+    }
+
+    fn expire_disputes(&mut self, timeout: u64, now: u64) {
+        let mut expired: Vec<(u16, u32)> = self
+            .dispute_opened_at
+            .iter()
+            .filter(|(_, opened)| opened.saturating_add(timeout) <= now)
+            .map(|(key, _)| *key)
+            .collect();
+        expired.sort_unstable();
+
+        for (client_id, tx_id) in expired {
+            let action = UserTransactions {
+                tx_type: TxType::Resolve,
+                client_id,
+                tx_id,
+                amount: None,
+                timestamp: Some(now),
+                ..Default::default()
+            };
+            if self.apply_action(action.clone()).is_ok() {
+                self.events.push(EngineEvent {
+                    kind: EventKind::AutoResolve,
+                    action,
+                });
             }
-            None => return,
-        };
+        }
+    }
 
-        if let Some(account) = self.accounts.get_mut(&action.client_id) {
-            account.held -= amount;
-            account.available += amount;
-            account.calculate_total();
+    /// Whether any transaction has been applied to `client_id`; opening
+    /// balances alone don't count.
+    pub fn was_touched(&self, client_id: u16) -> bool {
+        self.last_activity.contains_key(&client_id)
+    }
+
+    /// Position, counting from 1 across all clients, of the last transaction
+    /// applied to `client_id`.
+    pub fn last_activity(&self, client_id: u16) -> Option<u64> {
+        self.last_activity.get(&client_id).copied()
+    }
+
+    pub(crate) fn mark_activity(&mut self, client_id: u16) {
+        self.activity_seq += 1;
+        self.last_activity.insert(client_id, self.activity_seq);
+    }
+
+    /// Every account, by client id. Balances only change through
+    /// transactions and the [`admin`] API.
+    pub fn accounts(&self) -> &im::HashMap<u16, UserAccount> {
+        &self.accounts
+    }
+
+    /// Takes every event generated since the last call.
+    pub fn drain_events(&mut self) -> Vec<EngineEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Applies one transaction. A rejected transaction changes nothing: its
+    /// timestamp doesn't move the stream clock and the time-based sweeps it
+    /// would have set off are undone, so later disputes can't refer to it
+    /// and a bad timestamp can't expire anything.
+    pub fn process_action(&mut self, action: UserTransactions) -> Result<TxOutcome, EngineError> {
+        if self.wal.is_none() {
+            return self.process_unlogged(action);
         }
+        let entry = wal::WalEntry::Transaction(action.clone());
+        self.logged(entry, |engine| engine.process_unlogged(action))
     }
 
-    fn process_chargeback(&mut self, action: &UserTransactions) {
-        let amount = match self
-            .actions
-            .get(&action.client_id)
-            .and_then(|acts| acts.get(&action.tx_id))
-        {
-            Some(acts) => {
-                let has_dispute = acts.iter().any(|a| a.tx_type == TxType::Dispute);
-                if !has_dispute {
-                    return;
-                }
+    /// [`Self::process_action`] without the write-ahead log.
+    pub(crate) fn process_unlogged(
+        &mut self,
+        action: UserTransactions,
+    ) -> Result<TxOutcome, EngineError> {
+        // Only timed streams change anything before the action is checked.
+        let savepoint = (action.timestamp.is_some() || self.stream_time.is_some())
+            .then(|| self.row_savepoint());
+        let result = self.process_timed(action);
+        if let (Err(_), Some(savepoint)) = (&result, savepoint) {
+            self.roll_back(savepoint);
+        }
+        result
+    }
 
-                acts.iter()
-                    .find(|a| a.tx_type == TxType::Deposit || a.tx_type == TxType::Withdrawal)
-                    .and_then(|a| a.amount)
-                    .unwrap_or(Decimal::zero())
+    /// Advances the stream clock, runs what it has made due, then applies
+    /// the action.
+    fn process_timed(&mut self, action: UserTransactions) -> Result<TxOutcome, EngineError> {
+        let action = self.check_period(action)?;
+        let action = self.round_action(action);
+        if let Some(ts) = action.timestamp {
+            self.stream_time = Some(self.stream_time.map_or(ts, |now| now.max(ts)));
+        }
+        if let (Some(timeout), Some(now)) = (self.dispute_timeout_secs, self.stream_time) {
+            self.expire_disputes(timeout, now);
+        }
+        if let Some(now) = self.stream_time {
+            self.expire_quarantine(now);
+            if !self.reserved_funds.is_empty() {
+                self.release_reserved_funds(now);
+            }
+            if self.dormancy.is_some() {
+                self.check_dormancy(now);
             }
-            None => return,
+        }
+        let Some(action) = self.try_hold_blocked(action) else {
+            return Ok(TxOutcome::HeldForReview);
         };
-        if let Some(account) = self.accounts.get_mut(&action.client_id) {
-            account.held -= amount;
-            account.available -= amount;
-            account.locked = true;
-            account.calculate_total();
+        let Some(action) = self.try_quarantine(action) else {
+            return Ok(TxOutcome::Quarantined);
+        };
+
+        let (client_id, tx_id, tx_type) = (action.client_id, action.tx_id, action.tx_type);
+        let outcome = self.apply_action(action)?;
+        if matches!(tx_type, TxType::Deposit | TxType::Withdrawal) {
+            self.release_quarantined(client_id, tx_id);
         }
+        if !self.sweep_rules.is_empty() {
+            self.apply_sweeps(client_id);
+        }
+        if !self.queued_disputes.is_empty() {
+            self.release_queued_disputes();
+        }
+        Ok(outcome)
+    }
+
+    /// Rules about the client itself that every transaction must pass.
+    pub(crate) fn check_client(&self, client_id: u16, tx_type: TxType) -> Result<(), EngineError> {
+        self.check_access(client_id)?;
+        if RESERVED_ACCOUNTS.contains(&client_id) {
+            return Err(EngineError::new(
+                ErrorCode::ReservedAccount,
+                format!(
+                    "Client {} is reserved for the engine's own accounts",
+                    client_id
+                ),
+            ));
+        }
+        if self.closed_accounts.contains(&client_id) {
+            return Err(EngineError::new(
+                ErrorCode::AccountClosed,
+                format!("Client {} is closed", client_id),
+            ));
+        }
+        if tx_type != TxType::OpenAccount && !self.is_account_open(client_id) {
+            return Err(EngineError::new(
+                ErrorCode::AccountNotOpen,
+                format!("Client {} has no open account", client_id),
+            ));
+        }
+        Ok(())
     }
-    pub fn process_action(&mut self, action: UserTransactions) {
+
+    fn apply_action(&mut self, action: UserTransactions) -> Result<TxOutcome, EngineError> {
+        self.run_pre_hooks(&action);
+        let checked = self.check_client(action.client_id, action.tx_type);
+        self.note_client_decision(&action, &checked);
+        checked?;
+        self.check_rules(&action)?;
         match action.tx_type {
-            TxType::Deposit => self.process_deposit(&action),
-            TxType::Withdrawal => self.process_withdrawal(&action),
-            TxType::Dispute => self.process_dispute(&action),
+            TxType::Deposit | TxType::Withdrawal => self.process_transfer(&action),
+            TxType::Dispute => {
+                let (disputed, original) = self.referenced_transaction(&action)?;
+                let amount = original.value();
+                if disputed == TxType::Withdrawal {
+                    self.process_dispute(&action, disputed, amount, amount)
+                } else {
+                    match self.dispute_hold(&action, amount) {
+                        Some(hold) => self.process_dispute(&action, disputed, amount, hold),
+                        None => {
+                            self.queue_dispute(action, original);
+                            return Ok(TxOutcome::DisputeQueued);
+                        }
+                    }
+                }
+            }
             TxType::Resolve => self.process_resolve(&action),
             TxType::Chargeback => self.process_chargeback(&action),
+            TxType::OpenAccount => self.process_open_account(&action),
+            TxType::CloseAccount => self.process_close_account(&action),
+            TxType::Adjustment => Err(EngineError::new(
+                ErrorCode::AdminOnly,
+                "Adjustments can only be applied through the admin API",
+            )),
+            TxType::Transfer => self.process_client_transfer(&action),
+        }?;
+        self.run_post_hooks(&action);
+        self.mark_activity(action.client_id);
+        self.advance_dispute_state(&action);
+        if self.dormancy.is_some() {
+            self.note_activity_time(action.client_id, action.timestamp);
         }
 
-        self.actions
-            .entry(action.client_id)
-            .or_insert_with(HashMap::new)
-            .entry(action.tx_id)
-            .or_insert_with(Vec::new)
-            .push(action);
+        self.record_action(action);
+        Ok(TxOutcome::Applied)
     }
 }
 
@@ -201,9 +919,10 @@ mod tests {
             tx_type: TxType::Deposit,
             client_id: 1,
             tx_id: 1,
-            amount: Some(dec!(100.0)),
+            amount: Some(amount(dec!(100.0))),
+            ..Default::default()
         };
-        engine.process_action(action);
+        engine.process_action(action).unwrap();
 
         let account = engine.accounts.get(&1).unwrap();
         assert_eq!(account.available, dec!(100.0));
@@ -213,18 +932,24 @@ mod tests {
     #[test]
     fn test_multiple_deposits() {
         let mut engine = PaymentEngine::new();
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Deposit,
-            client_id: 1,
-            tx_id: 1,
-            amount: Some(dec!(50.0)),
-        });
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Deposit,
-            client_id: 1,
-            tx_id: 2,
-            amount: Some(dec!(75.5)),
-        });
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Deposit,
+                client_id: 1,
+                tx_id: 1,
+                amount: Some(amount(dec!(50.0))),
+                ..Default::default()
+            })
+            .unwrap();
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Deposit,
+                client_id: 1,
+                tx_id: 2,
+                amount: Some(amount(dec!(75.5))),
+                ..Default::default()
+            })
+            .unwrap();
 
         let account = engine.accounts.get(&1).unwrap();
         assert_eq!(account.available, dec!(125.5));
@@ -234,18 +959,24 @@ mod tests {
     #[test]
     fn test_withdrawal_with_sufficient_funds() {
         let mut engine = PaymentEngine::new();
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Deposit,
-            client_id: 1,
-            tx_id: 1,
-            amount: Some(dec!(100.0)),
-        });
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Withdrawal,
-            client_id: 1,
-            tx_id: 2,
-            amount: Some(dec!(30.0)),
-        });
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Deposit,
+                client_id: 1,
+                tx_id: 1,
+                amount: Some(amount(dec!(100.0))),
+                ..Default::default()
+            })
+            .unwrap();
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Withdrawal,
+                client_id: 1,
+                tx_id: 2,
+                amount: Some(amount(dec!(30.0))),
+                ..Default::default()
+            })
+            .unwrap();
 
         let account = engine.accounts.get(&1).unwrap();
         assert_eq!(account.available, dec!(70.0));
@@ -255,18 +986,25 @@ mod tests {
     #[test]
     fn test_withdrawal_with_insufficient_funds() {
         let mut engine = PaymentEngine::new();
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Deposit,
-            client_id: 1,
-            tx_id: 1,
-            amount: Some(dec!(50.0)),
-        });
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Withdrawal,
-            client_id: 1,
-            tx_id: 2,
-            amount: Some(dec!(100.0)),
-        });
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Deposit,
+                client_id: 1,
+                tx_id: 1,
+                amount: Some(amount(dec!(50.0))),
+                ..Default::default()
+            })
+            .unwrap();
+        let err = engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Withdrawal,
+                client_id: 1,
+                tx_id: 2,
+                amount: Some(amount(dec!(100.0))),
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InsufficientFunds);
 
         let account = engine.accounts.get(&1).unwrap();
         assert_eq!(account.available, dec!(50.0));
@@ -276,31 +1014,40 @@ mod tests {
     #[test]
     fn test_withdrawal_nonexistent_account() {
         let mut engine = PaymentEngine::new();
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Withdrawal,
-            client_id: 1,
-            tx_id: 1,
-            amount: Some(dec!(50.0)),
-        });
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Withdrawal,
+                client_id: 1,
+                tx_id: 1,
+                amount: Some(amount(dec!(50.0))),
+                ..Default::default()
+            })
+            .unwrap_err();
 
-        assert!(engine.accounts.get(&1).is_none());
+        assert!(!engine.accounts.contains_key(&1));
     }
 
     #[test]
     fn test_dispute_moves_funds_to_held() {
         let mut engine = PaymentEngine::new();
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Deposit,
-            client_id: 1,
-            tx_id: 1,
-            amount: Some(dec!(100.0)),
-        });
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Dispute,
-            client_id: 1,
-            tx_id: 1,
-            amount: None,
-        });
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Deposit,
+                client_id: 1,
+                tx_id: 1,
+                amount: Some(amount(dec!(100.0))),
+                ..Default::default()
+            })
+            .unwrap();
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Dispute,
+                client_id: 1,
+                tx_id: 1,
+                amount: None,
+                ..Default::default()
+            })
+            .unwrap();
 
         let account = engine.accounts.get(&1).unwrap();
         assert_eq!(account.available, dec!(0.0));
@@ -311,24 +1058,33 @@ mod tests {
     #[test]
     fn test_resolve_returns_funds_to_available() {
         let mut engine = PaymentEngine::new();
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Deposit,
-            client_id: 1,
-            tx_id: 1,
-            amount: Some(dec!(100.0)),
-        });
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Dispute,
-            client_id: 1,
-            tx_id: 1,
-            amount: None,
-        });
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Resolve,
-            client_id: 1,
-            tx_id: 1,
-            amount: None,
-        });
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Deposit,
+                client_id: 1,
+                tx_id: 1,
+                amount: Some(amount(dec!(100.0))),
+                ..Default::default()
+            })
+            .unwrap();
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Dispute,
+                client_id: 1,
+                tx_id: 1,
+                amount: None,
+                ..Default::default()
+            })
+            .unwrap();
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Resolve,
+                client_id: 1,
+                tx_id: 1,
+                amount: None,
+                ..Default::default()
+            })
+            .unwrap();
 
         let account = engine.accounts.get(&1).unwrap();
         assert_eq!(account.available, dec!(100.0));
@@ -340,46 +1096,62 @@ mod tests {
     #[test]
     fn test_chargeback_locks_account() {
         let mut engine = PaymentEngine::new();
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Deposit,
-            client_id: 1,
-            tx_id: 1,
-            amount: Some(dec!(100.0)),
-        });
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Dispute,
-            client_id: 1,
-            tx_id: 1,
-            amount: None,
-        });
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Chargeback,
-            client_id: 1,
-            tx_id: 1,
-            amount: None,
-        });
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Deposit,
+                client_id: 1,
+                tx_id: 1,
+                amount: Some(amount(dec!(100.0))),
+                ..Default::default()
+            })
+            .unwrap();
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Dispute,
+                client_id: 1,
+                tx_id: 1,
+                amount: None,
+                ..Default::default()
+            })
+            .unwrap();
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Chargeback,
+                client_id: 1,
+                tx_id: 1,
+                amount: None,
+                ..Default::default()
+            })
+            .unwrap();
 
         let account = engine.accounts.get(&1).unwrap();
+        assert_eq!(account.available, dec!(0.0));
         assert_eq!(account.held, dec!(0.0));
-        assert_eq!(account.total, dec!(-100.0));
+        assert_eq!(account.total, dec!(0.0));
         assert!(account.locked);
     }
 
     #[test]
     fn test_resolve_without_dispute_does_nothing() {
         let mut engine = PaymentEngine::new();
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Deposit,
-            client_id: 1,
-            tx_id: 1,
-            amount: Some(dec!(100.0)),
-        });
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Resolve,
-            client_id: 1,
-            tx_id: 1,
-            amount: None,
-        });
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Deposit,
+                client_id: 1,
+                tx_id: 1,
+                amount: Some(amount(dec!(100.0))),
+                ..Default::default()
+            })
+            .unwrap();
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Resolve,
+                client_id: 1,
+                tx_id: 1,
+                amount: None,
+                ..Default::default()
+            })
+            .unwrap_err();
 
         let account = engine.accounts.get(&1).unwrap();
         assert_eq!(account.available, dec!(100.0));
@@ -389,18 +1161,24 @@ mod tests {
     #[test]
     fn test_multiple_clients() {
         let mut engine = PaymentEngine::new();
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Deposit,
-            client_id: 1,
-            tx_id: 1,
-            amount: Some(dec!(100.0)),
-        });
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Deposit,
-            client_id: 2,
-            tx_id: 2,
-            amount: Some(dec!(200.0)),
-        });
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Deposit,
+                client_id: 1,
+                tx_id: 1,
+                amount: Some(amount(dec!(100.0))),
+                ..Default::default()
+            })
+            .unwrap();
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Deposit,
+                client_id: 2,
+                tx_id: 2,
+                amount: Some(amount(dec!(200.0))),
+                ..Default::default()
+            })
+            .unwrap();
 
         assert_eq!(engine.accounts.get(&1).unwrap().total, dec!(100.0));
         assert_eq!(engine.accounts.get(&2).unwrap().total, dec!(200.0));
@@ -409,32 +1187,275 @@ mod tests {
     #[test]
     fn test_deposit_with_zero_amount() {
         let mut engine = PaymentEngine::new();
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Deposit,
-            client_id: 1,
-            tx_id: 1,
-            amount: Some(dec!(0.0)),
-        });
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Deposit,
+                client_id: 1,
+                tx_id: 1,
+                amount: Some(amount(dec!(0.0))),
+                ..Default::default()
+            })
+            .unwrap();
 
         let account = engine.accounts.get(&1).unwrap();
         assert_eq!(account.available, dec!(0.0));
     }
 
     #[test]
-    fn test_dispute_nonexistent_transaction() {
+    fn test_opening_balances_seed_accounts() {
         let mut engine = PaymentEngine::new();
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Deposit,
-            client_id: 1,
-            tx_id: 1,
-            amount: Some(dec!(100.0)),
+        let mut opening = UserAccount::new(1);
+        opening.available = dec!(40.0);
+        let mut frozen = UserAccount::new(2);
+        frozen.locked = true;
+        engine
+            .load_opening_balances(vec![opening.clone(), frozen])
+            .unwrap();
+
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Deposit,
+                client_id: 1,
+                tx_id: 1,
+                amount: Some(amount(dec!(5.0))),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let account = engine.accounts.get(&1).unwrap();
+        assert_eq!(account.available, dec!(45.0));
+        assert_eq!(account.total, dec!(45.0));
+        assert!(engine.accounts.get(&2).unwrap().locked);
+
+        // Held funds would have no dispute to release them.
+        let mut disputed = UserAccount::new(3);
+        disputed.held = dec!(10.0);
+        let mut fresh = PaymentEngine::new();
+        let error = fresh
+            .load_opening_balances(vec![opening, disputed])
+            .unwrap_err();
+        assert_eq!(
+            error,
+            "Client 3 has 10.0 held; opening balances can't carry open disputes"
+        );
+        assert!(fresh.accounts.is_empty());
+    }
+
+    #[test]
+    fn test_purge_before_keeps_open_disputes() {
+        let mut engine = PaymentEngine::new();
+        for (tx_id, ts) in [(1, 100), (2, 200), (3, 300)] {
+            engine
+                .process_action(UserTransactions {
+                    tx_type: TxType::Deposit,
+                    client_id: 1,
+                    tx_id,
+                    amount: Some(amount(dec!(10.0))),
+                    timestamp: Some(ts),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Dispute,
+                client_id: 1,
+                tx_id: 1,
+                amount: None,
+                timestamp: Some(310),
+                ..Default::default()
+            })
+            .unwrap();
+
+        // tx 2 is old and settled, tx 1 is old but still disputed
+        assert_eq!(engine.purge_before(250), 1);
+
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Dispute,
+                client_id: 1,
+                tx_id: 2,
+                amount: None,
+                timestamp: Some(320),
+                ..Default::default()
+            })
+            .unwrap_err();
+        let account = engine.accounts.get(&1).unwrap();
+        assert_eq!(account.held, dec!(10.0));
+        assert_eq!(account.available, dec!(20.0));
+    }
+
+    #[test]
+    fn test_enforce_retention_uses_stream_time() {
+        let mut engine = PaymentEngine::new();
+        engine.set_retention(RetentionConfig {
+            dispute_window_secs: Some(50),
+            ..RetentionConfig::default()
         });
-        engine.process_action(UserTransactions {
-            tx_type: TxType::Dispute,
+        for (tx_id, ts) in [(1, 100), (2, 200)] {
+            engine
+                .process_action(UserTransactions {
+                    tx_type: TxType::Deposit,
+                    client_id: 1,
+                    tx_id,
+                    amount: Some(amount(dec!(10.0))),
+                    timestamp: Some(ts),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+
+        assert_eq!(engine.enforce_retention(), 1);
+        assert_eq!(engine.enforce_retention(), 0);
+    }
+
+    #[test]
+    fn test_expired_dispute_auto_resolves() {
+        let mut engine = PaymentEngine::new();
+        engine.set_dispute_timeout(100);
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Deposit,
+                client_id: 1,
+                tx_id: 1,
+                amount: Some(amount(dec!(50.0))),
+                timestamp: Some(1_000),
+                ..Default::default()
+            })
+            .unwrap();
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Dispute,
+                client_id: 1,
+                tx_id: 1,
+                amount: None,
+                timestamp: Some(1_010),
+                ..Default::default()
+            })
+            .unwrap();
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Deposit,
+                client_id: 2,
+                tx_id: 2,
+                amount: Some(amount(dec!(1.0))),
+                timestamp: Some(1_050),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(engine.accounts.get(&1).unwrap().held, dec!(50.0));
+        assert!(engine.drain_events().is_empty());
+
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Deposit,
+                client_id: 2,
+                tx_id: 3,
+                amount: Some(amount(dec!(1.0))),
+                timestamp: Some(1_110),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let account = engine.accounts.get(&1).unwrap();
+        assert_eq!(account.held, dec!(0.0));
+        assert_eq!(account.available, dec!(50.0));
+        let events = engine.drain_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, EventKind::AutoResolve);
+        assert_eq!(events[0].action.tx_id, 1);
+    }
+
+    #[test]
+    fn test_rejected_action_keeps_the_clock() {
+        let mut engine = PaymentEngine::new();
+        engine.set_dispute_timeout(100);
+        for (tx_type, value) in [(TxType::Deposit, Some(dec!(50.0))), (TxType::Dispute, None)] {
+            engine
+                .process_action(UserTransactions {
+                    tx_type,
+                    client_id: 1,
+                    tx_id: 1,
+                    amount: value.map(amount),
+                    timestamp: Some(1_000),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+
+        // Far past the timeout, but refused: the dispute stays open.
+        let error = engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Withdrawal,
+                client_id: 1,
+                tx_id: 2,
+                amount: Some(amount(dec!(80.0))),
+                timestamp: Some(5_000),
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert_eq!(error.code(), ErrorCode::InsufficientFunds);
+        assert_eq!(engine.stream_time, Some(1_000));
+        assert_eq!(engine.accounts[&1].held, dec!(50.0));
+        assert!(engine.drain_events().is_empty());
+    }
+
+    #[test]
+    fn test_disputed_withdrawal_holds_refund() {
+        let action = |tx_type, tx_id| UserTransactions {
+            tx_type,
             client_id: 1,
-            tx_id: 999,
+            tx_id,
             amount: None,
-        });
+            ..Default::default()
+        };
+        let mut engine = PaymentEngine::new();
+        engine.client(1).deposit(1, amount(dec!(10.0))).unwrap();
+        engine.client(1).withdraw(2, amount(dec!(4.0))).unwrap();
+        engine.client(1).withdraw(3, amount(dec!(1.0))).unwrap();
+
+        engine.process_action(action(TxType::Dispute, 2)).unwrap();
+        let account = &engine.accounts[&1];
+        assert_eq!(account.available, dec!(5.0));
+        assert_eq!(account.held, dec!(4.0));
+        assert_eq!(account.total, dec!(9.0));
+
+        engine.process_action(action(TxType::Resolve, 2)).unwrap();
+        let account = &engine.accounts[&1];
+        assert_eq!(account.available, dec!(9.0));
+        assert_eq!(account.held, dec!(0.0));
+
+        engine.process_action(action(TxType::Dispute, 3)).unwrap();
+        engine
+            .process_action(action(TxType::Chargeback, 3))
+            .unwrap();
+        let account = &engine.accounts[&1];
+        assert_eq!(account.available, dec!(9.0));
+        assert_eq!(account.held, dec!(0.0));
+        assert!(account.locked);
+    }
+
+    #[test]
+    fn test_dispute_nonexistent_transaction() {
+        let mut engine = PaymentEngine::new();
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Deposit,
+                client_id: 1,
+                tx_id: 1,
+                amount: Some(amount(dec!(100.0))),
+                ..Default::default()
+            })
+            .unwrap();
+        engine
+            .process_action(UserTransactions {
+                tx_type: TxType::Dispute,
+                client_id: 1,
+                tx_id: 999,
+                amount: None,
+                ..Default::default()
+            })
+            .unwrap_err();
 
         let account = engine.accounts.get(&1).unwrap();
         assert_eq!(account.available, dec!(100.0));
@@ -1,48 +1,1178 @@
-use std::process;
+use std::{
+    collections::hash_map::RandomState,
+    fs::File,
+    hash::{BuildHasher, Hasher},
+    io::IsTerminal,
+    ops::ControlFlow,
+    process,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
+use clap::Parser;
 use payment_engine::{
-    PaymentEngine,
-    data_sinks::{DataSink, csv::CsvDataSink},
-    data_sources::{DataSource, csv::CsvDataSource},
+    PaymentEngine, TxType, UserTransactions,
+    access::{AccessList, read_client_list},
+    accounts::read_account_seeds,
+    adjustments::read_adjustments,
+    admin::AdminCapability,
+    aggregation::WindowAggregator,
+    audit::{AuditLog, verify_log},
+    bench::{compare, generate_workload, standard_configurations, write_comparison},
+    cases::{liabilities, write_cases, write_liabilities},
+    checkpoints::{Checkpoint, CheckpointStore},
+    cli::{
+        BenchOptions, CasesOptions, Cli, Command, EstimateOptions, ExtractOptions, PreviewOptions,
+        ProcessOptions, ReconcileOptions, ReportArgs, ScenarioOptions, ValidateArgs,
+    },
+    data_sinks::{
+        RejectSink,
+        pseudonymize::{PSEUDONYM_KEY_ENV, Pseudonymizer},
+        rejects::CsvRejectSink,
+        staged::{StagedFile, write_staged},
+        write_accounts_atomic,
+    },
+    data_sources::{
+        DataSource,
+        client_map::ClientIdMap,
+        csv::{CsvDataSource, STDIN_PATH, read_accounts},
+        merge::{MergedSource, write_late_events},
+        multi::MultiFileDataSource,
+        transform::{ScaleAmounts, TransformedSource},
+    },
+    debts::write_debts,
+    decisions::write_decisions,
+    estimate::estimate,
+    extract::extract,
+    manifest::{CompletionMarker, OutputManifest, SIGNING_KEY_ENV},
+    money::Amount,
+    pipeline::{Pipeline, RecordOutcome, RunOutcome, RunSummary},
+    preview::{preview, write_changes},
+    provenance::Provenance,
+    quarantine::write_orphans,
+    reconcile::{reconcile, write_discrepancies},
+    report::{ReportFormat, report},
+    scenario::run_scenarios,
+    session::{ImportJournal, ImportSession, SessionStatus, hash_file},
+    settlement::{
+        PaymentInitiation, read_beneficiaries, read_debtor, settle, write_pain001, write_payouts,
+    },
+    sweeps::read_sweep_rules,
+    validation::{ValidationConfig, validate_csv},
 };
 
+/// Exit code used when the run was cut short by SIGINT/SIGTERM.
+const EXIT_INTERRUPTED: i32 = 130;
+/// Exit code used by `validate`, `reconcile` and `scenario` when problems
+/// were found.
+const EXIT_INVALID: i32 = 2;
+/// Exit code used with `--strict-exit` when records were skipped.
+const EXIT_SKIPPED: i32 = 3;
+/// Exit code used when skipped records exceed `--max-skipped` or
+/// `--max-skipped-percent`.
+const EXIT_THRESHOLD_EXCEEDED: i32 = 4;
+/// How often (in transactions) import progress is written to the journal.
+const JOURNAL_INTERVAL: u64 = 10_000;
+/// How often (in transactions) the retention window is enforced.
+const RETENTION_INTERVAL: u64 = 100_000;
+/// How often (in transactions) the watch-output timer is checked.
+const WATCH_CHECK_INTERVAL: u64 = 1_000;
+/// How often (in transactions) a checkpoint is taken by default.
+const CHECKPOINT_INTERVAL: u64 = 100_000;
+
 fn main() {
-    let file = std::env::args()
-        .nth(1)
-        .expect("Input file path required as first argument");
-    let output = std::env::args().nth(2);
+    let args: Vec<String> = std::env::args().skip(1).collect();
 
-    let mut data_source = Box::new(CsvDataSource::new(file));
+    match args.first().map(String::as_str) {
+        Some("process" | "validate" | "report") => run_command(&args),
+        Some("verify-log") => run_verify_log(&args[1..]),
+        Some("cases") => run_cases(&args[1..]),
+        Some("reconcile") => run_reconcile(&args[1..]),
+        Some("bench") => run_bench(&args[1..]),
+        Some("preview") => run_preview(&args[1..]),
+        Some("scenario") => run_scenario(&args[1..]),
+        Some("extract") => run_extract(&args[1..]),
+        Some("estimate") => run_estimate(&args[1..]),
+        _ => run_process(&args),
+    }
+}
 
+/// `cases <input> [--output cases.csv]`: lists disputes still open after
+/// processing `input`.
+fn run_cases(args: &[String]) {
+    let options = CasesOptions::parse(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
     let mut engine = PaymentEngine::new();
+    let mut data_source = CsvDataSource::new(options.input.clone());
+    if let Err(e) = Pipeline::new().process(&mut data_source, &mut engine, |_, _, _| {
+        ControlFlow::Continue(())
+    }) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+
+    let cases = engine.open_disputes();
+    let written = match &options.output {
+        Some(path) => write_staged(path, |file| write_cases(file, &cases)),
+        None => write_cases(std::io::stdout(), &cases),
+    };
+    if let Err(e) = written {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    eprintln!("{} open disputes", cases.len());
+}
+
+/// `extract <input> [--client ids] [--sample rate] [--with-referenced-txs] [-o out.csv]`:
+/// copies a slice of `input`, e.g. one client's rows, to reproduce an issue
+/// without the whole file.
+fn run_extract(args: &[String]) {
+    let options = ExtractOptions::parse(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let report = match &options.output {
+        Some(path) => write_staged(path, |file| extract(&options.input, &options.config, file)),
+        None => extract(&options.input, &options.config, std::io::stdout()),
+    }
+    .unwrap_or_else(|e| {
+        eprintln!("Failed to extract from '{}': {}", options.input, e);
+        process::exit(1);
+    });
+    eprintln!(
+        "Extracted {} of {} rows",
+        report.rows_written, report.rows_read
+    );
+}
+
+/// `estimate <input> [--sample-rows N] [--output estimate.json]`: projects
+/// the input's size and the engine's peak memory from a sample, to pick
+/// flags before a long run.
+fn run_estimate(args: &[String]) {
+    let options = EstimateOptions::parse(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let estimate = estimate(&options.input, options.sample_rows).unwrap_or_else(|e| {
+        eprintln!("Failed to sample '{}': {}", options.input, e);
+        process::exit(1);
+    });
+    let written = match &options.output {
+        Some(path) => write_staged(path, |file| estimate.write_json(file)),
+        None => estimate.write_json(std::io::stdout()),
+    };
+    if let Err(e) = written {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    eprintln!(
+        "Sampled {} rows, projecting {} rows and {} clients",
+        estimate.sampled_rows, estimate.projected_rows, estimate.projected_clients
+    );
+}
 
-    match data_source.read_transactions() {
-        Ok(actions) => {
-            for action in actions {
-                engine.process_action(action);
+/// `scenario run <dir>`: runs every `*.toml` scenario in `dir` and exits
+/// with [`EXIT_INVALID`] if any fails.
+fn run_scenario(args: &[String]) {
+    let options = ScenarioOptions::parse(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let results = run_scenarios(std::path::Path::new(&options.dir)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    let mut failed = 0;
+    for result in &results {
+        let name = result.name.as_deref().unwrap_or(&result.path);
+        if result.passed() {
+            eprintln!("PASS {}", name);
+        } else {
+            failed += 1;
+            eprintln!("FAIL {}", name);
+            for failure in &result.failures {
+                eprintln!("    {}", failure);
             }
         }
-        Err(e) => {
-            eprintln!("Failed to read data: {}", e);
+    }
+    eprintln!("{} scenarios: {} failed", results.len(), failed);
+    if failed > 0 {
+        process::exit(EXIT_INVALID);
+    }
+}
+
+/// `reconcile --expected balances.csv <input> [--tolerance N] [--output report.csv]`:
+/// processes `input` and reports where the resulting accounts differ from
+/// the expected balances.
+fn run_reconcile(args: &[String]) {
+    let options = ReconcileOptions::parse(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let expected = read_accounts(&options.expected).unwrap_or_else(|e| {
+        eprintln!(
+            "Failed to read expected balances '{}': {}",
+            options.expected, e
+        );
+        process::exit(1);
+    });
+    let mut engine = PaymentEngine::new();
+    let mut data_source = CsvDataSource::new(options.input.clone());
+    if let Err(e) = Pipeline::new().process(&mut data_source, &mut engine, |_, _, _| {
+        ControlFlow::Continue(())
+    }) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+
+    let discrepancies = reconcile(&engine, &expected, options.tolerance);
+    let written = match &options.output {
+        Some(path) => write_staged(path, |file| write_discrepancies(file, &discrepancies)),
+        None => write_discrepancies(std::io::stdout(), &discrepancies),
+    };
+    if let Err(e) = written {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    eprintln!("{} discrepancies", discrepancies.len());
+    if !discrepancies.is_empty() {
+        process::exit(EXIT_INVALID);
+    }
+}
+
+/// `bench compare [--records N] [--clients N] [--seed N]`: runs one
+/// generated workload through each standard engine configuration.
+fn run_bench(args: &[String]) {
+    let options = BenchOptions::parse(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let workload = generate_workload(&options.workload);
+    let results = compare(&workload, &standard_configurations());
+    if let Err(e) = write_comparison(std::io::stdout(), &results) {
+        eprintln!("Failed to write comparison: {}", e);
+        process::exit(1);
+    }
+}
+
+/// `preview <pending.csv> [--state accounts.csv] [--output changes.csv]`:
+/// reports how the pending file would change the accounts in `state`, and
+/// which of its rows would be rejected, without writing any state.
+fn run_preview(args: &[String]) {
+    let options = PreviewOptions::parse(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let mut engine = PaymentEngine::new();
+    if let Some(path) = options.state.as_deref() {
+        let accounts = read_accounts(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load state '{}': {}", path, e);
+            process::exit(1);
+        });
+        if let Err(e) = engine.load_opening_balances(accounts) {
+            eprintln!("Failed to load state '{}': {}", path, e);
             process::exit(1);
         }
     }
+    let mut data_source = CsvDataSource::new(options.input.clone());
+    let preview = preview(&engine, &mut data_source).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
 
-    let accounts: Vec<_> = engine.accounts.values().collect();
+    for row in &preview.rejected {
+        match row.code {
+            Some(code) => eprintln!(
+                "Would reject {}: {} {}",
+                row.position,
+                code.code(),
+                row.reason
+            ),
+            None => eprintln!("Would reject {}: {}", row.position, row.reason),
+        }
+    }
+    let written = match &options.output {
+        Some(path) => write_staged(path, |file| write_changes(file, &preview.changes)),
+        None => write_changes(std::io::stdout(), &preview.changes),
+    };
+    if let Err(e) = written {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    eprintln!(
+        "{} accounts would change; {} of {} records would be rejected",
+        preview.changes.len(),
+        preview.rejected.len(),
+        preview.summary.records_read
+    );
+}
 
-    let mut data_sink: Box<dyn DataSink> = match output {
-        Some(path) => {
-            let file = std::fs::File::create(&path).unwrap_or_else(|e| {
-                eprintln!("Failed to create output file '{}': {}", path, e);
+/// `verify-log <audit.jsonl>`
+fn run_verify_log(args: &[String]) {
+    let path = args
+        .first()
+        .expect("Audit log path required as first argument");
+    match verify_log(path) {
+        Ok(count) => eprintln!("Audit log '{}' intact: {} entries", path, count),
+        Err(e) => {
+            eprintln!("Audit log '{}' failed verification: {}", path, e);
+            process::exit(EXIT_INVALID);
+        }
+    }
+}
+
+/// `process`, `validate` and `report`, each taking `--input`, `--output`
+/// and `--format`; see [`Cli`].
+fn run_command(args: &[String]) {
+    let cli =
+        Cli::parse_from(std::iter::once("payment_engine").chain(args.iter().map(String::as_str)));
+    match cli.command {
+        Command::Process(args) => {
+            let options = args.into_options().unwrap_or_else(|e| {
+                eprintln!("{}", e);
                 process::exit(1);
             });
-            Box::new(CsvDataSink::new(file))
+            process_with(options);
+        }
+        Command::Validate(args) => run_validate(args),
+        Command::Report(args) => run_report(args),
+    }
+}
+
+/// `report --input <file> [--output report.json] [--format json|csv]`
+fn run_report(args: ReportArgs) {
+    check_input(&args.input);
+    if args.analytics && args.format == ReportFormat::Csv {
+        eprintln!("--analytics is only available with --format json");
+        process::exit(1);
+    }
+    let report =
+        report(&args.input, args.analytics.then_some(args.cohort_window)).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+    let written = match &args.output {
+        Some(path) => write_staged(path, |file| report.write(file, args.format)),
+        None => report.write(std::io::stdout(), args.format),
+    };
+    if let Err(e) = written {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+/// `validate --input <file> [--output report.json] [--format json|csv] [--max-amount N]`
+fn run_validate(args: ValidateArgs) {
+    check_input(&args.input);
+    let config = ValidationConfig {
+        max_amount: args.max_amount,
+    };
+    let report = validate_csv(&args.input, &config).unwrap_or_else(|e| {
+        eprintln!("Failed to read data: {}", e);
+        process::exit(1);
+    });
+
+    let written = match &args.output {
+        Some(path) => write_staged(path, |file| report.write(file, args.format)),
+        None => report.write(std::io::stdout(), args.format),
+    };
+    if let Err(e) = written {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+
+    eprintln!(
+        "Validated {} rows: {} anomalies",
+        report.rows,
+        report.anomalies.len()
+    );
+    if !report.is_clean() {
+        process::exit(EXIT_INVALID);
+    }
+}
+
+/// Refuses to wait for input typed at a terminal, which would look like a
+/// hang to someone who forgot to name a file.
+fn check_input(input: &str) {
+    if input == STDIN_PATH && std::io::stdin().is_terminal() {
+        eprintln!("No input: pass a transactions file or pipe CSV to stdin");
+        process::exit(1);
+    }
+}
+
+/// `[input|-] [output] [--flag value]...`; see [`ProcessOptions::parse`].
+fn run_process(args: &[String]) {
+    let options = ProcessOptions::parse(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    process_with(options);
+}
+
+fn process_with(mut options: ProcessOptions) {
+    if let Err(e) = options.expand_input_glob() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    check_input(&options.input);
+    let file = &options.input;
+    let pseudonymizer = options.pseudonymize.then(|| {
+        let key = std::env::var(PSEUDONYM_KEY_ENV).unwrap_or_else(|_| {
+            eprintln!("--pseudonymize requires {} to be set", PSEUDONYM_KEY_ENV);
+            process::exit(1);
+        });
+        let pseudonymizer = Pseudonymizer::new(key.as_bytes());
+        match options.amount_bucket {
+            Some(bucket) => pseudonymizer.with_amount_bucket(bucket),
+            None => pseudonymizer,
+        }
+    });
+    let mut provenance = options.provenance.as_ref().map(|_| {
+        Provenance::start(&format!("{:?}", options), &options.input_files()).unwrap_or_else(|e| {
+            eprintln!("Failed to hash inputs: {}", e);
+            process::exit(1);
+        })
+    });
+
+    let client_map = options.client_map.as_deref().map(|path| {
+        ClientIdMap::from_path(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load client map '{}': {}", path, e);
+            process::exit(1);
+        })
+    });
+    let mut journal = options.journal.as_deref().map(|path| {
+        ImportJournal::open(path).unwrap_or_else(|e| {
+            eprintln!("Failed to open journal '{}': {}", path, e);
+            process::exit(1);
+        })
+    });
+    let opening_balances = options.opening_balances.as_deref().map(|path| {
+        read_accounts(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load opening balances '{}': {}", path, e);
+            process::exit(1);
+        })
+    });
+    let mut audit_log = options.audit_log.as_deref().map(|path| {
+        AuditLog::open(path).unwrap_or_else(|e| {
+            eprintln!("Failed to open audit log '{}': {}", path, e);
+            process::exit(1);
+        })
+    });
+    let mut decisions_log = options.decisions_log.as_deref().map(|path| {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map(std::io::BufWriter::new)
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to open decisions log '{}': {}", path, e);
+                process::exit(1);
+            })
+    });
+    let mut rejects = options.rejects.as_deref().map(|path| {
+        StagedFile::create(path)
+            .map(|(staged, file)| (staged, CsvRejectSink::new(std::io::BufWriter::new(file))))
+            .unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(1);
+            })
+    });
+    let account_seeds = match options.account_seeds.as_deref() {
+        Some(path) => read_account_seeds(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load account seeds '{}': {}", path, e);
+            process::exit(1);
+        }),
+        None => Vec::new(),
+    };
+    let load_clients = |path: &str| {
+        read_client_list(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load client list '{}': {}", path, e);
+            process::exit(1);
+        })
+    };
+    let access = AccessList {
+        blocked: options
+            .blocklist
+            .as_deref()
+            .map(load_clients)
+            .unwrap_or_default(),
+        allowed: options.allowlist.as_deref().map(load_clients),
+        hold: options.hold_blocked,
+    };
+    let adjustments = match options.adjustments.as_deref() {
+        Some(path) => read_adjustments(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load adjustments '{}': {}", path, e);
+            process::exit(1);
+        }),
+        None => Vec::new(),
+    };
+    let sweep_rules = match options.sweep_rules.as_deref() {
+        Some(path) => read_sweep_rules(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load sweep rules '{}': {}", path, e);
+            process::exit(1);
+        }),
+        None => Vec::new(),
+    };
+    let beneficiaries = options.payout_accounts.as_deref().map(|path| {
+        read_beneficiaries(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load payout accounts '{}': {}", path, e);
+            process::exit(1);
+        })
+    });
+    let debtor = options.payout_debtor.as_deref().map(|path| {
+        read_debtor(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        })
+    });
+
+    let mut aggregator = options.aggregates.as_deref().map(|path| {
+        StagedFile::create(path)
+            .and_then(|(staged, file)| {
+                Ok((
+                    staged,
+                    WindowAggregator::new(file, options.aggregate_window)?,
+                ))
+            })
+            .unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(1);
+            })
+    });
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    {
+        let shutdown = Arc::clone(&shutdown);
+        if let Err(e) = ctrlc::set_handler(move || shutdown.store(true, Ordering::SeqCst)) {
+            eprintln!("Failed to install signal handler: {}", e);
+        }
+    }
+
+    let checkpoints = options.checkpoint_dir.as_deref().map(|dir| {
+        CheckpointStore::open(dir)
+            .unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(1);
+            })
+            .with_retention(options.checkpoint_retention)
+    });
+
+    // A completed session for the same content is skipped unless --force
+    // starts a new one. An interrupted one resumes from the checkpoint it
+    // took when it last recorded progress; without one it starts over.
+    let mut session = None;
+    let mut resume_from = None;
+    if let Some(journal) = journal.as_mut() {
+        let hash = hash_file(file).unwrap_or_else(|e| {
+            eprintln!("Failed to hash input '{}': {}", file, e);
+            process::exit(1);
+        });
+        match journal.find(&hash) {
+            Some(prev) if prev.status == SessionStatus::Completed && !options.force => {
+                eprintln!(
+                    "Input '{}' was already imported in session {}, skipping (use --force to import it again)",
+                    file, prev.session_id
+                );
+                return;
+            }
+            Some(prev)
+                if prev.status == SessionStatus::InProgress
+                    && let Some(checkpoint) = resume_checkpoint(checkpoints.as_ref(), prev) =>
+            {
+                eprintln!(
+                    "Resuming session {} after {} transactions",
+                    prev.session_id, prev.rows_processed
+                );
+                resume_from = Some(checkpoint);
+                session = Some(prev.session_id.clone());
+            }
+            prev => {
+                if let Some(prev) = prev.filter(|p| p.status == SessionStatus::InProgress) {
+                    eprintln!(
+                        "No checkpoint of session {} after {} transactions, starting over",
+                        prev.session_id, prev.rows_processed
+                    );
+                }
+                session = Some(journal.begin(file, &hash).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }))
+            }
+        }
+    }
+
+    let paths = std::iter::once(file).chain(&options.next_inputs).cloned();
+    let mut feed =
+        MultiFileDataSource::new(paths.collect()).with_amount_format(options.amount_format);
+    if options.sort_inputs {
+        feed = feed.sorted_by_name();
+    }
+    if let Some(client_map) = &client_map {
+        feed = feed.with_client_map(client_map.clone());
+    }
+    let mut merged = MergedSource::new().with_source(file.clone(), feed);
+    for path in &options.merge_inputs {
+        let mut source = CsvDataSource::new(path.clone()).with_amount_format(options.amount_format);
+        if let Some(client_map) = &client_map {
+            source = source.with_client_map(client_map.clone());
+        }
+        merged = merged.with_source(path.clone(), source);
+    }
+    if let Some(secs) = options.allowed_lateness_secs {
+        merged = merged.with_allowed_lateness(secs);
+    }
+    let mut data_source = TransformedSource::new(merged);
+    if let Some(factor) = options.amount_scale {
+        data_source = data_source.with_transform(ScaleAmounts(factor));
+    }
+    #[cfg(feature = "scripting")]
+    let script = options.script.as_deref().map(load_script);
+    #[cfg(feature = "scripting")]
+    if let Some(script) = &script
+        && script.has_fees()
+    {
+        data_source = data_source.with_transform(script.clone());
+    }
+
+    // A resumed engine already holds the opening balances, seeded accounts
+    // and closed period it started from.
+    let mut engine = match &resume_from {
+        Some(checkpoint) => File::open(&checkpoint.path)
+            .map_err(|e| format!("Failed to open '{}': {}", checkpoint.path.display(), e))
+            .and_then(PaymentEngine::restore)
+            .unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(1);
+            }),
+        None => {
+            let mut engine = PaymentEngine::new();
+            if let Some(accounts) = opening_balances
+                && let Err(e) = engine.load_opening_balances(accounts)
+            {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+            for (client_id, attributes) in account_seeds {
+                if let Err(e) = engine.open_account(client_id, attributes) {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+            // The period before this run was closed with the balances it
+            // starts from.
+            if let Some(boundary) = options.closed_before
+                && let Err(e) = engine.close_period(boundary)
+            {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+            engine
         }
-        None => Box::new(CsvDataSink::new(std::io::stdout())),
     };
+    // Only this function knows the secret, so nothing the engine is handed
+    // below, scripts and hooks included, can take admin rights.
+    let admin_secret = format!("{:016x}", RandomState::new().build_hasher().finish());
+    if let Err(e) = engine.set_admin_secret(&admin_secret) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    engine.set_require_open_accounts(options.require_open_accounts);
+    engine.set_dispute_funds_policy(options.dispute_funds_policy);
+    engine.set_duplicate_policy(options.duplicate_policy);
+    engine.set_debt_repayment(options.debt_repayment);
+    engine.set_backfill_mode(options.backfill);
+    engine.set_access_list(access);
+    engine.set_late_entry_policy(options.late_entries);
+    if let Some(config) = options.quarantine {
+        engine.enable_quarantine(config);
+    }
+    if let Some(retention) = options.retention {
+        engine.set_retention(retention);
+    }
+    if let Some(secs) = options.dispute_timeout_secs {
+        engine.set_dispute_timeout(secs);
+    }
+    engine.set_config(options.config);
+    for hold in &options.funds_holds {
+        engine.set_funds_hold(*hold);
+    }
+    if let Some(policy) = options.dormancy {
+        engine.set_dormancy_policy(policy);
+    }
+    #[cfg(feature = "scripting")]
+    if let Some(script) = script
+        && script.has_rules()
+    {
+        engine.add_rule(Arc::new(script));
+    }
+    engine.set_decision_audit(decisions_log.is_some());
+    engine.set_freeze_policy(options.freeze_policy);
+    engine.set_withdrawal_policy(options.withdrawal_policy);
+    for rule in sweep_rules {
+        engine.add_sweep_rule(rule);
+    }
+    let checkpoint_every = options.checkpoint_every.unwrap_or(CHECKPOINT_INTERVAL);
+    let resume_from = resume_from.map_or(0, |checkpoint| checkpoint.records);
+    let mut processed: u64 = resume_from;
+    let mut last_watch_write = Instant::now();
 
-    if let Err(e) = data_sink.write_accounts(accounts) {
+    let pipeline = Pipeline::new()
+        .with_skip(resume_from)
+        .with_shutdown_flag(Arc::clone(&shutdown));
+    let result = match options.threads {
+        Some(threads) => process_threaded(&mut data_source, &mut engine, threads, &shutdown)
+            .inspect(|summary| processed = summary.records_read),
+        None => pipeline.process(
+            &mut data_source,
+            &mut engine,
+            |engine, location, outcome| {
+                processed += 1;
+                let at = location.map_or(String::new(), |l| format!(" at {}", l));
+                match outcome {
+                    RecordOutcome::SourceError(e) => eprintln!("Error reading record{}: {}", at, e),
+                    RecordOutcome::Rejected(action, e) => {
+                        eprintln!(
+                            "Rejected tx {} for client {}{}: {} {}",
+                            action.tx_id,
+                            action.client_id,
+                            at,
+                            e.code().code(),
+                            e
+                        );
+                        if let Some((_, sink)) = rejects.as_mut()
+                            && let Err(e) = sink.write_rejection(action, e, location)
+                        {
+                            eprintln!("{}", e);
+                            process::exit(1);
+                        }
+                    }
+                    RecordOutcome::Applied(action) => {
+                        if let Some((_, aggregator)) = aggregator.as_mut()
+                            && let Err(e) = aggregator.observe(action)
+                        {
+                            eprintln!("{}", e);
+                            process::exit(1);
+                        }
+                        if let Some(log) = audit_log.as_mut()
+                            && let Err(e) = log.append("transaction", action)
+                        {
+                            eprintln!("{}", e);
+                            process::exit(1);
+                        }
+                    }
+                }
+                for event in engine.drain_events() {
+                    if let Some(log) = audit_log.as_mut()
+                        && let Err(e) = log.append(event.kind.as_str(), &event.action)
+                    {
+                        eprintln!("{}", e);
+                        process::exit(1);
+                    }
+                }
+                if let Some(log) = decisions_log.as_mut()
+                    && let Err(e) = write_decisions(log, &engine.drain_decisions())
+                {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+                if options.retention.is_some() && processed.is_multiple_of(RETENTION_INTERVAL) {
+                    engine.enforce_retention();
+                }
+                if let (Some(interval), Some(path)) =
+                    (options.watch_output, options.output.as_deref())
+                    && processed.is_multiple_of(WATCH_CHECK_INTERVAL)
+                    && last_watch_write.elapsed() >= interval
+                {
+                    let mut accounts = engine.account_views(options.filter.apply(engine));
+                    if let Some(pseudonymizer) = &pseudonymizer {
+                        accounts = pseudonymizer.apply(&accounts);
+                    }
+                    if let Err(e) = write_accounts_atomic(
+                        path,
+                        &accounts,
+                        options.format,
+                        options.style,
+                        options.columns.as_ref(),
+                        options.config,
+                    ) {
+                        eprintln!("{}", e);
+                    }
+                    last_watch_write = Instant::now();
+                }
+                // With checkpoints, progress is only recorded alongside one so
+                // a resume has the engine state to go with it.
+                let record_progress = match &checkpoints {
+                    Some(store) if processed.is_multiple_of(checkpoint_every) => {
+                        take_checkpoint(store, engine, processed);
+                        true
+                    }
+                    Some(_) => false,
+                    None => processed.is_multiple_of(JOURNAL_INTERVAL),
+                };
+                if record_progress
+                    && let (Some(journal), Some(id)) = (journal.as_mut(), session.as_deref())
+                    && let Err(e) = journal.record_progress(id, processed)
+                {
+                    eprintln!("{}", e);
+                }
+                ControlFlow::Continue(())
+            },
+        ),
+    };
+    let summary = result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    if let Some(store) = &checkpoints
+        && !processed.is_multiple_of(checkpoint_every)
+    {
+        take_checkpoint(store, &engine, processed);
+    }
+
+    if let Some((staged, aggregator)) = aggregator
+        && let Err(e) = aggregator.finish().and_then(|()| staged.commit())
+    {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+
+    // Ops corrections: this is the one place the CLI takes admin rights.
+    if !adjustments.is_empty() && !shutdown.load(Ordering::SeqCst) {
+        let capability = AdminCapability::grant(&admin_secret);
+        for request in adjustments {
+            let applied = request
+                .reason
+                .parse()
+                .and_then(|reason| {
+                    engine
+                        .admin(&capability)
+                        .and_then(|mut admin| {
+                            admin.adjust(request.client_id, request.amount, reason)
+                        })
+                        .map_err(String::from)
+                })
+                .map_err(|e| format!("Adjustment for client {}: {}", request.client_id, e));
+            if let Err(e) = applied {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        for event in engine.drain_events() {
+            if let Some(log) = audit_log.as_mut()
+                && let Err(e) = log.append(event.kind.as_str(), &event.action)
+            {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    // Settlement runs at the cutoff, i.e. once the whole input is applied.
+    if let Some(path) = options.payouts.as_deref()
+        && !shutdown.load(Ordering::SeqCst)
+    {
+        let mut settlement = options.settlement.clone();
+        // Merchants without bank details can't be paid by transfer, so their
+        // balances wait for the next cutoff.
+        if let Some(beneficiaries) = &beneficiaries {
+            let payable = beneficiaries.keys().copied();
+            settlement.merchants = Some(match settlement.merchants {
+                Some(merchants) => payable.filter(|id| merchants.contains(id)).collect(),
+                None => payable.collect(),
+            });
+        }
+        let settled = settle(&mut engine, &settlement).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+        for (client_id, e) in &settled.refused {
+            eprintln!(
+                "Refused payout for client {}: {} {}",
+                client_id,
+                e.code().code(),
+                e
+            );
+        }
+        let batch = settled.payouts;
+        if let Some(log) = audit_log.as_mut() {
+            for payout in &batch {
+                let action = UserTransactions {
+                    tx_type: TxType::Withdrawal,
+                    client_id: payout.client_id,
+                    tx_id: payout.tx_id,
+                    amount: Amount::new(payout.amount).ok(),
+                    ..Default::default()
+                };
+                if let Err(e) = log.append("payout", &action) {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        let written = write_staged(path, |file| match (&debtor, &beneficiaries) {
+            (Some(debtor), Some(beneficiaries)) => {
+                let initiation = PaymentInitiation {
+                    debtor,
+                    beneficiaries,
+                    created_at: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |d| d.as_secs()),
+                };
+                write_pain001(file, &batch, &initiation)
+            }
+            _ => write_payouts(file, &batch),
+        });
+        if let Err(e) = written {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+
+    if let Some(path) = options.orphans.as_deref() {
+        let orphans = engine.take_orphans();
+        let written = write_staged(path, |file| write_orphans(file, &orphans));
+        if let Err(e) = written {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+
+    let late = data_source.inner().late_events();
+    if !late.is_empty() {
+        eprintln!("Set aside {} late transactions", late.len());
+    }
+    if let Some(path) = options.late_events.as_deref() {
+        let written = write_staged(path, |file| write_late_events(file, late));
+        if let Err(e) = written {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+
+    if let Some(path) = options.debts.as_deref() {
+        let written = write_staged(path, |file| write_debts(file, engine.debts()));
+        if let Err(e) = written {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+
+    if let Some(path) = options.liabilities.as_deref() {
+        let written = write_staged(path, |file| {
+            write_liabilities(file, &liabilities(&engine.open_disputes()))
+        });
+        if let Err(e) = written {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+
+    let held = engine.take_held_for_review();
+    if !held.is_empty() {
+        eprintln!("Held {} transactions for blocked clients", held.len());
+    }
+
+    let mut accounts = engine.account_views(options.filter.apply(&engine));
+    if let Some(pseudonymizer) = &pseudonymizer {
+        accounts = pseudonymizer.apply(&accounts);
+    }
+    let written = accounts.len();
+
+    let mut data_sink = options.open_sink().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    if let Err(e) = data_sink
+        .write_accounts(&accounts)
+        .and_then(|()| data_sink.flush())
+    {
         eprintln!("Failed to write output: {}", e);
         process::exit(1);
     }
+    drop(data_sink);
+
+    if let (Some(manifest_path), Some(output)) =
+        (options.manifest.as_deref(), options.output.as_deref())
+    {
+        let key = std::env::var(SIGNING_KEY_ENV).ok();
+        let written = OutputManifest::build(output, key.as_deref().map(str::as_bytes))
+            .map_err(|e| format!("Failed to hash output '{}': {}", output, e))
+            .and_then(|m| m.write_json(manifest_path));
+        if let Err(e) = written {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+
+    if let (Some(provenance), Some(path)) = (provenance.as_mut(), options.provenance.as_deref()) {
+        let recorded = provenance
+            .finish(summary, options.output.as_deref(), written as u64)
+            .map_err(|e| format!("Failed to hash output: {}", e))
+            .and_then(|()| provenance.write_json(path));
+        if let Err(e) = recorded {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+
+    if let Some(log) = audit_log.as_mut()
+        && let Err(e) = log.flush()
+    {
+        eprintln!("Failed to flush audit log: {}", e);
+        process::exit(1);
+    }
+    if let Some((staged, mut sink)) = rejects
+        && let Err(e) = sink.flush().and_then(|()| staged.commit())
+    {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    if let Some(log) = decisions_log.as_mut() {
+        let written = write_decisions(&mut *log, &engine.drain_decisions())
+            .and_then(|()| std::io::Write::flush(log).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            eprintln!("Failed to flush decisions log: {}", e);
+            process::exit(1);
+        }
+    }
+
+    let interrupted = shutdown.load(Ordering::SeqCst);
+    if let (Some(journal), Some(id)) = (journal.as_mut(), session.as_deref()) {
+        let recorded = if interrupted {
+            journal.record_progress(id, processed)
+        } else {
+            journal.complete(id, processed)
+        };
+        if let Err(e) = recorded {
+            eprintln!("{}", e);
+        }
+    }
+
+    eprintln!(
+        "Read {} records: {} applied, {} rejected, {} source errors",
+        summary.records_read, summary.applied, summary.rejected, summary.source_errors
+    );
+    if interrupted {
+        eprintln!(
+            "Shutdown requested: stopped after {} records, wrote {} accounts",
+            processed, written
+        );
+        process::exit(EXIT_INTERRUPTED);
+    }
+    let outcome = summary.outcome(&options.skip_thresholds);
+    // Downstream jobs take the marker to mean every output is in place and
+    // safe to consume.
+    if let Some(path) = options.success_marker.as_deref()
+        && !matches!(outcome, RunOutcome::ThresholdExceeded)
+    {
+        let written = CompletionMarker::build(&options.output_files())
+            .map_err(|e| format!("Failed to hash outputs: {}", e))
+            .and_then(|marker| marker.write_json(path));
+        if let Err(e) = written {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+    match outcome {
+        RunOutcome::Clean => {}
+        RunOutcome::Skipped if options.strict_exit => process::exit(EXIT_SKIPPED),
+        RunOutcome::Skipped => {}
+        RunOutcome::ThresholdExceeded => {
+            eprintln!(
+                "Skipped {} of {} records, more than the configured threshold",
+                summary.skipped(),
+                summary.records_read
+            );
+            process::exit(EXIT_THRESHOLD_EXCEEDED);
+        }
+    }
+}
+
+/// Checkpoint to resume the interrupted session `prev` from: one this
+/// session took after exactly the transactions it recorded as processed.
+fn resume_checkpoint(store: Option<&CheckpointStore>, prev: &ImportSession) -> Option<Checkpoint> {
+    store?
+        .find(prev.rows_processed, prev.started_at)
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        })
+}
+
+/// Snapshots `engine` into `store` and prunes what the retention policy no
+/// longer keeps. Corrupt checkpoints are reported as they are dropped.
+fn take_checkpoint(store: &CheckpointStore, engine: &PaymentEngine, processed: u64) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let pruned = store
+        .save(engine, now, processed)
+        .and_then(|_| store.prune(now));
+    match pruned {
+        Ok(report) => {
+            for checkpoint in report.corrupt {
+                eprintln!(
+                    "Removed checkpoint '{}': failed verification",
+                    checkpoint.path.display()
+                );
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(feature = "scripting")]
+fn load_script(path: &str) -> payment_engine::scripting::Script {
+    payment_engine::scripting::Script::load(path).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    })
+}
+
+/// Reads all of `source` and applies it on `threads` workers, then merges
+/// their state back into `engine`. Rejected transactions are counted but,
+/// unlike a sequential run, not reported one by one. A shutdown stops the
+/// reading; what was read by then is still applied.
+fn process_threaded(
+    source: &mut dyn DataSource,
+    engine: &mut PaymentEngine,
+    threads: usize,
+    shutdown: &AtomicBool,
+) -> Result<RunSummary, String> {
+    let records = source
+        .read_located_transactions()
+        .map_err(|e| format!("Failed to read data: {}", e))?;
+    let mut read = RunSummary::default();
+    let mut actions = Vec::new();
+    for (location, record) in records {
+        if shutdown.load(Ordering::SeqCst) {
+            break;
+        }
+        match record {
+            Ok(action) => actions.push(action),
+            Err(e) => {
+                let at = location.map_or(String::new(), |l| format!(" at {}", l));
+                eprintln!("Error reading record{}: {}", at, e);
+                read.record_source_error();
+            }
+        }
+    }
+    let run = engine.process_parallel(actions, threads)?;
+    let summary = RunSummary {
+        records_read: read.records_read + run.summary.records_read,
+        source_errors: read.source_errors,
+        ..run.summary
+    };
+    run.merge_into(engine);
+    Ok(summary)
 }
@@ -0,0 +1,156 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::data_sinks::staged::write_staged;
+
+/// Environment variable holding the HMAC key used to sign outputs.
+pub const SIGNING_KEY_ENV: &str = "PAYMENT_ENGINE_SIGNING_KEY";
+
+/// Sidecar describing an output file so consumers can check it arrived
+/// unmodified.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct OutputManifest {
+    pub file: String,
+    /// Data rows, header excluded.
+    pub rows: u64,
+    pub sha256: String,
+    /// Present when a signing key was supplied.
+    pub hmac_sha256: Option<String>,
+}
+
+impl OutputManifest {
+    pub fn build(path: &str, key: Option<&[u8]>) -> Result<Self, io::Error> {
+        let (manifest, mac) = Self::digest(path, key)?;
+        Ok(Self {
+            hmac_sha256: mac.map(|mac| format!("{:x}", mac.finalize().into_bytes())),
+            ..manifest
+        })
+    }
+
+    /// The manifest of `path` without its signature, and the MAC state the
+    /// signature is taken from.
+    fn digest(path: &str, key: Option<&[u8]>) -> Result<(Self, Option<Hmac<Sha256>>), io::Error> {
+        let mut reader = BufReader::new(File::open(Path::new(path))?);
+        let mut hasher = Sha256::new();
+        let mut mac = key.map(|key| {
+            Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length")
+        });
+        let mut lines = 0u64;
+        let mut last = b'\n';
+        let mut buf = [0u8; 64 * 1024];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            let chunk = &buf[..n];
+            hasher.update(chunk);
+            if let Some(mac) = mac.as_mut() {
+                mac.update(chunk);
+            }
+            lines += chunk.iter().filter(|b| **b == b'\n').count() as u64;
+            last = chunk[n - 1];
+        }
+        if last != b'\n' {
+            lines += 1;
+        }
+
+        let manifest = Self {
+            file: path.to_string(),
+            rows: lines.saturating_sub(1),
+            sha256: format!("{:x}", hasher.finalize()),
+            hmac_sha256: None,
+        };
+        Ok((manifest, mac))
+    }
+
+    /// Rebuilds the manifest for `self.file` and checks it matches, including
+    /// the signature when one is recorded. The signature is compared in
+    /// constant time.
+    pub fn verify(&self, key: Option<&[u8]>) -> Result<bool, io::Error> {
+        let (actual, mac) = Self::digest(&self.file, key)?;
+        let signed = match (&self.hmac_sha256, mac) {
+            (None, _) => true,
+            (Some(recorded), Some(mac)) => {
+                decode_hex(recorded).is_some_and(|tag| mac.verify_slice(&tag).is_ok())
+            }
+            (Some(_), None) => false,
+        };
+        Ok(actual.rows == self.rows && actual.sha256 == self.sha256 && signed)
+    }
+
+    pub fn write_json(&self, path: &str) -> Result<(), String> {
+        write_staged(path, |file| {
+            serde_json::to_writer_pretty(file, self)
+                .map_err(|e| format!("Failed to write manifest '{}': {}", path, e))
+        })
+    }
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Written once every output of a run is in place, so downstream jobs can
+/// wait for it rather than guess whether a file is complete.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct CompletionMarker {
+    pub files: Vec<OutputManifest>,
+}
+
+impl CompletionMarker {
+    pub fn build(paths: &[&str]) -> Result<Self, io::Error> {
+        let files = paths
+            .iter()
+            .map(|path| OutputManifest::build(path, None))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { files })
+    }
+
+    pub fn write_json(&self, path: &str) -> Result<(), String> {
+        write_staged(path, |file| {
+            serde_json::to_writer_pretty(file, self)
+                .map_err(|e| format!("Failed to write marker '{}': {}", path, e))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_detects_tampering() {
+        let path = std::env::temp_dir().join(format!("manifest-{}.csv", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "client,available\n1,1.0000\n2,2.0000\n").unwrap();
+
+        let manifest = OutputManifest::build(path, Some(b"secret")).unwrap();
+        assert_eq!(manifest.rows, 2);
+        assert!(manifest.hmac_sha256.is_some());
+        assert!(manifest.verify(Some(b"secret")).unwrap());
+        assert!(!manifest.verify(Some(b"other key")).unwrap());
+        assert!(!manifest.verify(None).unwrap());
+        let mut forged = manifest.clone();
+        forged.hmac_sha256 = Some("zz".repeat(32));
+        assert!(!forged.verify(Some(b"secret")).unwrap());
+
+        std::fs::write(path, "client,available\n1,9.0000\n2,2.0000\n").unwrap();
+        assert!(!manifest.verify(Some(b"secret")).unwrap());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+use std::fmt;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Most decimal places an amount may carry.
+pub const MAX_SCALE: u32 = 4;
+
+/// A transaction amount: never negative and at most [`MAX_SCALE`] decimal
+/// places. Serializes as a bare decimal.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize, Serialize)]
+#[serde(try_from = "Decimal", into = "Decimal")]
+pub struct Amount {
+    value: Decimal,
+}
+
+impl Amount {
+    pub const ZERO: Amount = Amount {
+        value: Decimal::ZERO,
+    };
+
+    pub fn new(value: Decimal) -> Result<Self, String> {
+        if value.is_sign_negative() && !value.is_zero() {
+            return Err(format!("Amount {} is negative", value));
+        }
+        check_scale(value)?;
+        Ok(Self { value })
+    }
+
+    pub fn value(self) -> Decimal {
+        self.value
+    }
+}
+
+impl TryFrom<Decimal> for Amount {
+    type Error = String;
+
+    fn try_from(value: Decimal) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl From<Amount> for Decimal {
+    fn from(amount: Amount) -> Self {
+        amount.value
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.value, f)
+    }
+}
+
+/// An amount whose sign matters, e.g. an adjustment that credits
+/// (positive) or debits (negative). At most [`MAX_SCALE`] decimal places.
+/// Serializes as a bare decimal.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize, Serialize)]
+#[serde(try_from = "Decimal", into = "Decimal")]
+pub struct SignedAmount {
+    value: Decimal,
+}
+
+impl SignedAmount {
+    pub fn new(value: Decimal) -> Result<Self, String> {
+        check_scale(value)?;
+        Ok(Self { value })
+    }
+
+    pub fn value(self) -> Decimal {
+        self.value
+    }
+}
+
+impl TryFrom<Decimal> for SignedAmount {
+    type Error = String;
+
+    fn try_from(value: Decimal) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl From<SignedAmount> for Decimal {
+    fn from(amount: SignedAmount) -> Self {
+        amount.value
+    }
+}
+
+impl fmt::Display for SignedAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.value, f)
+    }
+}
+
+fn check_scale(value: Decimal) -> Result<(), String> {
+    if value.normalize().scale() > MAX_SCALE {
+        return Err(format!(
+            "Amount {} has more than {} decimal places",
+            value, MAX_SCALE
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_amount_invariants() {
+        assert!(Amount::new(dec!(-1)).is_err());
+        assert!(Amount::new(dec!(1.23456)).is_err());
+        assert!(Amount::new(dec!(1.23450)).is_ok());
+        assert!(SignedAmount::new(dec!(-1.5)).is_ok());
+        assert!(SignedAmount::new(dec!(-1.23456)).is_err());
+    }
+}
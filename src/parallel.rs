@@ -0,0 +1,361 @@
+use std::thread;
+
+use crate::{
+    PaymentEngine, TxType, UserAccount, UserTransactions, duplicates::DuplicatePolicy,
+    pipeline::RunSummary,
+};
+
+/// Result of [`PaymentEngine::process_parallel`].
+pub struct ParallelRun {
+    /// One engine per worker. Worker `i` owns the clients whose
+    /// `client_id % workers == i`; its copies of other clients' accounts are
+    /// stale.
+    pub shards: Vec<PaymentEngine>,
+    pub summary: RunSummary,
+}
+
+impl ParallelRun {
+    pub fn owner(&self, client_id: u16) -> usize {
+        usize::from(client_id) % self.shards.len()
+    }
+
+    /// Every account, taken from the shard that owns it, by client id.
+    pub fn accounts(&self) -> Vec<&UserAccount> {
+        let mut accounts: Vec<&UserAccount> = self
+            .shards
+            .iter()
+            .enumerate()
+            .flat_map(|(shard, engine)| {
+                engine
+                    .accounts
+                    .values()
+                    .filter(move |account| self.owner(account.client_id) == shard)
+            })
+            .collect();
+        accounts.sort_unstable_by_key(|account| account.client_id);
+        accounts
+    }
+
+    /// Replaces the client state of `engine`, the engine the run was forked
+    /// from, with what the shards left, each client taken from the shard
+    /// that owns it. Its policies and hooks are kept. Activity sequence
+    /// numbers were counted per shard, so they only order a client's own
+    /// transactions.
+    pub fn merge_into(self, engine: &mut PaymentEngine) {
+        let workers = self.shards.len();
+        for (shard, state) in self.shards.into_iter().enumerate() {
+            let owned = |client_id: u16| usize::from(client_id) % workers == shard;
+            engine.absorb(state, owned);
+        }
+    }
+}
+
+impl PaymentEngine {
+    /// Refuses engines whose features link clients or read the shared
+    /// stream clock, so they can't be split into shards by client; see
+    /// [`Self::process_parallel`].
+    pub(crate) fn check_shardable(&self) -> Result<(), String> {
+        if !self.sweep_rules.is_empty() {
+            return Err("Sweep rules can't be applied in parallel".to_string());
+        }
+        if self.dispute_timeout_secs.is_some()
+            || self.quarantine.is_some()
+            || self.dormancy.is_some()
+            || !self.funds_holds.is_empty()
+        {
+            return Err(
+                "Dispute timeouts, quarantine, dormancy and funds holds depend on stream order across clients"
+                    .to_string(),
+            );
+        }
+        // The limit is shared by all clients, so which transactions it drops
+        // depends on the order across them.
+        if self.retention.is_some_and(|r| r.max_transactions.is_some()) {
+            return Err("A transaction retention limit can't be kept in parallel".to_string());
+        }
+        if self.wal.is_some() {
+            return Err("A write-ahead log can't be kept in parallel".to_string());
+        }
+        // Shards are forks, which don't carry hooks, so none would run.
+        if self.hooks.is_some() {
+            return Err("Hooks can't be run in parallel".to_string());
+        }
+        if self.duplicate_policy == DuplicatePolicy::LastWriteWins {
+            return Err("Last-write-wins duplicates can't be applied in parallel".to_string());
+        }
+        Ok(())
+    }
+
+    /// Applies `transactions` on `workers` threads, each running a fork of
+    /// this engine.
+    ///
+    /// Ordering model: all transactions of a client go to the same worker
+    /// and are applied in input order, so every client ends up exactly as a
+    /// sequential run would leave it. Transactions of different clients have
+    /// no order between them. Features that link clients or read the shared
+    /// stream clock (sweep rules, dispute timeouts, quarantine, dormancy,
+    /// funds holds) would observe a different order than a sequential run,
+    /// so they are refused, as are hooks, an input that reuses a deposit or
+    /// withdrawal tx id across clients and the last-write-wins duplicate
+    /// policy.
+    pub fn process_parallel(
+        &self,
+        transactions: impl IntoIterator<Item = UserTransactions>,
+        workers: usize,
+    ) -> Result<ParallelRun, String> {
+        self.check_shardable()?;
+        let workers = workers.max(1);
+
+        let mut owners = self.seen_tx_ids.clone();
+        let mut partitions: Vec<Vec<UserTransactions>> = vec![Vec::new(); workers];
+        for action in transactions {
+            // A batch can span clients, so its legs would land on different
+            // workers.
+            if let Some(batch_id) = action.batch_id {
+                return Err(format!("Batch {} can't be applied in parallel", batch_id));
+            }
+            // So can a transfer.
+            if action.tx_type == TxType::Transfer {
+                return Err(format!(
+                    "Transfer {} can't be applied in parallel",
+                    action.tx_id
+                ));
+            }
+            if matches!(action.tx_type, TxType::Deposit | TxType::Withdrawal)
+                && *owners.entry(action.tx_id).or_insert(action.client_id) != action.client_id
+            {
+                return Err(format!(
+                    "Transaction {} is used by more than one client",
+                    action.tx_id
+                ));
+            }
+            partitions[usize::from(action.client_id) % workers].push(action);
+        }
+        let forks: Vec<PaymentEngine> = (0..workers).map(|_| self.fork()).collect();
+
+        let results: Vec<(PaymentEngine, RunSummary)> = thread::scope(|scope| {
+            let handles: Vec<_> = forks
+                .into_iter()
+                .zip(partitions)
+                .map(|(mut shard, partition)| {
+                    scope.spawn(move || {
+                        let mut summary = RunSummary::default();
+                        for action in partition {
+                            summary.record_outcome(&shard.process_action(action));
+                        }
+                        (shard, summary)
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("worker thread panicked"))
+                .collect()
+        });
+
+        let mut summary = RunSummary::default();
+        let mut shards = Vec::with_capacity(workers);
+        for (shard, shard_summary) in results {
+            summary.merge(&shard_summary);
+            shards.push(shard);
+        }
+        Ok(ParallelRun { shards, summary })
+    }
+
+    /// Replaces this engine's state for the clients `shard` owns with the
+    /// shard's, and takes over the events and decisions it raised for them.
+    fn absorb(&mut self, shard: PaymentEngine, owned: impl Fn(u16) -> bool) {
+        let owned_key = |&(client_id, _): &(u16, u32)| owned(client_id);
+        // Destructured so that a new field has to be merged, or left to this
+        // engine, here.
+        let PaymentEngine {
+            accounts,
+            actions,
+            dispute_opened_at,
+            events,
+            stats,
+            attributes,
+            dispute_holds,
+            dispute_states,
+            seen_tx_ids,
+            debts,
+            reserved_funds,
+            closed_accounts,
+            last_active_at,
+            dormant,
+            queued_disputes,
+            last_activity,
+            held_for_review,
+            decisions,
+            stream_time,
+            activity_seq,
+            // Policies and hooks are this engine's own. The rest isn't
+            // changed by what a shard can run.
+            retention: _,
+            tx_recency: _,
+            sweep_rules: _,
+            dispute_timeout_secs: _,
+            synthetic_ids: _,
+            freeze_policy: _,
+            withdrawal_policy: _,
+            hooks: _,
+            deferred_hooks: _,
+            rules: _,
+            require_open_accounts: _,
+            dispute_funds_policy: _,
+            duplicate_policy: _,
+            debt_repayment: _,
+            config: _,
+            funds_holds: _,
+            dormancy: _,
+            dormancy_due: _,
+            backfill: _,
+            quarantine: _,
+            access: _,
+            closed_periods: _,
+            late_entry_policy: _,
+            adjustments: _,
+            wal: _,
+            admin_secret: _,
+        } = shard;
+        take_owned(&mut self.accounts, accounts, |(c, _)| owned(*c));
+        take_owned(&mut self.actions, actions, |(c, _)| owned(*c));
+        take_owned(
+            &mut self.dispute_opened_at,
+            dispute_opened_at,
+            |(key, _)| owned_key(key),
+        );
+        take_owned(&mut self.stats, stats, |(c, _)| owned(*c));
+        take_owned(&mut self.attributes, attributes, |(c, _)| owned(*c));
+        take_owned(&mut self.dispute_holds, dispute_holds, |(key, _)| {
+            owned_key(key)
+        });
+        take_owned(&mut self.dispute_states, dispute_states, |(key, _)| {
+            owned_key(key)
+        });
+        take_owned(&mut self.seen_tx_ids, seen_tx_ids, |(_, c)| owned(*c));
+        take_owned(&mut self.debts, debts, |debt| owned(debt.client_id));
+        take_owned(&mut self.reserved_funds, reserved_funds, |funds| {
+            owned(funds.client_id)
+        });
+        take_owned(&mut self.closed_accounts, closed_accounts, |c| owned(*c));
+        take_owned(&mut self.last_active_at, last_active_at, |(c, _)| owned(*c));
+        take_owned(&mut self.dormant, dormant, |c| owned(*c));
+        take_owned(&mut self.queued_disputes, queued_disputes, |(action, _)| {
+            owned(action.client_id)
+        });
+        take_owned(&mut self.last_activity, last_activity, |(c, _)| owned(*c));
+        take_owned(&mut self.held_for_review, held_for_review, |action| {
+            owned(action.client_id)
+        });
+        self.events.extend(
+            events
+                .into_iter()
+                .filter(|event| owned(event.action.client_id)),
+        );
+        if let (Some(mine), Some(theirs)) = (self.decisions.as_mut(), decisions) {
+            mine.extend(
+                theirs
+                    .into_iter()
+                    .filter(|decision| owned(decision.client_id)),
+            );
+        }
+        self.stream_time = self.stream_time.max(stream_time);
+        self.activity_seq = self.activity_seq.max(activity_seq);
+    }
+}
+
+/// Swaps the entries of `mine` that `owned` picks out for those of `theirs`.
+fn take_owned<C>(mine: &mut C, theirs: C, owned: impl Fn(&C::Item) -> bool)
+where
+    C: Default + IntoIterator + FromIterator<C::Item>,
+{
+    *mine = std::mem::take(mine)
+        .into_iter()
+        .filter(|item| !owned(item))
+        .chain(theirs.into_iter().filter(|item| owned(item)))
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bench::{WorkloadConfig, generate_workload},
+        hooks::EngineHooks,
+        sweeps::SweepRule,
+        view::ClientAccountView,
+    };
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_parallel_matches_sequential_per_client() {
+        for seed in 1..=5 {
+            let workload = generate_workload(&WorkloadConfig {
+                records: 2_000,
+                clients: 25,
+                seed,
+            });
+            let mut sequential = PaymentEngine::new();
+            let mut expected = RunSummary::default();
+            for action in workload.clone() {
+                expected.record_outcome(&sequential.process_action(action));
+            }
+            let mut expected_accounts: Vec<ClientAccountView> = sequential
+                .accounts
+                .values()
+                .map(ClientAccountView::from)
+                .collect();
+            expected_accounts.sort_unstable_by_key(|account| account.client_id);
+
+            for workers in [1, 2, 3, 8] {
+                let run = PaymentEngine::new()
+                    .process_parallel(workload.clone(), workers)
+                    .unwrap();
+                assert_eq!(run.summary, expected, "seed {seed}, {workers} workers");
+                let accounts: Vec<ClientAccountView> = run
+                    .accounts()
+                    .into_iter()
+                    .map(ClientAccountView::from)
+                    .collect();
+                assert_eq!(accounts, expected_accounts);
+
+                let mut merged = PaymentEngine::new();
+                run.merge_into(&mut merged);
+                let mut accounts: Vec<ClientAccountView> = merged
+                    .accounts
+                    .values()
+                    .map(ClientAccountView::from)
+                    .collect();
+                accounts.sort_unstable_by_key(|account| account.client_id);
+                assert_eq!(accounts, expected_accounts);
+                assert_eq!(merged.seen_tx_ids, sequential.seen_tx_ids);
+                assert_eq!(merged.dispute_states, sequential.dispute_states);
+                assert_eq!(merged.debts().len(), sequential.debts().len());
+            }
+        }
+
+        let mut engine = PaymentEngine::new();
+        engine.add_sweep_rule(SweepRule {
+            from: 1,
+            to: 2,
+            threshold: dec!(1),
+        });
+        assert!(engine.process_parallel(Vec::new(), 2).is_err());
+
+        struct NoHooks;
+        impl EngineHooks for NoHooks {}
+        let mut engine = PaymentEngine::new();
+        engine.set_hooks(Box::new(NoHooks));
+        assert!(engine.process_parallel(Vec::new(), 2).is_err());
+
+        let shared_id = [1, 2].map(|client_id| UserTransactions {
+            tx_type: TxType::Deposit,
+            client_id,
+            tx_id: 1,
+            amount: None,
+            ..Default::default()
+        });
+        assert!(PaymentEngine::new().process_parallel(shared_id, 2).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use std::str::FromStr;
+
+use crate::{
+    EngineEvent, EventKind, PaymentEngine, UserAccount, UserTransactions,
+    errors::{EngineError, ErrorCode},
+};
+
+/// What happens to a transaction dated inside a closed period.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum LateEntryPolicy {
+    #[default]
+    Reject,
+    /// Apply it in the current period, re-dated to the period boundary, and
+    /// report it as a `period_adjustment` event.
+    Adjust,
+}
+
+impl FromStr for LateEntryPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(Self::Reject),
+            "adjust" => Ok(Self::Adjust),
+            other => Err(format!(
+                "Unknown late entry policy '{}', expected reject or adjust",
+                other
+            )),
+        }
+    }
+}
+
+/// Balances as they stood when a period was closed.
+#[derive(Debug, Clone)]
+pub struct ClosedPeriod {
+    /// Transactions dated before this are part of the period.
+    pub end: u64,
+    pub accounts: Vec<UserAccount>,
+}
+
+impl PaymentEngine {
+    pub fn set_late_entry_policy(&mut self, policy: LateEntryPolicy) {
+        self.late_entry_policy = policy;
+    }
+
+    /// Closes everything dated before `timestamp` and snapshots the current
+    /// balances. Periods only move forward.
+    pub fn close_period(&mut self, timestamp: u64) -> Result<(), String> {
+        if let Some(end) = self.period_end()
+            && timestamp <= end
+        {
+            return Err(format!(
+                "Period ending {} is already closed; can't close at {}",
+                end, timestamp
+            ));
+        }
+        let mut accounts: Vec<UserAccount> = self.accounts.values().cloned().collect();
+        accounts.sort_unstable_by_key(|a| a.client_id);
+        self.closed_periods.push_back(ClosedPeriod {
+            end: timestamp,
+            accounts,
+        });
+        Ok(())
+    }
+
+    pub fn closed_periods(&self) -> &im::Vector<ClosedPeriod> {
+        &self.closed_periods
+    }
+
+    fn period_end(&self) -> Option<u64> {
+        self.closed_periods.last().map(|p| p.end)
+    }
+
+    /// Rejects or re-dates `action` if it falls in a closed period.
+    /// Untimestamped transactions always belong to the current period.
+    pub(crate) fn check_period(
+        &mut self,
+        mut action: UserTransactions,
+    ) -> Result<UserTransactions, EngineError> {
+        let (Some(end), Some(ts)) = (self.period_end(), action.timestamp) else {
+            return Ok(action);
+        };
+        if ts >= end {
+            return Ok(action);
+        }
+        match self.late_entry_policy {
+            LateEntryPolicy::Reject => Err(EngineError::new(
+                ErrorCode::PeriodClosed,
+                format!(
+                    "Transaction dated {} falls in the period closed at {}",
+                    ts, end
+                ),
+            )),
+            LateEntryPolicy::Adjust => {
+                action.timestamp = Some(end);
+                self.events.push(EngineEvent {
+                    kind: EventKind::PeriodAdjustment,
+                    action: action.clone(),
+                });
+                Ok(action)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TxType, tx};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_closed_period_is_frozen() {
+        let mut engine = PaymentEngine::new();
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10)).at(50))
+            .unwrap();
+        engine.close_period(100).unwrap();
+        assert!(engine.close_period(90).is_err());
+
+        engine
+            .process_action(tx(TxType::Deposit, 1, 2).with_amount(dec!(5)).at(99))
+            .unwrap_err();
+        engine
+            .process_action(tx(TxType::Deposit, 1, 3).with_amount(dec!(5)).at(100))
+            .unwrap();
+        assert_eq!(engine.accounts[&1].available, dec!(15));
+        assert_eq!(engine.closed_periods()[0].accounts[0].available, dec!(10));
+
+        engine.set_late_entry_policy(LateEntryPolicy::Adjust);
+        engine
+            .process_action(tx(TxType::Deposit, 1, 4).with_amount(dec!(1)).at(20))
+            .unwrap();
+        assert_eq!(engine.accounts[&1].available, dec!(16));
+        let events = engine.drain_events();
+        assert_eq!(events[0].kind, EventKind::PeriodAdjustment);
+        assert_eq!(events[0].action.timestamp, Some(100));
+    }
+}
@@ -0,0 +1,439 @@
+use std::{
+    ops::ControlFlow,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{
+    PaymentEngine, TxOutcome, UserTransactions,
+    batches::rolled_back,
+    data_sinks::{DataSink, filter::AccountFilter},
+    data_sources::{DataSource, LocatedRecord, SourceError, SourceLocation, SourceRecord},
+    errors::EngineError,
+};
+
+/// Outcome counters for one run. Source failures (records that never became
+/// a transaction) and engine rejections (valid transactions refused by the
+/// business rules) are kept apart so they can be monitored separately.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize)]
+pub struct RunSummary {
+    pub records_read: u64,
+    pub source_errors: u64,
+    pub applied: u64,
+    pub rejected: u64,
+}
+
+impl RunSummary {
+    pub fn record_source_error(&mut self) {
+        self.records_read += 1;
+        self.source_errors += 1;
+    }
+
+    pub fn record_outcome<T, E>(&mut self, outcome: &Result<T, E>) {
+        self.records_read += 1;
+        match outcome {
+            Ok(_) => self.applied += 1,
+            Err(_) => self.rejected += 1,
+        }
+    }
+
+    /// Adds the counts of `other`, a run over more of the same input.
+    pub fn merge(&mut self, other: &RunSummary) {
+        self.records_read += other.records_read;
+        self.source_errors += other.source_errors;
+        self.applied += other.applied;
+        self.rejected += other.rejected;
+    }
+
+    /// Records that didn't change any balance: source errors plus rejections.
+    pub fn skipped(&self) -> u64 {
+        self.source_errors + self.rejected
+    }
+
+    pub fn outcome(&self, thresholds: &SkipThresholds) -> RunOutcome {
+        let skipped = self.skipped();
+        if skipped == 0 {
+            return RunOutcome::Clean;
+        }
+        let over_count = thresholds.max_skipped.is_some_and(|max| skipped > max);
+        let over_percent = thresholds.max_skipped_percent.is_some_and(|max| {
+            Decimal::from(skipped) * Decimal::ONE_HUNDRED > max * Decimal::from(self.records_read)
+        });
+        if over_count || over_percent {
+            RunOutcome::ThresholdExceeded
+        } else {
+            RunOutcome::Skipped
+        }
+    }
+}
+
+/// How many skipped records a run tolerates before it counts as failed.
+/// Unset limits tolerate any number.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct SkipThresholds {
+    pub max_skipped: Option<u64>,
+    /// Share of the records read, in percent: `0.1` fails a run that skips
+    /// more than one record in a thousand.
+    pub max_skipped_percent: Option<Decimal>,
+}
+
+/// Overall result of a run that got to the end of its input.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RunOutcome {
+    Clean,
+    /// Some records were skipped, within the configured thresholds.
+    Skipped,
+    ThresholdExceeded,
+}
+
+/// What to do when a record can't be read or the engine rejects it.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum ErrorPolicy {
+    /// Count it and move on to the next record.
+    #[default]
+    Skip,
+    /// Stop the run and return the error.
+    FailFast,
+}
+
+/// What happened to one record, as passed to [`Pipeline::process`] callers.
+#[derive(Debug)]
+pub enum RecordOutcome<'a> {
+    SourceError(&'a SourceError),
+    Applied(&'a UserTransactions),
+    Rejected(&'a UserTransactions, &'a EngineError),
+}
+
+/// Drives records from a [`DataSource`] through a [`PaymentEngine`].
+#[derive(Debug, Default, Clone)]
+pub struct Pipeline {
+    policy: ErrorPolicy,
+    skip: u64,
+    shutdown: Option<Arc<AtomicBool>>,
+    filter: AccountFilter,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Skips the first `records` records, e.g. to resume an interrupted import.
+    pub fn with_skip(mut self, records: u64) -> Self {
+        self.skip = records;
+        self
+    }
+
+    /// Stops before the next record once `flag` is set.
+    pub fn with_shutdown_flag(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.shutdown = Some(flag);
+        self
+    }
+
+    /// Limits which accounts [`Self::run`] writes to the sink.
+    pub fn with_filter(mut self, filter: AccountFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Feeds every record to `engine`, calling `on_record` after each one
+    /// with the record's location, if the source knows it. `on_record` can
+    /// end the run early by returning `ControlFlow::Break`.
+    ///
+    /// Consecutive records with the same batch id are applied together with
+    /// [`PaymentEngine::process_batch`]. A record that can't be read while
+    /// a batch is open fails that batch, and a batch cut short by a
+    /// shutdown isn't applied.
+    pub fn process<F>(
+        &self,
+        source: &mut dyn DataSource,
+        engine: &mut PaymentEngine,
+        mut on_record: F,
+    ) -> Result<RunSummary, String>
+    where
+        F: FnMut(&mut PaymentEngine, Option<&SourceLocation>, RecordOutcome) -> ControlFlow<()>,
+    {
+        let records = source
+            .read_located_transactions()
+            .map_err(|e| format!("Failed to read data: {}", e))?;
+        let mut summary = RunSummary::default();
+        let mut batch: Vec<LocatedRecord> = Vec::new();
+        let mut batch_id = None;
+
+        for (location, record) in records.skip(self.skip as usize) {
+            if self
+                .shutdown
+                .as_ref()
+                .is_some_and(|flag| flag.load(Ordering::SeqCst))
+            {
+                return Ok(summary);
+            }
+            let record_batch = record.as_ref().ok().and_then(|action| action.batch_id);
+            if !batch.is_empty() && record.is_ok() && record_batch != batch_id {
+                let batch = std::mem::take(&mut batch);
+                if self
+                    .apply_batch(batch, engine, &mut summary, &mut on_record)?
+                    .is_break()
+                {
+                    return Ok(summary);
+                }
+            }
+            if record_batch.is_some() || !batch.is_empty() {
+                batch_id = record_batch.or(batch_id);
+                batch.push((location, record));
+                continue;
+            }
+            if self
+                .apply_record(location, record, None, engine, &mut summary, &mut on_record)?
+                .is_break()
+            {
+                return Ok(summary);
+            }
+        }
+        if !batch.is_empty() {
+            // The run ends here whether or not `on_record` asks to stop.
+            let _ = self.apply_batch(batch, engine, &mut summary, &mut on_record)?;
+        }
+        Ok(summary)
+    }
+
+    fn position(&self, location: Option<&SourceLocation>, summary: &RunSummary) -> String {
+        match location {
+            Some(location) => location.to_string(),
+            None => format!("Record {}", self.skip + summary.records_read + 1),
+        }
+    }
+
+    /// Reports one record. A record of a batch comes with the batch's
+    /// outcome for it; any other record is applied here.
+    fn apply_record<F>(
+        &self,
+        location: Option<SourceLocation>,
+        record: SourceRecord,
+        batch_outcome: Option<Result<TxOutcome, EngineError>>,
+        engine: &mut PaymentEngine,
+        summary: &mut RunSummary,
+        on_record: &mut F,
+    ) -> Result<ControlFlow<()>, String>
+    where
+        F: FnMut(&mut PaymentEngine, Option<&SourceLocation>, RecordOutcome) -> ControlFlow<()>,
+    {
+        let position = self.position(location.as_ref(), summary);
+        let location = location.as_ref();
+        Ok(match record {
+            Err(e) => {
+                summary.record_source_error();
+                if self.policy == ErrorPolicy::FailFast {
+                    return Err(format!("{}: {}", position, e));
+                }
+                on_record(engine, location, RecordOutcome::SourceError(&e))
+            }
+            Ok(action) => {
+                let outcome =
+                    batch_outcome.unwrap_or_else(|| engine.process_action(action.clone()));
+                summary.record_outcome(&outcome);
+                match outcome {
+                    Ok(_) => on_record(engine, location, RecordOutcome::Applied(&action)),
+                    Err(e) if self.policy == ErrorPolicy::FailFast => {
+                        return Err(format!("{}: {} {}", position, e.code().code(), e));
+                    }
+                    Err(e) => on_record(engine, location, RecordOutcome::Rejected(&action, &e)),
+                }
+            }
+        })
+    }
+
+    fn apply_batch<F>(
+        &self,
+        batch: Vec<LocatedRecord>,
+        engine: &mut PaymentEngine,
+        summary: &mut RunSummary,
+        on_record: &mut F,
+    ) -> Result<ControlFlow<()>, String>
+    where
+        F: FnMut(&mut PaymentEngine, Option<&SourceLocation>, RecordOutcome) -> ControlFlow<()>,
+    {
+        let batch_id = batch
+            .iter()
+            .find_map(|(_, record)| record.as_ref().ok()?.batch_id)
+            .unwrap_or_default();
+        let actions: Option<Vec<UserTransactions>> = batch
+            .iter()
+            .map(|(_, record)| record.as_ref().ok().cloned())
+            .collect();
+        let result = actions.map(|actions| engine.process_batch(actions));
+        // Under fail-fast, report the record that sank the batch rather than
+        // the first one rolled back with it.
+        let failed_at = match &result {
+            None => batch.iter().position(|(_, record)| record.is_err()),
+            Some(Err(error)) => Some(error.index),
+            Some(Ok(_)) => None,
+        };
+
+        let mut outcomes = match result {
+            Some(Ok(outcomes)) => outcomes.into_iter().map(Ok).collect(),
+            Some(Err(error)) => (0..batch.len())
+                .map(|i| {
+                    Err(if i == error.index {
+                        error.error.clone()
+                    } else {
+                        rolled_back(batch_id)
+                    })
+                })
+                .collect(),
+            None => vec![Err(rolled_back(batch_id)); batch.len()],
+        }
+        .into_iter();
+        if self.policy == ErrorPolicy::FailFast
+            && let Some(index) = failed_at
+        {
+            let (location, record) = batch.into_iter().nth(index).expect("index is in the batch");
+            let outcome = outcomes.nth(index);
+            return self.apply_record(location, record, outcome, engine, summary, on_record);
+        }
+
+        for ((location, record), outcome) in batch.into_iter().zip(outcomes) {
+            let flow =
+                self.apply_record(location, record, Some(outcome), engine, summary, on_record)?;
+            if flow.is_break() {
+                return Ok(flow);
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Processes the whole source, then writes the resulting accounts to `sink`.
+    pub fn run(
+        &self,
+        source: &mut dyn DataSource,
+        engine: &mut PaymentEngine,
+        sink: &mut dyn DataSink,
+    ) -> Result<RunSummary, String> {
+        let summary = self.process(source, engine, |_, _, _| ControlFlow::Continue(()))?;
+        let accounts = engine.account_views(self.filter.apply(engine));
+        sink.write_accounts(&accounts)?;
+        sink.flush()?;
+        Ok(summary)
+    }
+}
+
+impl PaymentEngine {
+    /// Processes all of `source`, skipping records that fail or are
+    /// rejected, then writes every account to `sink` and flushes it. Use a
+    /// [`Pipeline`] for other error policies or per-record callbacks.
+    pub fn run(
+        &mut self,
+        source: &mut dyn DataSource,
+        sink: &mut dyn DataSink,
+    ) -> Result<RunSummary, String> {
+        Pipeline::new().run(source, self, sink)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl PaymentEngine {
+    /// Async counterpart of [`Self::run`]: awaits each record instead of
+    /// blocking on the source, so network sources don't tie up a thread.
+    /// Batches apply all or nothing as in [`Pipeline::process`].
+    pub async fn run_async(
+        &mut self,
+        source: &mut dyn crate::data_sources::AsyncDataSource,
+        sink: &mut dyn crate::data_sinks::AsyncDataSink,
+    ) -> Result<RunSummary, String> {
+        let mut records = source.read_transactions();
+        let mut summary = RunSummary::default();
+        let mut batch: Vec<SourceRecord> = Vec::new();
+        let mut batch_id = None;
+        while let Some(record) = std::future::poll_fn(|cx| records.as_mut().poll_next(cx)).await {
+            let record_batch = record.as_ref().ok().and_then(|action| action.batch_id);
+            if !batch.is_empty() && record.is_ok() && record_batch != batch_id {
+                self.apply_async_batch(std::mem::take(&mut batch), &mut summary);
+            }
+            if record_batch.is_some() || !batch.is_empty() {
+                batch_id = record_batch.or(batch_id);
+                batch.push(record);
+                continue;
+            }
+            match record {
+                Err(_) => summary.record_source_error(),
+                Ok(action) => summary.record_outcome(&self.process_action(action)),
+            }
+        }
+        if !batch.is_empty() {
+            self.apply_async_batch(batch, &mut summary);
+        }
+        let accounts = self.account_views(AccountFilter::default().apply(self));
+        sink.write_accounts(&accounts).await?;
+        sink.flush().await?;
+        Ok(summary)
+    }
+
+    /// Applies one batch of [`Self::run_async`]. A record that couldn't be
+    /// read fails the batch it is in.
+    fn apply_async_batch(&mut self, batch: Vec<SourceRecord>, summary: &mut RunSummary) {
+        let actions: Option<Vec<UserTransactions>> = batch
+            .iter()
+            .map(|record| record.as_ref().ok().cloned())
+            .collect();
+        let committed = actions.is_some_and(|actions| self.process_batch(actions).is_ok());
+        for record in &batch {
+            match record {
+                Err(_) => summary.record_source_error(),
+                Ok(_) => summary.record_outcome(&committed.then_some(()).ok_or(())),
+            }
+        }
+    }
+}
+
+/// Runs `source` through `engine` into `sink` with the default [`Pipeline`].
+pub fn run_pipeline(
+    source: &mut dyn DataSource,
+    engine: &mut PaymentEngine,
+    sink: &mut dyn DataSink,
+) -> Result<RunSummary, String> {
+    Pipeline::new().run(source, engine, sink)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_outcome_applies_thresholds() {
+        let summary = RunSummary {
+            records_read: 2000,
+            source_errors: 1,
+            applied: 1997,
+            rejected: 2,
+        };
+        let none = SkipThresholds::default();
+        assert_eq!(RunSummary::default().outcome(&none), RunOutcome::Clean);
+        assert_eq!(summary.outcome(&none), RunOutcome::Skipped);
+
+        let percent = |max| SkipThresholds {
+            max_skipped_percent: Some(max),
+            ..Default::default()
+        };
+        assert_eq!(summary.outcome(&percent(dec!(0.15))), RunOutcome::Skipped);
+        assert_eq!(
+            summary.outcome(&percent(dec!(0.1))),
+            RunOutcome::ThresholdExceeded
+        );
+        let count = SkipThresholds {
+            max_skipped: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(summary.outcome(&count), RunOutcome::ThresholdExceeded);
+    }
+}
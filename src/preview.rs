@@ -0,0 +1,115 @@
+use std::{io::Write, ops::ControlFlow};
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{
+    PaymentEngine, UserAccount,
+    data_sources::DataSource,
+    errors::ErrorCode,
+    pipeline::{Pipeline, RecordOutcome, RunSummary},
+    serialize_to_four_places,
+};
+
+/// How one account would change if the previewed input were applied.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct AccountChange {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    #[serde(serialize_with = "serialize_to_four_places")]
+    pub available_change: Decimal,
+    #[serde(serialize_with = "serialize_to_four_places")]
+    pub held_change: Decimal,
+    #[serde(serialize_with = "serialize_to_four_places")]
+    pub total_change: Decimal,
+    /// Whether the account would be locked afterwards.
+    pub locked: bool,
+}
+
+/// A record the engine would refuse, or that couldn't be read.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RejectedRow {
+    /// Where the record is in the input, or its position when unknown.
+    pub position: String,
+    /// Why the engine refused it; `None` when the record couldn't be read.
+    pub code: Option<ErrorCode>,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Preview {
+    /// Accounts that would change, ordered by client id.
+    pub changes: Vec<AccountChange>,
+    pub rejected: Vec<RejectedRow>,
+    pub summary: RunSummary,
+}
+
+/// Runs `source` against a fork of `engine` and reports the differences,
+/// leaving `engine` itself untouched.
+pub fn preview(engine: &PaymentEngine, source: &mut dyn DataSource) -> Result<Preview, String> {
+    let mut trial = engine.fork();
+    let mut rejected = Vec::new();
+    let mut seen = 0;
+    let summary = Pipeline::new().process(source, &mut trial, |_, location, outcome| {
+        seen += 1;
+        let (code, reason) = match outcome {
+            RecordOutcome::Applied(_) => return ControlFlow::Continue(()),
+            RecordOutcome::SourceError(e) => (None, e.to_string()),
+            RecordOutcome::Rejected(_, e) => (Some(e.code()), e.to_string()),
+        };
+        let position = match location {
+            Some(location) => location.to_string(),
+            None => format!("Record {}", seen),
+        };
+        rejected.push(RejectedRow {
+            position,
+            code,
+            reason,
+        });
+        ControlFlow::Continue(())
+    })?;
+
+    let mut changes: Vec<AccountChange> = trial
+        .accounts
+        .values()
+        .filter_map(|after| {
+            let before = engine
+                .accounts
+                .get(&after.client_id)
+                .cloned()
+                .unwrap_or_else(|| UserAccount::new(after.client_id));
+            let change = AccountChange {
+                client_id: after.client_id,
+                available_change: after.available - before.available,
+                held_change: after.held - before.held,
+                total_change: after.total - before.total,
+                locked: after.locked,
+            };
+            let unchanged = change.available_change.is_zero()
+                && change.held_change.is_zero()
+                && change.total_change.is_zero()
+                && after.locked == before.locked
+                && engine.accounts.contains_key(&after.client_id);
+            (!unchanged).then_some(change)
+        })
+        .collect();
+    changes.sort_unstable_by_key(|change| change.client_id);
+
+    Ok(Preview {
+        changes,
+        rejected,
+        summary,
+    })
+}
+
+pub fn write_changes<W: Write>(writer: W, changes: &[AccountChange]) -> Result<(), String> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for change in changes {
+        writer
+            .serialize(change)
+            .map_err(|e| format!("Failed to serialize change: {}", e))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to flush writer: {}", e))
+}
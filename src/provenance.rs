@@ -0,0 +1,131 @@
+use std::{
+    io,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{data_sinks::staged::write_staged, pipeline::RunSummary, session::hash_file};
+
+pub const ENGINE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct FileHash {
+    pub file: String,
+    pub sha256: String,
+}
+
+impl FileHash {
+    pub fn of(file: &str) -> Result<Self, io::Error> {
+        Ok(Self {
+            file: file.to_string(),
+            sha256: hash_file(file)?,
+        })
+    }
+}
+
+/// Sidecar tying an output back to the run that produced it: which engine,
+/// which settings, which inputs, and how the records fared.
+#[derive(Debug, Clone, Serialize)]
+pub struct Provenance {
+    pub engine_version: String,
+    /// Hash of the run's effective settings, so two runs can be checked for
+    /// identical configuration without storing the settings themselves.
+    pub config_sha256: String,
+    pub inputs: Vec<FileHash>,
+    /// The accounts file written, when there is one.
+    pub output: Option<FileHash>,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub summary: RunSummary,
+    pub accounts_written: u64,
+}
+
+impl Provenance {
+    /// Records the start of a run, hashing `config` and every input file.
+    pub fn start(config: &str, inputs: &[&str]) -> Result<Self, io::Error> {
+        let inputs = inputs
+            .iter()
+            .map(|file| FileHash::of(file))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            engine_version: ENGINE_VERSION.to_string(),
+            config_sha256: format!("{:x}", Sha256::digest(config.as_bytes())),
+            inputs,
+            output: None,
+            started_at: unix_now(),
+            finished_at: None,
+            summary: RunSummary::default(),
+            accounts_written: 0,
+        })
+    }
+
+    /// Records the end of the run and hashes the accounts file, if any.
+    pub fn finish(
+        &mut self,
+        summary: RunSummary,
+        output: Option<&str>,
+        accounts_written: u64,
+    ) -> Result<(), io::Error> {
+        self.finished_at = Some(unix_now());
+        self.summary = summary;
+        self.accounts_written = accounts_written;
+        self.output = output.map(FileHash::of).transpose()?;
+        Ok(())
+    }
+
+    pub fn write_json(&self, path: &str) -> Result<(), String> {
+        write_staged(path, |file| {
+            serde_json::to_writer_pretty(file, self)
+                .map_err(|e| format!("Failed to write provenance file '{}': {}", path, e))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provenance_hashes_inputs_and_config() {
+        let path = std::env::temp_dir().join(format!("provenance-{}.csv", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "type,client,tx,amount\n").unwrap();
+
+        let mut provenance = Provenance::start("--non-zero", &[path]).unwrap();
+        assert_eq!(provenance.inputs[0].sha256, hash_file(path).unwrap());
+        assert_ne!(
+            provenance.config_sha256,
+            Provenance::start("", &[]).unwrap().config_sha256
+        );
+        assert!(provenance.finished_at.is_none());
+
+        provenance
+            .finish(
+                RunSummary {
+                    records_read: 3,
+                    applied: 3,
+                    ..Default::default()
+                },
+                Some(path),
+                2,
+            )
+            .unwrap();
+        let json = serde_json::to_value(&provenance).unwrap();
+        assert_eq!(json["summary"]["applied"], 3);
+        assert_eq!(json["accounts_written"], 2);
+        assert_eq!(json["engine_version"], ENGINE_VERSION);
+        assert_eq!(json["output"]["sha256"], json["inputs"][0]["sha256"]);
+
+        std::fs::remove_file(path).unwrap();
+        assert!(Provenance::start("", &[path]).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+use std::io::Write;
+
+use crate::{EngineEvent, EventKind, PaymentEngine, TxType, UserTransactions};
+
+/// Bounds for the quarantine. Actions pushed out by either bound become
+/// orphans.
+#[derive(Debug, Clone, Copy)]
+pub struct QuarantineConfig {
+    pub max_entries: usize,
+    /// Stream time an action may stay parked. Untimestamped actions only
+    /// leave by the size bound.
+    pub max_age_secs: Option<u64>,
+}
+
+impl Default for QuarantineConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 10_000,
+            max_age_secs: None,
+        }
+    }
+}
+
+/// Disputes, resolves and chargebacks that arrived before the transaction
+/// they refer to, waiting for it to show up.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Quarantine {
+    config: QuarantineConfig,
+    parked: im::Vector<UserTransactions>,
+    orphans: im::Vector<UserTransactions>,
+}
+
+impl PaymentEngine {
+    /// Parks out-of-order references instead of rejecting them; see
+    /// [`QuarantineConfig`].
+    pub fn enable_quarantine(&mut self, config: QuarantineConfig) {
+        self.quarantine.get_or_insert_default().config = config;
+    }
+
+    /// Actions that never matched: those evicted so far plus everything
+    /// still parked.
+    pub fn take_orphans(&mut self) -> Vec<UserTransactions> {
+        match self.quarantine.as_mut() {
+            Some(quarantine) => {
+                let mut orphans = std::mem::take(&mut quarantine.orphans);
+                orphans.append(std::mem::take(&mut quarantine.parked));
+                orphans.into_iter().collect()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Parked and orphaned actions, if quarantine is on.
+    pub(crate) fn quarantined(&self) -> Option<(Vec<UserTransactions>, Vec<UserTransactions>)> {
+        let quarantine = self.quarantine.as_ref()?;
+        Some((
+            quarantine.parked.iter().cloned().collect(),
+            quarantine.orphans.iter().cloned().collect(),
+        ))
+    }
+
+    /// Puts back what [`Self::quarantined`] returned, under the default
+    /// limits until [`Self::enable_quarantine`] sets them.
+    pub(crate) fn restore_quarantined(
+        &mut self,
+        parked: Vec<UserTransactions>,
+        orphans: Vec<UserTransactions>,
+    ) {
+        let quarantine = self.quarantine.get_or_insert_default();
+        quarantine.parked = parked.into();
+        quarantine.orphans = orphans.into();
+    }
+
+    /// Parks `action` if quarantine is on and its transaction is unknown.
+    /// Returns the action back when it should be processed normally.
+    pub(crate) fn try_quarantine(&mut self, action: UserTransactions) -> Option<UserTransactions> {
+        let references = matches!(
+            action.tx_type,
+            TxType::Dispute | TxType::Resolve | TxType::Chargeback
+        );
+        let known = self
+            .actions
+            .get(&action.client_id)
+            .is_some_and(|txs| txs.contains_key(&action.tx_id));
+        let Some(quarantine) = self.quarantine.as_mut().filter(|_| references && !known) else {
+            return Some(action);
+        };
+
+        if quarantine.parked.len() >= quarantine.config.max_entries
+            && let Some(evicted) = quarantine.parked.pop_front()
+        {
+            quarantine.orphans.push_back(evicted);
+        }
+        quarantine.parked.push_back(action.clone());
+        self.events.push(EngineEvent {
+            kind: EventKind::Quarantined,
+            action,
+        });
+        None
+    }
+
+    /// Retries, in arrival order, everything parked on `client_id`'s `tx_id`.
+    pub(crate) fn release_quarantined(&mut self, client_id: u16, tx_id: u32) {
+        let Some(quarantine) = self.quarantine.as_mut() else {
+            return;
+        };
+        let (ready, waiting): (im::Vector<_>, _) = std::mem::take(&mut quarantine.parked)
+            .into_iter()
+            .partition(|a| a.client_id == client_id && a.tx_id == tx_id);
+        quarantine.parked = waiting;
+
+        for action in ready {
+            if self.apply_action(action.clone()).is_ok() {
+                self.events.push(EngineEvent {
+                    kind: EventKind::QuarantineReleased,
+                    action,
+                });
+            } else if let Some(quarantine) = self.quarantine.as_mut() {
+                quarantine.orphans.push_back(action);
+            }
+        }
+    }
+
+    pub(crate) fn expire_quarantine(&mut self, now: u64) {
+        let Some(quarantine) = self.quarantine.as_mut() else {
+            return;
+        };
+        let Some(max_age) = quarantine.config.max_age_secs else {
+            return;
+        };
+        let (expired, waiting): (im::Vector<_>, _) = std::mem::take(&mut quarantine.parked)
+            .into_iter()
+            .partition(|a| {
+                a.timestamp
+                    .is_some_and(|ts| ts.saturating_add(max_age) < now)
+            });
+        quarantine.parked = waiting;
+        quarantine.orphans.append(expired);
+    }
+}
+
+/// Writes orphans as `type,client,tx` CSV rows.
+pub fn write_orphans<W: Write>(writer: W, orphans: &[UserTransactions]) -> Result<(), String> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer
+        .write_record(["type", "client", "tx"])
+        .map_err(|e| format!("Failed to write header: {}", e))?;
+    for orphan in orphans {
+        let tx_type = match orphan.tx_type {
+            TxType::Dispute => "dispute",
+            TxType::Resolve => "resolve",
+            _ => "chargeback",
+        };
+        writer
+            .write_record([
+                tx_type,
+                &orphan.client_id.to_string(),
+                &orphan.tx_id.to_string(),
+            ])
+            .map_err(|e| format!("Failed to serialize orphan: {}", e))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to flush writer: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TxOutcome, tx};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_parked_dispute_applies_when_deposit_arrives() {
+        let mut engine = PaymentEngine::new();
+        engine.enable_quarantine(QuarantineConfig::default());
+
+        let parked = engine
+            .process_action(tx(TxType::Dispute, 1, 1).at(1))
+            .unwrap();
+        assert_eq!(parked, TxOutcome::Quarantined);
+        assert!(engine.accounts.is_empty());
+
+        let applied = engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10)).at(2))
+            .unwrap();
+        assert_eq!(applied, TxOutcome::Applied);
+        assert_eq!(engine.accounts[&1].held, dec!(10));
+        let kinds: Vec<_> = engine.drain_events().iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![EventKind::Quarantined, EventKind::QuarantineReleased]
+        );
+        assert!(engine.take_orphans().is_empty());
+    }
+
+    #[test]
+    fn test_bounds_turn_parked_actions_into_orphans() {
+        let mut engine = PaymentEngine::new();
+        engine.enable_quarantine(QuarantineConfig {
+            max_entries: 2,
+            max_age_secs: Some(100),
+        });
+
+        for tx_id in 1..=3 {
+            engine
+                .process_action(tx(TxType::Dispute, 1, tx_id).at(10))
+                .unwrap();
+        }
+        engine
+            .process_action(tx(TxType::Deposit, 1, 9).with_amount(dec!(1)).at(200))
+            .unwrap();
+
+        let orphans: Vec<u32> = engine.take_orphans().iter().map(|a| a.tx_id).collect();
+        assert_eq!(orphans, vec![1, 2, 3]);
+    }
+}
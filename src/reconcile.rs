@@ -0,0 +1,109 @@
+use std::{collections::HashMap, io::Write};
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{PaymentEngine, UserAccount};
+
+/// One way an account disagrees with the expected balances.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Discrepancy {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    /// `available`, `held`, `total`, `locked`, or `account` when the account
+    /// exists on only one side.
+    pub field: &'static str,
+    pub expected: String,
+    pub actual: String,
+}
+
+fn compare_amount(
+    client_id: u16,
+    field: &'static str,
+    expected: Decimal,
+    actual: Decimal,
+    tolerance: Decimal,
+    out: &mut Vec<Discrepancy>,
+) {
+    if (expected - actual).abs() > tolerance {
+        out.push(Discrepancy {
+            client_id,
+            field,
+            expected: format!("{:.4}", expected),
+            actual: format!("{:.4}", actual),
+        });
+    }
+}
+
+/// Compares the engine's accounts against `expected`, ignoring amount
+/// differences up to `tolerance`. Results are ordered by client id.
+pub fn reconcile(
+    engine: &PaymentEngine,
+    expected: &[UserAccount],
+    tolerance: Decimal,
+) -> Vec<Discrepancy> {
+    let expected: HashMap<u16, &UserAccount> = expected.iter().map(|a| (a.client_id, a)).collect();
+    let mut client_ids: Vec<u16> = expected
+        .keys()
+        .chain(engine.accounts.keys())
+        .copied()
+        .collect();
+    client_ids.sort_unstable();
+    client_ids.dedup();
+
+    let mut discrepancies = Vec::new();
+    for client_id in client_ids {
+        let (want, have) = match (expected.get(&client_id), engine.accounts.get(&client_id)) {
+            (Some(want), Some(have)) => (want, have),
+            (want, _) => {
+                let (expected, actual) = if want.is_some() {
+                    ("present", "missing")
+                } else {
+                    ("missing", "present")
+                };
+                discrepancies.push(Discrepancy {
+                    client_id,
+                    field: "account",
+                    expected: expected.to_string(),
+                    actual: actual.to_string(),
+                });
+                continue;
+            }
+        };
+        let out = &mut discrepancies;
+        compare_amount(
+            client_id,
+            "available",
+            want.available,
+            have.available,
+            tolerance,
+            out,
+        );
+        compare_amount(client_id, "held", want.held, have.held, tolerance, out);
+        compare_amount(client_id, "total", want.total, have.total, tolerance, out);
+        if want.locked != have.locked {
+            out.push(Discrepancy {
+                client_id,
+                field: "locked",
+                expected: want.locked.to_string(),
+                actual: have.locked.to_string(),
+            });
+        }
+    }
+    discrepancies
+}
+
+pub fn write_discrepancies<W: Write>(
+    writer: W,
+    discrepancies: &[Discrepancy],
+) -> Result<(), String> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for discrepancy in discrepancies {
+        writer
+            .serialize(discrepancy)
+            .map_err(|e| format!("Failed to serialize discrepancy: {}", e))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to flush writer: {}", e))
+}
@@ -0,0 +1,138 @@
+use std::{io::Write, str::FromStr};
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{
+    PaymentEngine,
+    aggregation::WindowSize,
+    analytics::PortfolioAnalytics,
+    data_sources::csv::CsvDataSource,
+    pipeline::{Pipeline, RunSummary},
+};
+
+/// File format of the `validate` and `report` outputs.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum ReportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            other => Err(format!(
+                "Unknown report format '{}', expected json or csv",
+                other
+            )),
+        }
+    }
+}
+
+/// Summary statistics of applying an input to a fresh engine.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct RunReport {
+    pub input: String,
+    pub records_read: u64,
+    pub applied: u64,
+    pub rejected: u64,
+    pub source_errors: u64,
+    pub clients: u64,
+    pub locked_clients: u64,
+    pub open_disputes: u64,
+    pub total_available: Decimal,
+    pub total_held: Decimal,
+    pub total: Decimal,
+    /// Only asked for in the JSON format, which can nest it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub analytics: Option<PortfolioAnalytics>,
+}
+
+impl RunReport {
+    pub fn new(input: &str, summary: RunSummary, engine: &PaymentEngine) -> Self {
+        let config = engine.config();
+        Self {
+            input: input.to_string(),
+            records_read: summary.records_read,
+            applied: summary.applied,
+            rejected: summary.rejected,
+            source_errors: summary.source_errors,
+            clients: engine.accounts.len() as u64,
+            locked_clients: engine.accounts.values().filter(|a| a.locked).count() as u64,
+            open_disputes: engine.open_disputes().len() as u64,
+            total_available: config.round(engine.accounts.values().map(|a| a.available).sum()),
+            total_held: config.round(engine.accounts.values().map(|a| a.held).sum()),
+            total: config.round(engine.accounts.values().map(|a| a.total).sum()),
+            analytics: None,
+        }
+    }
+
+    /// Writes the report as one JSON object, or a header and one CSV row.
+    pub fn write<W: Write>(&self, writer: W, format: ReportFormat) -> Result<(), String> {
+        let error = |e: String| format!("Failed to write report: {}", e);
+        match format {
+            ReportFormat::Json => {
+                serde_json::to_writer_pretty(writer, self).map_err(|e| error(e.to_string()))
+            }
+            ReportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(writer);
+                writer.serialize(self).map_err(|e| error(e.to_string()))?;
+                writer.flush().map_err(|e| error(e.to_string()))
+            }
+        }
+    }
+}
+
+/// Applies `input` to a fresh engine and summarizes the result, with
+/// portfolio analytics over cohorts of `cohort_window` if one is given.
+pub fn report(input: &str, cohort_window: Option<WindowSize>) -> Result<RunReport, String> {
+    let mut engine = PaymentEngine::new();
+    let summary = Pipeline::new().process(
+        &mut CsvDataSource::new(input.to_string()),
+        &mut engine,
+        |_, _, _| std::ops::ControlFlow::Continue(()),
+    )?;
+    let mut report = RunReport::new(input, summary, &engine);
+    report.analytics = cohort_window.map(|window| engine.analytics(window));
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_summarizes_a_run() {
+        let report = report("test_dispute.csv", None).unwrap();
+        assert_eq!(report.records_read, report.applied + report.rejected);
+        assert!(report.clients > 0);
+        assert_eq!(report.total, report.total_available + report.total_held);
+
+        let mut out = Vec::new();
+        report.write(&mut out, ReportFormat::Csv).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let mut lines = text.lines();
+        assert_eq!(
+            lines.next(),
+            Some(
+                "input,records_read,applied,rejected,source_errors,clients,locked_clients,open_disputes,total_available,total_held,total"
+            )
+        );
+        assert!(lines.next().unwrap().starts_with("test_dispute.csv,"));
+        assert_eq!("csv".parse(), Ok(ReportFormat::Csv));
+        assert!("xml".parse::<ReportFormat>().is_err());
+
+        let report = super::report("test_dispute.csv", Some(WindowSize::default())).unwrap();
+        let analytics = report.analytics.as_ref().unwrap();
+        assert_eq!(analytics.clients, report.clients);
+        let mut out = Vec::new();
+        report.write(&mut out, ReportFormat::Json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert!(json["analytics"]["cohorts"].is_array());
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{PaymentEngine, TxType, UserTransactions, disputes::DisputeState};
+
+/// Order in which kept transactions were last used, for dropping the least
+/// recently used one past [`crate::RetentionConfig::max_transactions`]. A
+/// transaction is used when it is stored and whenever a later record, e.g.
+/// a dispute, refers to it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TxRecency {
+    seq: u64,
+    last_used: im::HashMap<(u16, u32), u64>,
+    /// Uses in order. Entries superseded by a later use are skipped.
+    order: im::Vector<(u64, (u16, u32))>,
+}
+
+impl TxRecency {
+    fn touch(&mut self, key: (u16, u32)) {
+        self.seq += 1;
+        self.last_used.insert(key, self.seq);
+        self.order.push_back((self.seq, key));
+        if self.order.len() > 2 * self.last_used.len() + 16 {
+            let last_used = &self.last_used;
+            self.order
+                .retain(|(seq, key)| last_used.get(key) == Some(seq));
+        }
+    }
+
+    pub(crate) fn forget(&mut self, key: &(u16, u32)) {
+        self.last_used.remove(key);
+    }
+
+    fn len(&self) -> usize {
+        self.last_used.len()
+    }
+
+    /// Stops tracking the least recently used transaction and returns it.
+    fn pop_oldest(&mut self) -> Option<(u16, u32)> {
+        while let Some((seq, key)) = self.order.pop_front() {
+            if self.last_used.get(&key) == Some(&seq) {
+                self.last_used.remove(&key);
+                return Some(key);
+            }
+        }
+        None
+    }
+}
+
+impl PaymentEngine {
+    /// Stores an applied `action` for dispute lookups and the ledger, as far
+    /// as the retention config allows.
+    pub(crate) fn record_action(&mut self, action: UserTransactions) {
+        let retention = self.retention.unwrap_or_default();
+        let key = (action.client_id, action.tx_id);
+        if !retention.disputable_only
+            || matches!(action.tx_type, TxType::Deposit | TxType::Withdrawal)
+        {
+            self.actions
+                .entry(action.client_id)
+                .or_default()
+                .entry(action.tx_id)
+                .or_default()
+                .push(action);
+        }
+        if let Some(max) = retention.max_transactions {
+            if self.is_kept(key) {
+                self.tx_recency.touch(key);
+            }
+            self.evict_transactions(max);
+        }
+    }
+
+    fn is_kept(&self, (client_id, tx_id): (u16, u32)) -> bool {
+        self.actions
+            .get(&client_id)
+            .is_some_and(|txs| txs.contains_key(&tx_id))
+    }
+
+    /// Restarts use tracking from the transactions kept now, oldest id
+    /// first, and applies the limit to them.
+    pub(crate) fn track_kept_transactions(&mut self) {
+        self.tx_recency = TxRecency::default();
+        let Some(max) = self.retention.and_then(|r| r.max_transactions) else {
+            return;
+        };
+        let mut kept: Vec<(u16, u32)> = self
+            .actions
+            .iter()
+            .flat_map(|(&client_id, txs)| txs.keys().map(move |&tx_id| (client_id, tx_id)))
+            .collect();
+        kept.sort_unstable_by_key(|&(client_id, tx_id)| (tx_id, client_id));
+        for key in kept {
+            self.tx_recency.touch(key);
+        }
+        self.evict_transactions(max);
+    }
+
+    /// Drops least recently used transactions until at most `max` are kept,
+    /// or only open disputes are left.
+    fn evict_transactions(&mut self, max: usize) {
+        let mut spared = 0;
+        while self.tx_recency.len() > max && spared < self.tx_recency.len() {
+            let Some(key) = self.tx_recency.pop_oldest() else {
+                break;
+            };
+            // Resolving or charging back needs the disputed record.
+            if self.dispute_states.get(&key) == Some(&DisputeState::Disputed) {
+                self.tx_recency.touch(key);
+                spared += 1;
+                continue;
+            }
+            let (client_id, tx_id) = key;
+            if let Some(txs) = self.actions.get_mut(&client_id) {
+                txs.remove(&tx_id);
+                if txs.is_empty() {
+                    self.actions.remove(&client_id);
+                }
+            }
+            self.dispute_states.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RetentionConfig, errors::ErrorCode, tx};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_transaction_limit_evicts_least_recently_used() {
+        let mut engine = PaymentEngine::new();
+        engine.set_retention(RetentionConfig {
+            max_transactions: Some(2),
+            disputable_only: true,
+            ..RetentionConfig::default()
+        });
+        for tx_id in 1..=2 {
+            engine
+                .process_action(tx(TxType::Deposit, 1, tx_id).with_amount(dec!(10)))
+                .unwrap();
+        }
+        // Using tx 1 makes tx 2 the one to go, and keeps tx 1 as it's open.
+        engine.process_action(tx(TxType::Dispute, 1, 1)).unwrap();
+        engine
+            .process_action(tx(TxType::Deposit, 1, 3).with_amount(dec!(10)))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Deposit, 1, 4).with_amount(dec!(10)))
+            .unwrap();
+
+        let kept: Vec<u32> = engine
+            .transactions(1)
+            .iter()
+            .map(|entry| entry.tx_id)
+            .collect();
+        assert_eq!(kept, [1, 4]);
+        assert_eq!(engine.transactions(1)[0].records.len(), 1);
+        let err = engine
+            .process_action(tx(TxType::Dispute, 1, 2))
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TransactionNotFound);
+
+        engine.process_action(tx(TxType::Resolve, 1, 1)).unwrap();
+        assert_eq!(engine.accounts[&1].held, dec!(0));
+        engine
+            .process_action(tx(TxType::Deposit, 1, 5).with_amount(dec!(10)))
+            .unwrap();
+        let kept: Vec<u32> = engine
+            .transactions(1)
+            .iter()
+            .map(|entry| entry.tx_id)
+            .collect();
+        assert_eq!(kept, [1, 5]);
+
+        engine.set_retention(RetentionConfig {
+            max_transactions: Some(1),
+            ..RetentionConfig::default()
+        });
+        assert_eq!(engine.transactions(1).len(), 1);
+    }
+}
@@ -0,0 +1,313 @@
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    PaymentEngine, TxType,
+    errors::{EngineError, ErrorCode},
+    money::Amount,
+};
+
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize, Serialize)]
+pub struct AccountStats {
+    pub open_disputes: u32,
+    pub lifetime_chargebacks: u32,
+}
+
+/// Thresholds past which an account's withdrawals are frozen. The freeze
+/// lifts by itself once the account is back under every threshold.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FreezePolicy {
+    /// Freeze when more than this many disputes are open.
+    pub max_open_disputes: Option<u32>,
+    /// Freeze when `held / total` exceeds this ratio.
+    pub max_held_ratio: Option<Decimal>,
+}
+
+/// Answer of [`PaymentEngine::can_withdraw`].
+#[derive(Debug, PartialEq, Clone)]
+pub enum Decision {
+    Allowed,
+    /// The error the withdrawal would be rejected with.
+    Refused(EngineError),
+}
+
+impl Decision {
+    pub fn is_allowed(&self) -> bool {
+        matches!(self, Decision::Allowed)
+    }
+}
+
+/// How much of an account's balance a withdrawal may spend.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum WithdrawalPolicy {
+    /// Spend down to the available balance (plus any credit limit).
+    #[default]
+    AvailableOnly,
+    /// Always leave this much of the available balance untouched.
+    Reserve(Decimal),
+    /// Refuse every withdrawal while the account has a dispute open.
+    BlockWhileDisputed,
+}
+
+impl FromStr for WithdrawalPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "available" => Ok(Self::AvailableOnly),
+            None if s == "block-disputed" => Ok(Self::BlockWhileDisputed),
+            Some(("reserve", amount)) => {
+                let reserve: Decimal = amount
+                    .parse()
+                    .map_err(|_| format!("Invalid withdrawal reserve '{}'", amount))?;
+                if reserve < Decimal::ZERO {
+                    return Err(format!(
+                        "Withdrawal reserve must not be negative, got {}",
+                        reserve
+                    ));
+                }
+                Ok(Self::Reserve(reserve))
+            }
+            _ => Err(format!(
+                "Unknown withdrawal policy '{}', expected available, reserve:<amount> or block-disputed",
+                s
+            )),
+        }
+    }
+}
+
+impl PaymentEngine {
+    pub fn set_withdrawal_policy(&mut self, policy: WithdrawalPolicy) {
+        self.withdrawal_policy = policy;
+    }
+
+    /// Part of the available balance withdrawals must leave in place,
+    /// including deposits still under a funds hold, or an error if the
+    /// policy refuses withdrawals for `client_id` right now.
+    pub(crate) fn withdrawal_reserve(&self, client_id: u16) -> Result<Decimal, EngineError> {
+        let held_deposits = self.reserved_balance(client_id);
+        match self.withdrawal_policy {
+            WithdrawalPolicy::AvailableOnly => Ok(held_deposits),
+            WithdrawalPolicy::Reserve(reserve) => Ok(reserve + held_deposits),
+            WithdrawalPolicy::BlockWhileDisputed => {
+                if self.account_stats(client_id).open_disputes > 0 {
+                    Err(EngineError::new(
+                        ErrorCode::WithdrawalsBlockedByDispute,
+                        format!(
+                            "Withdrawals are blocked for client {} while a dispute is open",
+                            client_id
+                        ),
+                    ))
+                } else {
+                    Ok(held_deposits)
+                }
+            }
+        }
+    }
+
+    /// Whether a withdrawal of `amount` by `client_id` would be applied right
+    /// now, checked against the same rules as a real one: access, closed and
+    /// unopened accounts, freezes, the withdrawal policy, funds holds and
+    /// credit limits. Nothing is changed. A locked account is judged like
+    /// any other, as the engine doesn't refuse its withdrawals either.
+    pub fn can_withdraw(&self, client_id: u16, amount: Amount) -> Decision {
+        let amount = self.config().round(amount.value());
+        match self
+            .check_client(client_id, TxType::Withdrawal)
+            .and_then(|()| self.check_withdrawal(client_id, amount))
+        {
+            Ok(()) => Decision::Allowed,
+            Err(e) => Decision::Refused(e),
+        }
+    }
+
+    pub fn set_freeze_policy(&mut self, policy: FreezePolicy) {
+        self.freeze_policy = policy;
+    }
+
+    pub fn account_stats(&self, client_id: u16) -> AccountStats {
+        self.stats.get(&client_id).copied().unwrap_or_default()
+    }
+
+    pub fn is_withdrawal_frozen(&self, client_id: u16) -> bool {
+        let policy = &self.freeze_policy;
+        let stats = self.account_stats(client_id);
+        if policy
+            .max_open_disputes
+            .is_some_and(|max| stats.open_disputes > max)
+        {
+            return true;
+        }
+
+        match (policy.max_held_ratio, self.accounts.get(&client_id)) {
+            (Some(max), Some(account)) if account.held > Decimal::ZERO => {
+                account.total <= Decimal::ZERO || account.held / account.total > max
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{amount, tx};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_stats_track_disputes_and_chargebacks() {
+        let mut engine = PaymentEngine::new();
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10.0)))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Deposit, 1, 2).with_amount(dec!(10.0)))
+            .unwrap();
+        engine.process_action(tx(TxType::Dispute, 1, 1)).unwrap();
+        engine.process_action(tx(TxType::Dispute, 1, 2)).unwrap();
+        assert_eq!(engine.account_stats(1).open_disputes, 2);
+
+        engine.process_action(tx(TxType::Resolve, 1, 1)).unwrap();
+        engine.process_action(tx(TxType::Chargeback, 1, 2)).unwrap();
+        assert_eq!(
+            engine.account_stats(1),
+            AccountStats {
+                open_disputes: 0,
+                lifetime_chargebacks: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_open_disputes_freeze_withdrawals() {
+        let mut engine = PaymentEngine::new();
+        engine.set_freeze_policy(FreezePolicy {
+            max_open_disputes: Some(0),
+            ..Default::default()
+        });
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10.0)))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Deposit, 1, 2).with_amount(dec!(10.0)))
+            .unwrap();
+        engine.process_action(tx(TxType::Dispute, 1, 1)).unwrap();
+
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 3).with_amount(dec!(5.0)))
+            .unwrap_err();
+        assert_eq!(engine.accounts[&1].available, dec!(10.0));
+
+        engine.process_action(tx(TxType::Resolve, 1, 1)).unwrap();
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 4).with_amount(dec!(5.0)))
+            .unwrap();
+        assert_eq!(engine.accounts[&1].available, dec!(15.0));
+    }
+
+    #[test]
+    fn test_held_ratio_freezes_withdrawals() {
+        let mut engine = PaymentEngine::new();
+        engine.set_freeze_policy(FreezePolicy {
+            max_held_ratio: Some(dec!(0.5)),
+            ..Default::default()
+        });
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(30.0)))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Deposit, 1, 2).with_amount(dec!(70.0)))
+            .unwrap();
+        engine.process_action(tx(TxType::Dispute, 1, 1)).unwrap();
+
+        // 30% held: still allowed
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 3).with_amount(dec!(10.0)))
+            .unwrap();
+        assert_eq!(engine.accounts[&1].available, dec!(60.0));
+
+        engine.process_action(tx(TxType::Dispute, 1, 2)).unwrap();
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 4).with_amount(dec!(1.0)))
+            .unwrap_err();
+        assert_eq!(engine.accounts[&1].available, dec!(-10.0));
+    }
+
+    #[test]
+    fn test_withdrawal_policies() {
+        assert_eq!(
+            "reserve:25".parse::<WithdrawalPolicy>().unwrap(),
+            WithdrawalPolicy::Reserve(dec!(25))
+        );
+        assert!("reserve:-1".parse::<WithdrawalPolicy>().is_err());
+
+        let mut engine = PaymentEngine::new();
+        engine.set_withdrawal_policy(WithdrawalPolicy::Reserve(dec!(25)));
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(100.0)))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 2).with_amount(dec!(80.0)))
+            .unwrap_err();
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 3).with_amount(dec!(75.0)))
+            .unwrap();
+        assert_eq!(engine.accounts[&1].available, dec!(25.0));
+
+        engine.set_withdrawal_policy(WithdrawalPolicy::BlockWhileDisputed);
+        engine
+            .process_action(tx(TxType::Deposit, 1, 4).with_amount(dec!(10.0)))
+            .unwrap();
+        engine.process_action(tx(TxType::Dispute, 1, 4)).unwrap();
+        let err = engine
+            .process_action(tx(TxType::Withdrawal, 1, 5).with_amount(dec!(1.0)))
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::WithdrawalsBlockedByDispute);
+        assert_eq!(
+            err.to_string(),
+            "Withdrawals are blocked for client 1 while a dispute is open"
+        );
+        engine.process_action(tx(TxType::Resolve, 1, 4)).unwrap();
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 6).with_amount(dec!(1.0)))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_can_withdraw_matches_engine_without_mutating() {
+        let mut engine = PaymentEngine::new();
+        let refused = |decision: Decision| match decision {
+            Decision::Refused(e) => e.code(),
+            Decision::Allowed => panic!("expected a refusal"),
+        };
+        assert_eq!(
+            refused(engine.can_withdraw(1, amount(dec!(1)))),
+            ErrorCode::NoAccount
+        );
+
+        engine.set_withdrawal_policy(WithdrawalPolicy::BlockWhileDisputed);
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10.0)))
+            .unwrap();
+        assert!(engine.can_withdraw(1, amount(dec!(10))).is_allowed());
+        assert_eq!(
+            refused(engine.can_withdraw(1, amount(dec!(10.5)))),
+            ErrorCode::InsufficientFunds
+        );
+        // A zero withdrawal would be applied, so it is allowed here too.
+        assert!(engine.can_withdraw(1, amount(dec!(0))).is_allowed());
+
+        engine
+            .process_action(tx(TxType::Deposit, 1, 2).with_amount(dec!(5.0)))
+            .unwrap();
+        engine.process_action(tx(TxType::Dispute, 1, 2)).unwrap();
+        assert_eq!(
+            refused(engine.can_withdraw(1, amount(dec!(1)))),
+            ErrorCode::WithdrawalsBlockedByDispute
+        );
+        assert_eq!(engine.accounts[&1].available, dec!(10.0));
+        assert_eq!(engine.accounts[&1].held, dec!(5.0));
+    }
+}
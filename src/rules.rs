@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use crate::{
+    PaymentEngine, UserAccount, UserTransactions,
+    errors::{EngineError, ErrorCode},
+};
+
+/// A custom check every transaction must pass, run after the client rules
+/// and before the transaction is applied, e.g. a per-deployment validator
+/// or fraud rule. Rules are shared by forks and worker threads, so they
+/// must not depend on state of their own.
+pub trait TransactionRule: Send + Sync {
+    /// Shown in rejection messages.
+    fn name(&self) -> &str;
+
+    /// `Err` with a reason refuses `action`. `account` is `None` if the
+    /// client has none yet.
+    fn check(&self, action: &UserTransactions, account: Option<&UserAccount>)
+    -> Result<(), String>;
+}
+
+impl PaymentEngine {
+    /// Adds `rule` after any added before; the first to refuse decides.
+    pub fn add_rule(&mut self, rule: Arc<dyn TransactionRule>) {
+        self.rules.push(rule);
+    }
+
+    pub(crate) fn check_rules(&self, action: &UserTransactions) -> Result<(), EngineError> {
+        let account = self.accounts.get(&action.client_id);
+        for rule in &self.rules {
+            rule.check(action, account).map_err(|reason| {
+                EngineError::new(
+                    ErrorCode::RuleRejected,
+                    format!(
+                        "Rule '{}' refused tx {}: {}",
+                        rule.name(),
+                        action.tx_id,
+                        reason
+                    ),
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TxType, tx};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    struct MaxWithdrawal(Decimal);
+
+    impl TransactionRule for MaxWithdrawal {
+        fn name(&self) -> &str {
+            "max-withdrawal"
+        }
+
+        fn check(
+            &self,
+            action: &UserTransactions,
+            _account: Option<&UserAccount>,
+        ) -> Result<(), String> {
+            match action.amount {
+                Some(amount) if action.tx_type == TxType::Withdrawal && amount.value() > self.0 => {
+                    Err(format!("over {}", self.0))
+                }
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_rules_refuse_before_applying() {
+        let mut engine = PaymentEngine::new();
+        engine.add_rule(Arc::new(MaxWithdrawal(dec!(10))));
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(50)))
+            .unwrap();
+        let err = engine
+            .process_action(tx(TxType::Withdrawal, 1, 2).with_amount(dec!(11)))
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::RuleRejected);
+        assert_eq!(err.message(), "Rule 'max-withdrawal' refused tx 2: over 10");
+        assert_eq!(engine.accounts[&1].available, dec!(50));
+
+        let mut fork = engine.fork();
+        assert!(
+            fork.process_action(tx(TxType::Withdrawal, 1, 3).with_amount(dec!(11)))
+                .is_err()
+        );
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 3).with_amount(dec!(10)))
+            .unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+use std::{fs, path::Path};
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::{PaymentEngine, TxType, UserTransactions};
+
+/// A regression case written as data: transactions to feed a fresh engine
+/// and what it should end up with. See `tests/scenarios/` for examples.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub config: ScenarioConfig,
+    #[serde(default)]
+    pub transactions: Vec<UserTransactions>,
+    #[serde(default)]
+    pub expect: Expectations,
+}
+
+/// Engine policies, spelled as on the command line.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioConfig {
+    #[serde(default)]
+    pub dispute_funds_policy: Option<String>,
+    #[serde(default)]
+    pub withdrawal_policy: Option<String>,
+    #[serde(default)]
+    pub duplicate_policy: Option<String>,
+    #[serde(default)]
+    pub require_open_accounts: bool,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expectations {
+    /// Accounts to check; accounts not listed aren't checked.
+    #[serde(default)]
+    pub accounts: Vec<ExpectedAccount>,
+    /// Every transaction the engine should refuse. Any other rejection
+    /// fails the scenario.
+    #[serde(default)]
+    pub rejections: Vec<ExpectedRejection>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExpectedAccount {
+    pub client: u16,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+    #[serde(default)]
+    pub locked: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExpectedRejection {
+    pub tx: u32,
+    /// Only match a transaction of this type, to pick one of several steps
+    /// on the same tx.
+    #[serde(default, rename = "type")]
+    pub tx_type: Option<TxType>,
+    /// Error code such as `PE1001` or its name, `InsufficientFunds`.
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+pub fn load_scenario(path: &Path) -> Result<Scenario, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read scenario '{}': {}", path.display(), e))?;
+    toml::from_str(&text).map_err(|e| format!("Invalid scenario '{}': {}", path.display(), e))
+}
+
+impl Scenario {
+    /// Runs the scenario on a fresh engine and returns every mismatch with
+    /// its expectations; an empty list means it passed.
+    pub fn run(&self) -> Result<Vec<String>, String> {
+        let mut engine = PaymentEngine::new();
+        if let Some(policy) = &self.config.dispute_funds_policy {
+            engine.set_dispute_funds_policy(policy.parse()?);
+        }
+        if let Some(policy) = &self.config.withdrawal_policy {
+            engine.set_withdrawal_policy(policy.parse()?);
+        }
+        if let Some(policy) = &self.config.duplicate_policy {
+            engine.set_duplicate_policy(policy.parse()?);
+        }
+        engine.set_require_open_accounts(self.config.require_open_accounts);
+
+        let mut mismatches = Vec::new();
+        let mut expected_rejections = self.expect.rejections.clone();
+        let matches = |r: &ExpectedRejection, action: &UserTransactions| {
+            r.tx == action.tx_id && r.tx_type.is_none_or(|t| t == action.tx_type)
+        };
+        for action in &self.transactions {
+            let Err(e) = engine.process_action(action.clone()) else {
+                continue;
+            };
+            let Some(index) = expected_rejections.iter().position(|r| matches(r, action)) else {
+                mismatches.push(format!(
+                    "tx {}: unexpected rejection {} {}",
+                    action.tx_id,
+                    e.code().code(),
+                    e
+                ));
+                continue;
+            };
+            let rejection = expected_rejections.remove(index);
+            if let Some(code) = rejection.code
+                && code != e.code().code()
+                && code != e.code().name()
+            {
+                mismatches.push(format!(
+                    "tx {}: expected {}, rejected with {}",
+                    action.tx_id,
+                    code,
+                    e.code()
+                ));
+            }
+        }
+        for rejection in expected_rejections {
+            let in_input = self.transactions.iter().any(|a| matches(&rejection, a));
+            mismatches.push(format!(
+                "tx {}: expected a rejection, {}",
+                rejection.tx,
+                if in_input {
+                    "was applied"
+                } else {
+                    "not in input"
+                }
+            ));
+        }
+
+        for expected in &self.expect.accounts {
+            let Some(account) = engine.accounts.get(&expected.client) else {
+                mismatches.push(format!("client {}: no account", expected.client));
+                continue;
+            };
+            let actual = (
+                account.available,
+                account.held,
+                account.total,
+                account.locked,
+            );
+            let wanted = (
+                expected.available,
+                expected.held,
+                expected.total,
+                expected.locked,
+            );
+            if actual != wanted {
+                mismatches.push(format!(
+                    "client {}: expected available {}, held {}, total {}, locked {}; got {}, {}, {}, {}",
+                    expected.client,
+                    wanted.0,
+                    wanted.1,
+                    wanted.2,
+                    wanted.3,
+                    actual.0,
+                    actual.1,
+                    actual.2,
+                    actual.3
+                ));
+            }
+        }
+        Ok(mismatches)
+    }
+}
+
+/// Outcome of one scenario file.
+#[derive(Debug, Clone)]
+pub struct ScenarioResult {
+    pub path: String,
+    pub name: Option<String>,
+    /// Mismatches, or why the scenario couldn't be loaded or run.
+    pub failures: Vec<String>,
+}
+
+impl ScenarioResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Runs every `*.toml` scenario in `dir`, in file name order.
+pub fn run_scenarios(dir: &Path) -> Result<Vec<ScenarioResult>, String> {
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read '{}': {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    paths.sort();
+
+    Ok(paths
+        .iter()
+        .map(|path| {
+            let scenario = load_scenario(path);
+            let name = scenario.as_ref().ok().map(|s| s.name.clone());
+            let failures = scenario
+                .and_then(|scenario| scenario.run())
+                .unwrap_or_else(|e| vec![e]);
+            ScenarioResult {
+                path: path.display().to_string(),
+                name,
+                failures,
+            }
+        })
+        .collect())
+}
+
+/// Test helper: panics listing every failing scenario in `dir`.
+pub fn assert_scenarios(dir: impl AsRef<Path>) {
+    let results = run_scenarios(dir.as_ref()).unwrap();
+    assert!(!results.is_empty(), "no scenarios found");
+    let failed: Vec<String> = results
+        .iter()
+        .filter(|result| !result.passed())
+        .map(|result| format!("{}: {}", result.path, result.failures.join("; ")))
+        .collect();
+    assert!(failed.is_empty(), "{}", failed.join("\n"));
+}
@@ -0,0 +1,287 @@
+use std::sync::Arc;
+
+use rhai::{AST, Dynamic, Engine, Map, Scope};
+use rust_decimal::Decimal;
+
+use crate::{
+    TxType, UserAccount, UserTransactions,
+    data_sources::{SourceError, transform::Transform},
+    money::Amount,
+    rules::TransactionRule,
+};
+
+/// Operations a single call may run before it is stopped, so a runaway
+/// loop in a script can't stall the engine.
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// Validators, fraud rules and fee rules written in Rhai and loaded at
+/// runtime. A script defines any of these functions:
+///
+/// - `validate(tx)` and `fraud(tx, account)` return `true` or nothing to
+///   allow the transaction, and `false` or a reason string to refuse it.
+///   Used as a [`TransactionRule`].
+/// - `fee(tx)` returns what a deposit or withdrawal costs the client: a
+///   deposit credits its amount less the fee, a withdrawal debits its
+///   amount plus the fee. Used as a [`Transform`].
+///
+/// `tx` is a map with `type`, `client`, `tx`, `amount` and `timestamp`;
+/// `account` has `available`, `held`, `total` and `locked`, or is `()` for
+/// a client without one. Amounts are decimals. A script that fails at
+/// runtime refuses the transaction.
+#[derive(Clone)]
+pub struct Script {
+    inner: Arc<Compiled>,
+}
+
+struct Compiled {
+    name: String,
+    engine: Engine,
+    ast: AST,
+    validate: bool,
+    fraud: bool,
+    fee: bool,
+}
+
+impl Script {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read script '{}': {}", path, e))?;
+        Self::compile(path, &source)
+    }
+
+    /// `name` identifies the script in rejection messages.
+    pub fn compile(name: &str, source: &str) -> Result<Self, String> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine
+            .compile(source)
+            .map_err(|e| format!("Failed to compile script '{}': {}", name, e))?;
+        let defines = |function: &str, params: usize| {
+            ast.iter_functions()
+                .any(|f| f.name == function && f.params.len() == params)
+        };
+        let (validate, fraud, fee) = (
+            defines("validate", 1),
+            defines("fraud", 2),
+            defines("fee", 1),
+        );
+        if !(validate || fraud || fee) {
+            return Err(format!(
+                "Script '{}' defines none of validate(tx), fraud(tx, account) or fee(tx)",
+                name
+            ));
+        }
+        Ok(Self {
+            inner: Arc::new(Compiled {
+                name: name.to_string(),
+                engine,
+                ast,
+                validate,
+                fraud,
+                fee,
+            }),
+        })
+    }
+
+    pub fn has_rules(&self) -> bool {
+        self.inner.validate || self.inner.fraud
+    }
+
+    pub fn has_fees(&self) -> bool {
+        self.inner.fee
+    }
+
+    fn call(&self, function: &str, args: impl rhai::FuncArgs) -> Result<Dynamic, String> {
+        let inner = &self.inner;
+        inner
+            .engine
+            .call_fn(&mut Scope::new(), &inner.ast, function, args)
+            .map_err(|e| format!("{} failed: {}", function, e))
+    }
+}
+
+impl TransactionRule for Script {
+    fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    fn check(
+        &self,
+        action: &UserTransactions,
+        account: Option<&UserAccount>,
+    ) -> Result<(), String> {
+        if self.inner.validate {
+            verdict(self.call("validate", (tx_map(action),))?)?;
+        }
+        if self.inner.fraud {
+            let account = account.map_or(Dynamic::UNIT, |account| account_map(account).into());
+            verdict(self.call("fraud", (tx_map(action), account))?)?;
+        }
+        Ok(())
+    }
+}
+
+impl Transform for Script {
+    fn apply(&mut self, mut action: UserTransactions) -> Result<UserTransactions, SourceError> {
+        let Some(amount) = action.amount.filter(|_| {
+            self.inner.fee && matches!(action.tx_type, TxType::Deposit | TxType::Withdrawal)
+        }) else {
+            return Ok(action);
+        };
+        let fee = decimal(self.call("fee", (tx_map(&action),))?)?;
+        if fee < Decimal::ZERO {
+            return Err(format!("fee must not be negative, got {}", fee).into());
+        }
+        let charged = match action.tx_type {
+            TxType::Deposit => amount.value() - fee,
+            _ => amount.value() + fee,
+        };
+        let charged = Amount::new(charged)
+            .map_err(|e| format!("Fee {} leaves tx {} with {}", fee, action.tx_id, e))?;
+        action.amount = Some(charged);
+        Ok(action)
+    }
+}
+
+fn tx_type_name(tx_type: TxType) -> &'static str {
+    match tx_type {
+        TxType::Deposit => "deposit",
+        TxType::Withdrawal => "withdrawal",
+        TxType::Dispute => "dispute",
+        TxType::Resolve => "resolve",
+        TxType::Chargeback => "chargeback",
+        TxType::OpenAccount => "open_account",
+        TxType::CloseAccount => "close_account",
+        TxType::Adjustment => "adjustment",
+        TxType::Transfer => "transfer",
+    }
+}
+
+fn tx_map(action: &UserTransactions) -> Map {
+    let mut map = Map::new();
+    map.insert("type".into(), tx_type_name(action.tx_type).into());
+    map.insert("client".into(), i64::from(action.client_id).into());
+    map.insert("tx".into(), i64::from(action.tx_id).into());
+    map.insert(
+        "amount".into(),
+        action
+            .amount
+            .map_or(Dynamic::UNIT, |amount| amount.value().into()),
+    );
+    map.insert(
+        "timestamp".into(),
+        action
+            .timestamp
+            .and_then(|ts| i64::try_from(ts).ok())
+            .map_or(Dynamic::UNIT, Dynamic::from),
+    );
+    map
+}
+
+fn account_map(account: &UserAccount) -> Map {
+    let mut map = Map::new();
+    map.insert("available".into(), account.available.into());
+    map.insert("held".into(), account.held.into());
+    map.insert("total".into(), account.total.into());
+    map.insert("locked".into(), account.locked.into());
+    map
+}
+
+fn verdict(result: Dynamic) -> Result<(), String> {
+    if result.is_unit() {
+        return Ok(());
+    }
+    if let Ok(allowed) = result.as_bool() {
+        return if allowed {
+            Ok(())
+        } else {
+            Err("refused".to_string())
+        };
+    }
+    let type_name = result.type_name();
+    Err(result
+        .into_string()
+        .unwrap_or_else(|_| format!("expected a bool, a string or nothing, got {}", type_name)))
+}
+
+fn decimal(value: Dynamic) -> Result<Decimal, String> {
+    if value.is_unit() {
+        return Ok(Decimal::ZERO);
+    }
+    value
+        .as_decimal()
+        .or_else(|_| value.as_int().map(Decimal::from))
+        .or_else(|_| {
+            value
+                .as_float()
+                .map_err(|_| "")
+                .and_then(|f| Decimal::try_from(f).map_err(|_| ""))
+        })
+        .map_err(|_| format!("fee must be a number, got {}", value.type_name()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PaymentEngine, errors::ErrorCode, tx};
+    use rust_decimal_macros::dec;
+
+    const SCRIPT: &str = r#"
+        fn validate(tx) {
+            if tx.type == "withdrawal" && tx.amount > 100 { "over the withdrawal limit" }
+        }
+        fn fraud(tx, account) {
+            account == () || account.held == 0 || tx.type != "withdrawal"
+        }
+        fn fee(tx) {
+            if tx.type == "withdrawal" { tx.amount / 100 } else { 0 }
+        }
+    "#;
+
+    #[test]
+    fn test_script_rules_and_fees() {
+        let mut script = Script::compile("policy.rhai", SCRIPT).unwrap();
+        assert!(script.has_rules() && script.has_fees());
+        let mut engine = PaymentEngine::new();
+        engine.add_rule(Arc::new(script.clone()));
+        let mut apply = |engine: &mut PaymentEngine, action| {
+            let action = script.apply(action).map_err(|e| e.to_string())?;
+            engine.process_action(action).map_err(|e| e.to_string())
+        };
+
+        apply(
+            &mut engine,
+            tx(TxType::Deposit, 1, 1).with_amount(dec!(500)),
+        )
+        .unwrap();
+        apply(
+            &mut engine,
+            tx(TxType::Withdrawal, 1, 2).with_amount(dec!(50)),
+        )
+        .unwrap();
+        assert_eq!(engine.accounts[&1].available, dec!(449.5));
+
+        let err = apply(
+            &mut engine,
+            tx(TxType::Withdrawal, 1, 3).with_amount(dec!(150)),
+        )
+        .unwrap_err();
+        assert!(err.ends_with("over the withdrawal limit"), "{err}");
+        apply(&mut engine, tx(TxType::Deposit, 1, 4).with_amount(dec!(10))).unwrap();
+        apply(&mut engine, tx(TxType::Dispute, 1, 4)).unwrap();
+        let err = engine
+            .process_action(tx(TxType::Withdrawal, 1, 5).with_amount(dec!(1)))
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::RuleRejected);
+        assert_eq!(err.message(), "Rule 'policy.rhai' refused tx 5: refused");
+
+        assert!(Script::compile("empty.rhai", "let x = 1;").is_err());
+        assert!(Script::compile("broken.rhai", "fn validate(tx) {").is_err());
+        let looping = Script::compile("loop.rhai", "fn validate(tx) { loop {} }").unwrap();
+        assert!(
+            looping
+                .check(&tx(TxType::Deposit, 1, 6).with_amount(dec!(1)), None)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::data_sinks::staged::write_staged;
+
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionStatus {
+    InProgress,
+    Completed,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ImportSession {
+    pub session_id: String,
+    pub input: String,
+    pub content_hash: String,
+    /// Unix time the session began, so a resume only picks up checkpoints
+    /// taken by this session.
+    #[serde(default)]
+    pub started_at: u64,
+    /// Transactions applied so far, counted in source order.
+    pub rows_processed: u64,
+    pub status: SessionStatus,
+}
+
+/// Persistent record of every input file that has been imported, keyed by
+/// content hash so a re-run of the same file is caught even under a new name.
+#[derive(Debug)]
+pub struct ImportJournal {
+    path: String,
+    sessions: Vec<ImportSession>,
+}
+
+impl ImportJournal {
+    /// Opens the journal at `path`, starting empty if the file doesn't exist.
+    pub fn open(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let sessions = match File::open(path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path: path.to_string(),
+            sessions,
+        })
+    }
+
+    pub fn sessions(&self) -> &[ImportSession] {
+        &self.sessions
+    }
+
+    /// Latest session for a given content hash.
+    pub fn find(&self, content_hash: &str) -> Option<&ImportSession> {
+        self.sessions
+            .iter()
+            .rev()
+            .find(|s| s.content_hash == content_hash)
+    }
+
+    /// Starts a new session and persists it immediately.
+    pub fn begin(&mut self, input: &str, content_hash: &str) -> Result<String, String> {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let session_id = format!("{}-{}", &content_hash[..12], started);
+        self.sessions.push(ImportSession {
+            session_id: session_id.clone(),
+            input: input.to_string(),
+            content_hash: content_hash.to_string(),
+            started_at: started,
+            rows_processed: 0,
+            status: SessionStatus::InProgress,
+        });
+        self.save()?;
+        Ok(session_id)
+    }
+
+    pub fn record_progress(&mut self, session_id: &str, rows_processed: u64) -> Result<(), String> {
+        self.update(session_id, rows_processed, SessionStatus::InProgress)
+    }
+
+    pub fn complete(&mut self, session_id: &str, rows_processed: u64) -> Result<(), String> {
+        self.update(session_id, rows_processed, SessionStatus::Completed)
+    }
+
+    fn update(
+        &mut self,
+        session_id: &str,
+        rows_processed: u64,
+        status: SessionStatus,
+    ) -> Result<(), String> {
+        let session = self
+            .sessions
+            .iter_mut()
+            .find(|s| s.session_id == session_id)
+            .ok_or_else(|| format!("Unknown import session '{}'", session_id))?;
+        session.rows_processed = rows_processed;
+        session.status = status;
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        // A crash mid-save must not lose the progress of every session.
+        write_staged(&self.path, |file| {
+            serde_json::to_writer_pretty(file, &self.sessions)
+                .map_err(|e| format!("Failed to write journal '{}': {}", self.path, e))
+        })
+    }
+}
+
+/// Hex-encoded SHA-256 of a file's contents.
+pub fn hash_file(path: &str) -> Result<String, io::Error> {
+    let mut reader = BufReader::new(File::open(Path::new(path))?);
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_tracks_sessions_by_hash() {
+        let path = std::env::temp_dir().join(format!("journal-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let hash = hash_file("test_transactions.csv").unwrap();
+        let mut journal = ImportJournal::open(path).unwrap();
+        assert!(journal.find(&hash).is_none());
+
+        let id = journal.begin("test_transactions.csv", &hash).unwrap();
+        journal.record_progress(&id, 3).unwrap();
+
+        let mut reopened = ImportJournal::open(path).unwrap();
+        let session = reopened.find(&hash).unwrap();
+        assert_eq!(session.rows_processed, 3);
+        assert_eq!(session.status, SessionStatus::InProgress);
+
+        reopened.complete(&id, 5).unwrap();
+        let session = ImportJournal::open(path)
+            .unwrap()
+            .find(&hash)
+            .cloned()
+            .unwrap();
+        assert_eq!(session.status, SessionStatus::Completed);
+        assert_eq!(session.rows_processed, 5);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,493 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    path::Path,
+    str::FromStr,
+};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    PaymentEngine, TxType, UserTransactions,
+    errors::{EngineError, ErrorCode},
+    ids::SyntheticKind,
+    money::Amount,
+    serialize_to_four_places,
+};
+
+#[derive(Debug, Default, Clone)]
+pub struct SettlementConfig {
+    /// Accounts to settle. `None` settles every account.
+    pub merchants: Option<HashSet<u16>>,
+    /// Balances at or below this are left for the next cutoff.
+    pub min_payout: Decimal,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Payout {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    #[serde(rename = "tx")]
+    pub tx_id: u32,
+    #[serde(serialize_with = "serialize_to_four_places")]
+    pub amount: Decimal,
+}
+
+/// Outcome of one cutoff.
+#[derive(Debug, Default)]
+pub struct Settlement {
+    pub payouts: Vec<Payout>,
+    /// Accounts whose payout the engine refused for a reason other than a
+    /// freeze or lock, which only defer it to the next cutoff.
+    pub refused: Vec<(u16, EngineError)>,
+}
+
+/// Pays out each eligible account's available balance at the cutoff. Every
+/// payout is also applied to the engine as a withdrawal, so the accounts
+/// output and the payout file always agree. Payout ids come from the
+/// engine's payout block, so later cutoffs never reuse them. Fails, before
+/// paying anything, when the block can't cover every payout.
+pub fn settle(engine: &mut PaymentEngine, config: &SettlementConfig) -> Result<Settlement, String> {
+    let mut client_ids: Vec<u16> = engine
+        .accounts
+        .values()
+        .filter(|a| !a.locked && a.available > config.min_payout)
+        .filter(|a| {
+            config
+                .merchants
+                .as_ref()
+                .is_none_or(|m| m.contains(&a.client_id))
+        })
+        .map(|a| a.client_id)
+        .collect();
+    client_ids.sort_unstable();
+    let remaining = engine.synthetic_ids.remaining(SyntheticKind::Payout);
+    if client_ids.len() > remaining as usize {
+        return Err(format!(
+            "{} payouts don't fit in the {} payout ids left",
+            client_ids.len(),
+            remaining
+        ));
+    }
+
+    let timestamp = engine.stream_time;
+    let mut settlement = Settlement::default();
+    for client_id in client_ids {
+        let amount = engine.accounts[&client_id].available;
+        let Ok(withdrawal) = Amount::new(amount) else {
+            continue;
+        };
+        let tx_id = engine
+            .synthetic_ids
+            .next(SyntheticKind::Payout)
+            .map_err(|e| e.to_string())?;
+        let applied = engine.process_action(UserTransactions {
+            tx_type: TxType::Withdrawal,
+            client_id,
+            tx_id,
+            amount: Some(withdrawal),
+            timestamp,
+            ..Default::default()
+        });
+        match applied {
+            Ok(_) => settlement.payouts.push(Payout {
+                client_id,
+                tx_id,
+                amount,
+            }),
+            // Frozen and locked accounts simply wait for the next cutoff.
+            Err(e)
+                if matches!(
+                    e.code(),
+                    ErrorCode::WithdrawalsFrozen | ErrorCode::AccountLocked
+                ) => {}
+            Err(e) => settlement.refused.push((client_id, e)),
+        }
+    }
+    Ok(settlement)
+}
+
+pub fn write_payouts<W: Write>(writer: W, payouts: &[Payout]) -> Result<(), String> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for payout in payouts {
+        writer
+            .serialize(payout)
+            .map_err(|e| format!("Failed to serialize payout: {}", e))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to flush writer: {}", e))
+}
+
+/// How `--payouts` writes the payout instructions.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum PayoutFormat {
+    #[default]
+    Csv,
+    /// ISO 20022 customer credit transfer initiation, pain.001.001.03.
+    Pain001,
+}
+
+impl FromStr for PayoutFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "pain001" => Ok(Self::Pain001),
+            other => Err(format!(
+                "Unknown payout format '{}', expected csv or pain001",
+                other
+            )),
+        }
+    }
+}
+
+/// Bank account a merchant's payouts are credited to.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+pub struct Beneficiary {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    pub name: String,
+    pub iban: String,
+    #[serde(default)]
+    pub bic: Option<String>,
+}
+
+/// Loads a `client,name,iban,bic` CSV of merchant bank accounts. `bic` may
+/// be left empty.
+pub fn read_beneficiaries(
+    path: &str,
+) -> Result<HashMap<u16, Beneficiary>, Box<dyn std::error::Error>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(Path::new(path))?;
+    let mut beneficiaries = HashMap::new();
+    for result in rdr.deserialize::<Beneficiary>() {
+        let beneficiary = result?;
+        beneficiaries.insert(beneficiary.client_id, beneficiary);
+    }
+    Ok(beneficiaries)
+}
+
+/// Account payouts are paid from, read from a TOML file.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+pub struct Debtor {
+    pub name: String,
+    pub iban: String,
+    #[serde(default)]
+    pub bic: Option<String>,
+    /// ISO 4217 code of the payout amounts.
+    pub currency: String,
+}
+
+pub fn read_debtor(path: &str) -> Result<Debtor, String> {
+    let text =
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read '{}': {}", path, e))?;
+    toml::from_str(&text).map_err(|e| format!("Invalid debtor '{}': {}", path, e))
+}
+
+/// Everything a pain.001 file needs besides the payouts themselves.
+pub struct PaymentInitiation<'a> {
+    pub debtor: &'a Debtor,
+    pub beneficiaries: &'a HashMap<u16, Beneficiary>,
+    /// Unix time the file is created; payment is requested for the same day.
+    pub created_at: u64,
+}
+
+/// Writes `payouts` as one pain.001.001.03 payment from the debtor's
+/// account, one credit transfer per payout with its tx id as the end-to-end
+/// id. Every payout's client needs a [`Beneficiary`].
+pub fn write_pain001<W: Write>(
+    mut writer: W,
+    payouts: &[Payout],
+    initiation: &PaymentInitiation,
+) -> Result<(), String> {
+    let (date, time) = date_time(initiation.created_at);
+    let count = payouts.len();
+    let sum: Decimal = payouts.iter().map(|p| p.amount).sum();
+    let message_id = format!(
+        "PAYOUT-{}-{}",
+        initiation.created_at,
+        payouts.first().map_or(0, |p| p.tx_id)
+    );
+    let debtor = initiation.debtor;
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<Document xmlns=\"urn:iso:std:iso:20022:tech:xsd:pain.001.001.03\">\n");
+    xml.push_str("  <CstmrCdtTrfInitn>\n    <GrpHdr>\n");
+    xml.push_str(&format!("      <MsgId>{}</MsgId>\n", message_id));
+    xml.push_str(&format!("      <CreDtTm>{}T{}</CreDtTm>\n", date, time));
+    xml.push_str(&format!("      <NbOfTxs>{}</NbOfTxs>\n", count));
+    xml.push_str(&format!("      <CtrlSum>{}</CtrlSum>\n", xml_amount(sum)));
+    xml.push_str(&format!(
+        "      <InitgPty><Nm>{}</Nm></InitgPty>\n",
+        escape(&debtor.name)
+    ));
+    xml.push_str("    </GrpHdr>\n    <PmtInf>\n");
+    xml.push_str(&format!("      <PmtInfId>{}</PmtInfId>\n", message_id));
+    xml.push_str("      <PmtMtd>TRF</PmtMtd>\n");
+    xml.push_str(&format!("      <NbOfTxs>{}</NbOfTxs>\n", count));
+    xml.push_str(&format!("      <CtrlSum>{}</CtrlSum>\n", xml_amount(sum)));
+    xml.push_str(&format!("      <ReqdExctnDt>{}</ReqdExctnDt>\n", date));
+    xml.push_str(&format!(
+        "      <Dbtr><Nm>{}</Nm></Dbtr>\n",
+        escape(&debtor.name)
+    ));
+    xml.push_str(&format!(
+        "      <DbtrAcct><Id><IBAN>{}</IBAN></Id></DbtrAcct>\n",
+        escape(&debtor.iban)
+    ));
+    xml.push_str(&format!(
+        "      <DbtrAgt>{}</DbtrAgt>\n",
+        agent(&debtor.bic)
+    ));
+    for payout in payouts {
+        let beneficiary = initiation
+            .beneficiaries
+            .get(&payout.client_id)
+            .ok_or_else(|| format!("No bank account for client {}", payout.client_id))?;
+        xml.push_str("      <CdtTrfTxInf>\n");
+        xml.push_str(&format!(
+            "        <PmtId><EndToEndId>{}</EndToEndId></PmtId>\n",
+            payout.tx_id
+        ));
+        xml.push_str(&format!(
+            "        <Amt><InstdAmt Ccy=\"{}\">{}</InstdAmt></Amt>\n",
+            escape(&debtor.currency),
+            xml_amount(payout.amount)
+        ));
+        xml.push_str(&format!(
+            "        <CdtrAgt>{}</CdtrAgt>\n",
+            agent(&beneficiary.bic)
+        ));
+        xml.push_str(&format!(
+            "        <Cdtr><Nm>{}</Nm></Cdtr>\n",
+            escape(&beneficiary.name)
+        ));
+        xml.push_str(&format!(
+            "        <CdtrAcct><Id><IBAN>{}</IBAN></Id></CdtrAcct>\n",
+            escape(&beneficiary.iban)
+        ));
+        xml.push_str("      </CdtTrfTxInf>\n");
+    }
+    xml.push_str("    </PmtInf>\n  </CstmrCdtTrfInitn>\n</Document>\n");
+    writer
+        .write_all(xml.as_bytes())
+        .and_then(|()| writer.flush())
+        .map_err(|e| format!("Failed to write payouts: {}", e))
+}
+
+/// Financial institution element for an optional BIC.
+fn agent(bic: &Option<String>) -> String {
+    match bic.as_deref().filter(|bic| !bic.is_empty()) {
+        Some(bic) => format!("<FinInstnId><BIC>{}</BIC></FinInstnId>", escape(bic)),
+        None => "<FinInstnId><Othr><Id>NOTPROVIDED</Id></Othr></FinInstnId>".to_string(),
+    }
+}
+
+/// At least two decimal places, and no trailing zeros past them.
+fn xml_amount(amount: Decimal) -> String {
+    let amount = amount.normalize();
+    if amount.scale() < 2 {
+        format!("{:.2}", amount)
+    } else {
+        amount.to_string()
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// `YYYY-MM-DD` and `hh:mm:ss` of a unix time, in UTC.
+fn date_time(secs: u64) -> (String, String) {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    // Howard Hinnant's days-to-civil conversion.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (
+        format!("{:04}-{:02}-{:02}", year, month, day),
+        format!("{:02}:{:02}:{:02}", rem / 3_600, rem % 3_600 / 60, rem % 60),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_settle_pays_out_available_balances() {
+        let mut engine = PaymentEngine::new();
+        for (client_id, tx_id, amount) in
+            [(2, 1, dec!(50.0)), (1, 2, dec!(20.0)), (3, 3, dec!(1.0))]
+        {
+            engine
+                .process_action(UserTransactions {
+                    tx_type: TxType::Deposit,
+                    client_id,
+                    tx_id,
+                    amount: Some(Amount::new(amount).unwrap()),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+
+        let config = SettlementConfig {
+            min_payout: dec!(5.0),
+            ..Default::default()
+        };
+        let first_id = SyntheticKind::Payout.range_start();
+        let settlement = settle(&mut engine, &config).unwrap();
+        assert!(settlement.refused.is_empty());
+        let payouts = settlement.payouts;
+
+        assert_eq!(
+            payouts,
+            vec![
+                Payout {
+                    client_id: 1,
+                    tx_id: first_id,
+                    amount: dec!(20.0)
+                },
+                Payout {
+                    client_id: 2,
+                    tx_id: first_id + 1,
+                    amount: dec!(50.0)
+                },
+            ]
+        );
+        assert_eq!(engine.accounts[&1].available, dec!(0.0));
+        assert_eq!(engine.accounts[&2].total, dec!(0.0));
+        assert_eq!(engine.accounts[&3].available, dec!(1.0));
+
+        let mut buf = Vec::new();
+        write_payouts(&mut buf, &payouts).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "client,tx,amount\n1,4000000000,20.0000\n2,4000000001,50.0000\n"
+        );
+
+        // A later cutoff carries on from the ids already paid out, and
+        // reports payouts the engine refused.
+        for (client_id, tx_id) in [(1, 4), (4, 5)] {
+            engine
+                .process_action(UserTransactions {
+                    tx_type: TxType::Deposit,
+                    client_id,
+                    tx_id,
+                    amount: Some(Amount::new(dec!(8.0)).unwrap()),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        engine.close_account(4).unwrap();
+        let settlement = settle(&mut engine, &config).unwrap();
+        assert_eq!(
+            settlement.payouts,
+            vec![Payout {
+                client_id: 1,
+                tx_id: first_id + 2,
+                amount: dec!(8.0)
+            }]
+        );
+        let refused: Vec<_> = settlement
+            .refused
+            .iter()
+            .map(|(client_id, e)| (*client_id, e.code()))
+            .collect();
+        assert_eq!(refused, vec![(4, ErrorCode::AccountClosed)]);
+        assert_eq!(engine.accounts[&1].available, dec!(0.0));
+        assert_eq!(engine.accounts[&4].available, dec!(8.0));
+
+        // Three payouts with only two ids left in the block.
+        let mut engine = PaymentEngine::new();
+        for client_id in 1..=3 {
+            engine
+                .process_action(UserTransactions {
+                    tx_type: TxType::Deposit,
+                    client_id,
+                    tx_id: u32::from(client_id),
+                    amount: Some(Amount::new(dec!(1.0)).unwrap()),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        engine.synthetic_ids = serde_json::from_str(&format!(
+            r#"{{"issued":{{"Payout":{}}}}}"#,
+            crate::ids::SYNTHETIC_RANGE_LEN - 2
+        ))
+        .unwrap();
+        assert!(settle(&mut engine, &SettlementConfig::default()).is_err());
+        assert_eq!(engine.accounts[&1].available, dec!(1.0));
+    }
+
+    #[test]
+    fn test_write_pain001() {
+        let debtor = Debtor {
+            name: "Acme & Co".to_string(),
+            iban: "DE89370400440532013000".to_string(),
+            bic: Some("COBADEFFXXX".to_string()),
+            currency: "EUR".to_string(),
+        };
+        let beneficiaries = HashMap::from([(
+            1,
+            Beneficiary {
+                client_id: 1,
+                name: "Shop One".to_string(),
+                iban: "FR1420041010050500013M02606".to_string(),
+                bic: None,
+            },
+        )]);
+        let initiation = PaymentInitiation {
+            debtor: &debtor,
+            beneficiaries: &beneficiaries,
+            // 2024-03-01T12:30:05Z
+            created_at: 1_709_296_205,
+        };
+        let payouts = [Payout {
+            client_id: 1,
+            tx_id: 100,
+            amount: dec!(20.5000),
+        }];
+
+        let mut buf = Vec::new();
+        write_pain001(&mut buf, &payouts, &initiation).unwrap();
+        let xml = String::from_utf8(buf).unwrap();
+        for expected in [
+            "<MsgId>PAYOUT-1709296205-100</MsgId>",
+            "<CreDtTm>2024-03-01T12:30:05</CreDtTm>",
+            "<NbOfTxs>1</NbOfTxs>",
+            "<CtrlSum>20.50</CtrlSum>",
+            "<ReqdExctnDt>2024-03-01</ReqdExctnDt>",
+            "<Dbtr><Nm>Acme &amp; Co</Nm></Dbtr>",
+            "<DbtrAgt><FinInstnId><BIC>COBADEFFXXX</BIC></FinInstnId></DbtrAgt>",
+            "<EndToEndId>100</EndToEndId>",
+            "<InstdAmt Ccy=\"EUR\">20.50</InstdAmt>",
+            "<CdtrAgt><FinInstnId><Othr><Id>NOTPROVIDED</Id></Othr></FinInstnId></CdtrAgt>",
+            "<CdtrAcct><Id><IBAN>FR1420041010050500013M02606</IBAN></Id></CdtrAcct>",
+        ] {
+            assert!(xml.contains(expected), "missing {} in\n{}", expected, xml);
+        }
+
+        let unknown = [Payout {
+            client_id: 2,
+            ..payouts[0].clone()
+        }];
+        assert!(write_pain001(Vec::new(), &unknown, &initiation).is_err());
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    hash::Hash,
+    io::{Read, Write},
+};
+
+use im::{HashMap, HashSet, Vector};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    PaymentEngine, UserAccount, UserTransactions, adjustments::Adjustment, debts::Debt,
+    disputes::DisputeState, funds::ReservedFunds, ids::SyntheticIds, money::Amount,
+    periods::ClosedPeriod, risk::AccountStats,
+};
+
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Everything the engine has accumulated from its input, as JSON. Amounts
+/// keep their full precision.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    version: u32,
+    accounts: Vec<AccountState>,
+    transactions: Vec<TxHistory>,
+    stream_time: Option<u64>,
+    dispute_opened_at: Vec<((u16, u32), u64)>,
+    dispute_holds: Vec<((u16, u32), Decimal)>,
+    dispute_states: Vec<((u16, u32), DisputeState)>,
+    queued_disputes: Vector<(UserTransactions, Amount)>,
+    synthetic_ids: SyntheticIds,
+    stats: HashMap<u16, AccountStats>,
+    attributes: HashMap<u16, crate::accounts::AccountAttributes>,
+    seen_tx_ids: HashMap<u32, u16>,
+    debts: Vec<DebtState>,
+    reserved_funds: Vector<ReservedFunds>,
+    closed_accounts: HashSet<u16>,
+    last_active_at: HashMap<u16, u64>,
+    dormancy_due: Option<u64>,
+    dormant: HashSet<u16>,
+    last_activity: HashMap<u16, u64>,
+    activity_seq: u64,
+    /// Parked and orphaned actions, when quarantine is on.
+    quarantined: Option<(Vec<UserTransactions>, Vec<UserTransactions>)>,
+    held_for_review: Vector<UserTransactions>,
+    closed_periods: Vec<(u64, Vec<AccountState>)>,
+    adjustments: Vector<Adjustment>,
+}
+
+/// [`UserAccount`] without the four-place rounding of its CSV form.
+#[derive(Serialize, Deserialize)]
+struct AccountState {
+    client: u16,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: bool,
+}
+
+impl From<&UserAccount> for AccountState {
+    fn from(account: &UserAccount) -> Self {
+        Self {
+            client: account.client_id,
+            available: account.available,
+            held: account.held,
+            total: account.total,
+            locked: account.locked,
+        }
+    }
+}
+
+impl From<AccountState> for UserAccount {
+    fn from(state: AccountState) -> Self {
+        Self {
+            client_id: state.client,
+            available: state.available,
+            held: state.held,
+            total: state.total,
+            locked: state.locked,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TxHistory {
+    client: u16,
+    tx: u32,
+    records: Vec<UserTransactions>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DebtState {
+    client: u16,
+    tx: u32,
+    amount: Decimal,
+    outstanding: Decimal,
+}
+
+fn accounts<'a>(accounts: impl IntoIterator<Item = &'a UserAccount>) -> Vec<AccountState> {
+    accounts.into_iter().map(AccountState::from).collect()
+}
+
+fn pairs<K: Copy + Hash + Eq, V: Copy>(map: &HashMap<K, V>) -> Vec<(K, V)> {
+    map.iter().map(|(k, v)| (*k, *v)).collect()
+}
+
+impl PaymentEngine {
+    /// Writes the engine's accounts, transaction history and the rest of
+    /// the state built up from its input, so a long-running job can
+    /// checkpoint and later [`Self::restore`] instead of replaying the
+    /// stream. Policies, hooks and pending events aren't included.
+    pub fn snapshot<W: Write>(&self, writer: W) -> Result<(), String> {
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            accounts: accounts(self.accounts.values()),
+            transactions: self
+                .actions
+                .iter()
+                .flat_map(|(&client, txs)| {
+                    txs.iter().map(move |(&tx, records)| TxHistory {
+                        client,
+                        tx,
+                        records: records.clone(),
+                    })
+                })
+                .collect(),
+            stream_time: self.stream_time,
+            dispute_opened_at: pairs(&self.dispute_opened_at),
+            dispute_holds: pairs(&self.dispute_holds),
+            dispute_states: pairs(&self.dispute_states),
+            queued_disputes: self.queued_disputes.clone(),
+            synthetic_ids: self.synthetic_ids.clone(),
+            stats: self.stats.clone(),
+            attributes: self.attributes.clone(),
+            seen_tx_ids: self.seen_tx_ids.clone(),
+            debts: self
+                .debts
+                .iter()
+                .map(|debt| DebtState {
+                    client: debt.client_id,
+                    tx: debt.tx_id,
+                    amount: debt.amount,
+                    outstanding: debt.outstanding,
+                })
+                .collect(),
+            reserved_funds: self.reserved_funds.clone(),
+            closed_accounts: self.closed_accounts.clone(),
+            last_active_at: self.last_active_at.clone(),
+            dormancy_due: self.dormancy_due,
+            dormant: self.dormant.clone(),
+            last_activity: self.last_activity.clone(),
+            activity_seq: self.activity_seq,
+            quarantined: self.quarantined(),
+            held_for_review: self.held_for_review.clone(),
+            closed_periods: self
+                .closed_periods
+                .iter()
+                .map(|period| (period.end, accounts(&period.accounts)))
+                .collect(),
+            adjustments: self.adjustments.clone(),
+        };
+        serde_json::to_writer(writer, &snapshot)
+            .map_err(|e| format!("Failed to write snapshot: {}", e))
+    }
+
+    /// Engine with the state of a [`Self::snapshot`] and default policies;
+    /// set them as for a fresh engine before processing more input.
+    pub fn restore<R: Read>(reader: R) -> Result<PaymentEngine, String> {
+        let snapshot: Snapshot = serde_json::from_reader(reader)
+            .map_err(|e| format!("Failed to read snapshot: {}", e))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(format!(
+                "Unsupported snapshot version {}, expected {}",
+                snapshot.version, SNAPSHOT_VERSION
+            ));
+        }
+
+        let mut engine = PaymentEngine::new();
+        engine.accounts = snapshot
+            .accounts
+            .into_iter()
+            .map(|state| (state.client, UserAccount::from(state)))
+            .collect();
+        for history in snapshot.transactions {
+            engine
+                .actions
+                .entry(history.client)
+                .or_default()
+                .insert(history.tx, history.records);
+        }
+        engine.stream_time = snapshot.stream_time;
+        engine.dispute_opened_at = snapshot.dispute_opened_at.into_iter().collect();
+        engine.dispute_holds = snapshot.dispute_holds.into_iter().collect();
+        engine.dispute_states = snapshot.dispute_states.into_iter().collect();
+        engine.queued_disputes = snapshot.queued_disputes;
+        engine.synthetic_ids = snapshot.synthetic_ids;
+        engine.stats = snapshot.stats;
+        engine.attributes = snapshot.attributes;
+        engine.seen_tx_ids = snapshot.seen_tx_ids;
+        engine.debts = snapshot
+            .debts
+            .into_iter()
+            .map(|debt| Debt {
+                client_id: debt.client,
+                tx_id: debt.tx,
+                amount: debt.amount,
+                outstanding: debt.outstanding,
+            })
+            .collect();
+        engine.reserved_funds = snapshot.reserved_funds;
+        engine.closed_accounts = snapshot.closed_accounts;
+        engine.last_active_at = snapshot.last_active_at;
+        engine.dormancy_due = snapshot.dormancy_due;
+        engine.dormant = snapshot.dormant;
+        engine.last_activity = snapshot.last_activity;
+        engine.activity_seq = snapshot.activity_seq;
+        if let Some((parked, orphans)) = snapshot.quarantined {
+            engine.restore_quarantined(parked, orphans);
+        }
+        engine.held_for_review = snapshot.held_for_review;
+        engine.closed_periods = snapshot
+            .closed_periods
+            .into_iter()
+            .map(|(end, accounts)| ClosedPeriod {
+                end,
+                accounts: accounts.into_iter().map(UserAccount::from).collect(),
+            })
+            .collect();
+        engine.adjustments = snapshot.adjustments;
+        Ok(engine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TxType, tx};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_restored_engine_resumes_like_the_original() {
+        let mut engine = PaymentEngine::new();
+        for act in [
+            tx(TxType::Deposit, 1, 1).with_amount(dec!(10.1234)).at(10),
+            tx(TxType::Deposit, 2, 2).with_amount(dec!(5)).at(20),
+            tx(TxType::Withdrawal, 1, 3).with_amount(dec!(1.5)).at(30),
+            tx(TxType::Dispute, 2, 2).at(20),
+        ] {
+            engine.process_action(act).unwrap();
+        }
+
+        let mut buf = Vec::new();
+        engine.snapshot(&mut buf).unwrap();
+        let mut restored = PaymentEngine::restore(buf.as_slice()).unwrap();
+
+        let rest = [
+            tx(TxType::Chargeback, 2, 2).at(20),
+            tx(TxType::Dispute, 1, 1).at(10),
+            tx(TxType::Deposit, 1, 3).with_amount(dec!(1)).at(30),
+        ];
+        for act in rest {
+            let expected = engine.process_action(act.clone()).map_err(|e| e.code());
+            assert_eq!(restored.process_action(act).map_err(|e| e.code()), expected);
+        }
+        let view = |engine: &PaymentEngine, client_id| {
+            let account = &engine.accounts[&client_id];
+            (
+                account.available,
+                account.held,
+                account.total,
+                account.locked,
+            )
+        };
+        for client_id in [1, 2] {
+            assert_eq!(view(&restored, client_id), view(&engine, client_id));
+        }
+        assert_eq!(restored.accounts[&1].held, dec!(10.1234));
+
+        assert!(PaymentEngine::restore(&b"{\"version\": 2}"[..]).is_err());
+    }
+}
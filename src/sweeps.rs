@@ -0,0 +1,166 @@
+use std::path::Path;
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::{
+    EngineEvent, EventKind, PaymentEngine, TxType, UserTransactions, ids::SyntheticKind,
+    money::Amount,
+};
+
+/// First id given to sweep transfers.
+pub const DEFAULT_SYNTHETIC_TX_START: u32 = SyntheticKind::Sweep.range_start();
+
+/// Standing order: whenever `from`'s available balance exceeds `threshold`,
+/// move the excess to `to`.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+pub struct SweepRule {
+    pub from: u16,
+    pub to: u16,
+    pub threshold: Decimal,
+}
+
+/// Loads a `from,to,threshold` CSV of sweep rules.
+pub fn read_sweep_rules(path: &str) -> Result<Vec<SweepRule>, Box<dyn std::error::Error>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(Path::new(path))?;
+    let mut rules = Vec::new();
+    for result in rdr.deserialize::<SweepRule>() {
+        rules.push(result?);
+    }
+    Ok(rules)
+}
+
+impl PaymentEngine {
+    pub fn add_sweep_rule(&mut self, rule: SweepRule) {
+        self.sweep_rules.push(rule);
+    }
+
+    fn synthetic_action(
+        &mut self,
+        tx_type: TxType,
+        client_id: u16,
+        amount: Amount,
+    ) -> Result<UserTransactions, String> {
+        Ok(UserTransactions {
+            tx_type,
+            client_id,
+            tx_id: self.synthetic_ids.next(SyntheticKind::Sweep)?,
+            amount: Some(amount),
+            timestamp: self.stream_time,
+            ..Default::default()
+        })
+    }
+
+    /// Runs the rules for `client_id`, then for every account that received
+    /// a sweep, so chained orders settle in one pass. Each rule fires at most
+    /// once per call, which keeps circular orders from looping.
+    pub(crate) fn apply_sweeps(&mut self, client_id: u16) {
+        let mut fired = vec![false; self.sweep_rules.len()];
+        let mut pending = vec![client_id];
+
+        while let Some(from) = pending.pop() {
+            let matching: Vec<usize> = (0..self.sweep_rules.len())
+                .filter(|&i| !fired[i] && self.sweep_rules[i].from == from)
+                .collect();
+            for index in matching {
+                let rule = self.sweep_rules[index].clone();
+                let excess = match self.accounts.get(&rule.from) {
+                    Some(account) if !account.locked && account.available > rule.threshold => {
+                        account.available - rule.threshold
+                    }
+                    _ => continue,
+                };
+                // A threshold finer than an amount can express leaves nothing to move.
+                let Ok(excess) = Amount::new(excess) else {
+                    continue;
+                };
+                if !self.is_account_open(rule.to)
+                    || self.accounts.get(&rule.to).is_some_and(|a| a.locked)
+                {
+                    continue;
+                }
+                fired[index] = true;
+
+                // The debit can still be refused (e.g. a withdrawal freeze), in
+                // which case nothing moves.
+                let Ok(debit) = self.synthetic_action(TxType::Withdrawal, rule.from, excess) else {
+                    return;
+                };
+                if self.apply_action(debit.clone()).is_err() {
+                    continue;
+                }
+                let credit = self
+                    .synthetic_action(TxType::Deposit, rule.to, excess)
+                    .expect("ids are taken in debit/credit pairs");
+                self.apply_action(credit.clone())
+                    .expect("deposits are always accepted");
+                for action in [debit, credit] {
+                    self.events.push(EngineEvent {
+                        kind: EventKind::Sweep,
+                        action,
+                    });
+                }
+                pending.push(rule.to);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tx;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_sweep_moves_excess_and_emits_events() {
+        let mut engine = PaymentEngine::new();
+        engine.add_sweep_rule(SweepRule {
+            from: 1,
+            to: 2,
+            threshold: dec!(100.0),
+        });
+
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(80.0)))
+            .unwrap();
+        assert!(engine.drain_events().is_empty());
+
+        engine
+            .process_action(tx(TxType::Deposit, 1, 2).with_amount(dec!(50.0)))
+            .unwrap();
+        assert_eq!(engine.accounts[&1].available, dec!(100.0));
+        assert_eq!(engine.accounts[&2].available, dec!(30.0));
+
+        let events = engine.drain_events();
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|e| e.kind == EventKind::Sweep));
+        assert_eq!(events[0].action.tx_type, TxType::Withdrawal);
+        assert_eq!(events[1].action.client_id, 2);
+    }
+
+    #[test]
+    fn test_circular_sweeps_terminate() {
+        let mut engine = PaymentEngine::new();
+        engine.add_sweep_rule(SweepRule {
+            from: 1,
+            to: 2,
+            threshold: dec!(0.0),
+        });
+        engine.add_sweep_rule(SweepRule {
+            from: 2,
+            to: 1,
+            threshold: dec!(0.0),
+        });
+
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10.0)))
+            .unwrap();
+
+        assert_eq!(engine.accounts[&1].available, dec!(10.0));
+        assert_eq!(engine.accounts[&2].available, dec!(0.0));
+        assert_eq!(engine.drain_events().len(), 4);
+    }
+}
@@ -0,0 +1,217 @@
+use crate::{
+    PaymentEngine, UserTransactions,
+    errors::{EngineError, ErrorCode, no_account},
+    money::Amount,
+};
+
+impl PaymentEngine {
+    /// Moves a transfer's amount from `client_id`'s available balance to
+    /// `to_client_id`'s. Both sides are checked before either balance
+    /// changes, so a refused transfer leaves both accounts untouched.
+    pub(crate) fn process_client_transfer(
+        &mut self,
+        action: &UserTransactions,
+    ) -> Result<(), EngineError> {
+        let from = action.client_id;
+        let Some(amount) = action.amount.map(Amount::value) else {
+            return Err(EngineError::new(
+                ErrorCode::InvalidTransfer,
+                format!("Transfer {} has no amount", action.tx_id),
+            ));
+        };
+        let to = match action.to_client_id {
+            Some(to) if to != from => to,
+            Some(_) => {
+                return Err(EngineError::new(
+                    ErrorCode::InvalidTransfer,
+                    format!("Transfer {} moves funds to its own client", action.tx_id),
+                ));
+            }
+            None => {
+                return Err(EngineError::new(
+                    ErrorCode::InvalidTransfer,
+                    format!("Transfer {} has no destination client", action.tx_id),
+                ));
+            }
+        };
+        if let Some(client_id) = self.seen_tx_ids.get(&action.tx_id) {
+            return Err(EngineError::new(
+                ErrorCode::DuplicateTransaction,
+                format!(
+                    "Transaction {} was already applied for client {}",
+                    action.tx_id, client_id
+                ),
+            ));
+        }
+        // The source already passed these on its way into `apply_action`.
+        self.check_client(to, action.tx_type)?;
+        for client_id in [from, to] {
+            if self.accounts.get(&client_id).is_some_and(|a| a.locked) {
+                return Err(EngineError::new(
+                    ErrorCode::AccountLocked,
+                    format!("Client {} is locked", client_id),
+                ));
+            }
+        }
+        self.check_withdrawal(from, amount)?;
+
+        let source = self
+            .accounts
+            .get_mut(&from)
+            .ok_or_else(|| no_account(from))?;
+        source.available -= amount;
+        source.calculate_total();
+        let destination = self.get_or_create_account(to);
+        destination.available += amount;
+        destination.calculate_total();
+        if !self.debts.is_empty() {
+            self.repay_debts(to, amount);
+        }
+
+        self.seen_tx_ids.insert(action.tx_id, from);
+        self.mark_activity(to);
+        if self.dormancy.is_some() {
+            self.note_activity_time(to, action.timestamp);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TxType, UserAccount, hooks::EngineHooks, risk::FreezePolicy, tx};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl EngineHooks for Recorder {
+        fn pre_transfer(
+            &mut self,
+            action: &UserTransactions,
+            from: Option<&UserAccount>,
+            to: Option<&UserAccount>,
+        ) {
+            let available = |a: Option<&UserAccount>| a.map_or(Decimal::ZERO, |a| a.available);
+            self.0.lock().unwrap().push(format!(
+                "pre {} {} {}",
+                action.tx_id,
+                available(from),
+                available(to)
+            ));
+        }
+
+        fn post_transfer(
+            &mut self,
+            action: &UserTransactions,
+            from: &UserAccount,
+            to: &UserAccount,
+        ) {
+            self.0.lock().unwrap().push(format!(
+                "post {} {} {}",
+                action.tx_id, from.available, to.available
+            ));
+        }
+    }
+
+    #[test]
+    fn test_transfer_moves_available_funds() {
+        let mut engine = PaymentEngine::new();
+        for (client_id, tx_id) in [(1, 1), (3, 2)] {
+            engine
+                .process_action(tx(TxType::Deposit, client_id, tx_id).with_amount(dec!(50)))
+                .unwrap();
+        }
+
+        engine
+            .process_action(tx(TxType::Transfer, 1, 3).with_amount(dec!(20)).to(2))
+            .unwrap();
+        assert_eq!(engine.accounts[&1].available, dec!(30));
+        assert_eq!(engine.accounts[&2].available, dec!(20));
+        assert_eq!(engine.accounts[&2].total, dec!(20));
+
+        let refusals = [
+            (
+                tx(TxType::Transfer, 1, 4).with_amount(dec!(31)).to(2),
+                ErrorCode::InsufficientFunds,
+            ),
+            (
+                tx(TxType::Transfer, 1, 3).with_amount(dec!(1)).to(2),
+                ErrorCode::DuplicateTransaction,
+            ),
+            (
+                tx(TxType::Transfer, 1, 5).with_amount(dec!(1)).to(1),
+                ErrorCode::InvalidTransfer,
+            ),
+            (
+                tx(TxType::Transfer, 1, 6).with_amount(dec!(1)),
+                ErrorCode::InvalidTransfer,
+            ),
+            (tx(TxType::Transfer, 1, 6).to(2), ErrorCode::InvalidTransfer),
+            (tx(TxType::Dispute, 1, 3), ErrorCode::InvalidTransfer),
+        ];
+        for (refused, code) in refusals {
+            assert_eq!(engine.process_action(refused).unwrap_err().code(), code);
+        }
+
+        // Client 3 loses a chargeback and is locked: it can neither send
+        // nor receive.
+        engine.process_action(tx(TxType::Dispute, 3, 2)).unwrap();
+        engine.process_action(tx(TxType::Chargeback, 3, 2)).unwrap();
+        for refused in [
+            tx(TxType::Transfer, 1, 7).with_amount(dec!(5)).to(3),
+            tx(TxType::Transfer, 3, 8).with_amount(dec!(1)).to(1),
+        ] {
+            let error = engine.process_action(refused).unwrap_err();
+            assert_eq!(error.code(), ErrorCode::AccountLocked);
+            assert_eq!(error.message(), "Client 3 is locked");
+        }
+        assert_eq!(engine.accounts[&1].available, dec!(30));
+        assert_eq!(engine.accounts[&2].available, dec!(20));
+        assert_eq!(engine.accounts[&3].available, dec!(0));
+    }
+
+    #[test]
+    fn test_transfer_follows_withdrawal_rules() {
+        let mut engine = PaymentEngine::new();
+        engine.set_freeze_policy(FreezePolicy {
+            max_open_disputes: Some(0),
+            ..FreezePolicy::default()
+        });
+        for tx_id in [1, 2] {
+            engine
+                .process_action(tx(TxType::Deposit, 1, tx_id).with_amount(dec!(10)))
+                .unwrap();
+        }
+        engine.process_action(tx(TxType::Dispute, 1, 1)).unwrap();
+
+        let error = engine
+            .process_action(tx(TxType::Transfer, 1, 3).with_amount(dec!(5)).to(2))
+            .unwrap_err();
+        assert_eq!(error.code(), ErrorCode::WithdrawalsFrozen);
+        assert!(!engine.accounts.contains_key(&2));
+    }
+
+    #[test]
+    fn test_transfer_runs_hooks_with_both_accounts() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = PaymentEngine::new();
+        engine.set_hooks(Box::new(Recorder(calls.clone())));
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10)))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Transfer, 1, 2).with_amount(dec!(4)).to(2))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Transfer, 1, 3).with_amount(dec!(7)).to(2))
+            .unwrap_err();
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec!["pre 2 10 0", "post 2 6 4", "pre 3 6 4"]
+        );
+    }
+}
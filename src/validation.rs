@@ -0,0 +1,163 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+};
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{TxType, UserTransactions, data_sources::csv::open_input, report::ReportFormat};
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    BadSchema,
+    DuplicateTx,
+    OrphanDispute,
+    OverLimit,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Anomaly {
+    /// 1-based line number in the input file, header included.
+    pub row: u64,
+    pub kind: AnomalyKind,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ValidationReport {
+    pub input: String,
+    pub rows: u64,
+    pub anomalies: Vec<Anomaly>,
+}
+
+impl ValidationReport {
+    pub fn is_clean(&self) -> bool {
+        self.anomalies.is_empty()
+    }
+
+    pub fn write_json<W: Write>(&self, writer: W) -> Result<(), String> {
+        serde_json::to_writer_pretty(writer, self)
+            .map_err(|e| format!("Failed to write validation report: {}", e))
+    }
+
+    /// Writes the report as JSON, or its anomalies as CSV rows.
+    pub fn write<W: Write>(&self, writer: W, format: ReportFormat) -> Result<(), String> {
+        if format == ReportFormat::Json {
+            return self.write_json(writer);
+        }
+        let error = |e: csv::Error| format!("Failed to write validation report: {}", e);
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(writer);
+        writer
+            .write_record(["row", "kind", "message"])
+            .map_err(error)?;
+        for anomaly in &self.anomalies {
+            writer.serialize(anomaly).map_err(error)?;
+        }
+        writer
+            .flush()
+            .map_err(|e| format!("Failed to write validation report: {}", e))
+    }
+
+    fn push(&mut self, row: u64, kind: AnomalyKind, message: String) {
+        self.anomalies.push(Anomaly { row, kind, message });
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ValidationConfig {
+    /// Amounts strictly above this value are reported as `over_limit`.
+    pub max_amount: Option<Decimal>,
+}
+
+/// Scans a transactions CSV without touching any engine state and collects
+/// every anomaly found, tagged with the line it came from.
+pub fn validate_csv(
+    path: &str,
+    config: &ValidationConfig,
+) -> Result<ValidationReport, Box<dyn std::error::Error>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(open_input(path)?);
+    let headers = rdr.headers()?.clone();
+
+    let mut report = ValidationReport {
+        input: path.to_string(),
+        ..Default::default()
+    };
+    let mut seen: HashSet<u32> = HashSet::new();
+    let mut client_txs: HashMap<u16, HashSet<u32>> = HashMap::new();
+
+    for result in rdr.records() {
+        report.rows += 1;
+        let record = match result {
+            Ok(record) => record,
+            Err(e) => {
+                let row = e.position().map(|p| p.line()).unwrap_or(0);
+                report.push(row, AnomalyKind::BadSchema, e.to_string());
+                continue;
+            }
+        };
+        let row = record.position().map(|p| p.line()).unwrap_or(0);
+
+        let action: UserTransactions = match record.deserialize(Some(&headers)) {
+            Ok(action) => action,
+            Err(e) => {
+                report.push(row, AnomalyKind::BadSchema, e.to_string());
+                continue;
+            }
+        };
+
+        match action.tx_type {
+            TxType::Deposit | TxType::Withdrawal | TxType::Transfer => {
+                if !seen.insert(action.tx_id) {
+                    report.push(
+                        row,
+                        AnomalyKind::DuplicateTx,
+                        format!("tx {} was already used", action.tx_id),
+                    );
+                }
+                // Transfers can't be disputed, so disputes naming one are
+                // orphans.
+                if action.tx_type != TxType::Transfer {
+                    client_txs
+                        .entry(action.client_id)
+                        .or_default()
+                        .insert(action.tx_id);
+                }
+
+                if let (Some(amount), Some(max)) = (action.amount, config.max_amount)
+                    && amount.value() > max
+                {
+                    report.push(
+                        row,
+                        AnomalyKind::OverLimit,
+                        format!("amount {} exceeds limit {}", amount, max),
+                    );
+                }
+            }
+            TxType::OpenAccount | TxType::CloseAccount | TxType::Adjustment => {}
+            TxType::Dispute | TxType::Resolve | TxType::Chargeback => {
+                let known = client_txs
+                    .get(&action.client_id)
+                    .is_some_and(|txs| txs.contains(&action.tx_id));
+                if !known {
+                    report.push(
+                        row,
+                        AnomalyKind::OrphanDispute,
+                        format!(
+                            "tx {} not found for client {}",
+                            action.tx_id, action.client_id
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
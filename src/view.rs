@@ -0,0 +1,228 @@
+use std::{fmt::Display, str::FromStr};
+
+use rust_decimal::Decimal;
+
+use crate::{PaymentEngine, UserAccount};
+
+/// One account as sinks and APIs see it. Sinks write these instead of
+/// [`UserAccount`], so the engine's internal record can change without
+/// changing the output contract.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct ClientAccountView {
+    pub client_id: u16,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+    pub locked: bool,
+    pub currency: Option<String>,
+    pub tier: Option<String>,
+    pub open_disputes: u32,
+    pub lifetime_chargebacks: u32,
+    /// Chargeback debt not yet repaid; see [`PaymentEngine::debts`].
+    pub debt: Decimal,
+    /// Deposits still held back from withdrawals; see
+    /// [`PaymentEngine::reserved_balance`].
+    pub reserved: Decimal,
+    /// See [`PaymentEngine::is_dormant`].
+    pub dormant: bool,
+    pub closed: bool,
+    /// Whether any transaction was applied to the account in this run.
+    pub changed_this_run: bool,
+    /// See [`PaymentEngine::last_activity`].
+    pub last_activity_seq: Option<u64>,
+}
+
+impl From<&UserAccount> for ClientAccountView {
+    /// Balances only; fields derived from engine state are left empty.
+    fn from(account: &UserAccount) -> Self {
+        Self {
+            client_id: account.client_id,
+            available: account.available,
+            held: account.held,
+            total: account.total,
+            locked: account.locked,
+            ..Default::default()
+        }
+    }
+}
+
+/// A column a sink can write for each account.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AccountField {
+    Client,
+    Available,
+    Held,
+    Total,
+    Locked,
+    Currency,
+    Tier,
+    OpenDisputes,
+    LifetimeChargebacks,
+    Debt,
+    Reserved,
+    Dormant,
+    Closed,
+    ChangedThisRun,
+    LastActivitySeq,
+}
+
+impl AccountField {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountField::Client => "client",
+            AccountField::Available => "available",
+            AccountField::Held => "held",
+            AccountField::Total => "total",
+            AccountField::Locked => "locked",
+            AccountField::Currency => "currency",
+            AccountField::Tier => "tier",
+            AccountField::OpenDisputes => "open_disputes",
+            AccountField::LifetimeChargebacks => "lifetime_chargebacks",
+            AccountField::Debt => "debt",
+            AccountField::Reserved => "reserved",
+            AccountField::Dormant => "dormant",
+            AccountField::Closed => "closed",
+            AccountField::ChangedThisRun => "changed_this_run",
+            AccountField::LastActivitySeq => "last_activity_seq",
+        }
+    }
+}
+
+impl FromStr for AccountField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            AccountField::Client,
+            AccountField::Available,
+            AccountField::Held,
+            AccountField::Total,
+            AccountField::Locked,
+            AccountField::Currency,
+            AccountField::Tier,
+            AccountField::OpenDisputes,
+            AccountField::LifetimeChargebacks,
+            AccountField::Debt,
+            AccountField::Reserved,
+            AccountField::Dormant,
+            AccountField::Closed,
+            AccountField::ChangedThisRun,
+            AccountField::LastActivitySeq,
+        ]
+        .into_iter()
+        .find(|field| field.as_str() == s)
+        .ok_or_else(|| format!("Unknown account field '{}'", s))
+    }
+}
+
+/// Ordered columns of the accounts output. Defaults to the spec's
+/// `client,available,held,total,locked`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AccountColumns(pub Vec<AccountField>);
+
+impl Default for AccountColumns {
+    fn default() -> Self {
+        Self(vec![
+            AccountField::Client,
+            AccountField::Available,
+            AccountField::Held,
+            AccountField::Total,
+            AccountField::Locked,
+        ])
+    }
+}
+
+impl FromStr for AccountColumns {
+    type Err = String;
+
+    /// Parses a comma-separated list such as `client,total,open_disputes`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if fields.is_empty() {
+            return Err("No account fields given".to_string());
+        }
+        Ok(Self(fields))
+    }
+}
+
+impl Display for AccountColumns {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<&str> = self.0.iter().map(AccountField::as_str).collect();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+impl PaymentEngine {
+    /// Full view of `account`, including the fields derived from engine state.
+    pub fn account_view(&self, account: &UserAccount) -> ClientAccountView {
+        let attributes = self.account_attributes(account.client_id);
+        let stats = self.account_stats(account.client_id);
+        ClientAccountView {
+            currency: attributes.and_then(|a| a.currency.clone()),
+            tier: attributes.and_then(|a| a.tier.clone()),
+            open_disputes: stats.open_disputes,
+            lifetime_chargebacks: stats.lifetime_chargebacks,
+            debt: self.outstanding_debt(account.client_id),
+            reserved: self.reserved_balance(account.client_id),
+            dormant: self.is_dormant(account.client_id),
+            closed: self.is_closed(account.client_id),
+            changed_this_run: self.was_touched(account.client_id),
+            last_activity_seq: self.last_activity(account.client_id),
+            ..ClientAccountView::from(account)
+        }
+    }
+
+    pub fn account_views<'a>(
+        &self,
+        accounts: impl IntoIterator<Item = &'a UserAccount>,
+    ) -> Vec<ClientAccountView> {
+        accounts
+            .into_iter()
+            .map(|account| self.account_view(account))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{accounts::AccountAttributes, amount};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_view_includes_derived_fields() {
+        let mut engine = PaymentEngine::new();
+        engine
+            .open_account(
+                1,
+                AccountAttributes {
+                    currency: Some("EUR".to_string()),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        engine.client(1).deposit(1, amount(dec!(10.0))).unwrap();
+        engine.client(1).dispute(1).unwrap();
+
+        let view = engine.account_view(&engine.accounts[&1]);
+        assert_eq!(view.held, dec!(10.0));
+        assert_eq!(view.currency.as_deref(), Some("EUR"));
+        assert_eq!(view.open_disputes, 1);
+        assert!(view.changed_this_run);
+        assert_eq!(view.last_activity_seq, Some(2));
+
+        engine.load_opening_balances([UserAccount::new(2)]).unwrap();
+        let carried = engine.account_view(&engine.accounts[&2]);
+        assert!(!carried.changed_this_run);
+        assert_eq!(carried.last_activity_seq, None);
+
+        let columns: AccountColumns = "client, currency,open_disputes".parse().unwrap();
+        assert_eq!(columns.to_string(), "client,currency,open_disputes");
+        assert!("client,risk".parse::<AccountColumns>().is_err());
+    }
+}
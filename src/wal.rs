@@ -0,0 +1,432 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    iter::Peekable,
+    path::Path,
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    PaymentEngine, UserTransactions,
+    admin::AdminOp,
+    data_sources::{DataSource, LocatedRecord, SourceError, SourceLocation, SourceRecord},
+    errors::{EngineError, ErrorCode},
+    pipeline::{Pipeline, RunSummary},
+};
+
+/// Append-only log of every transaction the engine accepts and every
+/// [`crate::admin::AdminHandle`] operation, written and synced to disk
+/// before the engine returns or calls any hook. A change whose entry can't
+/// be written is undone and refused with [`ErrorCode::LogWriteFailed`].
+/// Rejected transactions change nothing, so they aren't logged. Replaying
+/// it with [`PaymentEngine::replay_wal`] on an engine set up the same way
+/// rebuilds the state it had.
+///
+/// One JSON entry per line: a transaction, or `{"admin": {"op": ...}}`.
+pub struct WriteAheadLog {
+    path: String,
+    file: File,
+}
+
+impl WriteAheadLog {
+    /// Opens `path` for appending, creating it if needed. A line left
+    /// incomplete by a crash is cut off first, so new entries start on a
+    /// line of their own.
+    pub fn open(path: &str) -> Result<Self, String> {
+        let error = |e: std::io::Error| format!("Failed to open write-ahead log '{}': {}", path, e);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)
+            .map_err(error)?;
+        let complete = complete_len(&mut file).map_err(error)?;
+        if complete < file.metadata().map_err(error)?.len() {
+            file.set_len(complete).map_err(error)?;
+        }
+        Ok(Self {
+            path: path.to_string(),
+            file,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Writes `entries` in one go and waits until they're on disk.
+    pub fn append(&mut self, entries: &[WalEntry]) -> Result<(), String> {
+        let mut lines = Vec::new();
+        for entry in entries {
+            serde_json::to_writer(&mut lines, entry)
+                .map_err(|e| format!("Failed to serialize log entry: {}", e))?;
+            lines.push(b'\n');
+        }
+        self.file
+            .write_all(&lines)
+            .and_then(|()| self.file.sync_data())
+            .map_err(|e| format!("Failed to write to '{}': {}", self.path, e))
+    }
+}
+
+/// One line of a [`WriteAheadLog`].
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum WalEntry {
+    Admin { admin: AdminOp },
+    Transaction(UserTransactions),
+}
+
+type LocatedEntry = (Option<SourceLocation>, Result<WalEntry, SourceError>);
+
+/// Length of `file` up to and including its last newline.
+fn complete_len(file: &mut File) -> std::io::Result<u64> {
+    const CHUNK: u64 = 4096;
+    let mut end = file.seek(SeekFrom::End(0))?;
+    let mut buf = vec![0; CHUNK as usize];
+    while end > 0 {
+        let start = end.saturating_sub(CHUNK);
+        let chunk = &mut buf[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+        if let Some(newline) = chunk.iter().rposition(|&b| b == b'\n') {
+            return Ok(start + newline as u64 + 1);
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+/// Reads every entry of the log at `path`. A last line without its newline
+/// was cut short by a crash before its change was accepted, so it is
+/// dropped.
+fn read_entries(path: &str) -> std::io::Result<impl Iterator<Item = LocatedEntry> + use<>> {
+    let mut reader = BufReader::new(File::open(Path::new(path))?);
+    let file: Arc<str> = Arc::from(path);
+    let (mut line_number, mut byte) = (0, 0);
+    Ok(std::iter::from_fn(move || {
+        let mut line = String::new();
+        let read = match reader.read_line(&mut line) {
+            Ok(0) => return None,
+            Ok(read) => read,
+            Err(e) => return Some((None, Err(format!("Failed to read log: {}", e).into()))),
+        };
+        if !line.ends_with('\n') {
+            return None;
+        }
+        line_number += 1;
+        let location = SourceLocation {
+            file: Some(file.clone()),
+            line: line_number,
+            byte,
+        };
+        byte += read as u64;
+        let entry =
+            serde_json::from_str(&line).map_err(|e| format!("Invalid log entry: {}", e).into());
+        Some((Some(location), entry))
+    }))
+}
+
+fn into_record(entry: Result<WalEntry, SourceError>) -> SourceRecord {
+    match entry? {
+        WalEntry::Transaction(action) => Ok(action),
+        WalEntry::Admin { .. } => Err("Admin operation, not a transaction".to_string().into()),
+    }
+}
+
+/// Reads the transactions of a [`WriteAheadLog`] back as a source. Admin
+/// operations come out as source errors.
+pub struct WalSource {
+    path: String,
+}
+
+impl WalSource {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl DataSource for WalSource {
+    fn read_transactions<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn Iterator<Item = SourceRecord> + 'a>, Box<dyn std::error::Error>> {
+        Ok(Box::new(
+            self.read_located_transactions()?.map(|(_, record)| record),
+        ))
+    }
+
+    fn read_located_transactions<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn Iterator<Item = LocatedRecord> + 'a>, Box<dyn std::error::Error>> {
+        Ok(Box::new(
+            read_entries(&self.path)?.map(|(location, entry)| (location, into_record(entry))),
+        ))
+    }
+}
+
+/// The transactions of a log up to its next admin operation.
+struct UntilAdmin<'a, I: Iterator<Item = LocatedEntry>> {
+    entries: &'a mut Peekable<I>,
+}
+
+impl<I: Iterator<Item = LocatedEntry>> DataSource for UntilAdmin<'_, I> {
+    fn read_transactions<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn Iterator<Item = SourceRecord> + 'a>, Box<dyn std::error::Error>> {
+        Ok(Box::new(
+            self.read_located_transactions()?.map(|(_, record)| record),
+        ))
+    }
+
+    fn read_located_transactions<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn Iterator<Item = LocatedRecord> + 'a>, Box<dyn std::error::Error>> {
+        Ok(Box::new(std::iter::from_fn(|| {
+            if let Some((_, Ok(WalEntry::Admin { .. }))) = self.entries.peek() {
+                return None;
+            }
+            let (location, entry) = self.entries.next()?;
+            Some((location, into_record(entry)))
+        })))
+    }
+}
+
+impl PaymentEngine {
+    /// Logs every accepted transaction and admin operation to `wal`, from
+    /// now on.
+    pub fn set_wal(&mut self, wal: WriteAheadLog) {
+        self.wal = Some(wal);
+    }
+
+    pub fn take_wal(&mut self) -> Option<WriteAheadLog> {
+        self.wal.take()
+    }
+
+    /// Applies every transaction in the log at `path`, batches included,
+    /// the way they were applied when logged. Policies, opening balances
+    /// and anything else not done through transactions must be set up
+    /// first, as they were then. Attach the log with [`Self::set_wal`]
+    /// afterwards, or the replay would log everything again.
+    ///
+    /// A log with admin operations in it needs
+    /// [`crate::admin::AdminHandle::replay_wal`]; this stops at the first
+    /// one, with the entries before it applied.
+    pub fn replay_wal(&mut self, path: &str) -> Result<RunSummary, String> {
+        self.replay(path, false)
+    }
+
+    pub(crate) fn replay(&mut self, path: &str, admin: bool) -> Result<RunSummary, String> {
+        if self.wal.is_some() {
+            return Err("Can't replay into an engine that is writing a log".to_string());
+        }
+        let mut entries = read_entries(path)
+            .map_err(|e| format!("Failed to read data: {}", e))?
+            .peekable();
+        let mut summary = RunSummary::default();
+        loop {
+            let run = Pipeline::new().process(
+                &mut UntilAdmin {
+                    entries: &mut entries,
+                },
+                self,
+                |_, _, _| std::ops::ControlFlow::Continue(()),
+            )?;
+            summary.merge(&run);
+            let Some((location, Ok(WalEntry::Admin { admin: op }))) = entries.next() else {
+                return Ok(summary);
+            };
+            let position = location.map_or_else(String::new, |l| format!("{}: ", l));
+            if !admin {
+                return Err(format!(
+                    "{}admin operation; replay this log through an admin handle",
+                    position
+                ));
+            }
+            let applied = self.apply_admin_op(op);
+            summary.record_outcome(&applied);
+            applied.map_err(|e| format!("{}{}", position, e))?;
+        }
+    }
+
+    /// Appends `entries` to the log, if there is one.
+    pub(crate) fn log(&mut self, entries: &[WalEntry]) -> Result<(), EngineError> {
+        match self.wal.as_mut() {
+            Some(wal) => wal
+                .append(entries)
+                .map_err(|e| EngineError::new(ErrorCode::LogWriteFailed, e)),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs `apply`, then logs `entry` once it has succeeded. Hooks wait
+    /// for the write, and a failed write undoes `apply`.
+    pub(crate) fn logged<T>(
+        &mut self,
+        entry: WalEntry,
+        apply: impl FnOnce(&mut Self) -> Result<T, EngineError>,
+    ) -> Result<T, EngineError> {
+        if self.wal.is_none() {
+            return apply(self);
+        }
+        let savepoint = self.savepoint();
+        self.defer_hooks();
+        let result = apply(self).and_then(|value| {
+            self.log(std::slice::from_ref(&entry))?;
+            Ok(value)
+        });
+        if result.is_err() {
+            self.roll_back(savepoint);
+        }
+        self.finish_deferred_hooks(result.is_ok());
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        TxType, UserAccount, adjustments::ADJUSTMENTS_ACCOUNT, admin::AdminCapability,
+        hooks::EngineHooks, signed_amount, tx,
+    };
+    use rust_decimal_macros::dec;
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl EngineHooks for Recorder {
+        fn pre_withdrawal(&mut self, action: &UserTransactions, _: Option<&UserAccount>) {
+            self.0.lock().unwrap().push(format!("pre {}", action.tx_id));
+        }
+
+        fn post_withdrawal(&mut self, action: &UserTransactions, _: &UserAccount) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("post {}", action.tx_id));
+        }
+    }
+
+    #[test]
+    fn test_replay_rebuilds_state_and_skips_torn_tail() {
+        let path = std::env::temp_dir().join(format!("wal-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let mut engine = PaymentEngine::new();
+        engine.set_wal(WriteAheadLog::open(path).unwrap());
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10)))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 2).with_amount(dec!(50)))
+            .unwrap_err();
+        let batch = [3, 4].map(|tx_id| {
+            tx(TxType::Withdrawal, 1, tx_id)
+                .with_amount(dec!(6))
+                .in_batch(7)
+        });
+        engine.process_batch(batch.to_vec()).unwrap_err();
+        engine.process_action(tx(TxType::Dispute, 1, 1)).unwrap();
+        drop(engine.take_wal());
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .unwrap()
+            .write_all(b"{\"type\":\"withdrawal\",\"cli")
+            .unwrap();
+
+        let mut replayed = PaymentEngine::new();
+        let summary = replayed.replay_wal(path).unwrap();
+        // The refused withdrawal and batch were never logged.
+        assert_eq!(summary.records_read, 2);
+        assert_eq!(summary.applied, 2);
+        let account = &replayed.accounts[&1];
+        assert_eq!((account.available, account.held), (dec!(0), dec!(10)));
+        assert_eq!(
+            account.available, engine.accounts[&1].available,
+            "replay matches the logged run"
+        );
+
+        replayed.set_wal(WriteAheadLog::open(path).unwrap());
+        assert!(replayed.replay_wal(path).is_err());
+        replayed
+            .process_action(tx(TxType::Deposit, 1, 5).with_amount(dec!(1)))
+            .unwrap();
+        drop(replayed.take_wal());
+        let mut again = PaymentEngine::new();
+        assert_eq!(again.replay_wal(path).unwrap().applied, 3);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_replay_repeats_admin_operations() {
+        let path = std::env::temp_dir().join(format!("wal-admin-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+        let capability = AdminCapability::grant("ops");
+
+        let mut engine = PaymentEngine::new();
+        engine.set_admin_secret("ops").unwrap();
+        engine.set_wal(WriteAheadLog::open(path).unwrap());
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10)))
+            .unwrap();
+        let mut admin = engine.admin(&capability).unwrap();
+        admin
+            .adjust(1, signed_amount(dec!(2.5)), "FEE_REFUND".parse().unwrap())
+            .unwrap();
+        // Refused, so not logged.
+        assert!(admin.force_balance(2, dec!(1), dec!(0)).is_err());
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 2).with_amount(dec!(12)))
+            .unwrap();
+        drop(engine.take_wal());
+
+        let mut plain = PaymentEngine::new();
+        assert!(plain.replay_wal(path).is_err());
+
+        let mut replayed = PaymentEngine::new();
+        replayed.set_admin_secret("ops").unwrap();
+        let summary = replayed
+            .admin(&capability)
+            .unwrap()
+            .replay_wal(path)
+            .unwrap();
+        assert_eq!((summary.records_read, summary.applied), (3, 3));
+        for client_id in [1, ADJUSTMENTS_ACCOUNT] {
+            let balance = |engine: &PaymentEngine| engine.accounts()[&client_id].total;
+            assert_eq!(balance(&replayed), balance(&engine));
+        }
+        assert_eq!(replayed.accounts()[&1].total, dec!(0.5));
+        assert_eq!(replayed.adjustments(), engine.adjustments());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_logged_rejection_still_runs_pre_hooks() {
+        let path = std::env::temp_dir().join(format!("wal-hooks-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = PaymentEngine::new();
+        engine.set_wal(WriteAheadLog::open(path).unwrap());
+        engine.set_hooks(Box::new(Recorder(calls.clone())));
+        engine
+            .process_action(tx(TxType::Deposit, 1, 1).with_amount(dec!(10)))
+            .unwrap();
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 2).with_amount(dec!(50)))
+            .unwrap_err();
+        engine
+            .process_action(tx(TxType::Withdrawal, 1, 3).with_amount(dec!(4)))
+            .unwrap();
+
+        assert_eq!(*calls.lock().unwrap(), vec!["pre 2", "pre 3", "post 3"]);
+        drop(engine.take_wal());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,6 +1,30 @@
+use std::ops::ControlFlow;
+
 use payment_engine::{
-    PaymentEngine,
-    data_sources::{DataSource, csv::CsvDataSource},
+    PaymentEngine, UserAccount,
+    accounts::read_account_seeds,
+    config::{EngineConfig, RoundingMode},
+    data_sinks::{
+        DataSink,
+        csv::{CsvDataSink, OutputStyle},
+        memory::MemoryDataSink,
+    },
+    data_sources::{
+        DataSource, SourceError,
+        amount::AmountFormat,
+        client_map::ClientIdMap,
+        csv::{CsvDataSource, read_accounts},
+        memory::MemoryDataSource,
+        validate::ValidationErrorKind,
+    },
+    errors::ErrorCode,
+    pipeline::{ErrorPolicy, Pipeline, RecordOutcome, RunSummary, run_pipeline},
+    preview::preview,
+    reconcile::reconcile,
+    scenario::{assert_scenarios, load_scenario},
+    sweeps::SweepRule,
+    validation::{AnomalyKind, ValidationConfig, validate_csv},
+    view::ClientAccountView,
 };
 use rust_decimal_macros::dec;
 
@@ -12,21 +36,21 @@ fn test_transactions_csv() {
     match data_source.read_transactions() {
         Ok(actions) => {
             for action in actions {
-                engine.process_action(action);
+                let _ = engine.process_action(action.unwrap());
             }
         }
         Err(e) => panic!("Failed to read data: {}", e),
     }
 
     // Client 1: deposit 1.0, deposit 2.0, withdrawal 1.5 = 1.5 available
-    let account1 = engine.accounts.get(&1).unwrap();
+    let account1 = engine.accounts().get(&1).unwrap();
     assert_eq!(account1.available, dec!(1.5));
     assert_eq!(account1.held, dec!(0.0));
     assert_eq!(account1.total, dec!(1.5));
     assert!(!account1.locked);
 
     // Client 2: deposit 2.0, withdrawal 3.0 (insufficient) = 2.0 available
-    let account2 = engine.accounts.get(&2).unwrap();
+    let account2 = engine.accounts().get(&2).unwrap();
     assert_eq!(account2.available, dec!(2.0));
     assert_eq!(account2.held, dec!(0.0));
     assert_eq!(account2.total, dec!(2.0));
@@ -43,14 +67,14 @@ fn test_insufficient_funds_csv() {
     match data_source.read_transactions() {
         Ok(actions) => {
             for action in actions {
-                engine.process_action(action);
+                let _ = engine.process_action(action.unwrap());
             }
         }
         Err(e) => panic!("Failed to read data: {}", e),
     }
 
     // Client 1: deposit 10.0, withdrawal 5.0, withdrawal 10.0 (insufficient) = 5.0 available
-    let account = engine.accounts.get(&1).unwrap();
+    let account = engine.accounts().get(&1).unwrap();
     assert_eq!(account.available, dec!(5.0));
     assert_eq!(account.held, dec!(0.0));
     assert_eq!(account.total, dec!(5.0));
@@ -65,7 +89,7 @@ fn test_dispute_csv() {
     match data_source.read_transactions() {
         Ok(actions) => {
             for action in actions {
-                engine.process_action(action);
+                let _ = engine.process_action(action.unwrap());
             }
         }
         Err(e) => panic!("Failed to read data: {}", e),
@@ -74,11 +98,11 @@ fn test_dispute_csv() {
     // Client 1:
     // - deposit 10.0, dispute, resolve = 10.0 available
     // - deposit 5.0, dispute, chargeback = 5.0 held then removed
-    // Final: 10.0 available, 0.0 held, but chargeback reduces by 5.0
-    let account = engine.accounts.get(&1).unwrap();
-    assert_eq!(account.available, dec!(5.0)); // 10.0 - 5.0 from chargeback
+    // Final: 10.0 available, 0.0 held
+    let account = engine.accounts().get(&1).unwrap();
+    assert_eq!(account.available, dec!(10.0));
     assert_eq!(account.held, dec!(0.0));
-    assert_eq!(account.total, dec!(5.0));
+    assert_eq!(account.total, dec!(10.0));
     assert!(account.locked);
 }
 
@@ -90,7 +114,7 @@ fn test_comprehensive_csv() {
     match data_source.read_transactions() {
         Ok(actions) => {
             for action in actions {
-                engine.process_action(action);
+                let _ = engine.process_action(action.unwrap());
             }
         }
         Err(e) => panic!("Failed to read data: {}", e),
@@ -98,26 +122,490 @@ fn test_comprehensive_csv() {
 
     // Client 1: deposit 10.0, withdrawal 2.5, dispute tx1, resolve tx1, deposit 20.0
     // = 10.0 - 2.5 + 20.0 = 27.5
-    let account1 = engine.accounts.get(&1).unwrap();
+    let account1 = engine.accounts().get(&1).unwrap();
     assert_eq!(account1.available, dec!(27.5));
     assert_eq!(account1.held, dec!(0.0));
     assert_eq!(account1.total, dec!(27.5));
     assert!(!account1.locked);
 
     // Client 2: deposit 5.0, dispute, chargeback
-    // = 5.0 held, then chargeback removes from both held and available
-    let account2 = engine.accounts.get(&2).unwrap();
-    assert_eq!(account2.available, dec!(-5.0));
+    // = 5.0 held, then the chargeback removes it from held only
+    let account2 = engine.accounts().get(&2).unwrap();
+    assert_eq!(account2.available, dec!(0.0));
     assert_eq!(account2.held, dec!(0.0));
-    assert_eq!(account2.total, dec!(-5.0));
+    assert_eq!(account2.total, dec!(0.0));
     assert!(account2.locked);
 
     // Client 3: deposit 100.0, withdrawal 50.0, dispute tx4
     // = 100.0 - 50.0 = 50.0, then dispute moves 100.0 to held
     // available = 50.0 - 100.0 = -50.0, held = 100.0
-    let account3 = engine.accounts.get(&3).unwrap();
+    let account3 = engine.accounts().get(&3).unwrap();
     assert_eq!(account3.available, dec!(-50.0));
     assert_eq!(account3.held, dec!(100.0));
     assert_eq!(account3.total, dec!(50.0));
     assert!(!account3.locked);
 }
+
+#[test]
+fn test_validation_csv() {
+    let config = ValidationConfig {
+        max_amount: Some(dec!(1000.0)),
+    };
+    let report = validate_csv("test_validation.csv", &config).unwrap();
+
+    assert_eq!(report.rows, 6);
+    let found: Vec<_> = report.anomalies.iter().map(|a| (a.row, a.kind)).collect();
+    assert_eq!(
+        found,
+        vec![
+            (3, AnomalyKind::DuplicateTx),
+            (5, AnomalyKind::OrphanDispute),
+            (6, AnomalyKind::OverLimit),
+            (7, AnomalyKind::BadSchema),
+        ]
+    );
+}
+
+#[test]
+fn test_invalid_amounts_csv() {
+    let mut source = CsvDataSource::new("test_invalid_amounts.csv".to_string());
+    let records: Vec<_> = source.read_transactions().unwrap().collect();
+    let errors: Vec<_> = records
+        .iter()
+        .filter_map(|record| match record {
+            Err(SourceError::Invalid(e)) => Some((e.kind, e.tx_id)),
+            Err(e) => panic!("unexpected source error: {}", e),
+            Ok(_) => None,
+        })
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            (ValidationErrorKind::NegativeAmount, 2),
+            (ValidationErrorKind::MissingAmount, 3),
+            (ValidationErrorKind::TooManyDecimals, 4),
+            (ValidationErrorKind::UnexpectedAmount, 1),
+        ]
+    );
+    assert_eq!(records.iter().filter(|record| record.is_ok()).count(), 2);
+}
+
+#[test]
+fn test_output_styles() {
+    let mut account = UserAccount::new(1);
+    account.available = dec!(1.5);
+    account.held = dec!(0.25);
+    account.calculate_total();
+
+    let render = |style: OutputStyle| {
+        let mut buf = Vec::new();
+        CsvDataSink::with_style(&mut buf, style)
+            .write_accounts(&[ClientAccountView::from(&account)])
+            .unwrap();
+        String::from_utf8(buf).unwrap()
+    };
+
+    assert_eq!(
+        render(OutputStyle::Spec),
+        "client,available,held,total,locked\n1,1.5000,0.2500,1.7500,false\n"
+    );
+    assert_eq!(
+        render(OutputStyle::Legacy),
+        "client_id,total,held,available,locked\n1,1.75,0.25,1.5,false\n"
+    );
+    assert_eq!(
+        render(OutputStyle::Quoted),
+        "\"Client\",\"Locked\",\"Available\",\"Held\",\"Total\"\n1,false,\"1.5000\",\"0.2500\",\"1.7500\"\n"
+    );
+
+    let mut buf = Vec::new();
+    CsvDataSink::new(&mut buf)
+        .with_columns("client,total,open_disputes".parse().unwrap())
+        .write_accounts(&[ClientAccountView::from(&account)])
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        "client,total,open_disputes\n1,1.7500,0\n"
+    );
+
+    // Explicit columns keep their order under the style's header names.
+    let mut buf = Vec::new();
+    CsvDataSink::with_style(&mut buf, OutputStyle::Quoted)
+        .with_columns("client,total,open_disputes".parse().unwrap())
+        .write_accounts(&[ClientAccountView::from(&account)])
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        "\"Client\",\"Total\",\"Open Disputes\"\n1,\"1.7500\",0\n"
+    );
+}
+
+#[test]
+fn test_amount_formats_csv() {
+    let mut strict = CsvDataSource::new("test_amount_formats.csv".to_string());
+    let strict_count = strict
+        .read_transactions()
+        .unwrap()
+        .filter(Result::is_ok)
+        .count();
+    // Only the plain withdrawal row parses in strict mode
+    assert_eq!(strict_count, 1);
+
+    let mut data_source = CsvDataSource::new("test_amount_formats.csv".to_string())
+        .with_amount_format(AmountFormat::Tolerant);
+    let mut engine = PaymentEngine::new();
+    for action in data_source.read_transactions().unwrap() {
+        engine.process_action(action.unwrap()).unwrap();
+    }
+
+    // 1234.56 + 1000.44 + 1000 - 234.5
+    let account = engine.accounts().get(&1).unwrap();
+    assert_eq!(account.available, dec!(3000.5));
+    assert_eq!(account.total, dec!(3000.5));
+}
+
+#[test]
+fn test_client_map_csv() {
+    let client_map = ClientIdMap::from_path("test_client_map.csv").unwrap();
+    let mut data_source =
+        CsvDataSource::new("test_client_refs.csv".to_string()).with_client_map(client_map);
+    let mut engine = PaymentEngine::new();
+    let records: Vec<_> = data_source.read_transactions().unwrap().collect();
+    assert_eq!(records.iter().filter(|r| r.is_err()).count(), 1);
+    for action in records.into_iter().flatten() {
+        engine.process_action(action).unwrap();
+    }
+
+    // Mapped references and plain numeric ids resolve, unknown references are dropped
+    assert_eq!(engine.accounts().len(), 3);
+    assert_eq!(engine.accounts().get(&1).unwrap().total, dec!(10.0));
+    assert_eq!(engine.accounts().get(&2).unwrap().total, dec!(5.0));
+    assert_eq!(engine.accounts().get(&3).unwrap().total, dec!(7.0));
+}
+
+#[test]
+fn test_opening_balances_csv() {
+    let mut engine = PaymentEngine::new();
+    engine
+        .load_opening_balances(read_accounts("test_opening_balances.csv").unwrap())
+        .unwrap();
+
+    let mut data_source = CsvDataSource::new("test_transactions.csv".to_string());
+    for action in data_source.read_transactions().unwrap() {
+        engine.process_action(action.unwrap()).unwrap();
+    }
+
+    // Client 1: 1.5 carried over + 1.5 from today's file
+    assert_eq!(engine.accounts().get(&1).unwrap().total, dec!(3.0));
+    // Client 2: the 3.0 withdrawal now succeeds against 2.0 carried over + 2.0 deposited
+    assert_eq!(engine.accounts().get(&2).unwrap().total, dec!(1.0));
+    let untouched = engine.accounts().get(&7).unwrap();
+    assert_eq!(untouched.total, dec!(3.0));
+    assert!(untouched.locked);
+}
+
+#[test]
+fn test_run_summary_separates_source_errors_from_rejections() {
+    let mut data_source = CsvDataSource::new("test_validation.csv".to_string());
+    let mut engine = PaymentEngine::new();
+    let mut output = Vec::new();
+    let summary = run_pipeline(
+        &mut data_source,
+        &mut engine,
+        &mut CsvDataSink::new(&mut output),
+    )
+    .unwrap();
+
+    // The unknown `refund` row never parses; the repeated tx 1, the withdrawal on
+    // a missing account and the dispute on an unknown tx are refused by the engine.
+    assert_eq!(
+        summary,
+        RunSummary {
+            records_read: 6,
+            source_errors: 1,
+            applied: 2,
+            rejected: 3,
+        }
+    );
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with("client,available,held,total,locked\n"));
+    assert!(output.contains("1,10.0000,0.0000,10.0000,false"));
+    assert!(output.contains("2,5000.0000,0.0000,5000.0000,false"));
+}
+
+#[test]
+fn test_pipeline_fail_fast_stops_at_first_rejection() {
+    let mut data_source = CsvDataSource::new("test_insufficient_funds.csv".to_string());
+    let mut engine = PaymentEngine::new();
+    let err = Pipeline::new()
+        .with_policy(ErrorPolicy::FailFast)
+        .process(&mut data_source, &mut engine, |_, _, _| {
+            ControlFlow::Continue(())
+        })
+        .unwrap_err();
+
+    assert!(err.starts_with("test_insufficient_funds.csv:4 (byte 58): PE1001 Insufficient funds"));
+    assert_eq!(engine.accounts().get(&1).unwrap().available, dec!(5.0));
+}
+
+#[test]
+fn test_batches_csv_apply_all_or_nothing() {
+    let mut data_source = CsvDataSource::new("test_batches.csv".to_string());
+    let mut engine = PaymentEngine::new();
+    let mut rejected = Vec::new();
+    let summary = Pipeline::new()
+        .process(&mut data_source, &mut engine, |_, _, outcome| {
+            if let RecordOutcome::Rejected(action, e) = outcome {
+                rejected.push((action.tx_id, e.code()));
+            }
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+
+    assert_eq!(summary.applied, 4);
+    assert_eq!(
+        rejected,
+        vec![
+            (4, ErrorCode::BatchRolledBack),
+            (5, ErrorCode::BatchRolledBack),
+            (6, ErrorCode::InsufficientFunds),
+        ]
+    );
+    assert_eq!(engine.accounts()[&1].available, dec!(2));
+    assert_eq!(engine.accounts()[&2].available, dec!(8));
+    assert_eq!(engine.accounts()[&3].available, dec!(1));
+}
+
+#[test]
+fn test_transfers_csv() {
+    let mut data_source = CsvDataSource::new("test_transfers.csv".to_string());
+    let mut engine = PaymentEngine::new();
+    let mut rejected = Vec::new();
+    let summary = Pipeline::new()
+        .process(&mut data_source, &mut engine, |_, _, outcome| {
+            if let RecordOutcome::Rejected(action, e) = outcome {
+                rejected.push((action.tx_id, e.code()));
+            }
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+
+    // Tx 4 names no destination client.
+    assert_eq!(summary.source_errors, 1);
+    assert_eq!(summary.applied, 6);
+    assert_eq!(
+        rejected,
+        vec![
+            (3, ErrorCode::InsufficientFunds),
+            (6, ErrorCode::AccountLocked),
+        ]
+    );
+    assert_eq!(engine.accounts()[&1].available, dec!(7.5));
+    assert_eq!(engine.accounts()[&2].available, dec!(2.5));
+    assert!(engine.accounts()[&3].locked);
+}
+
+#[test]
+fn test_open_accounts_csv() {
+    let mut engine = PaymentEngine::new();
+    engine.set_require_open_accounts(true);
+    for (client_id, attributes) in read_account_seeds("test_account_seeds.csv").unwrap() {
+        engine.open_account(client_id, attributes).unwrap();
+    }
+
+    let mut data_source = CsvDataSource::new("test_open_accounts.csv".to_string());
+    for action in data_source.read_transactions().unwrap() {
+        engine.process_action(action.unwrap()).unwrap();
+    }
+
+    // Client 1 overdraws within its 25.0 credit limit
+    assert_eq!(engine.accounts().get(&1).unwrap().available, dec!(-20.0));
+    let attributes = engine.account_attributes(1).unwrap();
+    assert_eq!(attributes.tier.as_deref(), Some("gold"));
+    assert_eq!(attributes.credit_limit, Some(dec!(25.0)));
+
+    // Client 2 was opened by the seed file, with empty columns left unset
+    assert_eq!(engine.accounts().get(&2).unwrap().available, dec!(5.0));
+    let attributes = engine.account_attributes(2).unwrap();
+    assert_eq!(attributes.kind.as_deref(), Some("business"));
+    assert_eq!(attributes.tier, None);
+}
+
+#[test]
+fn test_repeated_runs_are_byte_identical() {
+    let run = || {
+        let mut engine = PaymentEngine::new();
+        engine.add_sweep_rule(SweepRule {
+            from: 3,
+            to: 4,
+            threshold: dec!(60.0),
+        });
+        let mut output = Vec::new();
+        run_pipeline(
+            &mut CsvDataSource::new("test_comprehensive.csv".to_string()),
+            &mut engine,
+            &mut CsvDataSink::new(&mut output),
+        )
+        .unwrap();
+        let sweep_ids: Vec<u32> = engine
+            .drain_events()
+            .iter()
+            .map(|e| e.action.tx_id)
+            .collect();
+        (String::from_utf8(output).unwrap(), sweep_ids)
+    };
+
+    let (first, sweep_ids) = run();
+    assert_eq!(run(), (first.clone(), sweep_ids.clone()));
+    assert_eq!(sweep_ids, vec![4_100_000_000, 4_100_000_001]);
+    assert_eq!(
+        first,
+        "client,available,held,total,locked\n\
+         1,27.5000,0.0000,27.5000,false\n\
+         2,0.0000,0.0000,0.0000,true\n\
+         3,-90.0000,100.0000,10.0000,false\n\
+         4,40.0000,0.0000,40.0000,false\n"
+    );
+}
+
+#[test]
+fn test_reconcile_against_expected_balances() {
+    let mut engine = PaymentEngine::new();
+    let mut data_source = CsvDataSource::new("test_transactions.csv".to_string());
+    Pipeline::new()
+        .process(&mut data_source, &mut engine, |_, _, _| {
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+    let expected = read_accounts("test_expected_balances.csv").unwrap();
+
+    let discrepancies = reconcile(&engine, &expected, dec!(0.001));
+    let found: Vec<(u16, &str, &str, &str)> = discrepancies
+        .iter()
+        .map(|d| (d.client_id, d.field, d.expected.as_str(), d.actual.as_str()))
+        .collect();
+    // Client 1 is off by 0.0001, inside the tolerance.
+    assert_eq!(
+        found,
+        vec![
+            (2, "available", "2.5000", "2.0000"),
+            (2, "total", "2.5000", "2.0000"),
+            (3, "account", "present", "missing"),
+        ]
+    );
+    assert_eq!(reconcile(&engine, &expected, dec!(0)).len(), 5);
+}
+
+#[test]
+fn test_preview_leaves_state_untouched() {
+    let mut engine = PaymentEngine::new();
+    engine
+        .load_opening_balances(read_accounts("test_opening_balances.csv").unwrap())
+        .unwrap();
+
+    let mut pending = CsvDataSource::new("test_transactions.csv".to_string());
+    let result = preview(&engine, &mut pending).unwrap();
+    let changes: Vec<_> = result
+        .changes
+        .iter()
+        .map(|c| (c.client_id, c.total_change))
+        .collect();
+    assert_eq!(changes, vec![(1, dec!(1.5)), (2, dec!(-1.0))]);
+    assert!(result.rejected.is_empty());
+    assert_eq!(engine.accounts()[&1].total, dec!(1.5));
+
+    let mut pending = CsvDataSource::new("test_transactions.csv".to_string());
+    let result = preview(&PaymentEngine::new(), &mut pending).unwrap();
+    assert_eq!(result.rejected.len(), 1);
+    assert_eq!(
+        result.rejected[0].position,
+        "test_transactions.csv:6 (byte 89)"
+    );
+    assert_eq!(result.rejected[0].code, Some(ErrorCode::InsufficientFunds));
+}
+
+#[test]
+fn test_memory_source_and_sink() {
+    let mut csv = CsvDataSource::new("test_transactions.csv".to_string());
+    let transactions: Vec<_> = csv
+        .read_transactions()
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+
+    let mut engine = PaymentEngine::new();
+    engine.add_sweep_rule(SweepRule {
+        from: 2,
+        to: 3,
+        threshold: dec!(1.0),
+    });
+    let mut sink = MemoryDataSink::new();
+    let summary = engine
+        .run(&mut MemoryDataSource::new(transactions), &mut sink)
+        .unwrap();
+    sink.collect_events(&mut engine);
+
+    assert_eq!(summary.applied, 4);
+    assert_eq!(sink.accounts.len(), 3);
+    assert_eq!(sink.account(2).unwrap().available, dec!(1.0));
+    assert_eq!(sink.account(3).unwrap().available, dec!(1.0));
+    assert_eq!(sink.events.len(), 2);
+}
+
+#[test]
+fn test_scenarios() {
+    assert_scenarios("tests/scenarios");
+
+    let mut scenario =
+        load_scenario(std::path::Path::new("tests/scenarios/capped_dispute.toml")).unwrap();
+    scenario.expect.rejections.clear();
+    let failures = scenario.run().unwrap();
+    assert_eq!(failures.len(), 1);
+    assert!(failures[0].starts_with("tx 9: unexpected rejection PE2001"));
+}
+
+#[test]
+fn test_output_styles_round_trip_through_read_accounts() {
+    let accounts = [
+        ClientAccountView {
+            client_id: 1,
+            available: dec!(100),
+            held: dec!(0.00001),
+            total: dec!(100.00001),
+            ..Default::default()
+        },
+        ClientAccountView {
+            client_id: 2,
+            available: dec!(-12.345678),
+            held: dec!(1e20),
+            total: dec!(99999999999999999987.654322),
+            locked: true,
+            ..Default::default()
+        },
+    ];
+    let path = std::env::temp_dir().join(format!("styles-{}.csv", std::process::id()));
+    for precision in [4, 8] {
+        let config = EngineConfig::new(precision, RoundingMode::default()).unwrap();
+        for style in [OutputStyle::Spec, OutputStyle::Legacy, OutputStyle::Quoted] {
+            let mut out = Vec::new();
+            CsvDataSink::with_style(&mut out, style)
+                .with_config(config)
+                .write_accounts(&accounts)
+                .unwrap();
+            let text = String::from_utf8(out).unwrap();
+            assert!(!text.contains(['E', '+']) && !text.contains("e-"), "{text}");
+            assert_eq!(text.contains('"'), style == OutputStyle::Quoted, "{text}");
+
+            std::fs::write(&path, &text).unwrap();
+            let read = read_accounts(path.to_str().unwrap()).unwrap();
+            for (account, expected) in read.iter().zip(&accounts) {
+                assert_eq!(account.client_id, expected.client_id);
+                assert_eq!(account.available, config.round(expected.available));
+                assert_eq!(account.held, config.round(expected.held));
+                assert_eq!(account.total, config.round(expected.total));
+                assert_eq!(account.locked, expected.locked);
+            }
+        }
+    }
+    std::fs::remove_file(path).unwrap();
+}